@@ -0,0 +1,128 @@
+//! Times drawing frames, in which one stroke grows by a point and the scene
+//! is built again, on documents of more and more strokes in view. Compares
+//! keeping the scene between frames, which should take about as long
+//! whatever the stroke count, against drawing every stroke into a new scene
+//! each frame, with and without the stroke paths kept. Ends with how many
+//! times longer a frame with the scene kept takes on the most strokes than
+//! on the fewest. Run with `cargo bench --bench scene_cache`.
+use std::time::{Duration, Instant};
+use rust_pathfinder::prelude::*;
+use rust_pathfinder::app::document::{
+    Document, Layer, LayerId, Stroke, StrokeId, StrokeOrigin, StrokePoint, StrokeStyle,
+};
+use rust_pathfinder::app::fitting::FitOptions;
+use rust_pathfinder::app::scene::{self, SceneCache, StrokePaths};
+
+const STROKE_COUNTS: [usize; 4] = [1_000, 4_000, 16_000, 64_000];
+const POINTS_PER_STROKE: usize = 16;
+const FRAME_COUNT: usize = 60;
+/// A 1920x1080 window at 100% zoom, which every stroke is in.
+const WINDOW_SIZE: (f32, f32) = (1920.0, 1080.0);
+
+/// A linear congruential generator, so that runs are comparable.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+    fn point(&mut self, size: Vector2F) -> Vector2F {
+        Vector2F::new(self.next() * size.x(), self.next() * size.y())
+    }
+}
+
+fn random_stroke(random: &mut Random, id: u64, size: Vector2F) -> Stroke {
+    let mut position = random.point(size);
+    let points = (0..POINTS_PER_STROKE)
+        .map(|index| {
+            position = position + random.point(Vector2F::splat(20.0)) - Vector2F::splat(10.0);
+            StrokePoint { position, pressure: 1.0, time: index as f32 * 0.01 }
+        })
+        .collect();
+    Stroke {
+        id: StrokeId(id),
+        style: StrokeStyle::default(),
+        timestamp: 0,
+        points,
+        shape: None,
+        origin: StrokeOrigin::Brush,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Keep {
+    /// The scene and the stroke paths, as the app does.
+    Scene,
+    /// Only the stroke paths; every stroke is drawn into a new scene.
+    Paths,
+    Nothing,
+}
+
+/// Draws `FRAME_COUNT` frames of a new stroke on top of `document`, keeping
+/// what `keep` says in `cache` across frames.
+fn draw_frames(document: &mut Document, cache: &mut SceneCache, keep: Keep) -> Duration {
+    let size = Vector2F::new(WINDOW_SIZE.0, WINDOW_SIZE.1);
+    let fit_options = FitOptions::default();
+    let transform = pf::Transform2F::default();
+    let font_context = pf::CanvasFontContext::from_system_source();
+    let id = document.begin_stroke(StrokeStyle::default(), StrokeOrigin::Brush).unwrap();
+    let start = Instant::now();
+    for index in 0..FRAME_COUNT {
+        let position = Vector2F::new(100.0 + index as f32 * 10.0, 500.0);
+        document.push_point(id, StrokePoint { position, pressure: 1.0, time: index as f32 * 0.016 });
+        if keep == Keep::Scene {
+            cache.build_scene(document, &fit_options, size, transform, |_| {});
+            continue;
+        }
+        if keep == Keep::Nothing {
+            cache.paths = StrokePaths::default();
+        }
+        let mut ctx = pf::Canvas::new(size).get_context_2d(font_context.clone());
+        ctx.set_transform(&transform);
+        let visible_area = RectF::new(Vector2F::zero(), size);
+        let ctx = scene::draw_document(
+            ctx,
+            document,
+            &fit_options,
+            Some(visible_area),
+            &mut cache.paths,
+        );
+        ctx.into_canvas().into_scene();
+    }
+    let elapsed = start.elapsed();
+    document.remove_stroke(id);
+    elapsed / FRAME_COUNT as u32
+}
+
+fn main() {
+    let size = Vector2F::new(WINDOW_SIZE.0, WINDOW_SIZE.1);
+    println!(
+        "{:>8} {:>16} {:>16} {:>16}",
+        "strokes", "scene kept", "paths kept", "nothing kept",
+    );
+    let mut scene_kept_times = Vec::new();
+    for &count in STROKE_COUNTS.iter() {
+        let mut random = Random(0x5EED);
+        let mut layer = Layer::new(LayerId(0), String::from("Layer 1"));
+        layer.strokes = (0..count as u64).map(|id| random_stroke(&mut random, id, size)).collect();
+        let mut document = Document::from_layers(vec![layer], LayerId(0), 0, 1);
+
+        let mut cache = SceneCache::new();
+        // Draws every finished stroke once, as the frames before would have.
+        draw_frames(&mut document, &mut cache, Keep::Scene);
+        let scene_kept = draw_frames(&mut document, &mut cache, Keep::Scene);
+        let paths_kept = draw_frames(&mut document, &mut cache, Keep::Paths);
+        let nothing_kept = draw_frames(&mut document, &mut cache, Keep::Nothing);
+        println!("{:>8} {:>16?} {:>16?} {:>16?}", count, scene_kept, paths_kept, nothing_kept);
+        scene_kept_times.push(scene_kept);
+    }
+    let fewest = scene_kept_times[0].as_secs_f64();
+    let most = scene_kept_times[STROKE_COUNTS.len() - 1].as_secs_f64();
+    println!(
+        "scene kept: {:.1}x per frame at {} strokes against {}",
+        most / fewest,
+        STROKE_COUNTS[STROKE_COUNTS.len() - 1],
+        STROKE_COUNTS[0],
+    );
+}
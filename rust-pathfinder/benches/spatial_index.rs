@@ -0,0 +1,110 @@
+//! Times spatial index queries against a linear scan on a document of
+//! 100,000 strokes. Run with `cargo bench --bench spatial_index`.
+use std::time::{Duration, Instant};
+use rust_pathfinder::prelude::*;
+use rust_pathfinder::app::document::{
+    Document, Layer, LayerId, Stroke, StrokeId, StrokeOrigin, StrokePoint, StrokeStyle,
+};
+
+const STROKE_COUNT: usize = 100_000;
+const POINTS_PER_STROKE: usize = 16;
+/// Strokes are scattered over a square of this side, in document units.
+const CANVAS_SIZE: f32 = 50_000.0;
+const QUERY_COUNT: usize = 1_000;
+
+/// A linear congruential generator, so that runs are comparable.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+    fn point(&mut self, size: f32) -> Vector2F {
+        Vector2F::new(self.next() * size, self.next() * size)
+    }
+}
+
+fn random_stroke(random: &mut Random, id: u64) -> Stroke {
+    let mut position = random.point(CANVAS_SIZE);
+    let points = (0..POINTS_PER_STROKE)
+        .map(|index| {
+            position = position + random.point(20.0) - Vector2F::splat(10.0);
+            StrokePoint { position, pressure: 1.0, time: index as f32 * 0.01 }
+        })
+        .collect();
+    Stroke {
+        id: StrokeId(id),
+        style: StrokeStyle::default(),
+        timestamp: 0,
+        points,
+        shape: None,
+        origin: StrokeOrigin::Brush,
+    }
+}
+
+fn report(name: &str, iterations: usize, elapsed: Duration, hits: usize) {
+    let per_iteration = elapsed / iterations as u32;
+    println!("{:<32} {:>12?} per query, {} hits", name, per_iteration, hits);
+}
+
+fn main() {
+    let mut random = Random(0x5EED);
+    let mut layer = Layer::new(LayerId(0), String::from("Layer 1"));
+    layer.strokes = (0..STROKE_COUNT as u64).map(|id| random_stroke(&mut random, id)).collect();
+
+    let start = Instant::now();
+    let mut document = Document::from_layers(vec![layer], LayerId(0), 0, 1);
+    println!("{:<32} {:>12?}", "build index", start.elapsed());
+
+    // A 1920x1080 window at 100% zoom.
+    let viewports: Vec<RectF> = (0..QUERY_COUNT)
+        .map(|_| RectF::new(random.point(CANVAS_SIZE), Vector2F::new(1920.0, 1080.0)))
+        .collect();
+    let start = Instant::now();
+    let hits: usize = viewports.iter().map(|rect| document.strokes_in_rect(*rect).len()).sum();
+    report("viewport, index", QUERY_COUNT, start.elapsed(), hits);
+    let start = Instant::now();
+    let hits: usize = viewports
+        .iter()
+        .map(|rect| {
+            document
+                .strokes()
+                .filter(|stroke| stroke.ink_bounds().map_or(false, |bounds| bounds.intersects(*rect)))
+                .count()
+        })
+        .sum();
+    report("viewport, linear scan", QUERY_COUNT, start.elapsed(), hits);
+
+    let centers: Vec<Vector2F> = (0..QUERY_COUNT).map(|_| random.point(CANVAS_SIZE)).collect();
+    let start = Instant::now();
+    let hits: usize = centers.iter().map(|center| document.strokes_near(*center, 12.0).len()).sum();
+    report("eraser dab, index", QUERY_COUNT, start.elapsed(), hits);
+    let start = Instant::now();
+    let hits: usize = centers
+        .iter()
+        .map(|center| {
+            let area = RectF::from_points(*center, *center).dilate(12.0);
+            document
+                .strokes()
+                .filter(|stroke| stroke.ink_bounds().map_or(false, |bounds| bounds.intersects(area)))
+                .count()
+        })
+        .sum();
+    report("eraser dab, linear scan", QUERY_COUNT, start.elapsed(), hits);
+
+    // Zoomed out over the whole canvas, where the index falls back to a scan.
+    let everything = RectF::new(Vector2F::zero(), Vector2F::splat(CANVAS_SIZE));
+    let start = Instant::now();
+    let hits = document.strokes_in_rect(everything).len();
+    report("whole canvas, index", 1, start.elapsed(), hits);
+
+    // Drawing: every new point grows the bounds of the stroke being drawn.
+    let id = document.begin_stroke(StrokeStyle::default(), StrokeOrigin::Brush).unwrap();
+    let start = Instant::now();
+    for index in 0..QUERY_COUNT {
+        let position = Vector2F::new(index as f32 * 5.0, 1000.0);
+        document.push_point(id, StrokePoint { position, pressure: 1.0, time: 0.0 });
+    }
+    report("push point", QUERY_COUNT, start.elapsed(), 0);
+}
@@ -1,20 +1,55 @@
 pub mod types;
+pub mod document;
+pub mod format;
+pub mod history;
+pub mod fitting;
+pub mod dynamics;
+pub mod scene;
+pub mod eraser;
+pub mod spatial;
+pub mod selection;
+pub mod shape;
+pub mod paint;
+pub mod recognition;
+pub mod stabilizer;
+pub mod shape_tool;
+pub mod tool;
+pub mod brush;
+pub mod svg_import;
+pub mod svg_export;
+pub mod page_export;
 use std::collections::LinkedList;
-use std::intrinsics::sqrtf32;
 use std::sync::Mutex;
 use std::cell::Cell;
 use std::collections::VecDeque;
 use std::path::PathBuf;
+use std::time::Instant;
 use pathfinder_geometry::rect::RectI;
 use pathfinder_geometry::vector::{Vector2I, vec2i};
 use rayon::ThreadPoolBuilder;
-use io_surface::IOSurfaceRef;
 use lazy_static::lazy_static;
 // use surfman::{NativeDevice, SystemConnection, SystemDevice, SystemSurface};
-use surfman::{SurfaceAccess, SurfaceType};
 use euclid::default::Size2D;
 use crate::{app, prelude::*};
+use crate::engine::camera::{self, Camera};
+use crate::engine::headless::HeadlessWindow;
+use crate::engine::renderer;
+use crate::engine::surface::{AdapterKind, GpuSurface};
+use crate::engine::ui::{ScreenshotInfo, ScreenshotType};
+use crate::engine::window::Window;
 use types::WindowSize;
+use document::{BlendMode, Document, StrokeStyle};
+use history::{Command, History};
+use fitting::FitOptions;
+use page_export::{PageFormat, PageOptions};
+use scene::SceneCache;
+use eraser::EraserMode;
+use selection::SelectionMode;
+use shape_tool::ShapeOptions;
+use tool::{ActiveTool, PointerEvent, Tool, ToolContext, Tools};
+use crate::engine::pointer::{Pointer, PointerButton, PointerId, PointerInput};
+use crate::engine::pointer::{PointerKind, PointerPhase};
+use crate::engine::gesture::{GestureEvent, GestureRecognizer};
 
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -38,203 +73,676 @@ use types::WindowSize;
 // APP INSTANCE
 ///////////////////////////////////////////////////////////////////////////////
 
-
-pub struct AppState {
+pub struct AppState<W: Window> {
     pub should_exit: bool,
     pub should_redraw: bool,
     pub should_resize: bool,
-    pub mouse_down: bool,
+    pub pointers: PointerInput,
+    /// Takes two-finger touches for the camera before tools see them.
+    pub gestures: GestureRecognizer,
+    /// The pointer the active tool follows. Other pointers are ignored
+    /// until it lifts, so a hand resting on a touch screen does not fight
+    /// the pen or mouse.
+    pub active_pointer: Option<PointerId>,
     pub focused: bool,
     /// Cursor is within the window frame.
     pub cursor_active: bool,
-    pub segments: Vec<Vec<Vector2I>>,
-    pub renderer: pf::Renderer<pf::MetalDevice>,
+    pub document: Document,
+    pub tools: Tools,
+    /// Style applied to newly started strokes.
+    pub brush: StrokeStyle,
+    pub history: History,
+    pub fit_options: FitOptions,
+    /// Page layout for PDF and PostScript export.
+    pub page_options: PageOptions,
+    /// Where the document was last saved to or opened from.
+    pub document_path: Option<PathBuf>,
+    pub modifiers: wit::ModifiersState,
+    /// Maps world coordinates, which strokes are stored in, to the window.
+    pub camera: Camera,
+    /// Last cursor position, in device pixels.
+    pub cursor_position: Vector2F,
+    pub space_down: bool,
+    /// Dragging moves the camera instead of drawing.
+    pub panning: bool,
+    pub renderer: pf::Renderer<W::Device>,
+    /// The strokes that stopped changing, rendered first.
+    pub settled_scene_proxy: pf::SceneProxy,
+    /// The strokes above them and tool feedback, rendered over them.
     pub scene_proxy: pf::SceneProxy,
+    /// Stroke paths and fonts, kept from frame to frame.
+    pub scene_cache: SceneCache,
+    /// Saved once the next frame is rendered.
+    pub pending_screenshot: Option<ScreenshotInfo>,
 }
 
 
-impl AppState {
-    fn draw(&mut self, app_window: &mut AppWindow) {
+impl<W: Window> AppState<W> {
+    pub fn new(app_window: &mut W) -> AppState<W> {
+        let (renderer, settled_scene_proxy) = init_renderer(app_window);
+        let level = pf::RendererLevel::default_for_device(renderer.device());
+        let scene_proxy = pf::SceneProxy::new(level, pf::RayonExecutor);
+        AppState {
+            pointers: PointerInput::new(),
+            gestures: GestureRecognizer::new(),
+            active_pointer: None,
+            should_redraw: false,
+            should_exit: false,
+            should_resize: false,
+            focused: false,
+            cursor_active: false,
+            document: Document::new(),
+            tools: Tools::default(),
+            brush: StrokeStyle::default(),
+            history: History::default(),
+            fit_options: FitOptions::default(),
+            page_options: PageOptions::default(),
+            document_path: None,
+            modifiers: wit::ModifiersState::empty(),
+            camera: Camera::default(),
+            cursor_position: Vector2F::zero(),
+            space_down: false,
+            panning: false,
+            renderer,
+            settled_scene_proxy,
+            scene_proxy,
+            scene_cache: SceneCache::new(),
+            pending_screenshot: None,
+        }
+    }
+    fn draw(&mut self, app_window: &mut W) {
         ///////////////////////////////////////////////////////////////////////
         // SETUP
         ///////////////////////////////////////////////////////////////////////
         self.should_redraw = false;
-        let (width, height) = {
-            let s = app_window.window_size();
-            (s.x(), s.y())
-        };
-        let canvas = pf::Canvas::new(
-            Vector2F::new(width as f32, height as f32)
-        );
-        let canvas_font_context = pf::CanvasFontContext::from_system_source();
-        let mut ctx = canvas.get_context_2d(canvas_font_context);
-        ///////////////////////////////////////////////////////////////////////
-        // RENDERING CONTEXT
-        ///////////////////////////////////////////////////////////////////////
-        for segment in self.segments.iter() {
-            ctx.set_line_width(10.0);
-            let style = pf::FillStyle::Color(
-                pf::ColorU::new(u8::MAX, 0, 0, u8::MAX)
-            );
-            // ctx.set_stroke_style(style);
-            ctx.set_fill_style(pf::FillStyle::Color(
-                pf::ColorU::new(u8::MAX, 0, 0, u8::MAX)
-            ));
-            let mut last_point = None::<Vector2I>;
-            fn distance_between(
-                a: Vector2I,
-                b: Vector2I,
-            ) -> f32 {
-                let dx = (
-                    (b.x() - a.x()).pow(2)
-                ) as f32;
-                let dy = (
-                    (b.y() - a.y()).pow(2)
-                ) as f32;
-                unsafe {
-                    sqrtf32(dx + dy)
-                }
-            }
-            for (pix, point) in segment.iter().enumerate() {
-                ///////////////////////////////////////////////////////////////
-                // DEFAULT STYLING
-                ///////////////////////////////////////////////////////////////
-                ctx.set_fill_style(pf::FillStyle::Color(
-                    pf::ColorU::new(u8::MAX, 0, 0, u8::MAX)
-                ));
-                ctx.set_stroke_style(pf::FillStyle::Color(
-                    pf::ColorU::new(u8::MAX, 0, 0, u8::MAX)
-                ));
-                ///////////////////////////////////////////////////////////////
-                // DEBUG POINT
-                ///////////////////////////////////////////////////////////////
-                {
-                    let mut path = pf::Path2D::new();
-                    let center = Vector2F::new(
-                        point.x() as f32,
-                        point.y() as f32,
-                    );
-                    let tau = 2.0 * std::f32::consts::PI;
-                    path.arc(
-                        center,
-                        5.0,
-                        0.0,
-                        tau,
-                        pf::ArcDirection::CCW
-                    );
-                    ctx.fill_path(path, pf::FillRule::Winding);
-                }
-                ///////////////////////////////////////////////////////////////
-                // LINE
-                ///////////////////////////////////////////////////////////////
-                let mut path = pf::Path2D::new();
-                if let Some(previous) = last_point {
-                    let distance = distance_between(*point, previous);
-                    path.move_to(Vector2F::new(
-                        previous.x() as f32,
-                        previous.y() as f32,
-                    ));
-                    path.line_to(Vector2F::new(
-                        point.x() as f32,
-                        point.y() as f32,
-                    ));
-                    if distance < 30.0 {
-                        ctx.set_stroke_style(pf::FillStyle::Color(
-                            pf::ColorU::new(
-                                0,
-                                u8::MAX,
-                                0,
-                                u8::MAX
-                            )
-                        ));
-                    }
-                    ctx.stroke_path(path);
-                }
-                last_point = Some(*point);
-            }
-        }
+        let window_size = app_window.window_size().to_f32();
         ///////////////////////////////////////////////////////////////////////
         // SCENE
         ///////////////////////////////////////////////////////////////////////
-        self.scene_proxy.replace_scene(
-            ctx.into_canvas().into_scene()
+        let camera_transform = self.camera.transform();
+        let tools = &self.tools;
+        let frame = self.scene_cache.build_scene(
+            &self.document,
+            &self.fit_options,
+            window_size,
+            camera_transform,
+            |ctx| tools.active().draw_overlay(ctx, &camera_transform),
         );
-        let build_options = pf::BuildOptions {
+        if let Some(settled) = frame.settled {
+            self.settled_scene_proxy.replace_scene(settled);
+        }
+        self.scene_proxy.replace_scene(frame.live);
+        let build_options = || pf::BuildOptions {
             subpixel_aa_enabled: true,
             ..pf::BuildOptions::default()
         };
-        self.scene_proxy.build_and_render(&mut self.renderer, build_options);
+        self.settled_scene_proxy.build_and_render(&mut self.renderer, build_options());
+        // The live scene goes over the settled one without clearing it.
+        let background_color = self.renderer.options_mut().background_color.take();
+        self.scene_proxy.build_and_render(&mut self.renderer, build_options());
+        self.renderer.options_mut().background_color = background_color;
+        if let Some(info) = self.pending_screenshot.take() {
+            self.take_screenshot(&info, app_window);
+        }
         ///////////////////////////////////////////////////////////////////////
         // FINALIZE
         ///////////////////////////////////////////////////////////////////////
         app_window.present(self.renderer.device_mut());
         self.renderer.dest_framebuffer_size_changed();
     }
-    fn resize(&mut self, app_window: &mut AppWindow) {
-        self.should_resize = false;
-        let new_size = app_window.real_window_size();
-        let new_size = euclid::Size2D::new(
-            new_size.width as i32,
-            new_size.height as i32,
+    /// Must run after the frame is rendered and before it is presented. The
+    /// scene is drawn in device pixels already, so SVG content stays put.
+    fn take_screenshot(&mut self, info: &ScreenshotInfo, app_window: &W) {
+        let background = match self.renderer.options().background_color {
+            Some(color) => color.to_u8(),
+            None => pf::ColorU::transparent_black(),
+        };
+        let result = renderer::save_screenshot(
+            self.renderer.device(),
+            &[&self.settled_scene_proxy, &self.scene_proxy],
+            info,
+            &pf::Transform2F::default(),
+            app_window.window_size(),
+            background,
         );
-        app_window.device.resize_surface(
-            &mut app_window.surface,
-            new_size,
+        if let Err(error) = result {
+            eprintln!("Failed to save screenshot to {}: {}", info.path.display(), error);
+        }
+    }
+    /// Asks where to save a screenshot of the next frame.
+    fn request_screenshot(&mut self, kind: ScreenshotType, app_window: &W) {
+        if let Ok(path) = app_window.run_save_dialog(kind.extension()) {
+            self.pending_screenshot = Some(ScreenshotInfo { kind, path });
+            self.should_redraw = true;
+        }
+    }
+    fn resize(&mut self, app_window: &mut W) {
+        self.should_resize = false;
+        app_window.resize_surface(self.renderer.device_mut());
+        self.renderer.options_mut().dest = pf::DestFramebuffer::Default {
+            viewport: app_window.viewport(),
+            window_size: app_window.window_size(),
+        };
+        self.renderer.dest_framebuffer_size_changed();
+    }
+    /// Hands the active tool, or any other, what it may touch while
+    /// handling input.
+    fn with_tools<F, R>(&mut self, f: F) -> R where F: FnOnce(&mut Tools, &mut ToolContext) -> R {
+        let AppState {
+            ref mut tools,
+            ref mut document,
+            ref mut history,
+            ref fit_options,
+            ref brush,
+            ref camera,
+            ..
+        } = *self;
+        let mut ctx = ToolContext {
+            document,
+            history,
+            fit_options,
+            brush,
+            view: camera.transform(),
+            redraw: false,
+        };
+        let result = f(tools, &mut ctx);
+        let redraw = ctx.redraw;
+        self.should_redraw |= redraw;
+        result
+    }
+    fn pointer_event(&self, pointer: &Pointer) -> PointerEvent {
+        PointerEvent {
+            id: pointer.id,
+            kind: pointer.kind,
+            position: self.camera.to_world(pointer.position),
+            screen_position: pointer.position,
+            buttons: pointer.buttons,
+            modifiers: self.modifiers,
+            pressure: pointer.pressure,
+            tilt: pointer.tilt,
+            time: pointer.time,
+        }
+    }
+    fn handle_gesture_event(&mut self, event: GestureEvent) {
+        match event {
+            GestureEvent::Pointer(pointer) => self.handle_pointer(pointer),
+            GestureEvent::Transform(gesture) => {
+                self.camera.apply_gesture(&gesture);
+                self.should_redraw = true;
+            }
+        }
+    }
+    /// Pans with the middle button or while Space is held, and hands every
+    /// other pointer sample to the active tool if it follows that pointer.
+    fn handle_pointer(&mut self, pointer: Pointer) {
+        if pointer.kind == PointerKind::Mouse {
+            let delta = pointer.position - self.cursor_position;
+            self.cursor_position = pointer.position;
+            match (pointer.phase, pointer.button) {
+                (PointerPhase::Down, Some(PointerButton::Middle)) => {
+                    self.panning = true;
+                    return;
+                }
+                (PointerPhase::Down, Some(PointerButton::Primary)) if self.space_down => {
+                    self.panning = true;
+                    return;
+                }
+                (PointerPhase::Up, Some(PointerButton::Middle)) |
+                (PointerPhase::Up, Some(PointerButton::Primary)) => {
+                    self.panning = false;
+                }
+                (PointerPhase::Move, _) if self.panning => {
+                    self.camera.pan(delta);
+                    self.should_redraw = true;
+                    return;
+                }
+                _ => {}
+            }
+        }
+        let event = self.pointer_event(&pointer);
+        let follows = self.active_pointer == Some(pointer.id);
+        match pointer.phase {
+            PointerPhase::Down if pointer.button == Some(PointerButton::Primary) => {
+                if self.active_pointer.is_some() || !self.focused {
+                    return;
+                }
+                self.active_pointer = Some(pointer.id);
+                self.with_tools(|tools, ctx| tools.active_mut().pointer_down(ctx, &event));
+            }
+            // Without an active pointer, the mouse still hovers.
+            PointerPhase::Move => {
+                let hovering = self.active_pointer.is_none() &&
+                    pointer.kind == PointerKind::Mouse &&
+                    self.cursor_active;
+                if (follows || hovering) && self.focused {
+                    self.with_tools(|tools, ctx| tools.active_mut().pointer_move(ctx, &event));
+                }
+            }
+            PointerPhase::Up if follows && pointer.button == Some(PointerButton::Primary) => {
+                self.active_pointer = None;
+                self.with_tools(|tools, ctx| tools.active_mut().pointer_up(ctx, &event));
+            }
+            PointerPhase::Cancel if follows => {
+                self.active_pointer = None;
+                self.with_tools(|tools, ctx| tools.active_mut().cancel(ctx));
+            }
+            _ => {}
+        }
+    }
+    /// Ends whatever the active tool is in the middle of.
+    fn finish_tool(&mut self) {
+        self.with_tools(|tools, ctx| tools.active_mut().finish(ctx));
+    }
+    fn switch_tool(&mut self, tool: ActiveTool) {
+        self.with_tools(|tools, ctx| tools.switch(ctx, tool));
+    }
+    /// For edits that may leave the selection box out of date.
+    fn clear_selection(&mut self) {
+        self.with_tools(|tools, ctx| tools.select.clear(ctx));
+    }
+    /// Acts on timers that are due at `now`. Event loops should call this
+    /// by `next_deadline`.
+    pub fn handle_timers(&mut self, now: Instant) {
+        for event in self.gestures.handle_timers(now) {
+            self.handle_gesture_event(event);
+        }
+        self.with_tools(|tools, ctx| tools.active_mut().handle_timers(ctx, now));
+    }
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let deadlines = [self.gestures.next_deadline(), self.tools.active().next_deadline()];
+        deadlines.iter().flatten().min().copied()
+    }
+    /// Edits the new shape options and the selected shapes alike.
+    fn edit_shapes<F, G>(&mut self, edit_options: F, edit_shape: G)
+        where F: FnOnce(&mut ShapeOptions), G: FnMut(&mut shape::ShapeKind) -> bool {
+        edit_options(&mut self.tools.shape.options);
+        let ids = match self.tools.select.selection {
+            Some(ref selection) => selection.ids.clone(),
+            None => return,
+        };
+        if let Some(command) = Command::reshape(&self.document, &ids, edit_shape) {
+            self.history.execute(&mut self.document, command);
+            self.should_redraw = true;
+        }
+    }
+    fn save_document(&mut self, choose_path: bool, app_window: &W) {
+        self.finish_tool();
+        let path = match self.document_path.clone() {
+            Some(path) if !choose_path => path,
+            _ => match app_window.run_save_dialog(format::EXTENSION) {
+                Ok(path) => path,
+                Err(()) => return,
+            },
+        };
+        match format::save(&self.document, &path) {
+            Ok(()) => {
+                self.document_path = Some(path);
+            }
+            Err(error) => {
+                eprintln!("Failed to save {}: {}", path.display(), error);
+            }
+        }
+    }
+    fn open_document(&mut self, app_window: &W) {
+        if let Ok(path) = app_window.run_open_dialog(format::EXTENSION) {
+            self.open_path(path);
+        }
+    }
+    fn open_path(&mut self, path: PathBuf) {
+        match format::load(&path) {
+            Ok(document) => {
+                self.document = document;
+                self.document_path = Some(path);
+                self.tools.select.selection = None;
+                self.history.clear();
+                self.should_redraw = true;
+            }
+            Err(error) => {
+                eprintln!("Failed to open {}: {}", path.display(), error);
+            }
+        }
+    }
+    fn export_svg(&mut self, app_window: &W) {
+        self.finish_tool();
+        let path = match app_window.run_save_dialog(svg_import::EXTENSION) {
+            Ok(path) => path,
+            Err(()) => return,
+        };
+        if let Err(error) = svg_export::export(&self.document, &self.fit_options, &path) {
+            eprintln!("Failed to export {}: {}", path.display(), error);
+        }
+    }
+    /// Pages are set up by the document's page options file if it has one,
+    /// and by `page_options` otherwise. See `page_export`.
+    fn export_pages(&mut self, format: PageFormat, app_window: &W) {
+        self.finish_tool();
+        let options_path = self.document_path
+            .as_ref()
+            .map(|path| path.with_extension(page_export::OPTIONS_EXTENSION))
+            .filter(|path| path.is_file());
+        let page_options = match options_path {
+            Some(options_path) => match PageOptions::load(&options_path) {
+                Ok(page_options) => page_options,
+                Err(error) => {
+                    eprintln!("Failed to read {}: {}", options_path.display(), error);
+                    return;
+                }
+            },
+            None => self.page_options,
+        };
+        let path = match app_window.run_save_dialog(format.extension()) {
+            Ok(path) => path,
+            Err(()) => return,
+        };
+        let result = page_export::export(
+            &self.document,
+            &self.fit_options,
+            &page_options,
+            format,
+            &path,
         );
-        let (mut renderer, mut scene_proxy) = {
-            init_renderer(app_window)
+        if let Err(error) = result {
+            eprintln!("Failed to export {}: {}", path.display(), error);
+        }
+    }
+    fn import_svg(&mut self, app_window: &W) {
+        if let Ok(path) = app_window.run_open_dialog(svg_import::EXTENSION) {
+            self.import_svg_path(&path);
+        }
+    }
+    /// Adds the layers of the SVG above the active layer, as one step to
+    /// undo, and activates the top one.
+    fn import_svg_path(&mut self, path: &std::path::Path) {
+        self.finish_tool();
+        self.clear_selection();
+        let import = match svg_import::import_file(path, &mut self.document) {
+            Ok(import) => import,
+            Err(error) => {
+                eprintln!("Failed to import {}: {}", path.display(), error);
+                return;
+            }
+        };
+        let message = import.message();
+        if !message.is_empty() {
+            eprintln!("{}: {}", path.display(), message);
+        }
+        let top = match import.layers.last() {
+            Some(layer) => layer.id,
+            None => return,
         };
-        self.renderer = renderer;
-        self.scene_proxy = scene_proxy;
-    }
-    fn begin_new_segment(&mut self) {
-        let empty_last = self.segments
-            .last()
-            .map(|x| x.is_empty())
-            .unwrap_or(false);
-        if !empty_last {
-            self.segments.push(Vec::new());
+        let index = self.document.layer_index(self.document.active_layer).unwrap();
+        let commands = import.layers
+            .into_iter()
+            .enumerate()
+            .map(|(offset, layer)| Command::AddLayer { index: index + 1 + offset, layer })
+            .collect();
+        self.history.execute(&mut self.document, Command::Batch(commands));
+        self.document.active_layer = top;
+        self.should_redraw = true;
+    }
+    fn handle_key_pressed(&mut self, key: wit::VirtualKeyCode, app_window: &mut W) {
+        // Ctrl on Linux and Windows, Cmd on macOS.
+        let command = self.modifiers.ctrl() || self.modifiers.logo();
+        match key {
+            wit::VirtualKeyCode::Space => {
+                self.space_down = true;
+            }
+            wit::VirtualKeyCode::B if !command => {
+                self.switch_tool(ActiveTool::Brush);
+                self.clear_selection();
+            }
+            // Pressing it again switches between whole-stroke and precise
+            // erasing.
+            wit::VirtualKeyCode::E if !command => {
+                if self.tools.active == ActiveTool::Eraser {
+                    let options = &mut self.tools.eraser.options;
+                    options.mode = match options.mode {
+                        EraserMode::WholeStroke => EraserMode::Precise,
+                        EraserMode::Precise => EraserMode::WholeStroke,
+                    };
+                }
+                self.switch_tool(ActiveTool::Eraser);
+                self.clear_selection();
+            }
+            wit::VirtualKeyCode::L if !command => {
+                self.switch_tool(ActiveTool::Select);
+                self.tools.select.mode = SelectionMode::Lasso;
+            }
+            wit::VirtualKeyCode::M if !command => {
+                self.switch_tool(ActiveTool::Select);
+                self.tools.select.mode = SelectionMode::Rectangle;
+            }
+            // Pressing it again cycles through the shapes.
+            wit::VirtualKeyCode::U if !command => {
+                if self.tools.active == ActiveTool::Shape {
+                    let options = &mut self.tools.shape.options;
+                    options.shape_type = options.shape_type.next();
+                }
+                self.switch_tool(ActiveTool::Shape);
+                self.clear_selection();
+            }
+            // Pressing it cycles through the stabilizer modes of the brush.
+            wit::VirtualKeyCode::Y if !command => {
+                let stabilizer = &mut self.brush.stabilizer;
+                stabilizer.mode = stabilizer.mode.next();
+            }
+            wit::VirtualKeyCode::Comma | wit::VirtualKeyCode::Period
+                if !command && self.modifiers.alt() =>
+            {
+                let steps = if key == wit::VirtualKeyCode::Comma { -1 } else { 1 };
+                self.brush.stabilizer.step_strength(steps);
+            }
+            wit::VirtualKeyCode::Comma | wit::VirtualKeyCode::Period if !command => {
+                let steps = if key == wit::VirtualKeyCode::Comma { -1 } else { 1 };
+                self.edit_shapes(
+                    |options| options.step_parameter(steps),
+                    |kind| shape_tool::step_parameter(kind, steps),
+                );
+            }
+            wit::VirtualKeyCode::H if !command => {
+                self.edit_shapes(ShapeOptions::cycle_arrow_head, shape_tool::cycle_arrow_head);
+            }
+            wit::VirtualKeyCode::Escape => {
+                self.clear_selection();
+            }
+            wit::VirtualKeyCode::Back | wit::VirtualKeyCode::Delete
+                if !command && self.tools.select.selection.is_some() =>
+            {
+                self.with_tools(|tools, ctx| {
+                    tools.select.finish(ctx);
+                    if let Some(selection) = tools.select.selection.take() {
+                        let delete = Command::delete_strokes(ctx.document, &selection.ids);
+                        ctx.execute(delete);
+                    }
+                });
+            }
+            wit::VirtualKeyCode::LBracket if !command && !self.modifiers.alt() => {
+                self.tools.eraser.options.scale_radius(0.8);
+            }
+            wit::VirtualKeyCode::RBracket if !command && !self.modifiers.alt() => {
+                self.tools.eraser.options.scale_radius(1.25);
+            }
+            // Screenshots of the window, as PNG or SVG.
+            wit::VirtualKeyCode::P if !command => {
+                self.request_screenshot(ScreenshotType::PNG, app_window);
+            }
+            wit::VirtualKeyCode::S if !command => {
+                self.request_screenshot(ScreenshotType::SVG, app_window);
+            }
+            wit::VirtualKeyCode::S if command => {
+                self.save_document(self.modifiers.shift(), app_window);
+            }
+            wit::VirtualKeyCode::O if command => {
+                self.finish_tool();
+                self.open_document(app_window);
+            }
+            wit::VirtualKeyCode::I if command => {
+                self.import_svg(app_window);
+            }
+            // Ctrl+E alone merges layers.
+            wit::VirtualKeyCode::E if command && self.modifiers.shift() => {
+                self.export_svg(app_window);
+            }
+            wit::VirtualKeyCode::P if command => {
+                let format = if self.modifiers.shift() {
+                    PageFormat::PostScript
+                } else {
+                    PageFormat::Pdf
+                };
+                self.export_pages(format, app_window);
+            }
+            wit::VirtualKeyCode::Z if command => {
+                self.finish_tool();
+                self.clear_selection();
+                let changed = if self.modifiers.shift() {
+                    self.history.redo(&mut self.document)
+                } else {
+                    self.history.undo(&mut self.document)
+                };
+                self.should_redraw |= changed;
+            }
+            _ => {
+                self.handle_layer_shortcut(key, command);
+            }
         }
     }
-    fn add_point_to_current_segment(&mut self, point: Vector2I) {
-        self.segments
-            .last_mut()
-            .unwrap()
-            .push(point);
-    }
-    fn handle_window_event(&mut self, event: wit::WindowEvent, app_window: &mut AppWindow) {
-        let to_point = |pos: wit::PhysicalPosition<f64>| {
-            Vector2I::new(
-                pos.x as i32,
-                pos.y as i32,
-            )
+    /// Layer shortcuts follow common image editors and act on the active
+    /// layer.
+    fn handle_layer_shortcut(&mut self, key: wit::VirtualKeyCode, command: bool) {
+        use wit::VirtualKeyCode as Key;
+        let shift = self.modifiers.shift();
+        let alt = self.modifiers.alt();
+        let active = self.document.active_layer;
+        let index = self.document.layer_index(active).unwrap();
+        let mut properties = self.document.active_layer().properties.clone();
+        let layer_command = match key {
+            Key::N if command && shift => {
+                let layer = self.document.new_layer();
+                self.document.active_layer = layer.id;
+                Some(Command::AddLayer { index: index + 1, layer })
+            }
+            Key::J if command => {
+                let layer_command = Command::duplicate_layer(&mut self.document, active);
+                if let Some(Command::AddLayer { ref layer, .. }) = layer_command {
+                    self.document.active_layer = layer.id;
+                }
+                layer_command
+            }
+            Key::E if command => Command::merge_layer_down(&self.document, active),
+            Key::Back | Key::Delete if command && shift => {
+                Command::delete_layer(&self.document, active)
+            }
+            Key::RBracket if command => Command::move_layer(&self.document, active, index + 1),
+            Key::LBracket if command && index > 0 => {
+                Command::move_layer(&self.document, active, index - 1)
+            }
+            Key::RBracket | Key::LBracket if alt => {
+                let index = match key {
+                    Key::RBracket => (index + 1).min(self.document.layers().len() - 1),
+                    _ => index.saturating_sub(1),
+                };
+                self.document.active_layer = self.document.layers()[index].id;
+                None
+            }
+            Key::Comma if command => {
+                properties.visible = !properties.visible;
+                Command::set_layer_properties(&self.document, active, properties)
+            }
+            Key::Slash if command => {
+                properties.locked = !properties.locked;
+                Command::set_layer_properties(&self.document, active, properties)
+            }
+            Key::Equals | Key::Minus if shift => {
+                let count = BlendMode::ALL.len();
+                let current = BlendMode::ALL
+                    .iter()
+                    .position(|blend_mode| *blend_mode == properties.blend_mode)
+                    .unwrap();
+                let next = match key {
+                    Key::Equals => (current + 1) % count,
+                    _ => (current + count - 1) % count,
+                };
+                properties.blend_mode = BlendMode::ALL[next];
+                Command::set_layer_properties(&self.document, active, properties)
+            }
+            _ if !command && !alt => match layer_opacity_for_key(key) {
+                Some(opacity) => {
+                    properties.opacity = opacity;
+                    Command::set_layer_properties(&self.document, active, properties)
+                }
+                None => None,
+            },
+            _ => None,
         };
+        if let Some(layer_command) = layer_command {
+            self.finish_tool();
+            self.clear_selection();
+            self.history.execute(&mut self.document, layer_command);
+            self.should_redraw = true;
+        }
+    }
+    fn handle_window_event(&mut self, event: wit::WindowEvent, app_window: &mut W) {
         match event {
-            wit::WindowEvent::MouseInput{state: wit::ElementState::Pressed,..} => {
-                self.mouse_down = true;
-            }
-            wit::WindowEvent::MouseInput{state: wit::ElementState::Released,..} => {
-                self.mouse_down = false;
-                self.begin_new_segment();
+            wit::WindowEvent::MouseInput{..} |
+            wit::WindowEvent::CursorMoved{..} |
+            wit::WindowEvent::Touch(..) => {
+                if let Some(pointer) = self.pointers.handle_event(&event, Instant::now()) {
+                    for event in self.gestures.handle_pointer(pointer) {
+                        self.handle_gesture_event(event);
+                    }
+                }
             }
             wit::WindowEvent::CursorEntered{..} => {
                 self.cursor_active = true;
             }
             wit::WindowEvent::CursorLeft{..} => {
                 self.cursor_active = true;
-                self.begin_new_segment();
+                if self.active_pointer == Some(PointerId::Mouse) {
+                    self.active_pointer = None;
+                    self.finish_tool();
+                }
             }
-            wit::WindowEvent::CursorMoved{position, ..} => {
-                if self.cursor_active && self.mouse_down && self.focused {
-                    let point = to_point(position);
-                    self.add_point_to_current_segment(point);
-                    self.should_redraw = true;
+            wit::WindowEvent::MouseWheel{delta, ..} => {
+                let scale_delta = camera::scroll_zoom(camera::scroll_distance(delta));
+                self.camera.zoom_at(scale_delta, self.cursor_position);
+                self.should_redraw = true;
+            }
+            wit::WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+                let id = self.active_pointer.unwrap_or(PointerId::Mouse);
+                if let Some(pointer) = self.pointers.pointer(id) {
+                    let event = self.pointer_event(&pointer);
+                    self.with_tools(|tools, ctx| tools.active_mut().modifiers_changed(ctx, &event));
                 }
             }
+            wit::WindowEvent::KeyboardInput{
+                input: wit::KeyboardInput{
+                    state: wit::ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => {
+                self.handle_key_pressed(key, app_window);
+            }
+            wit::WindowEvent::KeyboardInput{
+                input: wit::KeyboardInput{
+                    state: wit::ElementState::Released,
+                    virtual_keycode: Some(wit::VirtualKeyCode::Space),
+                    ..
+                },
+                ..
+            } => {
+                self.space_down = false;
+            }
             wit::WindowEvent::Focused(focused) => {
                 self.focused = focused;
                 if focused == false {
-                    self.begin_new_segment();
+                    self.finish_tool();
+                    self.pointers.reset();
+                    self.gestures.reset();
+                    self.active_pointer = None;
+                    self.panning = false;
                 }
             }
             wit::WindowEvent::Destroyed => {
@@ -250,6 +758,20 @@ impl AppState {
             wit::WindowEvent::ScaleFactorChanged{..} => {
                 self.should_redraw = true;
             }
+            // SVGs are added to the document; anything else is opened in
+            // its place.
+            wit::WindowEvent::DroppedFile(path) => {
+                let is_svg = match path.extension() {
+                    Some(extension) => extension.eq_ignore_ascii_case(svg_import::EXTENSION),
+                    None => false,
+                };
+                if is_svg {
+                    self.import_svg_path(&path);
+                } else {
+                    self.finish_tool();
+                    self.open_path(path);
+                }
+            }
             _ => ()
         }
     }
@@ -258,10 +780,24 @@ impl AppState {
             _ => ()
         }
     }
+    /// Handles `event` and redraws right away if it changed anything, for
+    /// windows that are not driven by an event loop.
+    pub fn process_window_event(&mut self, event: wit::WindowEvent, app_window: &mut W) {
+        self.handle_window_event(event, app_window);
+        self.redraw_if_needed(app_window);
+    }
+    fn redraw_if_needed(&mut self, app_window: &mut W) {
+        if self.should_resize {
+            self.resize(app_window);
+        }
+        if self.should_redraw {
+            self.draw(app_window)
+        }
+    }
     pub fn handle_event(
         &mut self,
         event: wit::Event<()>,
-        app_window: &mut AppWindow
+        app_window: &mut W
     ) {
         match event {
             wit::Event::DeviceEvent{event, ..} => {
@@ -270,23 +806,47 @@ impl AppState {
             wit::Event::WindowEvent{event, ..} => {
                 self.handle_window_event(event, app_window)
             }
+            wit::Event::NewEvents(..) => {
+                self.handle_timers(Instant::now());
+            }
             wit::Event::LoopDestroyed => {
                 self.should_exit = true;
             }
             wit::Event::RedrawRequested(..) => {
-                if self.should_resize {
-                    // self.resize(app_window);
-                    unimplemented!()
-                }
-                if self.should_redraw {
-                    self.draw(app_window)
-                }
+                self.redraw_if_needed(app_window);
             }
             _ => ()
         }
     }
 }
 
+/// `1` through `9` set 10% through 90%, and `0` sets 100%.
+fn layer_opacity_for_key(key: wit::VirtualKeyCode) -> Option<f32> {
+    use wit::VirtualKeyCode as Key;
+    let digit = match key {
+        Key::Key1 => 1,
+        Key::Key2 => 2,
+        Key::Key3 => 3,
+        Key::Key4 => 4,
+        Key::Key5 => 5,
+        Key::Key6 => 6,
+        Key::Key7 => 7,
+        Key::Key8 => 8,
+        Key::Key9 => 9,
+        Key::Key0 => 10,
+        _ => return None,
+    };
+    Some(digit as f32 / 10.0)
+}
+
+impl AppState<HeadlessWindow> {
+    /// Feeds every scripted event to the app, in order.
+    pub fn run_scripted(&mut self, app_window: &mut HeadlessWindow) {
+        while let Some(event) = app_window.next_event() {
+            self.process_window_event(event, app_window);
+        }
+    }
+}
 
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -295,26 +855,27 @@ impl AppState {
 
 pub struct AppWindow {
     window: wit::Window,
-    connection: surfman::SystemConnection,
-    device: surfman::SystemDevice,
-    native_device: surfman::NativeDevice,
-    surface: surfman::SystemSurface,
+    gpu: GpuSurface,
     resource_loader: pf::FilesystemResourceLoader,
 }
 
-impl AppWindow {
-    pub fn metal_device(&self) -> metal::Device {
-        // FIXME(pcwalton): Remove once `surfman` upgrades `metal-rs` version.
-        unsafe {
-            std::mem::transmute(self.native_device.0.clone())
-        }
+impl Window for AppWindow {
+    type Device = pf::DeviceImpl;
+
+    fn create_device(&mut self) -> pf::DeviceImpl {
+        self.gpu.create_device()
+    }
+
+    fn present(&mut self, device: &mut pf::DeviceImpl) {
+        self.gpu.present(device);
     }
 
-    pub fn metal_io_surface(&self) -> IOSurfaceRef {
-        self.device.native_surface(&self.surface).0
+    fn resize_surface(&mut self, device: &mut pf::DeviceImpl) {
+        let size = self.window.inner_size();
+        self.gpu.resize(vec2i(size.width as i32, size.height as i32), device);
     }
 
-    pub fn size(&self) -> WindowSize {
+    fn size(&self) -> WindowSize {
         let window = &self.window;
         let (monitor, size) = {
             let monitor = window.current_monitor().unwrap();
@@ -326,26 +887,31 @@ impl AppWindow {
         }
     }
 
-    pub fn viewport(&self) -> RectI {
-        let WindowSize { logical_size, backing_scale_factor } = self.size();
-        let mut size = (logical_size.to_f32() * backing_scale_factor).to_i32();
-        let mut x_offset = 0;
-        RectI::new(vec2i(x_offset, 0), size)
+    fn resource_loader(&self) -> &dyn pf::ResourceLoader {
+        &self.resource_loader
     }
 
-    pub fn real_window_size(&self) -> wit::PhysicalSize<u32> {
-        self.window.inner_size()
+    /// The app has no timed messages yet, so there is nothing to wake.
+    fn create_user_event_id(&self) -> u32 {
+        0
     }
 
-    pub fn window_size(&self) -> Vector2I {
-        let WindowSize { logical_size, backing_scale_factor } = self.size();
-        let mut size = (logical_size.to_f32() * backing_scale_factor).to_i32();
-        size
+    fn push_user_event(_: u32, _: u32) {}
+
+    fn present_open_svg_dialog(&mut self) {}
+
+    fn run_open_dialog(&self, extension: &str) -> Result<PathBuf, ()> {
+        match nfd::open_file_dialog(Some(extension), None) {
+            Ok(nfd::Response::Okay(path)) => Ok(PathBuf::from(path)),
+            _ => Err(()),
+        }
     }
 
-    pub fn present(&mut self, metal_device: &mut pathfinder_metal::MetalDevice) {
-        self.device.present_surface(&mut self.surface).unwrap();
-        metal_device.swap_texture(self.device.native_surface(&self.surface).0);
+    fn run_save_dialog(&self, extension: &str) -> Result<PathBuf, ()> {
+        match nfd::open_save_dialog(Some(extension), None) {
+            Ok(nfd::Response::Okay(path)) => Ok(PathBuf::from(path)),
+            _ => Err(()),
+        }
     }
 }
 
@@ -353,18 +919,26 @@ impl AppWindow {
 // MAIN FUNCTIONS
 ///////////////////////////////////////////////////////////////////////////////
 
-fn init_renderer(app_window: &mut AppWindow) -> (pf::Renderer<pf::MetalDevice>, pf::SceneProxy) {
+fn init_renderer<W: Window>(app_window: &mut W) -> (pf::Renderer<W::Device>, pf::SceneProxy) {
+    let pf_device = app_window.create_device();
+    create_renderer(
+        pf_device,
+        app_window.viewport(),
+        app_window.window_size(),
+        app_window.resource_loader(),
+    )
+}
+
+pub fn create_renderer<D: pf::Device>(
+    pf_device: D,
+    viewport: RectI,
+    window_size: Vector2I,
+    resource_loader: &dyn pf::ResourceLoader,
+) -> (pf::Renderer<D>, pf::SceneProxy) {
     ///////////////////////////////////////////////////////////////////////
     // SETUP PATHFINDER DEVICE
     ///////////////////////////////////////////////////////////////////////
-    let viewport = app_window.viewport();
-    let window_size = app_window.window_size();
-    let metal_device = app_window.metal_device();
-    let metal_io_surface = app_window.metal_io_surface();
-    let pf_device = unsafe {
-        pf::MetalDevice::new(metal_device, metal_io_surface)
-    };
-    let dest_framebuffer = pf::DestFramebuffer::Default::<pf::MetalDevice> {
+    let dest_framebuffer = pf::DestFramebuffer::Default::<D> {
         viewport,
         window_size,
     };
@@ -380,7 +954,7 @@ fn init_renderer(app_window: &mut AppWindow) -> (pf::Renderer<pf::MetalDevice>,
     ///////////////////////////////////////////////////////////////////////
     let renderer = pf::Renderer::new(
         pf_device,
-        &app_window.resource_loader,
+        resource_loader,
         render_mode,
         render_options
     );
@@ -396,62 +970,50 @@ fn init_renderer(app_window: &mut AppWindow) -> (pf::Renderer<pf::MetalDevice>,
 }
 
 
+/// The screenshot `--screenshot` asks for, if any.
+fn screenshot_from_command_line() -> Option<ScreenshotInfo> {
+    let matches = clap::App::new("alphadraw")
+        .arg(crate::engine::screenshot_arg())
+        .get_matches();
+    let path = matches.value_of("screenshot")?;
+    ScreenshotInfo::from_path(PathBuf::from(path))
+}
+
 pub fn start() {
     let low_power_mode = false;
     let event_loop = winit::event_loop::EventLoop::<()>::new();
     let mut app_window = {
         ///////////////////////////////////////////////////////////////////////
-        // GPU - METAL SURFACE
+        // WINDOW
         ///////////////////////////////////////////////////////////////////////
         let window = winit::window::WindowBuilder::new()
             .with_title("Canvas")
             .build(&event_loop)
             .unwrap();
         ///////////////////////////////////////////////////////////////////////
-        // GPU - METAL SURFACE
+        // GPU SURFACE
         ///////////////////////////////////////////////////////////////////////
-        let connection = surfman::SystemConnection::from_winit_window(&window).unwrap();
-        let native_widget = connection.create_native_widget_from_winit_window(&window).unwrap();
-        let adapter = if low_power_mode {
-            connection.create_low_power_adapter().unwrap()
-        } else {
-            connection.create_hardware_adapter().unwrap()
-        };
-        let mut device = connection.create_device(&adapter).unwrap();
-        let native_device = device.native_device();
-        let surface_type = SurfaceType::Widget { native_widget };
-        let mut surface = device.create_surface(SurfaceAccess::GPUOnly, surface_type).unwrap();
+        let gpu = GpuSurface::new(&window, AdapterKind::from_env(!low_power_mode));
         let resource_loader = pf::FilesystemResourceLoader::locate();
-        AppWindow {window, connection, device, surface, native_device, resource_loader}
-    };
-    ///////////////////////////////////////////////////////////////////////////
-    // INIT RENDERER & SCENE
-    ///////////////////////////////////////////////////////////////////////////
-    let (mut renderer, mut scene_proxy) = {
-        init_renderer(&mut app_window)
+        AppWindow {window, gpu, resource_loader}
     };
     ///////////////////////////////////////////////////////////////////////////
     // APP STATE
     ///////////////////////////////////////////////////////////////////////////
-    let mut app_state = AppState {
-        mouse_down: false,
-        should_redraw: false,
-        should_exit: false,
-        should_resize: false,
-        focused: false,
-        cursor_active: false,
-        segments: vec![vec![]],
-        renderer,
-        scene_proxy,
-    };
+    let mut app_state = AppState::new(&mut app_window);
+    app_state.pending_screenshot = screenshot_from_command_line();
     // app_state.resize(&mut app_window);
     app_state.draw(&mut app_window);
     let tick = {
         move |event: wit::Event<()>, _: &wit::EventLoopWindowTarget<()>, control: &mut wit::ControlFlow| {
             app_state.handle_event(event, &mut app_window);
+            *control = match app_state.next_deadline() {
+                Some(deadline) => wit::ControlFlow::WaitUntil(deadline),
+                None => wit::ControlFlow::Wait,
+            };
             if app_state.should_exit {
                 *control = wit::ControlFlow::Exit;
-                app_window.device.destroy_surface(&mut app_window.surface);
+                app_window.gpu.destroy();
             }
             if app_state.should_redraw || app_state.should_resize {
                 app_window.window.request_redraw();
@@ -0,0 +1,162 @@
+//! Freehand drawing. Holding the pointer still at the end of a stroke, or
+//! releasing it with Shift held, turns the stroke into the shape it looks
+//! like.
+//!
+//! Points pass through the brush's stabilizer on their way into the stroke,
+//! and the stroke catches up to the pointer when it is released.
+use std::time::{Duration, Instant};
+use crate::prelude::*;
+use super::document::{StrokeId, StrokeOrigin, StrokePoint};
+use super::recognition;
+use super::stabilizer::Stabilizer;
+use super::tool::{PointerEvent, Tool, ToolContext};
+
+/// Holding the brush still this long turns the stroke being drawn into the
+/// shape it looks like.
+const SHAPE_HOLD_DURATION: Duration = Duration::from_millis(600);
+
+/// Movement within this many device pixels counts as holding still.
+const SHAPE_HOLD_TOLERANCE: f32 = 4.0;
+
+#[derive(Default)]
+pub struct BrushTool {
+    /// The stroke receiving input, and when its first point arrived.
+    current_stroke: Option<(StrokeId, Instant)>,
+    /// Steadies the points of the current stroke.
+    stabilizer: Option<Stabilizer>,
+    /// When the brush will have been held still long enough to recognize a
+    /// shape, and where it is held, in device pixels.
+    shape_hold: Option<(Instant, Vector2F)>,
+    /// The current drag already became a shape; the brush draws nothing
+    /// more until released.
+    shape_recognized: bool,
+}
+
+impl BrushTool {
+    /// Finishes the stroke receiving input; the next point starts a new one.
+    fn begin_new_segment(&mut self, ctx: &mut ToolContext) {
+        self.catch_up(ctx);
+        self.stabilizer = None;
+        self.shape_hold = None;
+        if let Some((id, _)) = self.current_stroke.take() {
+            ctx.finish_stroke(id);
+        }
+    }
+    /// Does nothing while the active layer is hidden or locked.
+    fn add_point(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        let (id, started) = match self.current_stroke {
+            Some(current) => current,
+            None => match ctx.document.begin_stroke(*ctx.brush, StrokeOrigin::Brush) {
+                Some(id) => {
+                    let scale = ctx.view.scale_factor();
+                    self.stabilizer = Some(Stabilizer::new(ctx.brush.stabilizer, scale));
+                    *self.current_stroke.get_or_insert((id, event.time))
+                }
+                None => return,
+            },
+        };
+        let point = StrokePoint {
+            position: event.position,
+            pressure: event.pressure,
+            time: event.time.saturating_duration_since(started).as_secs_f32(),
+        };
+        let point = match self.stabilizer {
+            Some(ref mut stabilizer) => stabilizer.push(point),
+            None => Some(point),
+        };
+        if let Some(point) = point {
+            ctx.document.push_point(id, point);
+            ctx.redraw = true;
+        }
+        self.restart_shape_hold(event);
+    }
+    /// Takes the current stroke the rest of the way to the pointer.
+    fn catch_up(&mut self, ctx: &mut ToolContext) {
+        let point = self.stabilizer.as_mut().and_then(Stabilizer::finish);
+        if let (Some((id, _)), Some(point)) = (self.current_stroke, point) {
+            ctx.document.push_point(id, point);
+            ctx.redraw = true;
+        }
+    }
+    /// Restarts the hold timer unless the pointer is still near where it
+    /// was last restarted.
+    fn restart_shape_hold(&mut self, event: &PointerEvent) {
+        let position = event.screen_position;
+        match self.shape_hold {
+            Some((_, anchor)) if (position - anchor).length() <= SHAPE_HOLD_TOLERANCE => {}
+            _ => self.shape_hold = Some((event.time + SHAPE_HOLD_DURATION, position)),
+        }
+    }
+    /// Replaces the stroke being drawn with the shape it looks like and
+    /// finishes it. Returns whether there was such a shape.
+    fn recognize_current_stroke(&mut self, ctx: &mut ToolContext) -> bool {
+        self.shape_hold = None;
+        let id = match self.current_stroke {
+            Some((id, _)) => id,
+            None => return false,
+        };
+        let positions: Vec<Vector2F> = match ctx.document.stroke(id) {
+            Some(stroke) => stroke.points.iter().map(|point| point.position).collect(),
+            None => return false,
+        };
+        let shape = match recognition::recognize(&positions) {
+            Some(shape) => shape,
+            None => return false,
+        };
+        // The shape replaces the points, so the stroke has nothing to
+        // catch up.
+        self.stabilizer = None;
+        ctx.document.update_stroke(id, |stroke| {
+            stroke.set_shape(shape);
+            stroke.origin = StrokeOrigin::Recognized;
+        });
+        self.begin_new_segment(ctx);
+        ctx.redraw = true;
+        true
+    }
+}
+
+impl Tool for BrushTool {
+    fn pointer_down(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        self.add_point(ctx, event);
+    }
+    fn pointer_move(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        if event.buttons.primary && !self.shape_recognized {
+            self.add_point(ctx, event);
+        }
+    }
+    fn pointer_up(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        self.catch_up(ctx);
+        // Shift turns the stroke into a shape right away.
+        if event.modifiers.shift() {
+            self.recognize_current_stroke(ctx);
+        }
+        self.shape_recognized = false;
+        self.begin_new_segment(ctx);
+    }
+    fn finish(&mut self, ctx: &mut ToolContext) {
+        self.begin_new_segment(ctx);
+    }
+    /// A shape already recognized during the drag was recorded when it was
+    /// recognized, and stays.
+    fn cancel(&mut self, ctx: &mut ToolContext) {
+        self.stabilizer = None;
+        self.shape_hold = None;
+        self.shape_recognized = false;
+        if let Some((id, _)) = self.current_stroke.take() {
+            ctx.document.remove_stroke(id);
+            ctx.redraw = true;
+        }
+    }
+    fn next_deadline(&self) -> Option<Instant> {
+        self.shape_hold.map(|(deadline, _)| deadline)
+    }
+    fn handle_timers(&mut self, ctx: &mut ToolContext, now: Instant) {
+        match self.shape_hold {
+            Some((deadline, _)) if deadline <= now => {
+                self.shape_recognized = self.recognize_current_stroke(ctx);
+            }
+            _ => {}
+        }
+    }
+}
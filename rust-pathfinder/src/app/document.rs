@@ -0,0 +1,752 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::prelude::*;
+use super::dynamics::BrushDynamics;
+use super::shape::Shape;
+use super::spatial::SpatialIndex;
+use super::stabilizer::StabilizerOptions;
+
+/// Fitted curves can stray slightly outside the bounds of their input
+/// points; ink bounds leave this much extra room, in document units.
+const CURVE_OVERSHOOT: f32 = 2.0;
+
+/// Shared by every document, so that a revision never comes up twice even
+/// when a document is replaced by one with the same stroke IDs.
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+/// How many stroke removals a document remembers; see `changes_since`.
+const REMOVALS_KEPT: usize = 4096;
+
+///////////////////////////////////////////////////////////////////////////////
+// STROKE
+///////////////////////////////////////////////////////////////////////////////
+
+/// Stable identity of a stroke; never reused within a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StrokeId(pub u64);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrokeStyle {
+    pub color: pf::ColorU,
+    pub width: f32,
+    /// In the range `0.0..=1.0`.
+    pub opacity: f32,
+    pub line_cap: pf::LineCap,
+    pub line_join: pf::LineJoin,
+    /// How pressure and speed modulate `width` along the stroke.
+    pub dynamics: BrushDynamics,
+    /// How input was steadied while drawing.
+    pub stabilizer: StabilizerOptions,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        StrokeStyle {
+            color: pf::ColorU::new(u8::MAX, 0, 0, u8::MAX),
+            width: 10.0,
+            opacity: 1.0,
+            line_cap: pf::LineCap::Round,
+            line_join: pf::LineJoin::Round,
+            dynamics: BrushDynamics::default(),
+            stabilizer: StabilizerOptions::default(),
+        }
+    }
+}
+
+impl StrokeStyle {
+    /// How far ink may reach from the centerline: half the widest the
+    /// dynamics can make the stroke, further where miter joins or square
+    /// caps stick out.
+    pub fn ink_radius(&self) -> f32 {
+        let pressure = self.dynamics.pressure;
+        let velocity = self.dynamics.velocity;
+        let width = self.width *
+            pressure.start.max(pressure.end) *
+            velocity.start.max(velocity.end);
+        let corner = match self.line_join {
+            pf::LineJoin::Miter(limit) => limit.max(1.0),
+            _ => 1.0,
+        };
+        let corner = match self.line_cap {
+            pf::LineCap::Square => corner.max(std::f32::consts::SQRT_2),
+            _ => corner,
+        };
+        width * 0.5 * corner
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrokePoint {
+    pub position: Vector2F,
+    /// Normalized pen pressure; input devices without pressure report `1.0`.
+    pub pressure: f32,
+    /// Seconds since the first point of the stroke.
+    pub time: f32,
+}
+
+/// What made a stroke.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StrokeOrigin {
+    /// Freehand ink from the brush.
+    Brush,
+    /// Dragged out with the shape tool.
+    ShapeTool,
+    /// Freehand ink that the brush recognized as a shape.
+    Recognized,
+    /// What the eraser left of a stroke it cut through.
+    EraserPiece,
+    /// Converted from another format.
+    Imported,
+}
+
+impl StrokeOrigin {
+    pub const ALL: [StrokeOrigin; 5] = [
+        StrokeOrigin::Brush,
+        StrokeOrigin::ShapeTool,
+        StrokeOrigin::Recognized,
+        StrokeOrigin::EraserPiece,
+        StrokeOrigin::Imported,
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub id: StrokeId,
+    pub style: StrokeStyle,
+    /// Milliseconds since the UNIX epoch at which the stroke was started.
+    pub timestamp: u64,
+    pub points: Vec<StrokePoint>,
+    /// Set for strokes that are a clean shape rather than freehand ink, in
+    /// which case `points` are samples along it.
+    pub shape: Option<Shape>,
+    pub origin: StrokeOrigin,
+}
+
+impl Stroke {
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Turns the stroke into `shape`, resampling its points along it at full
+    /// pressure over the time the stroke took to draw.
+    pub fn set_shape(&mut self, shape: Shape) {
+        let duration = self.points.last().map_or(0.0, |point| point.time);
+        let positions = shape.sample();
+        let step = duration / positions.len().saturating_sub(1).max(1) as f32;
+        self.points = positions
+            .into_iter()
+            .enumerate()
+            .map(|(index, position)| StrokePoint { position, pressure: 1.0, time: index as f32 * step })
+            .collect();
+        self.shape = Some(shape);
+    }
+
+    /// Bounds of the raw input points, ignoring the stroke width.
+    pub fn bounds(&self) -> Option<RectF> {
+        let first = self.points.first()?;
+        let mut bounds = RectF::from_points(first.position, first.position);
+        for point in self.points.iter().skip(1) {
+            bounds = bounds.union_point(point.position);
+        }
+        Some(bounds)
+    }
+
+    /// Bounds of everything the stroke may paint.
+    pub fn ink_bounds(&self) -> Option<RectF> {
+        Some(self.bounds()?.dilate(self.style.ink_radius() + CURVE_OVERSHOOT))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// LAYER
+///////////////////////////////////////////////////////////////////////////////
+
+/// Stable identity of a layer; never reused within a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LayerId(pub u64);
+
+/// How a layer is composited onto the layers below it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    Difference,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 7] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Darken,
+        BlendMode::Lighten,
+        BlendMode::Difference,
+    ];
+    pub fn to_composite_operation(self) -> pf::CompositeOperation {
+        match self {
+            BlendMode::Normal => pf::CompositeOperation::SourceOver,
+            BlendMode::Multiply => pf::CompositeOperation::Multiply,
+            BlendMode::Screen => pf::CompositeOperation::Screen,
+            BlendMode::Overlay => pf::CompositeOperation::Overlay,
+            BlendMode::Darken => pf::CompositeOperation::Darken,
+            BlendMode::Lighten => pf::CompositeOperation::Lighten,
+            BlendMode::Difference => pf::CompositeOperation::Difference,
+        }
+    }
+}
+
+/// Everything about a layer except its content.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerProperties {
+    pub name: String,
+    pub visible: bool,
+    /// Locked layers are drawn but cannot be edited.
+    pub locked: bool,
+    /// In the range `0.0..=1.0`, applied to the layer as a whole.
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+}
+
+impl LayerProperties {
+    pub fn new(name: String) -> Self {
+        LayerProperties {
+            name,
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+    /// Whether the layer can be drawn straight onto the layers below it,
+    /// without being composited separately.
+    pub fn is_pass_through(&self) -> bool {
+        self.opacity >= 1.0 && self.blend_mode == BlendMode::Normal
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub id: LayerId,
+    pub properties: LayerProperties,
+    /// In paint order, bottom to top.
+    pub strokes: Vec<Stroke>,
+}
+
+impl Layer {
+    pub fn new(id: LayerId, name: String) -> Self {
+        Layer { id, properties: LayerProperties::new(name), strokes: Vec::new() }
+    }
+    /// Visible and unlocked.
+    pub fn is_editable(&self) -> bool {
+        self.properties.visible && !self.properties.locked
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// DOCUMENT
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug)]
+pub struct Document {
+    /// In paint order, bottom to top. Never empty.
+    layers: Vec<Layer>,
+    /// Where new strokes go.
+    pub active_layer: LayerId,
+    /// Ink bounds of every non-empty stroke. Kept current by every method
+    /// that edits strokes, which is why `layers` is private.
+    index: SpatialIndex,
+    /// The layer of every stroke and its position there, kept like `index`.
+    locations: HashMap<StrokeId, (LayerId, usize)>,
+    /// Revision of every stroke, which changes whenever the stroke does, so
+    /// that what was built from it can be kept until then.
+    revisions: HashMap<StrokeId, u64>,
+    /// Every stroke by its revision, so that the strokes changed since a
+    /// revision are found without looking at the others.
+    changes: BTreeMap<u64, StrokeId>,
+    /// The latest removals, oldest first, by the revision they came at.
+    removals: VecDeque<(u64, StrokeId)>,
+    /// Changes since an older revision cannot be told: a removal after it
+    /// is forgotten, or the document was made after it.
+    removals_since: u64,
+    /// The newest revision of a stroke or of a removal.
+    revision: u64,
+    next_stroke_id: u64,
+    next_layer_id: u64,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Document::from_layers(Vec::new(), LayerId(0), 0, 0)
+    }
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Document::default()
+    }
+    /// Rebuilds a document from stored layers, keeping IDs unique even if
+    /// the stored counters are stale. Adds a layer if there are none, and
+    /// activates the top layer if `active_layer` does not exist.
+    pub fn from_layers(
+        mut layers: Vec<Layer>,
+        active_layer: LayerId,
+        next_stroke_id: u64,
+        next_layer_id: u64,
+    ) -> Self {
+        let next_stroke_id = layers
+            .iter()
+            .flat_map(|layer| layer.strokes.iter())
+            .map(|stroke| stroke.id.0 + 1)
+            .fold(next_stroke_id, u64::max);
+        let mut next_layer_id = layers
+            .iter()
+            .map(|layer| layer.id.0 + 1)
+            .fold(next_layer_id, u64::max);
+        if layers.is_empty() {
+            layers.push(Layer::new(LayerId(next_layer_id), default_layer_name(0)));
+            next_layer_id += 1;
+        }
+        let active_layer = if layers.iter().any(|layer| layer.id == active_layer) {
+            active_layer
+        } else {
+            layers.last().unwrap().id
+        };
+        let mut document = Document {
+            layers: Vec::new(),
+            active_layer,
+            index: SpatialIndex::default(),
+            locations: HashMap::new(),
+            revisions: HashMap::new(),
+            changes: BTreeMap::new(),
+            removals: VecDeque::new(),
+            removals_since: 0,
+            revision: 0,
+            next_stroke_id,
+            next_layer_id,
+        };
+        document.removals_since = document.next_revision();
+        document.revision = document.removals_since;
+        for layer in layers {
+            let index = document.layers.len();
+            document.insert_layer(index, layer);
+        }
+        document
+    }
+    /// A document with a single layer holding `strokes`.
+    pub fn from_strokes(strokes: Vec<Stroke>, next_stroke_id: u64) -> Self {
+        let mut layer = Layer::new(LayerId(0), default_layer_name(0));
+        layer.strokes = strokes;
+        Document::from_layers(vec![layer], LayerId(0), next_stroke_id, 1)
+    }
+    pub fn next_stroke_id(&self) -> u64 {
+        self.next_stroke_id
+    }
+    pub fn next_layer_id(&self) -> u64 {
+        self.next_layer_id
+    }
+    /// In paint order, bottom to top.
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+    /// Every stroke, in paint order.
+    pub fn strokes(&self) -> impl Iterator<Item = &Stroke> {
+        self.layers.iter().flat_map(|layer| layer.strokes.iter())
+    }
+    /// Strokes that can be edited, in paint order.
+    pub fn editable_strokes(&self) -> impl Iterator<Item = &Stroke> {
+        self.layers
+            .iter()
+            .filter(|layer| layer.is_editable())
+            .flat_map(|layer| layer.strokes.iter())
+    }
+    /// Starts an empty stroke on top of the active layer, unless that layer
+    /// is hidden or locked.
+    pub fn begin_stroke(&mut self, style: StrokeStyle, origin: StrokeOrigin) -> Option<StrokeId> {
+        let index = self.layer_index(self.active_layer)?;
+        if !self.layers[index].is_editable() {
+            return None;
+        }
+        let id = self.allocate_stroke_id();
+        let layer = &mut self.layers[index];
+        layer.strokes.push(Stroke {
+            id,
+            style,
+            timestamp: now_millis(),
+            points: Vec::new(),
+            shape: None,
+            origin,
+        });
+        self.locations.insert(id, (layer.id, layer.strokes.len() - 1));
+        self.touch(id);
+        Some(id)
+    }
+    pub fn allocate_stroke_id(&mut self) -> StrokeId {
+        let id = StrokeId(self.next_stroke_id);
+        self.next_stroke_id += 1;
+        id
+    }
+    pub fn stroke(&self, id: StrokeId) -> Option<&Stroke> {
+        let (layer, index) = self.locate(id)?;
+        self.layer(layer)?.strokes.get(index)
+    }
+    /// Edits a stroke in place and reindexes it. Returns whether the stroke
+    /// exists.
+    pub fn update_stroke<F>(&mut self, id: StrokeId, update: F) -> bool where F: FnOnce(&mut Stroke) {
+        let stroke = match self.stroke_mut(id) {
+            Some(stroke) => stroke,
+            None => return false,
+        };
+        update(stroke);
+        let bounds = stroke.ink_bounds();
+        self.reindex(id, bounds);
+        self.touch(id);
+        true
+    }
+    pub fn push_point(&mut self, id: StrokeId, point: StrokePoint) {
+        let stroke = match self.stroke_mut(id) {
+            Some(stroke) => stroke,
+            None => return,
+        };
+        stroke.points.push(point);
+        let radius = stroke.style.ink_radius();
+        let point_bounds = RectF::from_points(point.position, point.position)
+            .dilate(radius + CURVE_OVERSHOOT);
+        let bounds = match self.index.bounds(id) {
+            Some(bounds) => bounds.union_rect(point_bounds),
+            None => point_bounds,
+        };
+        self.index.insert(id, bounds);
+        self.touch(id);
+    }
+    /// The layer holding the stroke and the stroke's index in it.
+    pub fn locate(&self, id: StrokeId) -> Option<(LayerId, usize)> {
+        self.locations.get(&id).copied()
+    }
+    /// Inserts at `index`, or on top if the layer has fewer strokes. Does
+    /// nothing if the layer does not exist.
+    pub fn insert_stroke(&mut self, layer: LayerId, index: usize, stroke: Stroke) {
+        let layer_index = match self.layer_index(layer) {
+            Some(layer_index) => layer_index,
+            None => return,
+        };
+        let strokes = &mut self.layers[layer_index].strokes;
+        let index = index.min(strokes.len());
+        self.index.remove(stroke.id);
+        if let Some(bounds) = stroke.ink_bounds() {
+            self.index.insert(stroke.id, bounds);
+        }
+        let id = stroke.id;
+        strokes.insert(index, stroke);
+        self.relocate(layer_index, index);
+        self.touch(id);
+    }
+    pub fn remove_stroke(&mut self, id: StrokeId) -> Option<Stroke> {
+        let (layer, index) = self.locate(id)?;
+        let layer_index = self.layer_index(layer)?;
+        self.forget(id);
+        let stroke = self.layers[layer_index].strokes.remove(index);
+        self.relocate(layer_index, index);
+        Some(stroke)
+    }
+    /// The revision of a stroke, if it exists. See `revisions`.
+    pub fn stroke_revision(&self, id: StrokeId) -> Option<u64> {
+        self.revisions.get(&id).copied()
+    }
+    pub fn stroke_count(&self) -> usize {
+        self.revisions.len()
+    }
+    /// The newest stroke revision, or revision of a removal.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    /// The strokes added, changed or removed after `revision`, in no
+    /// particular order. `None` if the document no longer knows, because
+    /// the removals since are forgotten or the document is newer.
+    pub fn changes_since(&self, revision: u64) -> Option<Vec<StrokeId>> {
+        if revision < self.removals_since {
+            return None;
+        }
+        let mut changes: Vec<StrokeId> = self.changes
+            .range(revision + 1..)
+            .map(|(_, &id)| id)
+            .collect();
+        changes.extend(
+            self.removals
+                .iter()
+                .rev()
+                .take_while(|&&(removed, _)| removed > revision)
+                .map(|&(_, id)| id),
+        );
+        Some(changes)
+    }
+    /// Area covered by ink, including half of each stroke's width. Hidden
+    /// layers count too.
+    pub fn bounds(&self) -> Option<RectF> {
+        self.strokes()
+            .filter_map(|stroke| {
+                let bounds = stroke.bounds()?;
+                Some(bounds.dilate(stroke.style.width * 0.5))
+            })
+            .fold(None, |all: Option<RectF>, bounds| match all {
+                Some(all) => Some(all.union_rect(bounds)),
+                None => Some(bounds),
+            })
+    }
+    /// Ink bounds of a stroke, as indexed. `None` for empty strokes.
+    pub fn ink_bounds(&self, id: StrokeId) -> Option<RectF> {
+        self.index.bounds(id)
+    }
+    /// Strokes that may paint inside `rect`, in no particular order. Hidden
+    /// and locked layers are included.
+    pub fn strokes_in_rect(&self, rect: RectF) -> Vec<StrokeId> {
+        self.index.query_rect(rect)
+    }
+    /// Strokes whose ink may come within `radius` of `center`, in no
+    /// particular order.
+    pub fn strokes_near(&self, center: Vector2F, radius: f32) -> Vec<StrokeId> {
+        self.index.query_circle(center, radius)
+    }
+    pub fn layer(&self, id: LayerId) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.id == id)
+    }
+    pub fn layer_index(&self, id: LayerId) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.id == id)
+    }
+    pub fn active_layer(&self) -> &Layer {
+        self.layer(self.active_layer).unwrap_or_else(|| self.layers.last().unwrap())
+    }
+    pub fn set_layer_properties(&mut self, id: LayerId, properties: LayerProperties) {
+        if let Some(layer) = self.layers.iter_mut().find(|layer| layer.id == id) {
+            layer.properties = properties;
+        }
+    }
+    /// An empty layer with a fresh ID, not yet part of the document.
+    pub fn new_layer(&mut self) -> Layer {
+        let id = self.allocate_layer_id();
+        Layer::new(id, default_layer_name(self.layers.len()))
+    }
+    /// A copy of the layer with fresh layer and stroke IDs, not yet part of
+    /// the document.
+    pub fn duplicate_layer(&mut self, id: LayerId) -> Option<Layer> {
+        let mut layer = self.layer(id)?.clone();
+        layer.id = self.allocate_layer_id();
+        layer.properties.name = format!("{} copy", layer.properties.name);
+        for stroke in layer.strokes.iter_mut() {
+            stroke.id = self.allocate_stroke_id();
+        }
+        Some(layer)
+    }
+    pub fn allocate_layer_id(&mut self) -> LayerId {
+        let id = LayerId(self.next_layer_id);
+        self.next_layer_id += 1;
+        id
+    }
+    /// Inserts at `index`, or on top if the document has fewer layers.
+    pub fn insert_layer(&mut self, index: usize, layer: Layer) {
+        for (position, stroke) in layer.strokes.iter().enumerate() {
+            self.reindex(stroke.id, stroke.ink_bounds());
+            self.locations.insert(stroke.id, (layer.id, position));
+            self.touch(stroke.id);
+        }
+        let index = index.min(self.layers.len());
+        self.layers.insert(index, layer);
+    }
+    /// Removes the layer unless it is the last one. The layer below, or
+    /// else above, becomes active if the removed layer was.
+    pub fn remove_layer(&mut self, id: LayerId) -> Option<Layer> {
+        if self.layers.len() < 2 {
+            return None;
+        }
+        let index = self.layer_index(id)?;
+        let layer = self.layers.remove(index);
+        for stroke in layer.strokes.iter() {
+            self.forget(stroke.id);
+        }
+        if self.active_layer == id {
+            self.active_layer = self.layers[index.saturating_sub(1)].id;
+        }
+        Some(layer)
+    }
+    /// Moves a layer so that it ends up at index `to`.
+    pub fn move_layer(&mut self, id: LayerId, to: usize) {
+        if let Some(from) = self.layer_index(id) {
+            let layer = self.layers.remove(from);
+            let to = to.min(self.layers.len());
+            self.layers.insert(to, layer);
+        }
+    }
+    fn stroke_mut(&mut self, id: StrokeId) -> Option<&mut Stroke> {
+        let (layer, index) = self.locate(id)?;
+        let layer_index = self.layer_index(layer)?;
+        self.layers[layer_index].strokes.get_mut(index)
+    }
+    /// Records the positions of the strokes of a layer from `start` on,
+    /// after strokes were inserted or removed there.
+    fn relocate(&mut self, layer_index: usize, start: usize) {
+        let layer = &self.layers[layer_index];
+        for (position, stroke) in layer.strokes.iter().enumerate().skip(start) {
+            self.locations.insert(stroke.id, (layer.id, position));
+        }
+    }
+    fn touch(&mut self, id: StrokeId) {
+        let revision = self.next_revision();
+        if let Some(old) = self.revisions.insert(id, revision) {
+            self.changes.remove(&old);
+        }
+        self.changes.insert(revision, id);
+    }
+    /// Drops a removed stroke from the index and the revisions.
+    fn forget(&mut self, id: StrokeId) {
+        self.index.remove(id);
+        self.locations.remove(&id);
+        if let Some(old) = self.revisions.remove(&id) {
+            self.changes.remove(&old);
+        }
+        let revision = self.next_revision();
+        self.removals.push_back((revision, id));
+        if self.removals.len() > REMOVALS_KEPT {
+            let (forgotten, _) = self.removals.pop_front().unwrap();
+            self.removals_since = forgotten;
+        }
+    }
+    fn next_revision(&mut self) -> u64 {
+        self.revision = NEXT_REVISION.fetch_add(1, Ordering::Relaxed);
+        self.revision
+    }
+    fn reindex(&mut self, id: StrokeId, bounds: Option<RectF>) {
+        match bounds {
+            Some(bounds) => self.index.insert(id, bounds),
+            None => self.index.remove(id),
+        }
+    }
+}
+
+fn default_layer_name(index: usize) -> String {
+    format!("Layer {}", index + 1)
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::dynamics::DynamicsCurve;
+
+    /// A horizontal stroke from the origin to (100, 0).
+    fn stroke(style: StrokeStyle) -> Stroke {
+        let point = |x| StrokePoint { position: Vector2F::new(x, 0.0), pressure: 1.0, time: 0.0 };
+        Stroke {
+            id: StrokeId(0),
+            style,
+            timestamp: 0,
+            points: vec![point(0.0), point(100.0)],
+            shape: None,
+            origin: StrokeOrigin::Brush,
+        }
+    }
+
+    #[test]
+    fn ink_bounds_reach_the_widest_dynamics() {
+        let mut style = StrokeStyle { width: 10.0, ..StrokeStyle::default() };
+        style.dynamics.pressure = DynamicsCurve { start: 0.5, end: 3.0, exponent: 1.0 };
+        style.dynamics.velocity = DynamicsCurve { start: 2.0, end: 1.0, exponent: 1.0 };
+        let bounds = stroke(style).ink_bounds().unwrap();
+        assert_eq!(bounds.min_y(), -30.0 - CURVE_OVERSHOOT);
+        assert_eq!(bounds.max_x(), 130.0 + CURVE_OVERSHOOT);
+    }
+
+    #[test]
+    fn ink_bounds_reach_miters_and_square_caps() {
+        let style = StrokeStyle {
+            width: 10.0,
+            line_join: pf::LineJoin::Miter(4.0),
+            dynamics: BrushDynamics::none(),
+            ..StrokeStyle::default()
+        };
+        assert_eq!(stroke(style).ink_bounds().unwrap().min_y(), -20.0 - CURVE_OVERSHOOT);
+        let style = StrokeStyle {
+            line_join: pf::LineJoin::Bevel,
+            line_cap: pf::LineCap::Square,
+            ..style
+        };
+        let reach = 5.0 * std::f32::consts::SQRT_2;
+        assert_eq!(stroke(style).ink_bounds().unwrap().min_y(), -reach - CURVE_OVERSHOOT);
+    }
+
+    #[test]
+    fn pushed_points_are_indexed_with_the_ink_radius() {
+        let mut document = Document::new();
+        let mut style = StrokeStyle { width: 10.0, ..StrokeStyle::default() };
+        style.dynamics.pressure = DynamicsCurve::constant(4.0);
+        let id = document.begin_stroke(style, StrokeOrigin::Brush).unwrap();
+        let point = StrokePoint { position: Vector2F::zero(), pressure: 1.0, time: 0.0 };
+        document.push_point(id, point);
+        let rect = RectF::new(Vector2F::new(15.0, -1.0), Vector2F::splat(2.0));
+        assert_eq!(document.strokes_in_rect(rect), vec![id]);
+    }
+
+    #[test]
+    fn strokes_are_located_through_edits() {
+        let mut document = Document::new();
+        let lower = document.active_layer;
+        let ids: Vec<StrokeId> = (0..3)
+            .map(|_| document.begin_stroke(StrokeStyle::default(), StrokeOrigin::Brush).unwrap())
+            .collect();
+        let mut upper = document.new_layer();
+        upper.strokes.push(stroke(StrokeStyle::default()));
+        upper.strokes[0].id = document.allocate_stroke_id();
+        let upper_stroke = upper.strokes[0].id;
+        let upper_id = upper.id;
+        document.insert_layer(0, upper);
+        document.move_layer(upper_id, 1);
+        assert_eq!(document.locate(upper_stroke), Some((upper_id, 0)));
+
+        let removed = document.remove_stroke(ids[0]).unwrap();
+        assert_eq!(document.locate(ids[0]), None);
+        assert_eq!(document.locate(ids[2]), Some((lower, 1)));
+        document.insert_stroke(lower, 1, removed);
+        for (position, id) in [ids[1], ids[0], ids[2]].iter().enumerate() {
+            assert_eq!(document.locate(*id), Some((lower, position)));
+            assert_eq!(document.stroke(*id).unwrap().id, *id);
+        }
+        document.remove_layer(upper_id);
+        assert_eq!(document.locate(upper_stroke), None);
+        assert!(document.stroke(upper_stroke).is_none());
+    }
+
+    #[test]
+    fn changes_are_found_since_a_revision() {
+        let mut document = Document::new();
+        let point = StrokePoint { position: Vector2F::zero(), pressure: 1.0, time: 0.0 };
+        let ids: Vec<StrokeId> = (0..3)
+            .map(|_| document.begin_stroke(StrokeStyle::default(), StrokeOrigin::Brush).unwrap())
+            .collect();
+        let revision = document.revision();
+        assert_eq!(document.changes_since(revision), Some(vec![]));
+        document.push_point(ids[1], point);
+        document.remove_stroke(ids[2]);
+        let mut changes = document.changes_since(revision).unwrap();
+        changes.sort();
+        assert_eq!(changes, vec![ids[1], ids[2]]);
+        assert_eq!(document.changes_since(document.revision()), Some(vec![]));
+
+        // Too many removals ago, and before the document was made.
+        for _ in 0..REMOVALS_KEPT {
+            let id = document.begin_stroke(StrokeStyle::default(), StrokeOrigin::Brush).unwrap();
+            document.remove_stroke(id);
+        }
+        assert_eq!(document.changes_since(revision), None);
+        assert_eq!(Document::new().changes_since(document.revision()), None);
+    }
+}
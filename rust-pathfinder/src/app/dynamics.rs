@@ -0,0 +1,240 @@
+//! Variable-width strokes driven by pen pressure and drawing speed.
+use crate::prelude::*;
+use super::document::StrokePoint;
+use super::fitting::CubicSegment;
+
+/// Used for the time delta between points that arrived in the same instant.
+const MIN_TIME_DELTA: f32 = 0.001;
+
+/// Flattening step along fitted curves, in document units.
+const SAMPLE_SPACING: f32 = 2.0;
+const MAX_SAMPLES_PER_SEGMENT: usize = 64;
+
+/// Line segments used to approximate each round cap.
+const CAP_SEGMENTS: usize = 8;
+
+///////////////////////////////////////////////////////////////////////////////
+// BRUSH PARAMETERS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Maps an input in `0.0..=1.0` to a width multiplier, going from `start` at
+/// zero to `end` at one. `exponent` bends the response: above one the
+/// multiplier stays near `start` for longer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DynamicsCurve {
+    pub start: f32,
+    pub end: f32,
+    pub exponent: f32,
+}
+
+impl DynamicsCurve {
+    pub fn constant(value: f32) -> Self {
+        DynamicsCurve { start: value, end: value, exponent: 1.0 }
+    }
+    pub fn is_constant(&self) -> bool {
+        self.start == self.end
+    }
+    pub fn evaluate(&self, input: f32) -> f32 {
+        let t = input.max(0.0).min(1.0).powf(self.exponent);
+        self.start + (self.end - self.start) * t
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BrushDynamics {
+    /// Width multiplier over normalized pen pressure.
+    pub pressure: DynamicsCurve,
+    /// Width multiplier over speed divided by `max_speed`. Usually falls off
+    /// so that quick flicks come out thinner, like ink.
+    pub velocity: DynamicsCurve,
+    /// Speed, in document units per second, treated as full speed.
+    pub max_speed: f32,
+    /// How much of the previous speed is kept at each point, in
+    /// `0.0..1.0`, to keep mouse jitter out of the width.
+    pub smoothing: f32,
+}
+
+impl BrushDynamics {
+    /// Uniform width; the stroke is drawn with its line cap and join.
+    pub fn none() -> Self {
+        BrushDynamics {
+            pressure: DynamicsCurve::constant(1.0),
+            velocity: DynamicsCurve::constant(1.0),
+            max_speed: 1.0,
+            smoothing: 0.0,
+        }
+    }
+    pub fn is_constant(&self) -> bool {
+        self.pressure.is_constant() && self.velocity.is_constant()
+    }
+}
+
+impl Default for BrushDynamics {
+    fn default() -> Self {
+        BrushDynamics {
+            pressure: DynamicsCurve { start: 0.2, end: 1.0, exponent: 1.0 },
+            velocity: DynamicsCurve { start: 1.0, end: 0.35, exponent: 0.75 },
+            max_speed: 3000.0,
+            smoothing: 0.6,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// WIDTH PROFILE
+///////////////////////////////////////////////////////////////////////////////
+
+/// The stroke width at each of `points`.
+pub fn width_profile(
+    points: &[StrokePoint],
+    base_width: f32,
+    dynamics: &BrushDynamics,
+) -> Vec<f32> {
+    let mut widths = Vec::with_capacity(points.len());
+    let mut smoothed_speed = None::<f32>;
+    for (index, point) in points.iter().enumerate() {
+        // The first point has no predecessor; borrow the speed towards the
+        // second one.
+        let (a, b) = match index {
+            0 if points.len() > 1 => (&points[0], &points[1]),
+            0 => (point, point),
+            _ => (&points[index - 1], point),
+        };
+        let time_delta = (b.time - a.time).max(MIN_TIME_DELTA);
+        let speed = (b.position - a.position).length() / time_delta;
+        let speed = match smoothed_speed {
+            Some(previous) => previous * dynamics.smoothing + speed * (1.0 - dynamics.smoothing),
+            None => speed,
+        };
+        smoothed_speed = Some(speed);
+        let normalized_speed = speed / dynamics.max_speed.max(MIN_TIME_DELTA);
+        widths.push(
+            base_width *
+            dynamics.pressure.evaluate(point.pressure) *
+            dynamics.velocity.evaluate(normalized_speed)
+        );
+    }
+    widths
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// OUTLINE
+///////////////////////////////////////////////////////////////////////////////
+
+/// Builds the filled shape of a stroke whose centerline is `segments`, with
+/// the width varying as `widths` does over the input `points`. Widths are
+/// matched to the curve by relative arc length. Both ends get round caps.
+pub fn stroke_outline(
+    points: &[StrokePoint],
+    widths: &[f32],
+    segments: &[CubicSegment],
+) -> pf::Outline {
+    let mut outline = pf::Outline::new();
+    if points.is_empty() {
+        return outline;
+    }
+    if segments.is_empty() {
+        let radius = widths[0] * 0.5;
+        outline.push_contour(circle_contour(points[0].position, radius));
+        return outline;
+    }
+    // Width as a function of arc length along the input polyline.
+    let mut input_lengths = Vec::with_capacity(points.len());
+    let mut length = 0.0;
+    for (index, point) in points.iter().enumerate() {
+        if index > 0 {
+            length += (point.position - points[index - 1].position).length();
+        }
+        input_lengths.push(length);
+    }
+    let input_length = length;
+    // Flatten the centerline.
+    let mut samples = vec![segments[0].from];
+    for segment in segments.iter() {
+        let chord = (segment.to - segment.from).length() +
+            (segment.ctrl0 - segment.from).length() +
+            (segment.ctrl1 - segment.to).length();
+        let steps = ((chord / SAMPLE_SPACING).ceil() as usize)
+            .max(1)
+            .min(MAX_SAMPLES_PER_SEGMENT);
+        for step in 1..=steps {
+            samples.push(segment.sample(step as f32 / steps as f32));
+        }
+    }
+    let mut sample_lengths = Vec::with_capacity(samples.len());
+    let mut length = 0.0;
+    for (index, sample) in samples.iter().enumerate() {
+        if index > 0 {
+            length += (*sample - samples[index - 1]).length();
+        }
+        sample_lengths.push(length);
+    }
+    let sample_length = length.max(MIN_TIME_DELTA);
+    let mut cursor = 0;
+    let sample_widths = sample_lengths
+        .iter()
+        .map(|length| {
+            let target = length / sample_length * input_length;
+            while cursor + 1 < input_lengths.len() && input_lengths[cursor + 1] < target {
+                cursor += 1;
+            }
+            if cursor + 1 >= input_lengths.len() {
+                return widths[cursor];
+            }
+            let span = input_lengths[cursor + 1] - input_lengths[cursor];
+            let t = if span > 0.0 { (target - input_lengths[cursor]) / span } else { 0.0 };
+            widths[cursor] + (widths[cursor + 1] - widths[cursor]) * t.max(0.0).min(1.0)
+        })
+        .collect::<Vec<_>>();
+    // Offset both sides along the normals and join them with caps.
+    let last = samples.len() - 1;
+    let mut left = Vec::with_capacity(samples.len());
+    let mut right = Vec::with_capacity(samples.len());
+    let mut normal = Vector2F::zero();
+    for index in 0..samples.len() {
+        let before = samples[index.saturating_sub(1)];
+        let after = samples[(index + 1).min(last)];
+        let direction = after - before;
+        if direction.square_length() > 0.0 {
+            let direction = direction.normalize();
+            normal = Vector2F::new(-direction.y(), direction.x());
+        }
+        let offset = normal * (sample_widths[index] * 0.5);
+        left.push(samples[index] + offset);
+        right.push(samples[index] - offset);
+    }
+    let mut contour = pf::Contour::new();
+    for point in left.iter() {
+        contour.push_endpoint(*point);
+    }
+    push_cap(&mut contour, samples[last], left[last], sample_widths[last] * 0.5);
+    for point in right.iter().rev() {
+        contour.push_endpoint(*point);
+    }
+    push_cap(&mut contour, samples[0], right[0], sample_widths[0] * 0.5);
+    contour.close();
+    outline.push_contour(contour);
+    outline
+}
+
+/// Half circle around `center` from `from` to the opposite side, bulging
+/// away from the body of the stroke given the winding `stroke_outline` uses.
+fn push_cap(contour: &mut pf::Contour, center: Vector2F, from: Vector2F, radius: f32) {
+    let start = from - center;
+    let start_angle = start.y().atan2(start.x());
+    for step in 1..CAP_SEGMENTS {
+        let angle = start_angle - std::f32::consts::PI * step as f32 / CAP_SEGMENTS as f32;
+        contour.push_endpoint(center + Vector2F::new(angle.cos(), angle.sin()) * radius);
+    }
+}
+
+fn circle_contour(center: Vector2F, radius: f32) -> pf::Contour {
+    let mut contour = pf::Contour::new();
+    let segments = CAP_SEGMENTS * 2;
+    for step in 0..segments {
+        let angle = 2.0 * std::f32::consts::PI * step as f32 / segments as f32;
+        contour.push_endpoint(center + Vector2F::new(angle.cos(), angle.sin()) * radius);
+    }
+    contour.close();
+    contour
+}
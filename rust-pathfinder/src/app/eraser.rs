@@ -0,0 +1,458 @@
+//! Removing ink, either whole strokes or only the parts under the eraser.
+//!
+//! Precise erasing cuts the stored input points rather than the fitted
+//! curve: the pieces are refitted when drawn, and the two never differ by
+//! more than the fit tolerance.
+use std::collections::HashSet;
+use crate::prelude::*;
+use super::document::{Document, Stroke, StrokeId, StrokeOrigin, StrokePoint};
+use super::fitting::{self, CubicSegment, FitOptions};
+use super::history::Command;
+use super::tool::{PointerEvent, Tool, ToolContext};
+
+/// Dragging places a dab at least this often, as a fraction of the radius,
+/// so that fast moves do not skip over thin strokes.
+const DAB_SPACING: f32 = 0.5;
+
+/// Pieces shorter than this, in document units, are dropped rather than
+/// left behind as specks.
+const MIN_PIECE_LENGTH: f32 = 0.5;
+
+pub const MIN_RADIUS: f32 = 1.0;
+pub const MAX_RADIUS: f32 = 256.0;
+
+const EPSILON: f32 = 1.0e-6;
+
+///////////////////////////////////////////////////////////////////////////////
+// OPTIONS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EraserMode {
+    /// Deletes every stroke the eraser touches.
+    WholeStroke,
+    /// Cuts away the parts of strokes under the eraser, leaving the rest as
+    /// new strokes.
+    Precise,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EraserOptions {
+    pub mode: EraserMode,
+    /// In device pixels, so that the eraser covers the same area of the
+    /// screen at every zoom level.
+    pub radius: f32,
+}
+
+impl Default for EraserOptions {
+    fn default() -> Self {
+        EraserOptions { mode: EraserMode::WholeStroke, radius: 12.0 }
+    }
+}
+
+impl EraserOptions {
+    pub fn scale_radius(&mut self, factor: f32) {
+        self.radius = (self.radius * factor).max(MIN_RADIUS).min(MAX_RADIUS);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// TOOL
+///////////////////////////////////////////////////////////////////////////////
+
+/// Each drag is one undo step.
+#[derive(Default)]
+pub struct EraserTool {
+    pub options: EraserOptions,
+    /// Where the eraser last erased during the current drag, in world
+    /// coordinates.
+    last_position: Option<Vector2F>,
+}
+
+impl EraserTool {
+    /// Erases along the drag from the last eraser position to `position`.
+    fn erase_to(&mut self, ctx: &mut ToolContext, position: Vector2F) {
+        let radius = self.options.radius / ctx.view.scale_factor();
+        let dabs = match self.last_position {
+            Some(last) => dab_positions(last, position, radius),
+            None => vec![position],
+        };
+        self.last_position = Some(position);
+        for center in dabs {
+            let command = erase_at(ctx.document, center, radius, self.options.mode, ctx.fit_options);
+            if let Some(command) = command {
+                ctx.execute(command);
+            }
+        }
+    }
+}
+
+impl Tool for EraserTool {
+    fn pointer_down(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        ctx.history.begin_group();
+        self.erase_to(ctx, event.position);
+    }
+    fn pointer_move(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        if self.last_position.is_some() && event.buttons.primary {
+            self.erase_to(ctx, event.position);
+        }
+    }
+    fn pointer_up(&mut self, ctx: &mut ToolContext, _: &PointerEvent) {
+        self.finish(ctx);
+    }
+    fn finish(&mut self, ctx: &mut ToolContext) {
+        if self.last_position.take().is_some() {
+            ctx.history.end_group();
+        }
+    }
+    fn cancel(&mut self, ctx: &mut ToolContext) {
+        if self.last_position.take().is_some() {
+            ctx.history.cancel_group(ctx.document);
+            ctx.redraw = true;
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// EDITS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Dab centers from `from` to `to`, spaced for `radius`. Excludes `from`,
+/// which the previous drag event already erased at.
+pub fn dab_positions(from: Vector2F, to: Vector2F, radius: f32) -> Vec<Vector2F> {
+    let spacing = (radius * DAB_SPACING).max(EPSILON);
+    let count = ((to - from).length() / spacing).ceil().max(1.0) as usize;
+    (1..=count)
+        .map(|index| from.lerp(to, index as f32 / count as f32))
+        .collect()
+}
+
+/// The edit of one eraser dab at `center` to the editable strokes of
+/// `document`, or nothing if it misses them all. The command has not been
+/// applied yet. `radius` is in document units.
+pub fn erase_at(
+    document: &mut Document,
+    center: Vector2F,
+    radius: f32,
+    mode: EraserMode,
+    fit_options: &FitOptions,
+) -> Option<Command> {
+    let candidates: HashSet<StrokeId> = document.strokes_near(center, radius).into_iter().collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    match mode {
+        EraserMode::WholeStroke => {
+            let ids: Vec<StrokeId> = document
+                .editable_strokes()
+                .filter(|stroke| candidates.contains(&stroke.id))
+                .filter(|stroke| stroke_touches_circle(stroke, center, radius, fit_options))
+                .map(|stroke| stroke.id)
+                .collect();
+            if ids.is_empty() {
+                return None;
+            }
+            Some(Command::delete_strokes(document, &ids))
+        }
+        EraserMode::Precise => {
+            let mut cut_ids = Vec::new();
+            let mut additions = Vec::new();
+            for layer in document.layers().iter().filter(|layer| layer.is_editable()) {
+                // Net change in stroke count below the current stroke, once
+                // every cut stroke has been replaced by its pieces. Strokes
+                // erased completely leave no pieces, so it can go negative.
+                let mut offset: isize = 0;
+                for (index, stroke) in layer.strokes.iter().enumerate() {
+                    if !candidates.contains(&stroke.id) {
+                        continue;
+                    }
+                    let pieces = match cut_points(&stroke.points, center, radius) {
+                        Some(pieces) => pieces,
+                        None => continue,
+                    };
+                    cut_ids.push(stroke.id);
+                    let count = pieces.len();
+                    for (piece_index, points) in pieces.into_iter().enumerate() {
+                        // What is left of a shape is plain ink.
+                        let piece = Stroke {
+                            points,
+                            shape: None,
+                            origin: StrokeOrigin::EraserPiece,
+                            ..stroke.clone()
+                        };
+                        let at = (index as isize + offset) as usize + piece_index;
+                        additions.push((layer.id, at, piece));
+                    }
+                    offset += count as isize - 1;
+                }
+            }
+            if cut_ids.is_empty() {
+                return None;
+            }
+            let mut commands = vec![Command::delete_strokes(document, &cut_ids)];
+            for (layer, index, mut stroke) in additions {
+                stroke.id = document.allocate_stroke_id();
+                commands.push(Command::AddStroke { layer, index, stroke });
+            }
+            Some(Command::Batch(commands))
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// GEOMETRY
+///////////////////////////////////////////////////////////////////////////////
+
+/// Whether the eraser circle touches the ink of `stroke`, i.e. comes within
+/// half the stroke width of its fitted curve.
+pub fn stroke_touches_circle(
+    stroke: &Stroke,
+    center: Vector2F,
+    radius: f32,
+    fit_options: &FitOptions,
+) -> bool {
+    let radius = radius + stroke.style.width * 0.5;
+    match stroke.bounds() {
+        Some(bounds) if bounds.dilate(radius).contains_point(center) => {}
+        _ => return false,
+    }
+    let positions: Vec<Vector2F> = stroke.points.iter().map(|point| point.position).collect();
+    let segments = fitting::fit_curve(&positions, fit_options.tolerance);
+    if segments.is_empty() {
+        // A dot.
+        return (positions[0] - center).length() <= radius;
+    }
+    segments
+        .iter()
+        .any(|segment| cubic_touches_circle(segment, center, radius))
+}
+
+pub fn cubic_touches_circle(segment: &CubicSegment, center: Vector2F, radius: f32) -> bool {
+    // The curve lies within the hull of its control points.
+    let hull = RectF::from_points(segment.from, segment.from)
+        .union_point(segment.ctrl0)
+        .union_point(segment.ctrl1)
+        .union_point(segment.to);
+    hull.dilate(radius).contains_point(center) && segment.distance_to(center) <= radius
+}
+
+/// The part of the line segment from `from` to `to` inside the circle, as a
+/// range of the segment's parameter within `0.0..=1.0`.
+pub fn segment_circle_overlap(
+    from: Vector2F,
+    to: Vector2F,
+    center: Vector2F,
+    radius: f32,
+) -> Option<(f32, f32)> {
+    // Solve |from + t * direction - center|² = radius² for t.
+    let direction = to - from;
+    let offset = from - center;
+    let a = direction.dot(direction);
+    let half_b = offset.dot(direction);
+    let c = offset.dot(offset) - radius * radius;
+    if a < EPSILON {
+        return if c <= 0.0 { Some((0.0, 1.0)) } else { None };
+    }
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let t0 = (-half_b - root) / a;
+    let t1 = (-half_b + root) / a;
+    if t1 < 0.0 || t0 > 1.0 {
+        return None;
+    }
+    Some((t0.max(0.0), t1.min(1.0)))
+}
+
+/// Splits the polyline through `points` into the pieces outside the circle,
+/// interpolating pressure and time where the circle cuts it. Returns nothing
+/// if the circle misses the polyline, and an empty list if it covers it.
+pub fn cut_points(
+    points: &[StrokePoint],
+    center: Vector2F,
+    radius: f32,
+) -> Option<Vec<Vec<StrokePoint>>> {
+    let inside = |point: &StrokePoint| (point.position - center).length() <= radius;
+    match points {
+        [] => return None,
+        [point] => return if inside(point) { Some(Vec::new()) } else { None },
+        _ => {}
+    }
+    let mut touched = false;
+    let mut pieces = Vec::new();
+    let mut current = if inside(&points[0]) { None } else { Some(vec![points[0]]) };
+    for pair in points.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        match segment_circle_overlap(from.position, to.position, center, radius) {
+            None => {
+                current.get_or_insert_with(|| vec![*from]).push(*to);
+            }
+            Some((t0, t1)) => {
+                touched = true;
+                if let Some(mut piece) = current.take() {
+                    if t0 > 0.0 {
+                        piece.push(interpolate(from, to, t0));
+                    }
+                    pieces.push(piece);
+                }
+                if t1 < 1.0 {
+                    current = Some(vec![interpolate(from, to, t1), *to]);
+                }
+            }
+        }
+    }
+    if !touched {
+        return None;
+    }
+    pieces.extend(current);
+    pieces.retain(|piece| polyline_length(piece) >= MIN_PIECE_LENGTH);
+    Some(pieces)
+}
+
+fn interpolate(from: &StrokePoint, to: &StrokePoint, t: f32) -> StrokePoint {
+    StrokePoint {
+        position: from.position.lerp(to.position, t),
+        pressure: from.pressure + (to.pressure - from.pressure) * t,
+        time: from.time + (to.time - from.time) * t,
+    }
+}
+
+fn polyline_length(points: &[StrokePoint]) -> f32 {
+    points
+        .windows(2)
+        .map(|pair| (pair[1].position - pair[0].position).length())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use crate::app::document::StrokeStyle;
+    use crate::app::history::History;
+    use crate::engine::pointer::{PointerButtons, PointerId, PointerKind};
+    use super::*;
+
+    /// A horizontal stroke at height `y`, from `x` to `x + length`.
+    fn draw(document: &mut Document, x: f32, y: f32, length: f32) -> StrokeId {
+        let id = document.begin_stroke(Default::default(), StrokeOrigin::Brush).unwrap();
+        for index in 0..=10 {
+            let time = index as f32 * 0.01;
+            let position = Vector2F::new(x + index as f32 * length * 0.1, y);
+            document.push_point(id, StrokePoint { position, pressure: 1.0, time });
+        }
+        id
+    }
+
+    fn erase(document: &mut Document, center: Vector2F, radius: f32) -> Option<Command> {
+        let fit_options = FitOptions::default();
+        let command = erase_at(document, center, radius, EraserMode::Precise, &fit_options);
+        if let Some(ref command) = command {
+            command.apply(document);
+        }
+        command
+    }
+
+    /// The x range of each stroke in paint order.
+    fn spans(document: &Document) -> Vec<(f32, f32)> {
+        document
+            .strokes()
+            .map(|stroke| {
+                let first = stroke.points.first().unwrap().position.x();
+                (first, stroke.points.last().unwrap().position.x())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn precise_erasing_removes_covered_strokes() {
+        let mut document = Document::new();
+        let covered = draw(&mut document, 0.0, 0.0, 100.0);
+        let kept = draw(&mut document, 0.0, 500.0, 100.0);
+        let before = document.layers().to_vec();
+        let command = erase(&mut document, Vector2F::new(50.0, 0.0), 80.0).unwrap();
+        assert!(document.stroke(covered).is_none());
+        assert!(document.stroke(kept).is_some());
+        assert_eq!(document.strokes().count(), 1);
+        command.revert(&mut document);
+        assert_eq!(document.layers(), &before[..]);
+    }
+
+    #[test]
+    fn pieces_keep_their_place_after_covered_strokes() {
+        let mut document = Document::new();
+        draw(&mut document, 80.0, 0.0, 20.0);
+        draw(&mut document, 0.0, 200.0, 100.0);
+        // Cut in two around the first.
+        draw(&mut document, 0.0, 0.0, 200.0);
+        draw(&mut document, 300.0, 0.0, 100.0);
+        erase(&mut document, Vector2F::new(90.0, 0.0), 30.0).unwrap();
+        let spans = spans(&document);
+        assert_eq!(spans.len(), 4);
+        assert_eq!(spans[0], (0.0, 100.0));
+        assert_eq!(spans[1].0, 0.0);
+        assert!((spans[1].1 - 60.0).abs() < 0.01);
+        assert!((spans[2].0 - 120.0).abs() < 0.01);
+        assert_eq!(spans[2].1, 200.0);
+        assert_eq!(spans[3], (300.0, 400.0));
+    }
+
+    #[test]
+    fn cut_points_report_cover_and_misses() {
+        let points: Vec<StrokePoint> = (0..=4)
+            .map(|index| StrokePoint {
+                position: Vector2F::new(index as f32, 0.0),
+                pressure: 1.0,
+                time: 0.0,
+            })
+            .collect();
+        assert_eq!(cut_points(&points, Vector2F::new(2.0, 0.0), 10.0), Some(vec![]));
+        assert_eq!(cut_points(&points, Vector2F::new(2.0, 50.0), 10.0), None);
+        let pieces = cut_points(&points, Vector2F::new(2.0, 0.0), 0.5).unwrap();
+        assert_eq!(pieces.len(), 2);
+    }
+
+    #[test]
+    fn cancelled_drags_leave_no_trace() {
+        let mut document = Document::new();
+        let mut history = History::default();
+        let (fit_options, brush) = (FitOptions::default(), StrokeStyle::default());
+        let kept = draw(&mut document, 0.0, 0.0, 100.0);
+        let erased = draw(&mut document, 0.0, 100.0, 100.0);
+        let mut ctx = ToolContext {
+            document: &mut document,
+            history: &mut history,
+            fit_options: &fit_options,
+            brush: &brush,
+            view: pf::Transform2F::default(),
+            redraw: false,
+        };
+        let mut eraser = EraserTool::default();
+        eraser.pointer_down(&mut ctx, &press(Vector2F::new(50.0, 100.0)));
+        eraser.pointer_up(&mut ctx, &press(Vector2F::new(50.0, 100.0)));
+        let before = ctx.document.layers().to_vec();
+        eraser.pointer_down(&mut ctx, &press(Vector2F::new(50.0, 0.0)));
+        assert!(ctx.document.stroke(kept).is_none());
+        eraser.cancel(&mut ctx);
+        assert_eq!(ctx.document.layers(), &before[..]);
+        // Only the finished drag is left to undo.
+        assert!(ctx.history.undo(ctx.document));
+        assert!(!ctx.history.can_undo());
+        assert!(ctx.document.stroke(erased).is_some());
+    }
+
+    fn press(position: Vector2F) -> PointerEvent {
+        PointerEvent {
+            id: PointerId::Mouse,
+            kind: PointerKind::Mouse,
+            position,
+            screen_position: position,
+            buttons: PointerButtons { primary: true, ..Default::default() },
+            modifiers: wit::ModifiersState::empty(),
+            pressure: 1.0,
+            tilt: None,
+            time: Instant::now(),
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Fits piecewise cubic Béziers to raw input points.
+//!
+//! This is Philip J. Schneider's algorithm from "An Algorithm for
+//! Automatically Fitting Digitized Curves" (Graphics Gems, 1990): fit one
+//! cubic by least squares over a chord-length parameterization, refine the
+//! parameterization with Newton-Raphson, and split at the worst point when
+//! the fit is still outside the tolerance.
+use crate::prelude::*;
+
+/// Newton-Raphson refinement is only attempted when the first fit is within
+/// this multiple of the tolerance; worse fits are split immediately.
+const REPARAMETERIZE_ERROR_FACTOR: f32 = 4.0;
+const MAX_REPARAMETERIZE_ITERATIONS: usize = 4;
+
+/// Samples per segment used to seed the nearest-point search.
+const DEVIATION_SAMPLES: usize = 32;
+
+const EPSILON: f32 = 1.0e-6;
+
+///////////////////////////////////////////////////////////////////////////////
+// OPTIONS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FitOptions {
+    /// Maximum distance, in document units, between an input point and the
+    /// fitted curve.
+    pub tolerance: f32,
+}
+
+impl Default for FitOptions {
+    fn default() -> Self {
+        FitOptions { tolerance: 1.5 }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// CUBIC SEGMENT
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CubicSegment {
+    pub from: Vector2F,
+    pub ctrl0: Vector2F,
+    pub ctrl1: Vector2F,
+    pub to: Vector2F,
+}
+
+impl CubicSegment {
+    pub fn line(from: Vector2F, to: Vector2F) -> CubicSegment {
+        let delta = (to - from) * (1.0 / 3.0);
+        CubicSegment { from, ctrl0: from + delta, ctrl1: to - delta, to }
+    }
+    pub fn sample(&self, t: f32) -> Vector2F {
+        let s = 1.0 - t;
+        self.from * (s * s * s) +
+            self.ctrl0 * (3.0 * s * s * t) +
+            self.ctrl1 * (3.0 * s * t * t) +
+            self.to * (t * t * t)
+    }
+    fn derivative(&self, t: f32) -> Vector2F {
+        let s = 1.0 - t;
+        (self.ctrl0 - self.from) * (3.0 * s * s) +
+            (self.ctrl1 - self.ctrl0) * (6.0 * s * t) +
+            (self.to - self.ctrl1) * (3.0 * t * t)
+    }
+    fn second_derivative(&self, t: f32) -> Vector2F {
+        let s = 1.0 - t;
+        (self.ctrl1 - self.ctrl0 * 2.0 + self.from) * (6.0 * s) +
+            (self.to - self.ctrl1 * 2.0 + self.ctrl0) * (6.0 * t)
+    }
+    /// One Newton-Raphson step towards the parameter of the point on the
+    /// curve nearest to `point`.
+    fn refine_parameter(&self, point: Vector2F, t: f32) -> f32 {
+        let offset = self.sample(t) - point;
+        let d1 = self.derivative(t);
+        let d2 = self.second_derivative(t);
+        let numerator = offset.dot(d1);
+        let denominator = d1.dot(d1) + offset.dot(d2);
+        if denominator.abs() < EPSILON {
+            return t;
+        }
+        (t - numerator / denominator).max(0.0).min(1.0)
+    }
+    /// Distance from `point` to the nearest point on the segment.
+    pub fn distance_to(&self, point: Vector2F) -> f32 {
+        let mut best_t = 0.0;
+        let mut best_distance = f32::INFINITY;
+        for index in 0..=DEVIATION_SAMPLES {
+            let t = index as f32 / DEVIATION_SAMPLES as f32;
+            let distance = (self.sample(t) - point).square_length();
+            if distance < best_distance {
+                best_distance = distance;
+                best_t = t;
+            }
+        }
+        for _ in 0..MAX_REPARAMETERIZE_ITERATIONS {
+            best_t = self.refine_parameter(point, best_t);
+        }
+        let refined = (self.sample(best_t) - point).square_length();
+        best_distance.min(refined).sqrt()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// FITTING
+///////////////////////////////////////////////////////////////////////////////
+
+/// Fits cubic segments through `points` so that no point is further than
+/// `tolerance` from the result. Consecutive segments share endpoints and
+/// their tangents are continuous. Returns nothing for fewer than two
+/// distinct points.
+pub fn fit_curve(points: &[Vector2F], tolerance: f32) -> Vec<CubicSegment> {
+    let mut distinct: Vec<Vector2F> = Vec::with_capacity(points.len());
+    for point in points.iter() {
+        if distinct.last().map(|last| *last != *point).unwrap_or(true) {
+            distinct.push(*point);
+        }
+    }
+    let mut segments = Vec::new();
+    if distinct.len() < 2 {
+        return segments;
+    }
+    let last = distinct.len() - 1;
+    let left_tangent = (distinct[1] - distinct[0]).normalize();
+    let right_tangent = (distinct[last - 1] - distinct[last]).normalize();
+    let tolerance = tolerance.max(EPSILON);
+    fit_cubic(
+        &distinct,
+        left_tangent,
+        right_tangent,
+        tolerance * tolerance,
+        &mut segments,
+    );
+    segments
+}
+
+/// Largest distance from any of `points` to the nearest of `segments`.
+pub fn max_deviation(points: &[Vector2F], segments: &[CubicSegment]) -> f32 {
+    points
+        .iter()
+        .map(|point| {
+            segments
+                .iter()
+                .map(|segment| segment.distance_to(*point))
+                .fold(f32::INFINITY, f32::min)
+        })
+        .fold(0.0, f32::max)
+}
+
+fn fit_cubic(
+    points: &[Vector2F],
+    left_tangent: Vector2F,
+    right_tangent: Vector2F,
+    squared_tolerance: f32,
+    segments: &mut Vec<CubicSegment>,
+) {
+    let last = points.len() - 1;
+    if points.len() == 2 {
+        let distance = (points[1] - points[0]).length() / 3.0;
+        segments.push(CubicSegment {
+            from: points[0],
+            ctrl0: points[0] + left_tangent * distance,
+            ctrl1: points[1] + right_tangent * distance,
+            to: points[1],
+        });
+        return;
+    }
+    let mut parameters = chord_length_parameterize(points);
+    let mut curve = generate_bezier(points, &parameters, left_tangent, right_tangent);
+    let (mut error, mut split) = compute_max_error(points, &curve, &parameters);
+    if error < squared_tolerance {
+        segments.push(curve);
+        return;
+    }
+    let iteration_error = squared_tolerance *
+        REPARAMETERIZE_ERROR_FACTOR *
+        REPARAMETERIZE_ERROR_FACTOR;
+    if error < iteration_error {
+        for _ in 0..MAX_REPARAMETERIZE_ITERATIONS {
+            for (point, t) in points.iter().zip(parameters.iter_mut()) {
+                *t = curve.refine_parameter(*point, *t);
+            }
+            curve = generate_bezier(points, &parameters, left_tangent, right_tangent);
+            let (new_error, new_split) = compute_max_error(points, &curve, &parameters);
+            error = new_error;
+            split = new_split;
+            if error < squared_tolerance {
+                segments.push(curve);
+                return;
+            }
+        }
+    }
+    let mut center_tangent = points[split - 1] - points[split + 1];
+    if center_tangent.square_length() < EPSILON {
+        center_tangent = points[split - 1] - points[split];
+    }
+    let center_tangent = center_tangent.normalize();
+    fit_cubic(&points[..=split], left_tangent, center_tangent, squared_tolerance, segments);
+    fit_cubic(&points[split..=last], -center_tangent, right_tangent, squared_tolerance, segments);
+}
+
+fn chord_length_parameterize(points: &[Vector2F]) -> Vec<f32> {
+    let mut parameters = Vec::with_capacity(points.len());
+    parameters.push(0.0);
+    for window in points.windows(2) {
+        let length = *parameters.last().unwrap() + (window[1] - window[0]).length();
+        parameters.push(length);
+    }
+    let total = *parameters.last().unwrap();
+    for t in parameters.iter_mut() {
+        *t /= total;
+    }
+    parameters
+}
+
+/// Least-squares fit of the two inner control points, keeping the end
+/// tangents fixed.
+fn generate_bezier(
+    points: &[Vector2F],
+    parameters: &[f32],
+    left_tangent: Vector2F,
+    right_tangent: Vector2F,
+) -> CubicSegment {
+    let first = points[0];
+    let last = points[points.len() - 1];
+    let mut c = [[0.0f32; 2]; 2];
+    let mut x = [0.0f32; 2];
+    for (point, t) in points.iter().zip(parameters.iter()) {
+        let s = 1.0 - t;
+        let b0 = s * s * s;
+        let b1 = 3.0 * t * s * s;
+        let b2 = 3.0 * t * t * s;
+        let b3 = t * t * t;
+        let a0 = left_tangent * b1;
+        let a1 = right_tangent * b2;
+        c[0][0] += a0.dot(a0);
+        c[0][1] += a0.dot(a1);
+        c[1][1] += a1.dot(a1);
+        let rest = *point - (first * (b0 + b1) + last * (b2 + b3));
+        x[0] += a0.dot(rest);
+        x[1] += a1.dot(rest);
+    }
+    c[1][0] = c[0][1];
+    let det_c0_c1 = c[0][0] * c[1][1] - c[1][0] * c[0][1];
+    let det_c0_x = c[0][0] * x[1] - c[1][0] * x[0];
+    let det_x_c1 = x[0] * c[1][1] - x[1] * c[0][1];
+    let (alpha_left, alpha_right) = if det_c0_c1.abs() < EPSILON {
+        (0.0, 0.0)
+    } else {
+        (det_x_c1 / det_c0_c1, det_c0_x / det_c0_c1)
+    };
+    // Degenerate or backwards handles, and handles that cross over each
+    // other along the chord and so loop the curve away from the points
+    // between its samples, fall back to the Wu/Barsky heuristic of placing
+    // the control points a third of the way along the chord.
+    let chord = last - first;
+    let segment_length = chord.length();
+    let minimum = EPSILON * segment_length;
+    let crossed = (left_tangent * alpha_left).dot(chord) -
+        (right_tangent * alpha_right).dot(chord) > chord.square_length();
+    if alpha_left < minimum || alpha_right < minimum || crossed {
+        let distance = segment_length / 3.0;
+        return CubicSegment {
+            from: first,
+            ctrl0: first + left_tangent * distance,
+            ctrl1: last + right_tangent * distance,
+            to: last,
+        };
+    }
+    CubicSegment {
+        from: first,
+        ctrl0: first + left_tangent * alpha_left,
+        ctrl1: last + right_tangent * alpha_right,
+        to: last,
+    }
+}
+
+/// Largest squared distance between an interior point and the curve at that
+/// point's parameter, and the index of that point.
+fn compute_max_error(
+    points: &[Vector2F],
+    curve: &CubicSegment,
+    parameters: &[f32],
+) -> (f32, usize) {
+    let mut max_error = 0.0;
+    let mut split = points.len() / 2;
+    for index in 1..points.len() - 1 {
+        let error = (curve.sample(parameters[index]) - points[index]).square_length();
+        if error >= max_error {
+            max_error = error;
+            split = index;
+        }
+    }
+    (max_error, split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCES: [f32; 3] = [0.25, 1.5, 4.0];
+
+    /// Rounding slack on top of the tolerance.
+    const SLACK: f32 = 1.0e-3;
+
+    fn line(from: Vector2F, to: Vector2F, count: usize) -> Vec<Vector2F> {
+        (0..count).map(|index| from.lerp(to, index as f32 / (count - 1) as f32)).collect()
+    }
+
+    fn arc(radius: f32, sweep: f32, count: usize) -> Vec<Vector2F> {
+        (0..count)
+            .map(|index| {
+                let angle = sweep * index as f32 / (count - 1) as f32;
+                Vector2F::new(angle.cos(), angle.sin()) * radius
+            })
+            .collect()
+    }
+
+    /// A wobbly zigzag, jittered by a fixed linear congruential sequence.
+    fn noisy(count: usize) -> Vec<Vector2F> {
+        let mut seed: u32 = 12345;
+        let mut jitter = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as f32 / 65536.0 - 0.5
+        };
+        (0..count)
+            .map(|index| {
+                let x = index as f32 * 2.0;
+                let y = (index as f32 * 0.3).sin() * 20.0 + if index % 10 < 5 { 0.0 } else { 8.0 };
+                Vector2F::new(x + jitter() * 3.0, y + jitter() * 3.0)
+            })
+            .collect()
+    }
+
+    fn assert_fits(points: &[Vector2F], tolerance: f32) {
+        let segments = fit_curve(points, tolerance);
+        assert!(!segments.is_empty());
+        assert_eq!(segments[0].from, points[0]);
+        assert_eq!(segments[segments.len() - 1].to, points[points.len() - 1]);
+        for pair in segments.windows(2) {
+            assert_eq!(pair[0].to, pair[1].from);
+            let incoming = (pair[0].to - pair[0].ctrl1).normalize();
+            let outgoing = (pair[1].ctrl0 - pair[1].from).normalize();
+            assert!(incoming.dot(outgoing) > 0.999, "tangent break at {:?}", pair[0].to);
+        }
+        let deviation = max_deviation(points, &segments);
+        assert!(deviation <= tolerance + SLACK, "{} > {}", deviation, tolerance);
+        // Nor may the curve loop away between the points.
+        let spacing = points
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).length())
+            .fold(0.0, f32::max);
+        for segment in segments.iter() {
+            for index in 0..=16 {
+                let sample = segment.sample(index as f32 / 16.0);
+                let nearest = points
+                    .iter()
+                    .map(|point| (*point - sample).length())
+                    .fold(f32::INFINITY, f32::min);
+                assert!(nearest <= spacing + tolerance, "{:?} strays from the points", sample);
+            }
+        }
+    }
+
+    #[test]
+    fn lines_fit_in_one_segment() {
+        for tolerance in TOLERANCES.iter() {
+            let points = line(Vector2F::new(-3.0, 7.0), Vector2F::new(120.0, 40.0), 50);
+            assert_fits(&points, *tolerance);
+            assert_eq!(fit_curve(&points, *tolerance).len(), 1);
+        }
+    }
+
+    #[test]
+    fn arcs_fit_within_tolerance() {
+        for tolerance in TOLERANCES.iter() {
+            assert_fits(&arc(30.0, 1.0, 20), *tolerance);
+            assert_fits(&arc(100.0, 5.5, 200), *tolerance);
+        }
+    }
+
+    #[test]
+    fn noisy_input_fits_within_tolerance() {
+        for tolerance in TOLERANCES.iter() {
+            assert_fits(&noisy(300), *tolerance);
+        }
+    }
+
+    #[test]
+    fn corners_fit_within_tolerance() {
+        let corners = [
+            Vector2F::zero(),
+            Vector2F::new(50.0, 0.0),
+            Vector2F::new(50.0, 50.0),
+            Vector2F::zero(),
+        ];
+        let mut points = vec![corners[0]];
+        for pair in corners.windows(2) {
+            points.extend(line(pair[0], pair[1], 20).into_iter().skip(1));
+        }
+        for tolerance in TOLERANCES.iter() {
+            let segments = fit_curve(&points, *tolerance);
+            assert!(max_deviation(&points, &segments) <= *tolerance + SLACK);
+        }
+    }
+
+    #[test]
+    fn single_points_fit_nothing() {
+        assert!(fit_curve(&[], 1.0).is_empty());
+        assert!(fit_curve(&[Vector2F::splat(3.0)], 1.0).is_empty());
+        assert!(fit_curve(&[Vector2F::splat(3.0); 5], 1.0).is_empty());
+    }
+
+    #[test]
+    fn two_points_fit_a_straight_segment() {
+        let points = [Vector2F::new(1.0, 2.0), Vector2F::new(31.0, -8.0)];
+        let segments = fit_curve(&points, 1.0);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].from, points[0]);
+        assert_eq!(segments[0].to, points[1]);
+        let middle = segments[0].sample(0.5);
+        assert!((middle - points[0].lerp(points[1], 0.5)).length() < SLACK);
+    }
+
+    #[test]
+    fn duplicate_points_are_ignored() {
+        let points = arc(40.0, 2.0, 30);
+        let mut doubled = Vec::new();
+        for point in points.iter() {
+            doubled.push(*point);
+            doubled.push(*point);
+        }
+        assert_eq!(fit_curve(&doubled, 1.0), fit_curve(&points, 1.0));
+        assert_fits(&doubled, 1.0);
+    }
+
+    #[test]
+    fn zero_tolerance_still_terminates() {
+        let points = noisy(60);
+        let segments = fit_curve(&points, 0.0);
+        assert!(max_deviation(&points, &segments) <= SLACK);
+    }
+}
@@ -0,0 +1,1103 @@
+//! The native `.alphadraw` document format.
+//!
+//! All integers are little-endian. A file is the 8-byte magic `ALPHADRW`,
+//! a `u16` schema version, then a sequence of sections until end of file:
+//!
+//! ```text
+//! section := tag: [u8; 4]  length: u32  payload: [u8; length]
+//! ```
+//!
+//! Readers skip sections with unknown tags, and ignore trailing bytes in the
+//! payloads and records they do understand, so new data can be added without
+//! a version bump. The version only changes when old readers would
+//! misinterpret a file.
+//!
+//! Strokes belong to the nearest preceding `LAYR` section; version 1 files
+//! have none and load as a single layer.
+use std::convert::TryInto;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use crate::prelude::*;
+use super::document::{BlendMode, Document, Layer, LayerId, LayerProperties, Stroke, StrokeId};
+use super::document::{StrokeOrigin, StrokePoint, StrokeStyle};
+use super::dynamics::{BrushDynamics, DynamicsCurve};
+use super::fitting::CubicSegment;
+use super::paint::{Fill, Gradient, GradientKind, GradientStop, Paint};
+use super::shape::{ArrowHead, Shape, ShapeContour, ShapeKind};
+use super::stabilizer::{StabilizerMode, StabilizerOptions};
+
+pub static EXTENSION: &'static str = "alphadraw";
+
+const MAGIC: [u8; 8] = *b"ALPHADRW";
+pub const VERSION: u16 = 2;
+
+const DOCUMENT_TAG: [u8; 4] = *b"DOCU";
+const LAYER_TAG: [u8; 4] = *b"LAYR";
+const STROKE_TAG: [u8; 4] = *b"STRK";
+
+/// Position, pressure and time, as `f32`s.
+const POINT_RECORD_SIZE: usize = 16;
+
+const LINE_CAP_BUTT: u8 = 0;
+const LINE_CAP_SQUARE: u8 = 1;
+const LINE_CAP_ROUND: u8 = 2;
+
+const LINE_JOIN_MITER: u8 = 0;
+const LINE_JOIN_BEVEL: u8 = 1;
+const LINE_JOIN_ROUND: u8 = 2;
+
+/// Polygons and stars with absurd vertex counts from damaged files are
+/// clamped rather than allocated.
+const MAX_SHAPE_VERTICES: u32 = 1024;
+
+const SHAPE_LINE: u8 = 0;
+const SHAPE_ARC: u8 = 1;
+const SHAPE_ELLIPSE: u8 = 2;
+const SHAPE_RECTANGLE: u8 = 3;
+const SHAPE_POLYLINE: u8 = 4;
+const SHAPE_POLYGON: u8 = 5;
+const SHAPE_STAR: u8 = 6;
+const SHAPE_PATH: u8 = 7;
+
+/// Four points.
+const SEGMENT_RECORD_SIZE: usize = 32;
+/// Offset and color.
+const STOP_RECORD_SIZE: usize = 8;
+
+const FILL_RULE_WINDING: u8 = 0;
+const FILL_RULE_EVEN_ODD: u8 = 1;
+
+const PAINT_COLOR: u8 = 0;
+const PAINT_LINEAR_GRADIENT: u8 = 1;
+const PAINT_RADIAL_GRADIENT: u8 = 2;
+
+///////////////////////////////////////////////////////////////////////////////
+// ERRORS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum FormatError {
+    Io(io::Error),
+    /// Not an AlphaDraw document.
+    BadMagic,
+    /// Written by a newer version of AlphaDraw.
+    TooNew { version: u16, supported: u16 },
+    /// The file is truncated or internally inconsistent.
+    Corrupt(&'static str),
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Io(error) => write!(f, "I/O error: {}", error),
+            FormatError::BadMagic => write!(f, "not an AlphaDraw document"),
+            FormatError::TooNew { version, supported } => write!(
+                f,
+                "document version {} is newer than the supported version {}",
+                version,
+                supported,
+            ),
+            FormatError::Corrupt(reason) => write!(f, "corrupt document: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FormatError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FormatError {
+    fn from(error: io::Error) -> Self {
+        FormatError::Io(error)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// PUBLIC API
+///////////////////////////////////////////////////////////////////////////////
+
+pub fn save(document: &Document, path: &Path) -> Result<(), FormatError> {
+    let file = File::create(path)?;
+    write_document(document, BufWriter::new(file))
+}
+
+pub fn load(path: &Path) -> Result<Document, FormatError> {
+    let file = File::open(path)?;
+    read_document(BufReader::new(file))
+}
+
+pub fn write_document<W: Write>(document: &Document, mut writer: W) -> Result<(), FormatError> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    let mut payload = ByteWriter::default();
+    payload.u64(document.next_stroke_id());
+    payload.u64(document.next_layer_id());
+    payload.u64(document.active_layer.0);
+    write_section(&mut writer, DOCUMENT_TAG, &payload.bytes)?;
+    for layer in document.layers().iter() {
+        let mut payload = ByteWriter::default();
+        write_layer(&mut payload, layer);
+        write_section(&mut writer, LAYER_TAG, &payload.bytes)?;
+        for stroke in layer.strokes.iter() {
+            let mut payload = ByteWriter::default();
+            write_stroke(&mut payload, stroke);
+            write_section(&mut writer, STROKE_TAG, &payload.bytes)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_document<R: Read>(mut reader: R) -> Result<Document, FormatError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let mut input = ByteReader { data: &data, offset: 0 };
+    if input.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(FormatError::BadMagic);
+    }
+    let version = input.u16()?;
+    if version > VERSION {
+        return Err(FormatError::TooNew { version, supported: VERSION });
+    }
+    let mut next_stroke_id = 0;
+    let mut next_layer_id = 0;
+    let mut active_layer = LayerId(0);
+    let mut layers: Vec<Layer> = Vec::new();
+    while !input.is_empty() {
+        let tag = input.bytes(4)?;
+        let length = input.u32()? as usize;
+        let mut payload = ByteReader { data: input.bytes(length)?, offset: 0 };
+        match [tag[0], tag[1], tag[2], tag[3]] {
+            DOCUMENT_TAG => {
+                next_stroke_id = payload.u64()?;
+                if !payload.is_empty() {
+                    next_layer_id = payload.u64()?;
+                    active_layer = LayerId(payload.u64()?);
+                }
+            }
+            LAYER_TAG => {
+                layers.push(read_layer(&mut payload)?);
+            }
+            STROKE_TAG => {
+                let stroke = read_stroke(&mut payload)?;
+                match layers.last_mut() {
+                    Some(layer) => layer.strokes.push(stroke),
+                    None if version < 2 => {
+                        let mut layer = Layer::new(LayerId(0), String::from("Layer 1"));
+                        layer.strokes.push(stroke);
+                        layers.push(layer);
+                    }
+                    None => return Err(FormatError::Corrupt("stroke outside of a layer")),
+                }
+            }
+            _ => ()
+        }
+    }
+    Ok(Document::from_layers(layers, active_layer, next_stroke_id, next_layer_id))
+}
+
+/// The record of `layer`, without its strokes, for keeping in other file
+/// formats.
+pub fn encode_layer(layer: &Layer) -> Vec<u8> {
+    let mut out = ByteWriter::default();
+    write_layer(&mut out, layer);
+    out.bytes
+}
+
+pub fn decode_layer(data: &[u8]) -> Result<Layer, FormatError> {
+    read_layer(&mut ByteReader { data, offset: 0 })
+}
+
+/// The record of `stroke`, for keeping in other file formats.
+pub fn encode_stroke(stroke: &Stroke) -> Vec<u8> {
+    let mut out = ByteWriter::default();
+    write_stroke(&mut out, stroke);
+    out.bytes
+}
+
+pub fn decode_stroke(data: &[u8]) -> Result<Stroke, FormatError> {
+    read_stroke(&mut ByteReader { data, offset: 0 })
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// RECORDS
+///////////////////////////////////////////////////////////////////////////////
+
+fn write_section<W: Write>(writer: &mut W, tag: [u8; 4], payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&tag)?;
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(payload)
+}
+
+/// Layer records are `id`, a length-prefixed UTF-8 name, `visible` and
+/// `locked` flags, `opacity` and the blend mode as an index into
+/// `BlendMode::ALL`.
+fn write_layer(out: &mut ByteWriter, layer: &Layer) {
+    let properties = &layer.properties;
+    out.u64(layer.id.0);
+    out.u32(properties.name.len() as u32);
+    out.bytes.extend_from_slice(properties.name.as_bytes());
+    out.u8(properties.visible as u8);
+    out.u8(properties.locked as u8);
+    out.f32(properties.opacity);
+    let blend_mode = BlendMode::ALL
+        .iter()
+        .position(|blend_mode| *blend_mode == properties.blend_mode)
+        .unwrap();
+    out.u8(blend_mode as u8);
+}
+
+fn read_layer(input: &mut ByteReader) -> Result<Layer, FormatError> {
+    let id = LayerId(input.u64()?);
+    let name_length = input.u32()? as usize;
+    let name = String::from_utf8(input.bytes(name_length)?.to_vec())
+        .map_err(|_| FormatError::Corrupt("layer name is not UTF-8"))?;
+    let visible = input.u8()? != 0;
+    let locked = input.u8()? != 0;
+    let opacity = input.f32()?.max(0.0).min(1.0);
+    // Unknown blend modes from newer writers degrade to normal blending.
+    let blend_mode = BlendMode::ALL
+        .get(input.u8()? as usize)
+        .cloned()
+        .unwrap_or(BlendMode::Normal);
+    Ok(Layer {
+        id,
+        properties: LayerProperties { name, visible, locked, opacity, blend_mode },
+        strokes: Vec::new(),
+    })
+}
+
+/// Stroke records are `id`, `timestamp`, a length-prefixed style blob, the
+/// points, each prefixed by the per-point record size, a length-prefixed
+/// shape blob, empty for freehand strokes, then the origin as a `u8`.
+fn write_stroke(out: &mut ByteWriter, stroke: &Stroke) {
+    out.u64(stroke.id.0);
+    out.u64(stroke.timestamp);
+    let mut style = ByteWriter::default();
+    write_style(&mut style, &stroke.style);
+    out.u32(style.bytes.len() as u32);
+    out.bytes.extend_from_slice(&style.bytes);
+    out.u32(stroke.points.len() as u32);
+    out.u16(POINT_RECORD_SIZE as u16);
+    for point in stroke.points.iter() {
+        out.f32(point.position.x());
+        out.f32(point.position.y());
+        out.f32(point.pressure);
+        out.f32(point.time);
+    }
+    let mut shape = ByteWriter::default();
+    if let Some(ref stroke_shape) = stroke.shape {
+        write_shape(&mut shape, stroke_shape);
+    }
+    out.u32(shape.bytes.len() as u32);
+    out.bytes.extend_from_slice(&shape.bytes);
+    let origin = StrokeOrigin::ALL.iter().position(|origin| *origin == stroke.origin);
+    out.u8(origin.unwrap() as u8);
+}
+
+fn read_stroke(input: &mut ByteReader) -> Result<Stroke, FormatError> {
+    let id = StrokeId(input.u64()?);
+    let timestamp = input.u64()?;
+    let style_length = input.u32()? as usize;
+    let style = read_style(&mut ByteReader { data: input.bytes(style_length)?, offset: 0 })?;
+    let point_count = input.u32()? as usize;
+    let record_size = input.u16()? as usize;
+    if record_size < POINT_RECORD_SIZE {
+        return Err(FormatError::Corrupt("point record too small"));
+    }
+    if point_count.saturating_mul(record_size) > input.remaining() {
+        return Err(FormatError::Corrupt("point data truncated"));
+    }
+    let mut points = Vec::with_capacity(point_count);
+    for _ in 0..point_count {
+        let mut record = ByteReader { data: input.bytes(record_size)?, offset: 0 };
+        let x = record.f32()?;
+        let y = record.f32()?;
+        points.push(StrokePoint {
+            position: Vector2F::new(x, y),
+            pressure: record.f32()?,
+            time: record.f32()?,
+        });
+    }
+    // Version 2 files written before shapes existed end here.
+    let shape = if input.remaining() >= 4 {
+        let shape_length = input.u32()? as usize;
+        read_shape(&mut ByteReader { data: input.bytes(shape_length)?, offset: 0 })?
+    } else {
+        None
+    };
+    // Strokes written before origins were recorded, or with origins from
+    // newer writers, load as brush strokes.
+    let origin = if input.is_empty() {
+        StrokeOrigin::Brush
+    } else {
+        StrokeOrigin::ALL.get(input.u8()? as usize).cloned().unwrap_or(StrokeOrigin::Brush)
+    };
+    Ok(Stroke { id, style, timestamp, points, shape, origin })
+}
+
+/// The shape's transform as a row-major 2x3 matrix, a kind tag, the kind's
+/// parameters, then whether it is stroked and its fill.
+fn write_shape(out: &mut ByteWriter, shape: &Shape) {
+    write_transform(out, &shape.transform);
+    match shape.kind {
+        ShapeKind::Line { from, to, start_head, end_head, head_size } => {
+            out.u8(SHAPE_LINE);
+            out.vector(from);
+            out.vector(to);
+            out.u8(arrow_head_index(start_head));
+            out.u8(arrow_head_index(end_head));
+            out.f32(head_size);
+        }
+        ShapeKind::Arc { center, radius, start_angle, sweep_angle } => {
+            out.u8(SHAPE_ARC);
+            out.vector(center);
+            out.f32(radius);
+            out.f32(start_angle);
+            out.f32(sweep_angle);
+        }
+        ShapeKind::Ellipse { center, radii } => {
+            out.u8(SHAPE_ELLIPSE);
+            out.vector(center);
+            out.vector(radii);
+        }
+        ShapeKind::Rectangle { rect, corner_radius } => {
+            out.u8(SHAPE_RECTANGLE);
+            out.vector(rect.origin());
+            out.vector(rect.size());
+            out.f32(corner_radius);
+        }
+        ShapeKind::Polygon { center, radii, sides } => {
+            out.u8(SHAPE_POLYGON);
+            out.vector(center);
+            out.vector(radii);
+            out.u32(sides);
+        }
+        ShapeKind::Star { center, radii, inner_ratio, points } => {
+            out.u8(SHAPE_STAR);
+            out.vector(center);
+            out.vector(radii);
+            out.f32(inner_ratio);
+            out.u32(points);
+        }
+        ShapeKind::Polyline { ref vertices, closed } => {
+            out.u8(SHAPE_POLYLINE);
+            out.u8(closed as u8);
+            out.u32(vertices.len() as u32);
+            for vertex in vertices.iter() {
+                out.vector(*vertex);
+            }
+        }
+        ShapeKind::Path { ref contours } => {
+            out.u8(SHAPE_PATH);
+            out.u32(contours.len() as u32);
+            for contour in contours.iter() {
+                out.u8(contour.closed as u8);
+                out.u32(contour.segments.len() as u32);
+                for segment in contour.segments.iter() {
+                    out.vector(segment.from);
+                    out.vector(segment.ctrl0);
+                    out.vector(segment.ctrl1);
+                    out.vector(segment.to);
+                }
+            }
+        }
+    }
+    out.u8(shape.stroked as u8);
+    match shape.fill {
+        None => out.u8(0),
+        Some(ref fill) => {
+            out.u8(1);
+            write_fill(out, fill);
+        }
+    }
+}
+
+/// Shapes of kinds from newer writers are dropped, leaving their points as
+/// freehand ink.
+fn read_shape(input: &mut ByteReader) -> Result<Option<Shape>, FormatError> {
+    if input.remaining() == 0 {
+        return Ok(None);
+    }
+    let transform = read_transform(input)?;
+    let kind = match input.u8()? {
+        SHAPE_LINE => ShapeKind::Line {
+            from: input.vector()?,
+            to: input.vector()?,
+            start_head: read_arrow_head(input)?,
+            end_head: read_arrow_head(input)?,
+            head_size: input.f32()?,
+        },
+        SHAPE_ARC => ShapeKind::Arc {
+            center: input.vector()?,
+            radius: input.f32()?,
+            start_angle: input.f32()?,
+            sweep_angle: input.f32()?,
+        },
+        SHAPE_ELLIPSE => ShapeKind::Ellipse { center: input.vector()?, radii: input.vector()? },
+        SHAPE_RECTANGLE => ShapeKind::Rectangle {
+            rect: RectF::new(input.vector()?, input.vector()?),
+            corner_radius: input.f32()?,
+        },
+        SHAPE_POLYGON => ShapeKind::Polygon {
+            center: input.vector()?,
+            radii: input.vector()?,
+            sides: input.u32()?.max(3).min(MAX_SHAPE_VERTICES),
+        },
+        SHAPE_STAR => ShapeKind::Star {
+            center: input.vector()?,
+            radii: input.vector()?,
+            inner_ratio: input.f32()?,
+            points: input.u32()?.max(3).min(MAX_SHAPE_VERTICES),
+        },
+        SHAPE_POLYLINE => {
+            let closed = input.u8()? != 0;
+            let count = input.u32()? as usize;
+            if count.saturating_mul(8) > input.remaining() {
+                return Err(FormatError::Corrupt("shape vertices truncated"));
+            }
+            let vertices = (0..count).map(|_| input.vector()).collect::<Result<_, _>>()?;
+            ShapeKind::Polyline { vertices, closed }
+        }
+        SHAPE_PATH => {
+            let count = input.u32()? as usize;
+            if count.saturating_mul(5) > input.remaining() {
+                return Err(FormatError::Corrupt("shape contours truncated"));
+            }
+            let mut contours = Vec::with_capacity(count);
+            for _ in 0..count {
+                let closed = input.u8()? != 0;
+                let count = input.u32()? as usize;
+                if count.saturating_mul(SEGMENT_RECORD_SIZE) > input.remaining() {
+                    return Err(FormatError::Corrupt("shape segments truncated"));
+                }
+                let mut segments = Vec::with_capacity(count);
+                for _ in 0..count {
+                    segments.push(CubicSegment {
+                        from: input.vector()?,
+                        ctrl0: input.vector()?,
+                        ctrl1: input.vector()?,
+                        to: input.vector()?,
+                    });
+                }
+                contours.push(ShapeContour { segments, closed });
+            }
+            ShapeKind::Path { contours }
+        }
+        _ => return Ok(None),
+    };
+    // Shapes written before fills existed end here and were only stroked.
+    if input.is_empty() {
+        return Ok(Some(Shape { kind, transform, fill: None, stroked: true }));
+    }
+    let stroked = input.u8()? != 0;
+    let fill = if input.u8()? != 0 { read_fill(input)? } else { None };
+    Ok(Some(Shape { kind, transform, fill, stroked }))
+}
+
+fn write_transform(out: &mut ByteWriter, transform: &pf::Transform2F) {
+    for value in [transform.m11(), transform.m12(), transform.m21(), transform.m22()].iter() {
+        out.f32(*value);
+    }
+    out.vector(transform.translation());
+}
+
+fn read_transform(input: &mut ByteReader) -> Result<pf::Transform2F, FormatError> {
+    let matrix = [input.f32()?, input.f32()?, input.f32()?, input.f32()?];
+    let translation = input.vector()?;
+    Ok(pf::Transform2F::row_major(
+        matrix[0],
+        matrix[1],
+        matrix[2],
+        matrix[3],
+        translation.x(),
+        translation.y(),
+    ))
+}
+
+/// The fill rule, a paint tag, then the paint's parameters. Gradients end
+/// with their transform and stops.
+fn write_fill(out: &mut ByteWriter, fill: &Fill) {
+    out.u8(match fill.rule {
+        pf::FillRule::Winding => FILL_RULE_WINDING,
+        pf::FillRule::EvenOdd => FILL_RULE_EVEN_ODD,
+    });
+    let gradient = match fill.paint {
+        Paint::Color(color) => {
+            out.u8(PAINT_COLOR);
+            write_color(out, color);
+            return;
+        }
+        Paint::Gradient(ref gradient) => gradient,
+    };
+    match gradient.kind {
+        GradientKind::Linear { from, to } => {
+            out.u8(PAINT_LINEAR_GRADIENT);
+            out.vector(from);
+            out.vector(to);
+        }
+        GradientKind::Radial { focus, center, radius } => {
+            out.u8(PAINT_RADIAL_GRADIENT);
+            out.vector(focus);
+            out.vector(center);
+            out.f32(radius);
+        }
+    }
+    write_transform(out, &gradient.transform);
+    out.u32(gradient.stops.len() as u32);
+    for stop in gradient.stops.iter() {
+        out.f32(stop.offset);
+        write_color(out, stop.color);
+    }
+}
+
+/// Fills with paints from newer writers are dropped, leaving the shape
+/// unfilled.
+fn read_fill(input: &mut ByteReader) -> Result<Option<Fill>, FormatError> {
+    let rule = match input.u8()? {
+        FILL_RULE_EVEN_ODD => pf::FillRule::EvenOdd,
+        _ => pf::FillRule::Winding,
+    };
+    let kind = match input.u8()? {
+        PAINT_COLOR => {
+            let paint = Paint::Color(read_color(input)?);
+            return Ok(Some(Fill { paint, rule }));
+        }
+        PAINT_LINEAR_GRADIENT => GradientKind::Linear {
+            from: input.vector()?,
+            to: input.vector()?,
+        },
+        PAINT_RADIAL_GRADIENT => GradientKind::Radial {
+            focus: input.vector()?,
+            center: input.vector()?,
+            radius: input.f32()?,
+        },
+        _ => return Ok(None),
+    };
+    let transform = read_transform(input)?;
+    let count = input.u32()? as usize;
+    if count.saturating_mul(STOP_RECORD_SIZE) > input.remaining() {
+        return Err(FormatError::Corrupt("gradient stops truncated"));
+    }
+    let stops = (0..count)
+        .map(|_| Ok(GradientStop { offset: input.f32()?, color: read_color(input)? }))
+        .collect::<Result<_, FormatError>>()?;
+    let paint = Paint::Gradient(Gradient { kind, stops, transform });
+    Ok(Some(Fill { paint, rule }))
+}
+
+fn write_color(out: &mut ByteWriter, color: pf::ColorU) {
+    let pf::ColorU { r, g, b, a } = color;
+    out.bytes.extend_from_slice(&[r, g, b, a]);
+}
+
+fn read_color(input: &mut ByteReader) -> Result<pf::ColorU, FormatError> {
+    let color = input.bytes(4)?;
+    Ok(pf::ColorU::new(color[0], color[1], color[2], color[3]))
+}
+
+fn arrow_head_index(head: ArrowHead) -> u8 {
+    ArrowHead::ALL.iter().position(|other| *other == head).unwrap() as u8
+}
+
+fn read_arrow_head(input: &mut ByteReader) -> Result<ArrowHead, FormatError> {
+    Ok(ArrowHead::ALL.get(input.u8()? as usize).cloned().unwrap_or(ArrowHead::Open))
+}
+
+fn write_style(out: &mut ByteWriter, style: &StrokeStyle) {
+    write_color(out, style.color);
+    out.f32(style.width);
+    out.f32(style.opacity);
+    out.u8(match style.line_cap {
+        pf::LineCap::Butt => LINE_CAP_BUTT,
+        pf::LineCap::Square => LINE_CAP_SQUARE,
+        pf::LineCap::Round => LINE_CAP_ROUND,
+    });
+    let (join, miter_limit) = match style.line_join {
+        pf::LineJoin::Miter(limit) => (LINE_JOIN_MITER, limit),
+        pf::LineJoin::Bevel => (LINE_JOIN_BEVEL, 0.0),
+        pf::LineJoin::Round => (LINE_JOIN_ROUND, 0.0),
+    };
+    out.u8(join);
+    out.f32(miter_limit);
+    let dynamics = &style.dynamics;
+    for curve in [dynamics.pressure, dynamics.velocity].iter() {
+        out.f32(curve.start);
+        out.f32(curve.end);
+        out.f32(curve.exponent);
+    }
+    out.f32(dynamics.max_speed);
+    out.f32(dynamics.smoothing);
+    let mode = StabilizerMode::ALL.iter().position(|mode| *mode == style.stabilizer.mode);
+    out.u8(mode.unwrap() as u8);
+    out.f32(style.stabilizer.strength);
+}
+
+fn read_style(input: &mut ByteReader) -> Result<StrokeStyle, FormatError> {
+    let color = read_color(input)?;
+    let width = input.f32()?;
+    let opacity = input.f32()?;
+    let line_cap = match input.u8()? {
+        LINE_CAP_BUTT => pf::LineCap::Butt,
+        LINE_CAP_SQUARE => pf::LineCap::Square,
+        LINE_CAP_ROUND => pf::LineCap::Round,
+        _ => return Err(FormatError::Corrupt("unknown line cap")),
+    };
+    let join = input.u8()?;
+    let miter_limit = input.f32()?;
+    let line_join = match join {
+        LINE_JOIN_MITER => pf::LineJoin::Miter(miter_limit),
+        LINE_JOIN_BEVEL => pf::LineJoin::Bevel,
+        LINE_JOIN_ROUND => pf::LineJoin::Round,
+        _ => return Err(FormatError::Corrupt("unknown line join")),
+    };
+    // Styles written before brush dynamics existed end here and were drawn
+    // with a uniform width.
+    let dynamics = if input.is_empty() {
+        BrushDynamics::none()
+    } else {
+        let mut read_curve = || -> Result<DynamicsCurve, FormatError> {
+            Ok(DynamicsCurve {
+                start: input.f32()?,
+                end: input.f32()?,
+                exponent: input.f32()?,
+            })
+        };
+        let pressure = read_curve()?;
+        let velocity = read_curve()?;
+        BrushDynamics {
+            pressure,
+            velocity,
+            max_speed: input.f32()?,
+            smoothing: input.f32()?,
+        }
+    };
+    // Styles written before the stabilizer existed end here and were drawn
+    // without one.
+    let stabilizer = if input.is_empty() {
+        StabilizerOptions::none()
+    } else {
+        let mode = StabilizerMode::ALL.get(input.u8()? as usize).cloned();
+        StabilizerOptions {
+            mode: mode.unwrap_or(StabilizerMode::Off),
+            strength: input.f32()?,
+        }
+    };
+    Ok(StrokeStyle { color, width, opacity, line_cap, line_join, dynamics, stabilizer })
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// BYTE HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Default)]
+struct ByteWriter {
+    bytes: Vec<u8>,
+}
+
+impl ByteWriter {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    fn vector(&mut self, value: Vector2F) {
+        self.f32(value.x());
+        self.f32(value.y());
+    }
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+    fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], FormatError> {
+        if count > self.remaining() {
+            return Err(FormatError::Corrupt("unexpected end of data"));
+        }
+        let bytes = &self.data[self.offset..self.offset + count];
+        self.offset += count;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, FormatError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Result<u64, FormatError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    fn f32(&mut self) -> Result<f32, FormatError> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn vector(&mut self) -> Result<Vector2F, FormatError> {
+        let x = self.f32()?;
+        Ok(Vector2F::new(x, self.f32()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f32, y: f32) -> StrokePoint {
+        StrokePoint { position: Vector2F::new(x, y), pressure: 0.5, time: x * 0.01 }
+    }
+
+    /// A stroke with every style field away from its default.
+    fn freehand() -> Stroke {
+        let mut style = StrokeStyle::default();
+        style.line_cap = pf::LineCap::Square;
+        style.line_join = pf::LineJoin::Miter(4.0);
+        style.dynamics.pressure.exponent = 2.0;
+        style.stabilizer = StabilizerOptions { mode: StabilizerMode::PulledString, strength: 0.7 };
+        Stroke {
+            id: StrokeId(3),
+            style,
+            timestamp: 1_600_000_000_000,
+            points: vec![point(1.0, 2.0), point(3.0, 5.0), point(8.0, 13.0)],
+            shape: None,
+            origin: StrokeOrigin::EraserPiece,
+        }
+    }
+
+    fn shaped(shape: Shape) -> Stroke {
+        let mut stroke = freehand();
+        stroke.set_shape(shape);
+        stroke.origin = StrokeOrigin::ShapeTool;
+        stroke
+    }
+
+    fn segment(from: Vector2F, to: Vector2F) -> CubicSegment {
+        CubicSegment { from, ctrl0: from.lerp(to, 1.0 / 3.0), ctrl1: from.lerp(to, 2.0 / 3.0), to }
+    }
+
+    fn shapes() -> Vec<Shape> {
+        let center = Vector2F::new(50.0, 40.0);
+        let radii = Vector2F::new(20.0, 10.0);
+        let kinds = vec![
+            ShapeKind::Line {
+                from: Vector2F::zero(),
+                to: Vector2F::new(30.0, 10.0),
+                start_head: ArrowHead::Closed,
+                end_head: ArrowHead::Open,
+                head_size: 6.0,
+            },
+            ShapeKind::Arc { center, radius: 12.0, start_angle: 0.5, sweep_angle: -2.0 },
+            ShapeKind::Ellipse { center, radii },
+            ShapeKind::Rectangle { rect: RectF::new(center, radii), corner_radius: 3.0 },
+            ShapeKind::Polygon { center, radii, sides: 6 },
+            ShapeKind::Star { center, radii, inner_ratio: 0.4, points: 5 },
+            ShapeKind::Polyline { vertices: vec![Vector2F::zero(), center, radii], closed: true },
+            ShapeKind::Path {
+                contours: vec![
+                    ShapeContour {
+                        segments: vec![segment(Vector2F::zero(), center)],
+                        closed: false,
+                    },
+                    ShapeContour {
+                        segments: vec![segment(center, radii), segment(radii, center)],
+                        closed: true,
+                    },
+                ],
+            },
+        ];
+        kinds
+            .into_iter()
+            .map(|kind| {
+                let mut shape = Shape::new(kind);
+                shape.transform = pf::Transform2F::row_major(1.0, 0.5, -0.25, 2.0, 7.0, -3.0);
+                shape
+            })
+            .collect()
+    }
+
+    fn fills() -> Vec<Fill> {
+        let stops = vec![
+            GradientStop { offset: 0.0, color: pf::ColorU::new(255, 0, 0, 255) },
+            GradientStop { offset: 0.25, color: pf::ColorU::new(0, 255, 0, 128) },
+            GradientStop { offset: 1.0, color: pf::ColorU::new(0, 0, 255, 0) },
+        ];
+        let transform = pf::Transform2F::from_scale(2.0);
+        vec![
+            Fill { paint: Paint::Color(pf::ColorU::new(1, 2, 3, 4)), rule: pf::FillRule::Winding },
+            Fill {
+                paint: Paint::Gradient(Gradient {
+                    kind: GradientKind::Linear { from: Vector2F::zero(), to: Vector2F::splat(9.0) },
+                    stops: stops.clone(),
+                    transform,
+                }),
+                rule: pf::FillRule::EvenOdd,
+            },
+            Fill {
+                paint: Paint::Gradient(Gradient {
+                    kind: GradientKind::Radial {
+                        focus: Vector2F::splat(1.0),
+                        center: Vector2F::splat(2.0),
+                        radius: 5.0,
+                    },
+                    stops,
+                    transform,
+                }),
+                rule: pf::FillRule::Winding,
+            },
+        ]
+    }
+
+    fn sample_document() -> Document {
+        let mut document = Document::new();
+        let id = document.begin_stroke(StrokeStyle::default(), StrokeOrigin::Brush).unwrap();
+        document.push_point(id, point(1.0, 2.0));
+        document.push_point(id, point(4.0, 6.0));
+        let mut layer = document.new_layer();
+        layer.properties.name = String::from("Ink ✓");
+        layer.properties.opacity = 0.5;
+        layer.properties.blend_mode = BlendMode::Multiply;
+        layer.properties.locked = true;
+        layer.properties.visible = false;
+        for mut stroke in vec![freehand(), shaped(shapes().pop().unwrap())] {
+            stroke.id = document.allocate_stroke_id();
+            layer.strokes.push(stroke);
+        }
+        document.active_layer = layer.id;
+        document.insert_layer(1, layer);
+        document
+    }
+
+    fn write(document: &Document) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_document(document, &mut data).unwrap();
+        data
+    }
+
+    fn is_corrupt(result: Result<Document, FormatError>) -> bool {
+        match result {
+            Err(FormatError::Corrupt(_)) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn documents_round_trip() {
+        let document = sample_document();
+        let loaded = read_document(&write(&document)[..]).unwrap();
+        assert_eq!(loaded.layers(), document.layers());
+        assert_eq!(loaded.active_layer, document.active_layer);
+        assert_eq!(loaded.next_stroke_id(), document.next_stroke_id());
+        assert_eq!(loaded.next_layer_id(), document.next_layer_id());
+    }
+
+    #[test]
+    fn styles_round_trip() {
+        let mut stroke = freehand();
+        for mode in StabilizerMode::ALL.iter() {
+            stroke.style.stabilizer.mode = *mode;
+            assert_eq!(decode_stroke(&encode_stroke(&stroke)).unwrap(), stroke);
+        }
+    }
+
+    #[test]
+    fn every_shape_kind_and_fill_round_trips() {
+        for shape in shapes() {
+            let stroke = shaped(shape.clone());
+            assert_eq!(decode_stroke(&encode_stroke(&stroke)).unwrap(), stroke);
+            for fill in fills() {
+                let mut filled = shape.clone();
+                filled.fill = Some(fill);
+                filled.stroked = false;
+                let stroke = shaped(filled);
+                assert_eq!(decode_stroke(&encode_stroke(&stroke)).unwrap(), stroke);
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_without_fill_records_load_as_stroked() {
+        let mut shape = shapes().remove(2);
+        shape.stroked = false;
+        let stroke = shaped(shape);
+        let mut bare = stroke.clone();
+        bare.shape = None;
+        // Up to the shape length and the origin.
+        let prefix = encode_stroke(&bare).len() - 5;
+        let data = encode_stroke(&stroke);
+        // Drop the trailing `stroked` flag and the empty fill tag.
+        let shape_length = data.len() - prefix - 4 - 1 - 2;
+        let mut old = data[..prefix].to_vec();
+        old.extend_from_slice(&(shape_length as u32).to_le_bytes());
+        old.extend_from_slice(&data[prefix + 4..prefix + 4 + shape_length]);
+        let loaded = decode_stroke(&old).unwrap();
+        let loaded_shape = loaded.shape.unwrap();
+        assert_eq!(loaded_shape.kind, stroke.shape.as_ref().unwrap().kind);
+        assert!(loaded_shape.stroked);
+        assert!(loaded_shape.fill.is_none());
+    }
+
+    #[test]
+    fn strokes_without_shape_records_load_as_freehand() {
+        let stroke = Stroke { origin: StrokeOrigin::Brush, ..freehand() };
+        let data = encode_stroke(&stroke);
+        // The empty shape's length, then the origin.
+        assert_eq!(decode_stroke(&data[..data.len() - 5]).unwrap(), stroke);
+    }
+
+    #[test]
+    fn origins_round_trip_and_default_to_the_brush() {
+        for origin in StrokeOrigin::ALL.iter() {
+            let stroke = Stroke { origin: *origin, ..shaped(shapes().remove(1)) };
+            let mut data = encode_stroke(&stroke);
+            assert_eq!(decode_stroke(&data).unwrap(), stroke);
+            let brush = Stroke { origin: StrokeOrigin::Brush, ..stroke };
+            assert_eq!(decode_stroke(&data[..data.len() - 1]).unwrap(), brush);
+            *data.last_mut().unwrap() = 200;
+            assert_eq!(decode_stroke(&data).unwrap(), brush);
+        }
+    }
+
+    #[test]
+    fn unknown_shape_kinds_load_as_freehand() {
+        let stroke = shaped(shapes().remove(0));
+        let mut data = encode_stroke(&stroke);
+        let mut bare = stroke.clone();
+        bare.shape = None;
+        // The kind tag follows the shape length and the six-float transform.
+        let kind = encode_stroke(&bare).len() - 1 + 24;
+        data[kind] = 200;
+        assert_eq!(decode_stroke(&data).unwrap(), bare);
+    }
+
+    #[test]
+    fn styles_without_newer_fields_load_with_defaults() {
+        let stroke = freehand();
+        let data = encode_stroke(&stroke);
+        // The style length follows the ID and timestamp.
+        let style_length = u32::from_le_bytes(data[16..20].try_into().unwrap()) as usize;
+        let style = &data[20..20 + style_length];
+        let rest = &data[20 + style_length..];
+        let truncate = |length: usize| {
+            let mut old = data[..16].to_vec();
+            old.extend_from_slice(&(length as u32).to_le_bytes());
+            old.extend_from_slice(&style[..length]);
+            old.extend_from_slice(rest);
+            decode_stroke(&old).unwrap().style
+        };
+        // Five bytes of stabilizer, then 32 of dynamics.
+        let without_stabilizer = truncate(style_length - 5);
+        assert_eq!(without_stabilizer.stabilizer, StabilizerOptions::none());
+        assert_eq!(without_stabilizer.dynamics, stroke.style.dynamics);
+        let without_dynamics = truncate(style_length - 5 - 32);
+        assert_eq!(without_dynamics.dynamics, BrushDynamics::none());
+        assert_eq!(without_dynamics.line_join, stroke.style.line_join);
+    }
+
+    #[test]
+    fn unknown_sections_are_skipped() {
+        let document = sample_document();
+        let mut data = write(&document);
+        let mut extra = Vec::new();
+        write_section(&mut extra, *b"XTRA", &[1, 2, 3]).unwrap();
+        // After the magic, version and document section.
+        let at = MAGIC.len() + 2 + 8 + 24;
+        data.splice(at..at, extra);
+        assert_eq!(read_document(&data[..]).unwrap().layers(), document.layers());
+    }
+
+    #[test]
+    fn version_1_strokes_load_into_one_layer() {
+        let stroke = freehand();
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&1u16.to_le_bytes());
+        write_section(&mut data, DOCUMENT_TAG, &9u64.to_le_bytes()).unwrap();
+        write_section(&mut data, STROKE_TAG, &encode_stroke(&stroke)).unwrap();
+        let document = read_document(&data[..]).unwrap();
+        assert_eq!(document.layers().len(), 1);
+        assert_eq!(document.layers()[0].strokes, vec![stroke]);
+        assert_eq!(document.next_stroke_id(), 9);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let mut data = write(&sample_document());
+        data[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        match read_document(&data[..]) {
+            Err(FormatError::TooNew { version, supported }) => {
+                assert_eq!(version, VERSION + 1);
+                assert_eq!(supported, VERSION);
+            }
+            _ => panic!("expected TooNew"),
+        }
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        match read_document(&b"<svg></svg>"[..]) {
+            Err(FormatError::BadMagic) => {}
+            _ => panic!("expected BadMagic"),
+        }
+        assert!(read_document(&b""[..]).is_err());
+    }
+
+    #[test]
+    fn truncated_documents_are_corrupt() {
+        let data = write(&sample_document());
+        // Every cut inside a section; cuts between sections load fine.
+        for length in MAGIC.len() + 2..data.len() {
+            let result = read_document(&data[..length]);
+            assert!(result.is_ok() || is_corrupt(result), "cut at {}", length);
+        }
+        assert!(is_corrupt(read_document(&data[..data.len() - 1])));
+        assert!(is_corrupt(read_document(&data[..MAGIC.len() + 1])));
+    }
+
+    #[test]
+    fn corrupt_records_are_refused() {
+        let stroke = freehand();
+        let mut data = encode_stroke(&stroke);
+        // Line cap, after the color, width and opacity.
+        data[20 + 12] = 9;
+        assert!(decode_stroke(&data).is_err());
+
+        let mut data = encode_stroke(&stroke);
+        let style_length = u32::from_le_bytes(data[16..20].try_into().unwrap()) as usize;
+        let count = 20 + style_length;
+        data[count..count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode_stroke(&data).is_err());
+        data[count..count + 4].copy_from_slice(&3u32.to_le_bytes());
+        data[count + 4..count + 6].copy_from_slice(&4u16.to_le_bytes());
+        assert!(decode_stroke(&data).is_err());
+
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        write_section(&mut data, STROKE_TAG, &encode_stroke(&stroke)).unwrap();
+        assert!(is_corrupt(read_document(&data[..])));
+    }
+}
@@ -0,0 +1,748 @@
+//! Undoable edits to a `Document`.
+use std::collections::VecDeque;
+use std::mem;
+use std::time::{Duration, Instant};
+use crate::prelude::*;
+use super::document::{
+    Document, Layer, LayerId, LayerProperties, Stroke, StrokeId, StrokePoint, StrokeStyle,
+};
+use super::shape::{Shape, ShapeKind};
+
+/// Default upper bound on the memory retained by the undo and redo stacks.
+pub const DEFAULT_MEMORY_CAP: usize = 64 * 1024 * 1024;
+
+/// Repeated transforms, restyles or reshapes of the same strokes closer
+/// together than this collapse into one undo step, e.g. nudging a selection
+/// with the keys.
+const MERGE_INTERVAL: Duration = Duration::from_millis(750);
+
+///////////////////////////////////////////////////////////////////////////////
+// COMMANDS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    AddStroke {
+        layer: LayerId,
+        index: usize,
+        stroke: Stroke,
+    },
+    /// Removed strokes with their layer and the index each one had, in
+    /// paint order.
+    DeleteStrokes {
+        removed: Vec<(LayerId, usize, Stroke)>,
+    },
+    Transform {
+        ids: Vec<StrokeId>,
+        transform: pf::Transform2F,
+    },
+    Restyle {
+        ids: Vec<StrokeId>,
+        /// Parallel to `ids`.
+        before: Vec<StrokeStyle>,
+        after: StrokeStyle,
+    },
+    /// Changes the parameters of shape strokes.
+    Reshape {
+        ids: Vec<StrokeId>,
+        /// Parallel to `ids`.
+        before: Vec<Shape>,
+        after: Vec<Shape>,
+    },
+    AddLayer {
+        index: usize,
+        layer: Layer,
+    },
+    DeleteLayer {
+        index: usize,
+        layer: Layer,
+    },
+    MoveLayer {
+        id: LayerId,
+        from: usize,
+        to: usize,
+    },
+    SetLayerProperties {
+        id: LayerId,
+        before: LayerProperties,
+        after: LayerProperties,
+    },
+    /// Applied front to back, reverted back to front.
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    /// Captures the strokes `ids` refer to so that deleting them can be
+    /// reverted. Unknown IDs are ignored.
+    pub fn delete_strokes(document: &Document, ids: &[StrokeId]) -> Command {
+        let removed = document.layers()
+            .iter()
+            .flat_map(|layer| {
+                layer.strokes
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, stroke)| (layer.id, index, stroke))
+            })
+            .filter(|(_, _, stroke)| ids.contains(&stroke.id))
+            .map(|(layer, index, stroke)| (layer, index, stroke.clone()))
+            .collect();
+        Command::DeleteStrokes { removed }
+    }
+    pub fn restyle(document: &Document, ids: &[StrokeId], after: StrokeStyle) -> Command {
+        let ids: Vec<StrokeId> = ids
+            .iter()
+            .cloned()
+            .filter(|id| document.stroke(*id).is_some())
+            .collect();
+        let before = ids
+            .iter()
+            .map(|id| document.stroke(*id).unwrap().style)
+            .collect();
+        Command::Restyle { ids, before, after }
+    }
+    /// Edits the shapes of the strokes `ids` with `edit`, which returns
+    /// whether it changed anything. Freehand strokes are skipped. Nothing if
+    /// no shape changed.
+    pub fn reshape<F>(document: &Document, ids: &[StrokeId], mut edit: F) -> Option<Command>
+        where F: FnMut(&mut ShapeKind) -> bool {
+        let mut changed_ids = Vec::new();
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        for id in ids.iter() {
+            let shape = match document.stroke(*id).and_then(|stroke| stroke.shape.as_ref()) {
+                Some(shape) => shape,
+                None => continue,
+            };
+            let mut edited = shape.clone();
+            if edit(&mut edited.kind) {
+                changed_ids.push(*id);
+                before.push(shape.clone());
+                after.push(edited);
+            }
+        }
+        if changed_ids.is_empty() {
+            return None;
+        }
+        Some(Command::Reshape { ids: changed_ids, before, after })
+    }
+    /// Deleting the only layer is not allowed.
+    pub fn delete_layer(document: &Document, id: LayerId) -> Option<Command> {
+        if document.layers().len() < 2 {
+            return None;
+        }
+        let index = document.layer_index(id)?;
+        Some(Command::DeleteLayer { index, layer: document.layers()[index].clone() })
+    }
+    /// Inserts a copy of the layer right above it.
+    pub fn duplicate_layer(document: &mut Document, id: LayerId) -> Option<Command> {
+        let index = document.layer_index(id)?;
+        let layer = document.duplicate_layer(id)?;
+        Some(Command::AddLayer { index: index + 1, layer })
+    }
+    /// Moves the strokes of a layer onto the top of the layer below it and
+    /// deletes the layer. The layer's opacity is folded into each stroke's;
+    /// its blend mode is lost.
+    pub fn merge_layer_down(document: &Document, id: LayerId) -> Option<Command> {
+        let index = document.layer_index(id)?;
+        if index == 0 {
+            return None;
+        }
+        let upper = &document.layers()[index];
+        let lower = &document.layers()[index - 1];
+        let mut commands = vec![Command::DeleteLayer { index, layer: upper.clone() }];
+        for (offset, stroke) in upper.strokes.iter().enumerate() {
+            let mut stroke = stroke.clone();
+            stroke.style.opacity *= upper.properties.opacity;
+            commands.push(Command::AddStroke {
+                layer: lower.id,
+                index: lower.strokes.len() + offset,
+                stroke,
+            });
+        }
+        Some(Command::Batch(commands))
+    }
+    pub fn move_layer(document: &Document, id: LayerId, to: usize) -> Option<Command> {
+        let from = document.layer_index(id)?;
+        let to = to.min(document.layers().len() - 1);
+        if from == to {
+            return None;
+        }
+        Some(Command::MoveLayer { id, from, to })
+    }
+    pub fn set_layer_properties(
+        document: &Document,
+        id: LayerId,
+        after: LayerProperties,
+    ) -> Option<Command> {
+        let before = document.layer(id)?.properties.clone();
+        if before == after {
+            return None;
+        }
+        Some(Command::SetLayerProperties { id, before, after })
+    }
+    pub fn apply(&self, document: &mut Document) {
+        match self {
+            Command::AddStroke { layer, index, stroke } => {
+                document.insert_stroke(*layer, *index, stroke.clone());
+            }
+            Command::DeleteStrokes { removed } => {
+                for (_, _, stroke) in removed.iter() {
+                    document.remove_stroke(stroke.id);
+                }
+            }
+            Command::Transform { ids, transform } => {
+                transform_strokes(document, ids, transform);
+            }
+            Command::Restyle { ids, after, .. } => {
+                for id in ids.iter() {
+                    document.update_stroke(*id, |stroke| stroke.style = *after);
+                }
+            }
+            Command::Reshape { ids, after, .. } => {
+                set_shapes(document, ids, after);
+            }
+            Command::AddLayer { index, layer } => {
+                document.insert_layer(*index, layer.clone());
+            }
+            Command::DeleteLayer { layer, .. } => {
+                document.remove_layer(layer.id);
+            }
+            Command::MoveLayer { id, to, .. } => {
+                document.move_layer(*id, *to);
+            }
+            Command::SetLayerProperties { id, after, .. } => {
+                document.set_layer_properties(*id, after.clone());
+            }
+            Command::Batch(commands) => {
+                for command in commands.iter() {
+                    command.apply(document);
+                }
+            }
+        }
+    }
+    pub fn revert(&self, document: &mut Document) {
+        match self {
+            Command::AddStroke { stroke, .. } => {
+                document.remove_stroke(stroke.id);
+            }
+            Command::DeleteStrokes { removed } => {
+                for (layer, index, stroke) in removed.iter() {
+                    document.insert_stroke(*layer, *index, stroke.clone());
+                }
+            }
+            Command::Transform { ids, transform } => {
+                transform_strokes(document, ids, &transform.inverse());
+            }
+            Command::Restyle { ids, before, .. } => {
+                for (id, style) in ids.iter().zip(before.iter()) {
+                    document.update_stroke(*id, |stroke| stroke.style = *style);
+                }
+            }
+            Command::Reshape { ids, before, .. } => {
+                set_shapes(document, ids, before);
+            }
+            Command::AddLayer { layer, .. } => {
+                document.remove_layer(layer.id);
+            }
+            Command::DeleteLayer { index, layer } => {
+                document.insert_layer(*index, layer.clone());
+            }
+            Command::MoveLayer { id, from, .. } => {
+                document.move_layer(*id, *from);
+            }
+            Command::SetLayerProperties { id, before, .. } => {
+                document.set_layer_properties(*id, before.clone());
+            }
+            Command::Batch(commands) => {
+                for command in commands.iter().rev() {
+                    command.revert(document);
+                }
+            }
+        }
+    }
+    /// Folds `next`, which was applied right after `self`, into `self` when
+    /// the pair is equivalent to a single command. Gives `next` back
+    /// otherwise.
+    fn merge(&mut self, next: Command) -> Result<(), Command> {
+        match (self, next) {
+            (
+                Command::Transform { ids, transform },
+                Command::Transform { ids: next_ids, transform: next_transform },
+            ) if *ids == next_ids => {
+                *transform = next_transform * *transform;
+                Ok(())
+            }
+            (
+                Command::Restyle { ids, after, .. },
+                Command::Restyle { ids: next_ids, after: next_after, .. },
+            ) if *ids == next_ids => {
+                *after = next_after;
+                Ok(())
+            }
+            (
+                Command::Reshape { ids, after, .. },
+                Command::Reshape { ids: next_ids, after: next_after, .. },
+            ) if *ids == next_ids => {
+                *after = next_after;
+                Ok(())
+            }
+            (
+                Command::SetLayerProperties { id, after, .. },
+                Command::SetLayerProperties { id: next_id, after: next_after, .. },
+            ) if *id == next_id => {
+                *after = next_after;
+                Ok(())
+            }
+            (_, next) => Err(next),
+        }
+    }
+    /// Rough number of heap and inline bytes retained by this command.
+    fn memory_size(&self) -> usize {
+        let stroke_size = |stroke: &Stroke| {
+            mem::size_of::<Stroke>() + stroke.points.len() * mem::size_of::<StrokePoint>()
+        };
+        mem::size_of::<Command>() + match self {
+            Command::AddStroke { stroke, .. } => stroke_size(stroke),
+            Command::DeleteStrokes { removed } => {
+                removed.iter().map(|(_, _, stroke)| stroke_size(stroke)).sum()
+            }
+            Command::Transform { ids, .. } => ids.len() * mem::size_of::<StrokeId>(),
+            Command::Restyle { ids, .. } => {
+                ids.len() * (mem::size_of::<StrokeId>() + mem::size_of::<StrokeStyle>())
+            }
+            Command::Reshape { ids, .. } => {
+                ids.len() * (mem::size_of::<StrokeId>() + 2 * mem::size_of::<Shape>())
+            }
+            Command::AddLayer { layer, .. } | Command::DeleteLayer { layer, .. } => {
+                layer.properties.name.len() + layer.strokes.iter().map(stroke_size).sum::<usize>()
+            }
+            Command::MoveLayer { .. } => 0,
+            Command::SetLayerProperties { before, after, .. } => {
+                before.name.len() + after.name.len()
+            }
+            Command::Batch(commands) => {
+                commands.iter().map(Command::memory_size).sum()
+            }
+        }
+    }
+}
+
+fn transform_strokes(document: &mut Document, ids: &[StrokeId], transform: &pf::Transform2F) {
+    for id in ids.iter() {
+        document.update_stroke(*id, |stroke| {
+            for point in stroke.points.iter_mut() {
+                point.position = *transform * point.position;
+            }
+            if let Some(ref mut shape) = stroke.shape {
+                shape.apply_transform(transform);
+            }
+        });
+    }
+}
+
+fn set_shapes(document: &mut Document, ids: &[StrokeId], shapes: &[Shape]) {
+    for (id, shape) in ids.iter().zip(shapes.iter()) {
+        document.update_stroke(*id, |stroke| stroke.set_shape(shape.clone()));
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HISTORY
+///////////////////////////////////////////////////////////////////////////////
+
+struct Entry {
+    command: Command,
+    recorded: Instant,
+    memory_size: usize,
+    /// Later commands never merge into a sealed entry.
+    sealed: bool,
+}
+
+impl Entry {
+    fn new(command: Command) -> Entry {
+        let memory_size = command.memory_size();
+        Entry { command, recorded: Instant::now(), memory_size, sealed: false }
+    }
+    fn accepts_merge(&self) -> bool {
+        !self.sealed && self.recorded.elapsed() < MERGE_INTERVAL
+    }
+}
+
+pub struct History {
+    undo_stack: VecDeque<Entry>,
+    redo_stack: Vec<Entry>,
+    /// While set, new commands join the newest undo step.
+    group_open: bool,
+    memory_used: usize,
+    /// The oldest undo steps are dropped once the stacks retain more than
+    /// this many bytes. The newest step is always kept.
+    pub memory_cap: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(DEFAULT_MEMORY_CAP)
+    }
+}
+
+impl History {
+    pub fn new(memory_cap: usize) -> Self {
+        History {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            group_open: false,
+            memory_used: 0,
+            memory_cap,
+        }
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.group_open = false;
+        self.memory_used = 0;
+    }
+    /// Applies `command` to `document` and records it.
+    pub fn execute(&mut self, document: &mut Document, command: Command) {
+        command.apply(document);
+        self.record(command);
+    }
+    /// Records a command whose effect is already in the document, such as a
+    /// stroke that was drawn point by point.
+    pub fn record(&mut self, command: Command) {
+        self.memory_used -= self.redo_stack.iter().map(|entry| entry.memory_size).sum::<usize>();
+        self.redo_stack.clear();
+        let command = match self.undo_stack.back_mut() {
+            Some(last) if self.group_open => {
+                let previous = mem::replace(&mut last.command, Command::Batch(Vec::new()));
+                last.command = match previous {
+                    Command::Batch(mut commands) => {
+                        commands.push(command);
+                        Command::Batch(commands)
+                    }
+                    previous => Command::Batch(vec![previous, command]),
+                };
+                None
+            }
+            Some(last) if last.accepts_merge() => {
+                last.command.merge(command).err()
+            }
+            _ => Some(command),
+        };
+        match command {
+            Some(command) => {
+                let entry = Entry::new(command);
+                self.memory_used += entry.memory_size;
+                self.undo_stack.push_back(entry);
+            }
+            None => {
+                let last = self.undo_stack.back_mut().unwrap();
+                let memory_size = last.command.memory_size();
+                self.memory_used = self.memory_used - last.memory_size + memory_size;
+                last.memory_size = memory_size;
+                last.recorded = Instant::now();
+            }
+        }
+        self.enforce_memory_cap();
+    }
+    /// Makes every command recorded until `end_group` part of one undo step.
+    /// The group starts with the next recorded command.
+    pub fn begin_group(&mut self) {
+        self.end_group();
+        let entry = Entry::new(Command::Batch(Vec::new()));
+        self.memory_used += entry.memory_size;
+        self.undo_stack.push_back(entry);
+        self.group_open = true;
+    }
+    pub fn end_group(&mut self) {
+        if !self.group_open {
+            return;
+        }
+        self.group_open = false;
+        let last = self.undo_stack.back_mut().unwrap();
+        last.sealed = true;
+        // Drop groups nothing was recorded into.
+        let is_empty = match last.command {
+            Command::Batch(ref commands) => commands.is_empty(),
+            _ => false,
+        };
+        if is_empty {
+            let entry = self.undo_stack.pop_back().unwrap();
+            self.memory_used -= entry.memory_size;
+        }
+    }
+    /// Reverts every command recorded since `begin_group` and forgets them,
+    /// leaving the redo stack as it is.
+    pub fn cancel_group(&mut self, document: &mut Document) {
+        if !self.group_open {
+            return;
+        }
+        self.group_open = false;
+        let entry = self.undo_stack.pop_back().unwrap();
+        self.memory_used -= entry.memory_size;
+        entry.command.revert(document);
+    }
+    pub fn undo(&mut self, document: &mut Document) -> bool {
+        self.end_group();
+        match self.undo_stack.pop_back() {
+            Some(entry) => {
+                entry.command.revert(document);
+                self.redo_stack.push(entry);
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn redo(&mut self, document: &mut Document) -> bool {
+        self.end_group();
+        match self.redo_stack.pop() {
+            Some(mut entry) => {
+                entry.command.apply(document);
+                entry.sealed = true;
+                self.undo_stack.push_back(entry);
+                true
+            }
+            None => false,
+        }
+    }
+    fn enforce_memory_cap(&mut self) {
+        while self.memory_used > self.memory_cap && self.undo_stack.len() > 1 {
+            let entry = self.undo_stack.pop_front().unwrap();
+            self.memory_used -= entry.memory_size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::document::StrokeOrigin;
+
+    /// Two layers: a freehand stroke and a rectangle below, and a freehand
+    /// stroke above. Returns the strokes in that order.
+    fn document() -> (Document, [StrokeId; 3]) {
+        let mut document = Document::new();
+        let lower = draw(&mut document, 0.0);
+        let rectangle = draw(&mut document, 100.0);
+        let kind = ShapeKind::Rectangle {
+            rect: RectF::new(Vector2F::splat(100.0), Vector2F::splat(50.0)),
+            corner_radius: 0.0,
+        };
+        document.update_stroke(rectangle, |stroke| stroke.set_shape(Shape::new(kind)));
+        let layer = document.new_layer();
+        document.active_layer = layer.id;
+        document.insert_layer(1, layer);
+        let upper = draw(&mut document, 200.0);
+        (document, [lower, rectangle, upper])
+    }
+
+    /// A short horizontal stroke at height `y` on the active layer.
+    fn draw(document: &mut Document, y: f32) -> StrokeId {
+        let id = document.begin_stroke(StrokeStyle::default(), StrokeOrigin::Brush).unwrap();
+        for index in 0..4 {
+            let position = Vector2F::new(index as f32 * 10.0, y);
+            document.push_point(id, StrokePoint { position, pressure: 1.0, time: 0.1 });
+        }
+        id
+    }
+
+    fn position(document: &Document, id: StrokeId) -> Vector2F {
+        document.stroke(id).unwrap().points[0].position
+    }
+
+    /// Moves the strokes `ids` right by `x`.
+    fn nudge(ids: &[StrokeId], x: f32) -> Command {
+        let transform = pf::Transform2F::from_translation(Vector2F::new(x, 0.0));
+        Command::Transform { ids: ids.to_vec(), transform }
+    }
+
+    #[test]
+    fn commands_revert_what_they_apply() {
+        let (mut document, [lower, rectangle, upper]) = document();
+        let lower_layer = document.layers()[0].id;
+        let upper_layer = document.layers()[1].id;
+        let mut copy = document.stroke(lower).unwrap().clone();
+        copy.id = document.allocate_stroke_id();
+        let thin = StrokeStyle { width: 1.0, ..StrokeStyle::default() };
+        let mut faded = document.layers()[1].properties.clone();
+        faded.opacity = 0.5;
+        let round = |kind: &mut ShapeKind| match kind {
+            ShapeKind::Rectangle { corner_radius, .. } => {
+                *corner_radius = 10.0;
+                true
+            }
+            _ => false,
+        };
+        let new_layer = document.new_layer();
+        let commands = vec![
+            Command::AddStroke { layer: lower_layer, index: 1, stroke: copy },
+            Command::delete_strokes(&document, &[lower, upper]),
+            nudge(&[lower, upper], 10.0),
+            Command::restyle(&document, &[rectangle, upper], thin),
+            Command::reshape(&document, &[lower, rectangle], round).unwrap(),
+            Command::AddLayer { index: 1, layer: new_layer },
+            Command::delete_layer(&document, upper_layer).unwrap(),
+            Command::move_layer(&document, upper_layer, 0).unwrap(),
+            Command::set_layer_properties(&document, upper_layer, faded).unwrap(),
+            Command::Batch(vec![
+                Command::delete_strokes(&document, &[rectangle]),
+                Command::move_layer(&document, lower_layer, 1).unwrap(),
+            ]),
+        ];
+        for command in commands {
+            let before = document.layers().to_vec();
+            command.apply(&mut document);
+            assert_ne!(document.layers(), &before[..], "{:?} changed nothing", command);
+            command.revert(&mut document);
+            assert_eq!(document.layers(), &before[..], "{:?} was not reverted", command);
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_step_through_commands() {
+        let (mut document, [lower, _, upper]) = document();
+        let mut history = History::default();
+        let command = Command::delete_strokes(&document, &[lower]);
+        history.execute(&mut document, command);
+        let command = Command::delete_strokes(&document, &[upper]);
+        history.execute(&mut document, command);
+        assert!(history.undo(&mut document));
+        assert!(document.stroke(upper).is_some() && document.stroke(lower).is_none());
+        assert!(history.undo(&mut document));
+        assert!(document.stroke(lower).is_some());
+        assert!(!history.undo(&mut document));
+        assert!(history.redo(&mut document));
+        assert!(document.stroke(lower).is_none() && document.stroke(upper).is_some());
+    }
+
+    #[test]
+    fn quick_repeats_merge_into_one_step() {
+        let (mut document, [lower, _, upper]) = document();
+        let mut history = History::default();
+        history.execute(&mut document, nudge(&[lower], 10.0));
+        history.execute(&mut document, nudge(&[lower], 5.0));
+        assert_eq!(position(&document, lower), Vector2F::new(15.0, 0.0));
+        // Different strokes start a new step.
+        history.execute(&mut document, nudge(&[upper], 5.0));
+        assert!(history.undo(&mut document));
+        assert!(history.undo(&mut document));
+        assert_eq!(position(&document, lower), Vector2F::zero());
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn slow_repeats_stay_separate_steps() {
+        let (mut document, [lower, _, _]) = document();
+        let mut history = History::default();
+        history.execute(&mut document, nudge(&[lower], 10.0));
+        let last = history.undo_stack.back_mut().unwrap();
+        last.recorded = last.recorded.checked_sub(MERGE_INTERVAL).unwrap();
+        history.execute(&mut document, nudge(&[lower], 5.0));
+        assert!(history.undo(&mut document));
+        assert_eq!(position(&document, lower), Vector2F::new(10.0, 0.0));
+        assert!(history.undo(&mut document));
+        assert_eq!(position(&document, lower), Vector2F::zero());
+    }
+
+    #[test]
+    fn redone_steps_take_no_merges() {
+        let (mut document, [lower, _, _]) = document();
+        let mut history = History::default();
+        history.execute(&mut document, nudge(&[lower], 10.0));
+        history.undo(&mut document);
+        history.redo(&mut document);
+        history.execute(&mut document, nudge(&[lower], 5.0));
+        assert!(history.undo(&mut document));
+        assert_eq!(position(&document, lower), Vector2F::new(10.0, 0.0));
+    }
+
+    #[test]
+    fn groups_undo_as_one_step() {
+        let (mut document, [lower, rectangle, upper]) = document();
+        let before = document.layers().to_vec();
+        let mut history = History::default();
+        history.begin_group();
+        history.end_group();
+        assert!(!history.can_undo(), "empty groups are dropped");
+        history.begin_group();
+        let command = Command::delete_strokes(&document, &[rectangle]);
+        history.execute(&mut document, command);
+        history.execute(&mut document, nudge(&[lower], 10.0));
+        let command = Command::delete_strokes(&document, &[upper]);
+        history.execute(&mut document, command);
+        history.end_group();
+        // Sealed: a repeat right after does not join the group.
+        history.execute(&mut document, nudge(&[lower], 10.0));
+        assert!(history.undo(&mut document));
+        assert!(history.undo(&mut document));
+        assert_eq!(document.layers(), &before[..]);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn cancelled_groups_revert_and_keep_redo() {
+        let (mut document, [lower, _, upper]) = document();
+        let mut history = History::default();
+        history.execute(&mut document, nudge(&[lower], 10.0));
+        history.undo(&mut document);
+        history.begin_group();
+        history.cancel_group(&mut document);
+        assert!(history.can_redo());
+        assert!(!history.can_undo());
+
+        history.execute(&mut document, nudge(&[upper], 10.0));
+        let before = document.layers().to_vec();
+        history.begin_group();
+        let command = Command::delete_strokes(&document, &[lower]);
+        history.execute(&mut document, command);
+        history.execute(&mut document, nudge(&[upper], 10.0));
+        history.cancel_group(&mut document);
+        assert_eq!(document.layers(), &before[..]);
+        // Only the step before the group is left.
+        assert!(history.undo(&mut document));
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn new_edits_clear_redo() {
+        let (mut document, [lower, _, upper]) = document();
+        let mut history = History::default();
+        history.execute(&mut document, nudge(&[lower], 10.0));
+        history.undo(&mut document);
+        assert!(history.can_redo());
+        history.execute(&mut document, nudge(&[upper], 10.0));
+        assert!(!history.can_redo());
+        assert!(!history.redo(&mut document));
+        assert_eq!(position(&document, lower), Vector2F::zero());
+    }
+
+    #[test]
+    fn memory_cap_drops_the_oldest_steps() {
+        let (mut document, ids) = document();
+        let step = Command::restyle(&document, &ids[..1], StrokeStyle::default());
+        let mut history = History::new(step.memory_size() * 2);
+        for (index, id) in ids.iter().enumerate() {
+            let style = StrokeStyle { width: index as f32 + 1.0, ..StrokeStyle::default() };
+            let command = Command::restyle(&document, &[*id], style);
+            history.execute(&mut document, command);
+        }
+        assert!(history.undo(&mut document));
+        assert!(history.undo(&mut document));
+        assert!(!history.undo(&mut document));
+        let width = |id| document.stroke(id).unwrap().style.width;
+        assert_eq!(width(ids[0]), 1.0, "the oldest step can no longer be undone");
+        assert_eq!(width(ids[1]), StrokeStyle::default().width);
+        // The newest step stays whatever its size.
+        let mut history = History::new(0);
+        history.execute(&mut document, nudge(&ids, 10.0));
+        let command = Command::delete_strokes(&document, &ids);
+        history.execute(&mut document, command);
+        assert!(history.undo(&mut document));
+        assert!(!history.can_undo());
+    }
+}
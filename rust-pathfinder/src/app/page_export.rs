@@ -0,0 +1,473 @@
+//! Lays documents out on pages and exports them as PDF or PostScript
+//! through `pathfinder_export`.
+//!
+//! Pages are measured in points, 1/72 inch, and document units are CSS
+//! pixels, 1/96 inch. Documents have no artboards, so a page holds either
+//! every visible layer or a single one. `pathfinder_export` writes one page
+//! per file, so several pages are written as numbered files.
+//!
+//! The app takes its page options from a file next to the document, e.g.
+//! `drawing.pages` for `drawing.alphadraw`, holding the same settings as
+//! the flags of `alphadraw-render`:
+//!
+//! ```text
+//! page letter
+//! orientation landscape
+//! margin 18
+//! per-layer
+//! ```
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use crate::prelude::*;
+use crate::prelude::pf::Export;
+use super::document::Document;
+use super::fitting::FitOptions;
+use super::scene::{self, StrokePaths};
+
+pub const POINTS_PER_UNIT: f32 = 72.0 / 96.0;
+
+/// Of page options files. See `PageOptions::parse`.
+pub static OPTIONS_EXTENSION: &'static str = "pages";
+
+/// 210 by 297 millimeters.
+const A4_SIZE: (f32, f32) = (595.276, 841.89);
+/// 8.5 by 11 inches.
+const LETTER_SIZE: (f32, f32) = (612.0, 792.0);
+
+/// Half an inch.
+const DEFAULT_MARGIN: f32 = 36.0;
+
+///////////////////////////////////////////////////////////////////////////////
+// OPTIONS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PageSize {
+    A4,
+    Letter,
+    /// Width and height in points.
+    Custom(Vector2F),
+}
+
+impl PageSize {
+    /// `a4`, `letter`, or `WIDTHxHEIGHT` in points.
+    pub fn from_name(name: &str) -> Option<PageSize> {
+        let name = name.to_lowercase();
+        match name.as_str() {
+            "a4" => return Some(PageSize::A4),
+            "letter" => return Some(PageSize::Letter),
+            _ => {}
+        }
+        let mut parts = name.splitn(2, 'x');
+        let width = parts.next()?.trim().parse::<f32>().ok()?;
+        let height = parts.next()?.trim().parse::<f32>().ok()?;
+        if width > 0.0 && height > 0.0 {
+            Some(PageSize::Custom(Vector2F::new(width, height)))
+        } else {
+            None
+        }
+    }
+    /// In points, as given for custom sizes and upright otherwise.
+    pub fn size(self) -> Vector2F {
+        match self {
+            PageSize::A4 => Vector2F::new(A4_SIZE.0, A4_SIZE.1),
+            PageSize::Letter => Vector2F::new(LETTER_SIZE.0, LETTER_SIZE.1),
+            PageSize::Custom(size) => size,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+impl Orientation {
+    /// `portrait` or `landscape`.
+    pub fn from_name(name: &str) -> Option<Orientation> {
+        match name.to_lowercase().as_str() {
+            "portrait" => Some(Orientation::Portrait),
+            "landscape" => Some(Orientation::Landscape),
+            _ => None,
+        }
+    }
+}
+
+/// How content is placed in the area inside the margins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// Scaled to fill the area and centered in it.
+    Fit,
+    /// At actual size from the top left of the area. What does not fit is
+    /// cut off at the page edges.
+    Crop,
+}
+
+impl Placement {
+    /// `fit` or `crop`.
+    pub fn from_name(name: &str) -> Option<Placement> {
+        match name.to_lowercase().as_str() {
+            "fit" => Some(Placement::Fit),
+            "crop" => Some(Placement::Crop),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pagination {
+    /// Every visible layer on one page.
+    Document,
+    /// Each visible layer with strokes on a page of its own, bottom first.
+    PerLayer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageOptions {
+    pub size: PageSize,
+    pub orientation: Orientation,
+    pub placement: Placement,
+    /// In points, on every side.
+    pub margin: f32,
+    pub pagination: Pagination,
+}
+
+impl PageOptions {
+    /// In points, turned to the orientation.
+    pub fn page_size(&self) -> Vector2F {
+        let size = self.size.size();
+        let (short, long) = (size.x().min(size.y()), size.x().max(size.y()));
+        match self.orientation {
+            Orientation::Portrait => Vector2F::new(short, long),
+            Orientation::Landscape => Vector2F::new(long, short),
+        }
+    }
+    /// The area inside the margins, or the whole page if they leave none.
+    pub fn content_area(&self) -> RectF {
+        let page = RectF::new(Vector2F::zero(), self.page_size());
+        let area = page.contract(Vector2F::splat(self.margin.max(0.0)));
+        if area.width() > 0.0 && area.height() > 0.0 { area } else { page }
+    }
+    /// Changes the setting `name` as the `alphadraw-render` flag of that
+    /// name does: `page`, `orientation`, `placement`, `margin`, or
+    /// `per-layer`, which takes no value.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        let value = match (name, value) {
+            ("per-layer", None) => {
+                self.pagination = Pagination::PerLayer;
+                return Ok(());
+            }
+            ("per-layer", Some(_)) => return Err(String::from("\"per-layer\" takes no value")),
+            (_, Some(value)) => value,
+            (_, None) => return Err(format!("\"{}\" needs a value", name)),
+        };
+        match name {
+            "page" => {
+                self.size = PageSize::from_name(value).ok_or_else(|| {
+                    format!("Invalid page \"{}\", expected a4, letter or WIDTHxHEIGHT", value)
+                })?;
+            }
+            "orientation" => {
+                self.orientation = Orientation::from_name(value).ok_or_else(|| {
+                    format!("Invalid orientation \"{}\", expected portrait or landscape", value)
+                })?;
+            }
+            "placement" => {
+                self.placement = Placement::from_name(value).ok_or_else(|| {
+                    format!("Invalid placement \"{}\", expected fit or crop", value)
+                })?;
+            }
+            "margin" => {
+                self.margin = match value.parse::<f32>() {
+                    Ok(margin) if margin >= 0.0 => margin,
+                    _ => return Err(format!("Invalid margin \"{}\"", value)),
+                };
+            }
+            _ => return Err(format!("Unknown page option \"{}\"", name)),
+        }
+        Ok(())
+    }
+    /// The options of a page options file: one setting per line, named as
+    /// for `set` and followed by its value, over the defaults. Blank lines
+    /// and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<PageOptions, String> {
+        let mut options = PageOptions::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let name = parts.next().unwrap();
+            let value = parts.next().map(str::trim);
+            options.set(name, value).map_err(|error| format!("line {}: {}", index + 1, error))?;
+        }
+        Ok(options)
+    }
+    pub fn load(path: &Path) -> Result<PageOptions, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        PageOptions::parse(&text)
+    }
+}
+
+impl Default for PageOptions {
+    fn default() -> Self {
+        PageOptions {
+            size: PageSize::A4,
+            orientation: Orientation::Portrait,
+            placement: Placement::Fit,
+            margin: DEFAULT_MARGIN,
+            pagination: Pagination::Document,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageFormat {
+    Pdf,
+    PostScript,
+}
+
+impl PageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            PageFormat::Pdf => "pdf",
+            PageFormat::PostScript => "ps",
+        }
+    }
+    pub fn file_format(self) -> pf::FileFormat {
+        match self {
+            PageFormat::Pdf => pf::FileFormat::PDF,
+            PageFormat::PostScript => pf::FileFormat::PS,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// LAYOUT
+///////////////////////////////////////////////////////////////////////////////
+
+/// A scene the size of the page, in points, for each page of `document`.
+/// Empty if no visible layer has strokes.
+pub fn layout_pages(
+    document: &Document,
+    fit_options: &FitOptions,
+    options: &PageOptions,
+) -> Vec<pf::Scene> {
+    let font_context = pf::CanvasFontContext::from_system_source();
+    let visible_layers = document.layers().iter().filter(|layer| layer.properties.visible);
+    match options.pagination {
+        Pagination::Document => {
+            layout_page(document, fit_options, options, &font_context).into_iter().collect()
+        }
+        Pagination::PerLayer => visible_layers
+            .filter_map(|layer| {
+                let page_document = Document::from_layers(vec![layer.clone()], layer.id, 0, 0);
+                layout_page(&page_document, fit_options, options, &font_context)
+            })
+            .collect(),
+    }
+}
+
+fn layout_page(
+    document: &Document,
+    fit_options: &FitOptions,
+    options: &PageOptions,
+    font_context: &pf::CanvasFontContext,
+) -> Option<pf::Scene> {
+    let bounds = document.layers()
+        .iter()
+        .filter(|layer| layer.properties.visible)
+        .flat_map(|layer| layer.strokes.iter())
+        .filter_map(|stroke| stroke.ink_bounds())
+        .fold(None, |all: Option<RectF>, bounds| match all {
+            Some(all) => Some(all.union_rect(bounds)),
+            None => Some(bounds),
+        })?;
+    let transform = page_transform(bounds, options);
+    let canvas = pf::Canvas::new(options.page_size());
+    let mut ctx = canvas.get_context_2d(font_context.clone());
+    ctx.set_transform(&transform);
+    let ctx = scene::draw_document(
+        ctx,
+        document,
+        fit_options,
+        None,
+        &mut StrokePaths::default(),
+    );
+    Some(ctx.into_canvas().into_scene())
+}
+
+/// Maps content with `bounds`, in document units, onto the page, in
+/// points.
+fn page_transform(bounds: RectF, options: &PageOptions) -> pf::Transform2F {
+    let area = options.content_area();
+    match options.placement {
+        Placement::Fit => {
+            let scale = (area.width() / bounds.width()).min(area.height() / bounds.height());
+            pf::Transform2F::from_translation(area.center() - bounds.center() * scale) *
+                pf::Transform2F::from_scale(scale)
+        }
+        Placement::Crop => {
+            pf::Transform2F::from_translation(area.origin() - bounds.origin() * POINTS_PER_UNIT) *
+                pf::Transform2F::from_scale(POINTS_PER_UNIT)
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// EXPORT
+///////////////////////////////////////////////////////////////////////////////
+
+/// Writes the pages of `document` to `path`, or to `path` numbered from 1,
+/// e.g. `drawing-1.pdf`, if there are several. Returns the files written.
+pub fn export(
+    document: &Document,
+    fit_options: &FitOptions,
+    options: &PageOptions,
+    format: PageFormat,
+    path: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    let pages = layout_pages(document, fit_options, options);
+    if pages.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no visible strokes to export"));
+    }
+    let page_count = pages.len();
+    let mut paths = Vec::with_capacity(page_count);
+    for (index, page) in pages.into_iter().enumerate() {
+        let page_path = if page_count == 1 {
+            path.to_path_buf()
+        } else {
+            numbered_path(path, index + 1)
+        };
+        let mut writer = BufWriter::new(File::create(&page_path)?);
+        page.export(&mut writer, format.file_format())?;
+        paths.push(page_path);
+    }
+    Ok(paths)
+}
+
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}-{}", stem, number);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vector2F, b: Vector2F) {
+        assert!((a - b).length() < 1.0e-3, "{:?} != {:?}", a, b);
+    }
+
+    fn options(size: PageSize, orientation: Orientation, margin: f32) -> PageOptions {
+        PageOptions { size, orientation, margin, ..PageOptions::default() }
+    }
+
+    #[test]
+    fn page_sizes_parse_by_name() {
+        assert_eq!(PageSize::from_name("a4"), Some(PageSize::A4));
+        assert_eq!(PageSize::from_name("Letter"), Some(PageSize::Letter));
+        let custom = PageSize::Custom(Vector2F::new(200.0, 300.5));
+        assert_eq!(PageSize::from_name("200x300.5"), Some(custom));
+        assert_eq!(PageSize::from_name(" 200 X 300.5 "), Some(custom));
+        for name in ["", "a5", "200", "200x", "x300", "0x300", "200x-1", "wxh"].iter() {
+            assert_eq!(PageSize::from_name(name), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn page_sizes_turn_to_the_orientation() {
+        let a4 = options(PageSize::A4, Orientation::Portrait, 0.0);
+        assert_eq!(a4.page_size(), Vector2F::new(A4_SIZE.0, A4_SIZE.1));
+        let letter = options(PageSize::Letter, Orientation::Landscape, 0.0);
+        assert_eq!(letter.page_size(), Vector2F::new(LETTER_SIZE.1, LETTER_SIZE.0));
+        let wide = PageSize::Custom(Vector2F::new(800.0, 600.0));
+        assert_eq!(wide.size(), Vector2F::new(800.0, 600.0));
+        let portrait = options(wide, Orientation::Portrait, 0.0);
+        assert_eq!(portrait.page_size(), Vector2F::new(600.0, 800.0));
+        let landscape = options(wide, Orientation::Landscape, 0.0);
+        assert_eq!(landscape.page_size(), Vector2F::new(800.0, 600.0));
+    }
+
+    #[test]
+    fn content_areas_leave_the_margins() {
+        let size = PageSize::Custom(Vector2F::new(400.0, 600.0));
+        let area = options(size, Orientation::Portrait, 50.0).content_area();
+        assert_eq!(area, RectF::new(Vector2F::splat(50.0), Vector2F::new(300.0, 500.0)));
+        let whole_page = RectF::new(Vector2F::zero(), Vector2F::new(400.0, 600.0));
+        assert_eq!(options(size, Orientation::Portrait, 200.0).content_area(), whole_page);
+        assert_eq!(options(size, Orientation::Portrait, -10.0).content_area(), whole_page);
+    }
+
+    #[test]
+    fn fitting_fills_and_centers_the_area() {
+        let size = PageSize::Custom(Vector2F::new(400.0, 600.0));
+        let options = options(size, Orientation::Portrait, 50.0);
+        let area = options.content_area();
+        let bounds = RectF::new(Vector2F::new(-100.0, 20.0), Vector2F::new(600.0, 200.0));
+        let transform = page_transform(bounds, &options);
+        // Wide content is limited by the area's width.
+        assert_close(transform * bounds.center(), area.center());
+        assert_close(transform * bounds.origin(), Vector2F::new(50.0, 250.0));
+        assert_close(transform * bounds.lower_right(), Vector2F::new(350.0, 350.0));
+    }
+
+    #[test]
+    fn cropping_keeps_the_size_from_the_top_left() {
+        let size = PageSize::Custom(Vector2F::new(400.0, 600.0));
+        let options = PageOptions {
+            placement: Placement::Crop,
+            ..options(size, Orientation::Portrait, 50.0)
+        };
+        let bounds = RectF::new(Vector2F::new(-100.0, 20.0), Vector2F::new(960.0, 96.0));
+        let transform = page_transform(bounds, &options);
+        assert_close(transform * bounds.origin(), Vector2F::splat(50.0));
+        assert_close(transform * bounds.lower_right(), Vector2F::new(770.0, 122.0));
+    }
+
+    #[test]
+    fn settings_follow_the_command_line_flags() {
+        let text = "# For the wall\n\npage 200x300\norientation Landscape\nplacement crop\n\
+                    margin 18\nper-layer\n";
+        let options = PageOptions::parse(text).unwrap();
+        assert_eq!(options, PageOptions {
+            size: PageSize::Custom(Vector2F::new(200.0, 300.0)),
+            orientation: Orientation::Landscape,
+            placement: Placement::Crop,
+            margin: 18.0,
+            pagination: Pagination::PerLayer,
+        });
+        assert_eq!(PageOptions::parse("").unwrap(), PageOptions::default());
+        let invalid = [
+            "page a5",
+            "page",
+            "orientation sideways",
+            "placement stretch",
+            "margin -1",
+            "margin wide",
+            "per-layer yes",
+            "paper a4",
+        ];
+        for text in invalid.iter() {
+            assert!(PageOptions::parse(text).is_err(), "{:?}", text);
+        }
+        let error = PageOptions::parse("page a4\nmargin x").unwrap_err();
+        assert_eq!(error, "line 2: Invalid margin \"x\"");
+    }
+
+    #[test]
+    fn pages_are_numbered_before_the_extension() {
+        let path = Path::new("out/drawing.pdf");
+        assert_eq!(numbered_path(path, 2), Path::new("out/drawing-2.pdf"));
+        assert_eq!(numbered_path(Path::new("drawing"), 1), Path::new("drawing-1"));
+        assert_eq!(numbered_path(Path::new("a.tar.ps"), 10), Path::new("a.tar-10.ps"));
+    }
+}
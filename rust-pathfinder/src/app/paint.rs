@@ -0,0 +1,40 @@
+//! How the inside of shapes is painted. Shapes drawn in the app are only
+//! outlined; fills come with imported artwork.
+use crate::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GradientStop {
+    /// In the range `0.0..=1.0`.
+    pub offset: f32,
+    pub color: pf::ColorU,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GradientKind {
+    /// Along the line from `from` to `to`.
+    Linear { from: Vector2F, to: Vector2F },
+    /// Outward from `focus`, reaching the last stop on the circle of
+    /// `radius` around `center`.
+    Radial { focus: Vector2F, center: Vector2F, radius: f32 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    /// In order of offset.
+    pub stops: Vec<GradientStop>,
+    /// Maps the gradient's coordinates to the shape's local coordinates.
+    pub transform: pf::Transform2F,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Paint {
+    Color(pf::ColorU),
+    Gradient(Gradient),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub paint: Paint,
+    pub rule: pf::FillRule,
+}
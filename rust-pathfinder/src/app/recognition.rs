@@ -0,0 +1,648 @@
+//! Recognizes freehand strokes as clean shapes: lines, arrows, arcs,
+//! ellipses, rectangles and triangles.
+//!
+//! Tolerances scale with the size of the stroke, so a small sloppy circle
+//! and a large one are judged alike. Every candidate is checked against the
+//! input before being accepted; anything else is left as drawn.
+use std::f32::consts::PI;
+use crate::prelude::*;
+use super::shape::{ArrowHead, Shape, ShapeKind};
+
+/// Strokes are resampled to this many points, evenly spaced along their
+/// length, so that slow and fast parts weigh the same.
+const SAMPLE_COUNT: usize = 64;
+
+/// Strokes shorter than this, in document units, are left alone.
+const MIN_LENGTH: f32 = 16.0;
+
+/// Largest distance between the stroke and its shape, as a fraction of the
+/// diagonal of the stroke's bounds, and its floor in document units.
+const RELATIVE_TOLERANCE: f32 = 0.06;
+const MIN_TOLERANCE: f32 = 2.0;
+
+/// A stroke is closed if its ends are this close, as a fraction of its
+/// length.
+const CLOSURE_GAP: f32 = 0.15;
+
+/// Rectangle corners may be off a right angle by this much.
+const MAX_CORNER_ERROR: f32 = PI / 9.0;
+
+/// Rotations this close to the axes are snapped to them.
+const ANGLE_SNAP: f32 = PI / 36.0;
+
+/// Ellipses whose radii differ by less than this fraction become circles.
+const CIRCLE_SNAP: f32 = 0.1;
+
+/// Shorter arcs are more likely a wobbly line.
+const MIN_ARC_SWEEP: f32 = PI / 6.0;
+
+/// The head of an arrow is at most this long, as a fraction of its shaft.
+const MAX_HEAD_RATIO: f32 = 0.5;
+
+const EPSILON: f32 = 1.0e-6;
+
+/// The shape that `points` were meant to be, if any.
+pub fn recognize(points: &[Vector2F]) -> Option<Shape> {
+    let length = path_length(points);
+    if length < MIN_LENGTH {
+        return None;
+    }
+    let samples = resample(points, SAMPLE_COUNT);
+    let bounds = samples[1..]
+        .iter()
+        .fold(RectF::from_points(samples[0], samples[0]), |bounds, point| bounds.union_point(*point));
+    let tolerance = (bounds.size().length() * RELATIVE_TOLERANCE).max(MIN_TOLERANCE);
+    let gap = (samples[samples.len() - 1] - samples[0]).length();
+    if gap <= length * CLOSURE_GAP {
+        let corners = closed_corners(&samples, tolerance);
+        let polygon = match corners.len() {
+            3 => recognize_triangle(&corners, &samples, tolerance),
+            4 => recognize_rectangle(&corners, &samples, tolerance),
+            _ => None,
+        };
+        polygon.or_else(|| recognize_ellipse(&samples, tolerance))
+    } else {
+        recognize_line(&samples, tolerance)
+            .or_else(|| recognize_arrow(&samples, tolerance))
+            .or_else(|| recognize_arc(&samples, tolerance))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// OPEN SHAPES
+///////////////////////////////////////////////////////////////////////////////
+
+fn recognize_line(samples: &[Vector2F], tolerance: f32) -> Option<Shape> {
+    let (from, to) = (samples[0], samples[samples.len() - 1]);
+    if (to - from).length() < tolerance {
+        return None;
+    }
+    if samples.iter().any(|point| distance_to_segment(*point, from, to) > tolerance) {
+        return None;
+    }
+    Some(Shape::new(ShapeKind::Line {
+        from,
+        to,
+        start_head: ArrowHead::None,
+        end_head: ArrowHead::None,
+        head_size: 0.0,
+    }))
+}
+
+/// A straight shaft followed by a head drawn in one go: barbs on both sides
+/// of the tip, pointing back along the shaft.
+fn recognize_arrow(samples: &[Vector2F], tolerance: f32) -> Option<Shape> {
+    let vertices = simplify(samples, tolerance);
+    if vertices.len() < 3 {
+        return None;
+    }
+    let (from, tip) = (samples[0], samples[vertices[1]]);
+    let shaft = tip - from;
+    let shaft_length = shaft.length();
+    let direction = shaft.normalize();
+    let head = &samples[vertices[1]..];
+    let head_size = head.iter().map(|point| (*point - tip).length()).fold(0.0, f32::max);
+    if head_size < tolerance || head_size > shaft_length * MAX_HEAD_RATIO {
+        return None;
+    }
+    let mut left = false;
+    let mut right = false;
+    for point in head.iter() {
+        let offset = *point - tip;
+        if offset.dot(direction) > tolerance {
+            // Past the tip.
+            return None;
+        }
+        let side = direction.det(offset);
+        left = left || side > tolerance;
+        right = right || side < -tolerance;
+    }
+    if !(left && right) {
+        return None;
+    }
+    Some(Shape::new(ShapeKind::Line {
+        from,
+        to: tip,
+        start_head: ArrowHead::None,
+        end_head: ArrowHead::Open,
+        head_size,
+    }))
+}
+
+fn recognize_arc(samples: &[Vector2F], tolerance: f32) -> Option<Shape> {
+    let (center, radius) = fit_circle(samples)?;
+    if samples.iter().any(|point| ((*point - center).length() - radius).abs() > tolerance) {
+        return None;
+    }
+    let angle = |point: Vector2F| (point.y() - center.y()).atan2(point.x() - center.x());
+    let mut sweep_angle = 0.0;
+    for pair in samples.windows(2) {
+        let mut delta = angle(pair[1]) - angle(pair[0]);
+        if delta > PI {
+            delta -= PI * 2.0;
+        } else if delta < -PI {
+            delta += PI * 2.0;
+        }
+        sweep_angle += delta;
+    }
+    if sweep_angle.abs() < MIN_ARC_SWEEP {
+        return None;
+    }
+    Some(Shape::new(ShapeKind::Arc {
+        center,
+        radius,
+        start_angle: angle(samples[0]),
+        sweep_angle: sweep_angle.max(-PI * 2.0).min(PI * 2.0),
+    }))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// CLOSED SHAPES
+///////////////////////////////////////////////////////////////////////////////
+
+fn recognize_triangle(corners: &[Vector2F], samples: &[Vector2F], tolerance: f32) -> Option<Shape> {
+    let error = samples
+        .iter()
+        .map(|point| distance_to_polygon(*point, corners))
+        .fold(0.0, f32::max);
+    if error > tolerance {
+        return None;
+    }
+    Some(Shape::new(ShapeKind::Polyline { vertices: corners.to_vec(), closed: true }))
+}
+
+fn recognize_rectangle(corners: &[Vector2F], samples: &[Vector2F], tolerance: f32) -> Option<Shape> {
+    // The direction of the sides, modulo a quarter turn, averaged by length.
+    let mut sum = Vector2F::zero();
+    for index in 0..4 {
+        let side = corners[(index + 1) % 4] - corners[index];
+        let next = corners[(index + 2) % 4] - corners[(index + 1) % 4];
+        let corner_angle = side.det(next).atan2(side.dot(next)).abs();
+        if (corner_angle - PI * 0.5).abs() > MAX_CORNER_ERROR {
+            return None;
+        }
+        let angle = side.y().atan2(side.x()) * 4.0;
+        sum = sum + Vector2F::new(angle.cos(), angle.sin()) * side.length();
+    }
+    let angle = snap_angle(sum.y().atan2(sum.x()) * 0.25);
+
+    // Each side sits between the two corners nearest to it.
+    let center = (corners[0] + corners[1] + corners[2] + corners[3]) * 0.25;
+    let to_local = pf::Transform2F::from_rotation(-angle) * pf::Transform2F::from_translation(-center);
+    let mut xs: Vec<f32> = corners.iter().map(|corner| (to_local * *corner).x()).collect();
+    let mut ys: Vec<f32> = corners.iter().map(|corner| (to_local * *corner).y()).collect();
+    xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let min = Vector2F::new(xs[0] + xs[1], ys[0] + ys[1]) * 0.5;
+    let max = Vector2F::new(xs[2] + xs[3], ys[2] + ys[3]) * 0.5;
+    let rect = RectF::from_points(min, max);
+
+    let half_size = rect.size() * 0.5;
+    let rect_center = rect.center();
+    for point in samples.iter() {
+        let offset = to_local * *point - rect_center;
+        // Signed distance to the rectangle's outline.
+        let excess = Vector2F::new(offset.x().abs(), offset.y().abs()) - half_size;
+        let outside = excess.max(Vector2F::zero()).length();
+        let inside = excess.x().max(excess.y()).min(0.0);
+        if (outside + inside).abs() > tolerance {
+            return None;
+        }
+    }
+    let mut shape = Shape::new(ShapeKind::Rectangle { rect, corner_radius: 0.0 });
+    shape.transform = to_local.inverse();
+    Some(shape)
+}
+
+fn recognize_ellipse(samples: &[Vector2F], tolerance: f32) -> Option<Shape> {
+    // The closing point repeats the first, more or less.
+    let points = &samples[..samples.len() - 1];
+    let count = points.len() as f32;
+    let center = points.iter().fold(Vector2F::zero(), |sum, point| sum + *point) * (1.0 / count);
+    let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+    for point in points.iter() {
+        let offset = *point - center;
+        xx += offset.x() * offset.x() / count;
+        yy += offset.y() * offset.y() / count;
+        xy += offset.x() * offset.y() / count;
+    }
+    // The principal axes of the points are the axes of the ellipse.
+    let angle = 0.5 * (2.0 * xy).atan2(xx - yy);
+    let radii = fit_radii(points, center, angle, (xx + yy).sqrt())?;
+    let (major, minor) = (radii.x().max(radii.y()), radii.x().min(radii.y()));
+    if minor < tolerance {
+        return None;
+    }
+    let (radii, angle) = if major - minor < major * CIRCLE_SNAP {
+        (Vector2F::splat((major + minor) * 0.5), 0.0)
+    } else if (angle / (PI * 0.5)).round() as i32 % 2 == 0 {
+        (radii, snap_angle(angle))
+    } else {
+        // Turning back by a quarter turn swaps the axes.
+        (Vector2F::new(radii.y(), radii.x()), snap_angle(angle))
+    };
+
+    let to_local = pf::Transform2F::from_rotation(-angle) * pf::Transform2F::from_translation(-center);
+    let mean_radius = (radii.x() + radii.y()) * 0.5;
+    for point in points.iter() {
+        let local = (to_local * *point) * Vector2F::new(1.0 / radii.x(), 1.0 / radii.y());
+        if (local.length() - 1.0).abs() * mean_radius > tolerance {
+            return None;
+        }
+    }
+    let mut shape = Shape::new(ShapeKind::Ellipse { center: Vector2F::zero(), radii });
+    shape.transform = to_local.inverse();
+    Some(shape)
+}
+
+/// Radii along the axes turned by `angle` around `center` that fit `points`
+/// best, solving x²/a² + y²/b² = 1 by least squares in units of `scale`.
+fn fit_radii(points: &[Vector2F], center: Vector2F, angle: f32, scale: f32) -> Option<Vector2F> {
+    let to_local = pf::Transform2F::from_scale(1.0 / scale) *
+        pf::Transform2F::from_rotation(-angle) *
+        pf::Transform2F::from_translation(-center);
+    let (mut xxxx, mut yyyy, mut xxyy, mut xx, mut yy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for point in points.iter() {
+        let local = to_local * *point;
+        let (x2, y2) = (local.x() * local.x(), local.y() * local.y());
+        xxxx += x2 * x2;
+        yyyy += y2 * y2;
+        xxyy += x2 * y2;
+        xx += x2;
+        yy += y2;
+    }
+    // Normal equations for 1/a² and 1/b².
+    let determinant = xxxx * yyyy - xxyy * xxyy;
+    if determinant.abs() < EPSILON {
+        return None;
+    }
+    let inverse_a2 = (xx * yyyy - yy * xxyy) / determinant;
+    let inverse_b2 = (yy * xxxx - xx * xxyy) / determinant;
+    if inverse_a2 <= 0.0 || inverse_b2 <= 0.0 {
+        return None;
+    }
+    Some(Vector2F::new(inverse_a2.sqrt().recip(), inverse_b2.sqrt().recip()) * scale)
+}
+
+/// Corners of a closed stroke, in order, with corners on straight sides
+/// removed.
+fn closed_corners(samples: &[Vector2F], tolerance: f32) -> Vec<Vector2F> {
+    // Split the loop at the point furthest from the start and simplify each
+    // half, since simplification needs distinct ends.
+    let far = (0..samples.len())
+        .max_by(|a, b| {
+            let a = (samples[*a] - samples[0]).square_length();
+            let b = (samples[*b] - samples[0]).square_length();
+            a.partial_cmp(&b).unwrap()
+        })
+        .unwrap_or(0);
+    if far == 0 {
+        return Vec::new();
+    }
+    let mut corners: Vec<Vector2F> = simplify(&samples[..=far], tolerance)
+        .into_iter()
+        .map(|index| samples[index])
+        .collect();
+    corners.pop();
+    corners.extend(simplify(&samples[far..], tolerance).into_iter().map(|index| samples[far + index]));
+    // The last point closes the loop.
+    corners.pop();
+    // The start of the stroke is rarely a corner.
+    loop {
+        let count = corners.len();
+        if count < 3 {
+            return corners;
+        }
+        let straight = (0..count).find(|index| {
+            let previous = corners[(index + count - 1) % count];
+            let next = corners[(index + 1) % count];
+            distance_to_segment(corners[*index], previous, next) <= tolerance
+        });
+        match straight {
+            Some(index) => {
+                corners.remove(index);
+            }
+            None => return corners,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// GEOMETRY
+///////////////////////////////////////////////////////////////////////////////
+
+fn path_length(points: &[Vector2F]) -> f32 {
+    points.windows(2).map(|pair| (pair[1] - pair[0]).length()).sum()
+}
+
+/// `count` points evenly spaced along the polyline through `points`,
+/// starting and ending with its ends.
+fn resample(points: &[Vector2F], count: usize) -> Vec<Vector2F> {
+    let spacing = path_length(points) / (count - 1) as f32;
+    let mut result = Vec::with_capacity(count);
+    result.push(points[0]);
+    let mut walked = 0.0;
+    let mut next = spacing;
+    for pair in points.windows(2) {
+        let length = (pair[1] - pair[0]).length();
+        while result.len() < count - 1 && walked + length >= next {
+            result.push(pair[0].lerp(pair[1], (next - walked) / length.max(EPSILON)));
+            next += spacing;
+        }
+        walked += length;
+    }
+    result.push(points[points.len() - 1]);
+    result
+}
+
+/// Douglas-Peucker: indices of the points to keep so that the polyline
+/// through them stays within `tolerance` of `points`.
+fn simplify(points: &[Vector2F], tolerance: f32) -> Vec<usize> {
+    let last = points.len() - 1;
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[last] = true;
+    let mut ranges = vec![(0, last)];
+    while let Some((first, last)) = ranges.pop() {
+        let furthest = (first + 1..last)
+            .map(|index| (index, distance_to_segment(points[index], points[first], points[last])))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        if let Some((index, distance)) = furthest {
+            if distance > tolerance {
+                keep[index] = true;
+                ranges.push((first, index));
+                ranges.push((index, last));
+            }
+        }
+    }
+    (0..points.len()).filter(|index| keep[*index]).collect()
+}
+
+/// Least-squares circle through `points` (Kåsa's method), as its center and
+/// radius. Nothing if the points are collinear.
+fn fit_circle(points: &[Vector2F]) -> Option<(Vector2F, f32)> {
+    // Solve for the circle x² + y² + a x + b y + c = 0, around the mean for
+    // precision.
+    let count = points.len() as f32;
+    let mean = points.iter().fold(Vector2F::zero(), |sum, point| sum + *point) * (1.0 / count);
+    let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+    let (mut xz, mut yz, mut z) = (0.0, 0.0, 0.0);
+    for point in points.iter() {
+        let offset = *point - mean;
+        let (x, y) = (offset.x(), offset.y());
+        let square = x * x + y * y;
+        xx += x * x;
+        yy += y * y;
+        xy += x * y;
+        xz += x * square;
+        yz += y * square;
+        z += square;
+    }
+    // With centered points the sums of x and y vanish, which decouples c.
+    let determinant = xx * yy - xy * xy;
+    if determinant.abs() < EPSILON {
+        return None;
+    }
+    let a = (-xz * yy + yz * xy) / determinant;
+    let b = (-yz * xx + xz * xy) / determinant;
+    let c = -z / count;
+    let center = Vector2F::new(-a * 0.5, -b * 0.5);
+    let radius_squared = center.square_length() - c;
+    if radius_squared <= 0.0 {
+        return None;
+    }
+    Some((mean + center, radius_squared.sqrt()))
+}
+
+fn distance_to_segment(point: Vector2F, from: Vector2F, to: Vector2F) -> f32 {
+    let direction = to - from;
+    let length_squared = direction.square_length();
+    if length_squared < EPSILON {
+        return (point - from).length();
+    }
+    let t = ((point - from).dot(direction) / length_squared).max(0.0).min(1.0);
+    (from + direction * t - point).length()
+}
+
+fn distance_to_polygon(point: Vector2F, vertices: &[Vector2F]) -> f32 {
+    (0..vertices.len())
+        .map(|index| distance_to_segment(point, vertices[index], vertices[(index + 1) % vertices.len()]))
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Snaps `angle` to the nearest multiple of a quarter turn if it is close,
+/// and brings it within a quarter turn either way.
+fn snap_angle(angle: f32) -> f32 {
+    let quarter = PI * 0.5;
+    let nearest = (angle / quarter).round() * quarter;
+    if (angle - nearest).abs() < ANGLE_SNAP {
+        return 0.0;
+    }
+    angle - nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points every two units along the polyline through `vertices`, with
+    /// a hand's worth of jitter.
+    fn trace(vertices: &[Vector2F]) -> Vec<Vector2F> {
+        let mut points = vec![vertices[0]];
+        for pair in vertices.windows(2) {
+            let steps = ((pair[1] - pair[0]).length() / 2.0).ceil().max(1.0) as usize;
+            for step in 1..=steps {
+                points.push(pair[0].lerp(pair[1], step as f32 / steps as f32));
+            }
+        }
+        jitter(points)
+    }
+
+    /// `count` points along an ellipse turned by `rotation`, with jitter.
+    fn ellipse_points(
+        center: Vector2F,
+        radii: Vector2F,
+        rotation: f32,
+        start_angle: f32,
+        sweep_angle: f32,
+        count: usize,
+    ) -> Vec<Vector2F> {
+        let transform = pf::Transform2F::from_translation(center) *
+            pf::Transform2F::from_rotation(rotation);
+        let points = (0..count).map(|index| {
+            let angle = start_angle + sweep_angle * index as f32 / (count - 1) as f32;
+            transform * (Vector2F::new(angle.cos(), angle.sin()) * radii)
+        });
+        jitter(points.collect())
+    }
+
+    fn jitter(points: Vec<Vector2F>) -> Vec<Vector2F> {
+        let last = points.len() - 1;
+        points
+            .into_iter()
+            .enumerate()
+            .map(|(index, point)| {
+                if index == 0 || index == last {
+                    return point;
+                }
+                let offset = Vector2F::new((index * 37 % 7) as f32, (index * 53 % 7) as f32);
+                point + (offset - Vector2F::splat(3.0)) * 0.2
+            })
+            .collect()
+    }
+
+    fn assert_near(a: Vector2F, b: Vector2F, tolerance: f32) {
+        assert!((a - b).length() < tolerance, "{:?} is not near {:?}", a, b);
+    }
+
+    #[test]
+    fn straight_strokes_become_lines() {
+        let from = Vector2F::new(10.0, 20.0);
+        let to = Vector2F::new(110.0, 60.0);
+        let shape = recognize(&trace(&[from, to])).unwrap();
+        assert_eq!(
+            shape.kind,
+            ShapeKind::Line {
+                from,
+                to,
+                start_head: ArrowHead::None,
+                end_head: ArrowHead::None,
+                head_size: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn barbs_at_the_end_make_arrows() {
+        let tip = Vector2F::new(100.0, 0.0);
+        let points = trace(&[
+            Vector2F::zero(),
+            tip,
+            Vector2F::new(85.0, -10.0),
+            tip,
+            Vector2F::new(85.0, 10.0),
+        ]);
+        match recognize(&points).unwrap().kind {
+            ShapeKind::Line { from, to, end_head, head_size, .. } => {
+                assert_near(from, Vector2F::zero(), 0.01);
+                assert_near(to, tip, 3.0);
+                assert_eq!(end_head, ArrowHead::Open);
+                assert!((head_size - 18.0).abs() < 3.0, "head of {}", head_size);
+            }
+            kind => panic!("{:?} is not an arrow", kind),
+        }
+    }
+
+    #[test]
+    fn open_curves_become_arcs() {
+        let center = Vector2F::new(50.0, 50.0);
+        let points = ellipse_points(center, Vector2F::splat(40.0), 0.0, 0.0, PI, 100);
+        match recognize(&points).unwrap().kind {
+            ShapeKind::Arc { center: fitted, radius, start_angle, sweep_angle } => {
+                assert_near(fitted, center, 1.0);
+                assert!((radius - 40.0).abs() < 1.0);
+                assert!(start_angle.abs() < 0.05);
+                assert!((sweep_angle - PI).abs() < 0.05);
+            }
+            kind => panic!("{:?} is not an arc", kind),
+        }
+    }
+
+    #[test]
+    fn loops_become_circles_and_turned_ellipses() {
+        let center = Vector2F::new(20.0, 30.0);
+        let points = ellipse_points(center, Vector2F::splat(50.0), 0.0, 0.0, PI * 2.0, 120);
+        let shape = recognize(&points).unwrap();
+        match shape.kind {
+            ShapeKind::Ellipse { radii, .. } => {
+                assert!((radii.x() - 50.0).abs() < 1.0);
+                assert_eq!(radii.x(), radii.y());
+            }
+            ref kind => panic!("{:?} is not a circle", kind),
+        }
+        assert_near(shape.transform * Vector2F::zero(), center, 1.0);
+
+        let radii = Vector2F::new(80.0, 30.0);
+        let points = ellipse_points(center, radii, 0.5, 0.0, PI * 2.0, 120);
+        let shape = recognize(&points).unwrap();
+        match shape.kind {
+            ShapeKind::Ellipse { radii: fitted, .. } => assert_near(fitted, radii, 2.0),
+            ref kind => panic!("{:?} is not an ellipse", kind),
+        }
+        let end_of_major_axis = center + Vector2F::new(0.5f32.cos(), 0.5f32.sin()) * 80.0;
+        assert_near(shape.transform * Vector2F::new(80.0, 0.0), end_of_major_axis, 2.0);
+    }
+
+    #[test]
+    fn four_corners_become_turned_rectangles() {
+        let angle = PI / 9.0;
+        let transform = pf::Transform2F::from_translation(Vector2F::new(200.0, 100.0)) *
+            pf::Transform2F::from_rotation(angle);
+        let corners = [
+            Vector2F::new(-50.0, -30.0),
+            Vector2F::new(50.0, -30.0),
+            Vector2F::new(50.0, 30.0),
+            Vector2F::new(-50.0, 30.0),
+        ];
+        let mut vertices: Vec<Vector2F> =
+            corners.iter().map(|corner| transform * *corner).collect();
+        vertices.push(vertices[0]);
+        let shape = recognize(&trace(&vertices)).unwrap();
+        let rect = match shape.kind {
+            ShapeKind::Rectangle { rect, corner_radius } => {
+                assert_eq!(corner_radius, 0.0);
+                rect
+            }
+            ref kind => panic!("{:?} is not a rectangle", kind),
+        };
+        assert_near(rect.size(), Vector2F::new(100.0, 60.0), 2.0);
+        let fitted = [rect.origin(), rect.upper_right(), rect.lower_right(), rect.lower_left()];
+        for (fitted, corner) in fitted.iter().zip(corners.iter()) {
+            assert_near(shape.transform * *fitted, transform * *corner, 2.0);
+        }
+    }
+
+    #[test]
+    fn three_corners_become_triangles() {
+        let corners = [Vector2F::zero(), Vector2F::new(100.0, 0.0), Vector2F::new(50.0, 80.0)];
+        let vertices = [corners[0], corners[1], corners[2], corners[0]];
+        match recognize(&trace(&vertices)).unwrap().kind {
+            ShapeKind::Polyline { vertices, closed } => {
+                assert!(closed);
+                assert_eq!(vertices.len(), 3);
+                for corner in corners.iter() {
+                    assert!(vertices.iter().any(|vertex| (*vertex - *corner).length() < 3.0));
+                }
+            }
+            kind => panic!("{:?} is not a triangle", kind),
+        }
+    }
+
+    #[test]
+    fn scribbles_and_short_strokes_stay_as_drawn() {
+        let zigzag: Vec<Vector2F> = (0..10)
+            .map(|index| Vector2F::new(index as f32 * 10.0, (index % 2) as f32 * 40.0))
+            .collect();
+        assert!(recognize(&trace(&zigzag)).is_none());
+        let squiggle: Vec<Vector2F> = (0..=40)
+            .map(|x| Vector2F::new(x as f32, (x as f32 * PI / 20.0).sin() * 8.0))
+            .collect();
+        assert!(recognize(&squiggle).is_none());
+        let short = [Vector2F::zero(), Vector2F::new(10.0, 0.0), Vector2F::new(10.0, 5.0)];
+        assert!(recognize(&trace(&short)).is_none());
+    }
+
+    #[test]
+    fn short_bows_are_not_arcs() {
+        // Less than a twelfth of a turn.
+        let sweep = PI / 8.0;
+        let radii = Vector2F::splat(100.0);
+        let points = ellipse_points(Vector2F::zero(), radii, 0.0, -sweep * 0.5, sweep, 40);
+        if let Some(shape) = recognize(&points) {
+            match shape.kind {
+                ShapeKind::Line { .. } => {}
+                kind => panic!("{:?} is not a line", kind),
+            }
+        }
+    }
+}
@@ -0,0 +1,596 @@
+//! Turns a `Document` into a Pathfinder scene.
+//!
+//! Fitting and outlining strokes is most of the work, so each stroke's
+//! path is kept from frame to frame until the stroke changes. The scene of
+//! the strokes that stopped changing is kept too, and a frame only draws
+//! the strokes above it into a second scene, rendered on top.
+use std::collections::{HashMap, HashSet};
+use crate::prelude::*;
+use super::document::{Document, Layer, LayerId, LayerProperties, Stroke, StrokeId};
+use super::dynamics;
+use super::fitting::{self, FitOptions};
+use super::paint::{Fill, GradientKind, Paint};
+use super::shape::Shape;
+
+///////////////////////////////////////////////////////////////////////////////
+// CACHE
+///////////////////////////////////////////////////////////////////////////////
+
+/// What strokes paint, as paths to fill in document units, by the stroke
+/// revision they were built from.
+#[derive(Default)]
+pub struct StrokePaths {
+    fit_options: Option<FitOptions>,
+    paths: HashMap<StrokeId, (u64, Option<StrokePath>)>,
+}
+
+/// What one stroke paints, in document units.
+struct StrokePath {
+    /// The inside of a filled shape, painted first.
+    fill: Option<pf::Path2D>,
+    /// The area the stroke's line covers, painted in the stroke's color.
+    line: Option<pf::Path2D>,
+}
+
+impl StrokePaths {
+    /// Builds the paths of `stroke` unless the ones built before are
+    /// current. Nothing for empty strokes.
+    fn get(
+        &mut self,
+        document: &Document,
+        stroke: &Stroke,
+        fit_options: &FitOptions,
+    ) -> Option<&StrokePath> {
+        if self.fit_options != Some(*fit_options) {
+            self.paths.clear();
+            self.fit_options = Some(*fit_options);
+        }
+        let revision = document.stroke_revision(stroke.id);
+        let current = match self.paths.get(&stroke.id) {
+            Some((built, _)) => Some(*built) == revision,
+            None => false,
+        };
+        if !current {
+            let path = build_stroke_path(stroke, fit_options);
+            self.paths.insert(stroke.id, (revision.unwrap_or(u64::MAX), path));
+        }
+        self.paths[&stroke.id].1.as_ref()
+    }
+    /// Forgets the paths of strokes that are gone.
+    fn prune(&mut self, document: &Document) {
+        if self.paths.len() > document.stroke_count() {
+            self.paths.retain(|id, _| document.stroke_revision(*id).is_some());
+        }
+    }
+}
+
+/// The bottom of the document drawn into a scene, up to the first stroke
+/// that changed since the last frame. Grows as strokes stop changing, and
+/// is started over when a stroke in it, or one below its top, changes or
+/// goes away.
+struct DocumentScene {
+    size: Vector2F,
+    transform: pf::Transform2F,
+    fit_options: FitOptions,
+    /// How every layer was to be composited.
+    layers: Vec<(LayerId, LayerProperties)>,
+    scene: pf::Scene,
+    /// The strokes in `scene`.
+    drawn: HashSet<StrokeId>,
+    /// The last of them in paint order.
+    top: Option<StrokeId>,
+}
+
+impl DocumentScene {
+    fn new(
+        size: Vector2F,
+        transform: pf::Transform2F,
+        fit_options: FitOptions,
+        layers: Vec<(LayerId, LayerProperties)>,
+    ) -> Self {
+        let scene = pf::Canvas::new(size).into_scene();
+        DocumentScene {
+            size,
+            transform,
+            fit_options,
+            layers,
+            scene,
+            drawn: HashSet::new(),
+            top: None,
+        }
+    }
+    /// Where the strokes above the scene start, as a layer index and a
+    /// position in that layer. `None` if a stroke that changed after
+    /// `revision` is in the scene, or may paint inside `area` below its top.
+    fn next_position(
+        &self,
+        document: &Document,
+        revision: u64,
+        area: RectF,
+    ) -> Option<(usize, usize)> {
+        let next = match self.top {
+            Some(id) => {
+                let (layer, position) = document.locate(id)?;
+                (document.layer_index(layer)?, position + 1)
+            }
+            None => (0, 0),
+        };
+        for id in document.changes_since(revision)? {
+            if self.drawn.contains(&id) {
+                return None;
+            }
+            let (layer, position) = match document.locate(id) {
+                Some(location) => location,
+                None => continue,
+            };
+            let index = document.layer_index(layer)?;
+            let paints = document.layers()[index].properties.visible &&
+                document.ink_bounds(id).map_or(false, |bounds| bounds.intersects(area));
+            if paints && (index, position) < next {
+                return None;
+            }
+        }
+        Some(next)
+    }
+}
+
+/// A frame, as two scenes to render one over the other.
+pub struct FrameScenes {
+    /// The kept bottom of the document, if it changed since the last frame.
+    pub settled: Option<pf::Scene>,
+    /// The strokes above it, which changed lately, and the overlay.
+    pub live: pf::Scene,
+}
+
+/// Everything kept between frames.
+pub struct SceneCache {
+    font_context: pf::CanvasFontContext,
+    pub paths: StrokePaths,
+    document_scene: Option<DocumentScene>,
+    /// The document revision at the last frame. Strokes with newer ones
+    /// changed since.
+    revision: Option<u64>,
+}
+
+impl SceneCache {
+    pub fn new() -> Self {
+        SceneCache {
+            font_context: pf::CanvasFontContext::from_system_source(),
+            paths: StrokePaths::default(),
+            document_scene: None,
+            revision: None,
+        }
+    }
+
+    /// Builds a frame of `size` with the visible layers of `document` drawn
+    /// through `transform`, which maps document units to the scene. Strokes
+    /// outside the scene are left out. `draw_overlay` then draws on top,
+    /// e.g. tool feedback, starting with the document transform.
+    ///
+    /// Strokes that did not change since the last frame are added to the
+    /// kept document scene, below the first that did; the rest are drawn
+    /// into the live scene. Only the strokes above the kept scene and the
+    /// ones that changed are looked at. Panning, zooming or resizing starts
+    /// it over.
+    pub fn build_scene<F>(
+        &mut self,
+        document: &Document,
+        fit_options: &FitOptions,
+        size: Vector2F,
+        transform: pf::Transform2F,
+        draw_overlay: F,
+    ) -> FrameScenes where F: FnOnce(&mut pf::CanvasRenderingContext2D) {
+        let visible_area = transform.inverse() * RectF::new(Vector2F::zero(), size);
+        let layers: Vec<(LayerId, LayerProperties)> = document
+            .layers()
+            .iter()
+            .map(|layer| (layer.id, layer.properties.clone()))
+            .collect();
+        let revision = self.revision;
+        let kept = self.document_scene.take().and_then(|document_scene| {
+            if document_scene.size != size ||
+                document_scene.transform != transform ||
+                document_scene.fit_options != *fit_options ||
+                document_scene.layers != layers {
+                return None;
+            }
+            let next = document_scene.next_position(document, revision?, visible_area)?;
+            let strokes = strokes_from(document, visible_area, next);
+            // Strokes of a composited layer must all be drawn together.
+            let splits_layer = match strokes.first() {
+                Some(&(layer, _)) => {
+                    layer == next.0 && next.1 > 0 &&
+                        !document.layers()[layer].properties.is_pass_through()
+                }
+                None => false,
+            };
+            if splits_layer {
+                None
+            } else {
+                Some((document_scene, strokes))
+            }
+        });
+        let (mut document_scene, strokes, end, started_over) = match kept {
+            Some((document_scene, strokes)) => {
+                let unchanged = strokes
+                    .iter()
+                    .take_while(|&&(_, stroke)| {
+                        let changed = document.stroke_revision(stroke.id);
+                        changed.map_or(false, |changed| Some(changed) <= revision)
+                    })
+                    .count();
+                let end = layer_boundary(document, &strokes, unchanged);
+                (document_scene, strokes, end, false)
+            }
+            // Drawn from scratch this frame, and kept from the next if the
+            // view holds still.
+            None => {
+                let document_scene = DocumentScene::new(size, transform, *fit_options, layers);
+                (document_scene, visible_strokes(document, Some(visible_area)), 0, true)
+            }
+        };
+        if end > 0 {
+            let mut ctx = pf::Canvas::from_scene(document_scene.scene)
+                .get_context_2d(self.font_context.clone());
+            ctx.set_transform(&transform);
+            let settled = &strokes[..end];
+            let ctx = draw_strokes(ctx, document, settled, fit_options, &mut self.paths);
+            document_scene.scene = ctx.into_canvas().into_scene();
+            document_scene.drawn.extend(settled.iter().map(|&(_, stroke)| stroke.id));
+            document_scene.top = Some(settled[end - 1].1.id);
+        }
+        // Handed over only when it changed; the kept one goes on growing.
+        let settled = if started_over || end > 0 {
+            Some(document_scene.scene.clone())
+        } else {
+            None
+        };
+
+        let mut ctx = pf::Canvas::new(size).get_context_2d(self.font_context.clone());
+        ctx.set_transform(&transform);
+        let mut ctx = draw_strokes(ctx, document, &strokes[end..], fit_options, &mut self.paths);
+        self.paths.prune(document);
+        self.document_scene = Some(document_scene);
+        self.revision = Some(document.revision());
+        draw_overlay(&mut ctx);
+        FrameScenes { settled, live: ctx.into_canvas().into_scene() }
+    }
+}
+
+impl Default for SceneCache {
+    fn default() -> Self {
+        SceneCache::new()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// DRAWING
+///////////////////////////////////////////////////////////////////////////////
+
+/// Draws the visible layers of `document`, bottom to top, with the current
+/// transform of `ctx`. Given a `visible_area` in document units, only the
+/// strokes that may paint inside it are drawn. Paths are taken from `paths`
+/// where current, and left there for next time.
+///
+/// Layers with partial opacity or a blend mode other than normal are drawn
+/// into a render target the size of the view box first and composited as a
+/// whole, so their own strokes do not show through each other. Hence the
+/// context is passed by value: the scene moves out of it and back.
+pub fn draw_document(
+    ctx: pf::CanvasRenderingContext2D,
+    document: &Document,
+    fit_options: &FitOptions,
+    visible_area: Option<RectF>,
+    paths: &mut StrokePaths,
+) -> pf::CanvasRenderingContext2D {
+    let strokes = visible_strokes(document, visible_area);
+    let ctx = draw_strokes(ctx, document, &strokes, fit_options, paths);
+    paths.prune(document);
+    ctx
+}
+
+/// The strokes of the visible layers in paint order, with the index of
+/// their layer. Given a `visible_area`, only those that may paint inside it,
+/// found without looking at the others.
+fn visible_strokes(document: &Document, visible_area: Option<RectF>) -> Vec<(usize, &Stroke)> {
+    let layers = document.layers();
+    let area = match visible_area {
+        Some(area) => area,
+        None => {
+            return layers
+                .iter()
+                .enumerate()
+                .filter(|(_, layer)| layer.properties.visible)
+                .flat_map(|(index, layer)| layer.strokes.iter().map(move |stroke| (index, stroke)))
+                .collect();
+        }
+    };
+    let layer_indices: HashMap<LayerId, usize> =
+        layers.iter().enumerate().map(|(index, layer)| (layer.id, index)).collect();
+    let mut hits: Vec<(usize, usize)> = document
+        .strokes_in_rect(area)
+        .into_iter()
+        .filter_map(|id| {
+            let (layer, position) = document.locate(id)?;
+            let index = layer_indices[&layer];
+            if layers[index].properties.visible {
+                Some((index, position))
+            } else {
+                None
+            }
+        })
+        .collect();
+    hits.sort_unstable();
+    hits.into_iter().map(|(index, position)| (index, &layers[index].strokes[position])).collect()
+}
+
+/// The strokes of the visible layers that may paint inside `area`, in
+/// paint order, from `start`, a layer index and a position in that layer.
+fn strokes_from(document: &Document, area: RectF, start: (usize, usize)) -> Vec<(usize, &Stroke)> {
+    if start == (0, 0) {
+        return visible_strokes(document, Some(area));
+    }
+    let (start_layer, start_position) = start;
+    document
+        .layers()
+        .iter()
+        .enumerate()
+        .skip(start_layer)
+        .filter(|(_, layer)| layer.properties.visible)
+        .flat_map(|(index, layer)| {
+            let skip = if index == start_layer { start_position } else { 0 };
+            layer.strokes.iter().skip(skip).map(move |stroke| (index, stroke))
+        })
+        .filter(|&(_, stroke)| {
+            document.ink_bounds(stroke.id).map_or(false, |bounds| bounds.intersects(area))
+        })
+        .collect()
+}
+
+/// `index` into `strokes`, moved back to the start of its layer if that
+/// layer is composited: its strokes must be drawn together.
+fn layer_boundary(document: &Document, strokes: &[(usize, &Stroke)], mut index: usize) -> usize {
+    if index == 0 || index >= strokes.len() {
+        return index;
+    }
+    let layer = strokes[index].0;
+    if document.layers()[layer].properties.is_pass_through() {
+        return index;
+    }
+    while index > 0 && strokes[index - 1].0 == layer {
+        index -= 1;
+    }
+    index
+}
+
+/// Draws `strokes`, as from `visible_strokes`, layer by layer. Strokes of
+/// a composited layer must all be there.
+fn draw_strokes(
+    mut ctx: pf::CanvasRenderingContext2D,
+    document: &Document,
+    strokes: &[(usize, &Stroke)],
+    fit_options: &FitOptions,
+    paths: &mut StrokePaths,
+) -> pf::CanvasRenderingContext2D {
+    let mut start = 0;
+    while start < strokes.len() {
+        let layer_index = strokes[start].0;
+        let end = strokes[start..]
+            .iter()
+            .position(|&(index, _)| index != layer_index)
+            .map_or(strokes.len(), |count| start + count);
+        let layer = &document.layers()[layer_index];
+        let layer_strokes: Vec<&Stroke> = strokes[start..end]
+            .iter()
+            .map(|&(_, stroke)| stroke)
+            .collect();
+        if layer.properties.is_pass_through() {
+            for stroke in layer_strokes {
+                draw_stroke(&mut ctx, document, stroke, fit_options, paths);
+            }
+        } else {
+            ctx = draw_composited_layer(ctx, document, layer, &layer_strokes, fit_options, paths);
+        }
+        start = end;
+    }
+    ctx
+}
+
+fn draw_composited_layer(
+    ctx: pf::CanvasRenderingContext2D,
+    document: &Document,
+    layer: &Layer,
+    strokes: &[&Stroke],
+    fit_options: &FitOptions,
+    paths: &mut StrokePaths,
+) -> pf::CanvasRenderingContext2D {
+    let transform = ctx.transform();
+    let font_context = ctx.font_context();
+    let mut scene = ctx.into_canvas().into_scene();
+    let size = scene.view_box().size().ceil().to_i32();
+    let render_target = pf::RenderTarget::new(size, layer.properties.name.clone());
+    let render_target_id = scene.push_render_target(render_target);
+    let mut layer_ctx = pf::Canvas::from_scene(scene).get_context_2d(font_context.clone());
+    layer_ctx.set_transform(&transform);
+    for stroke in strokes {
+        draw_stroke(&mut layer_ctx, document, stroke, fit_options, paths);
+    }
+    let mut scene = layer_ctx.into_canvas().into_scene();
+    scene.pop_render_target();
+
+    let mut ctx = pf::Canvas::from_scene(scene).get_context_2d(font_context);
+    let pattern = pf::Pattern::from_render_target(render_target_id, size);
+    ctx.set_global_alpha(layer.properties.opacity);
+    ctx.set_global_composite_operation(layer.properties.blend_mode.to_composite_operation());
+    ctx.set_fill_style(pf::FillStyle::Pattern(pattern));
+    ctx.fill_rect(RectF::new(Vector2F::zero(), size.to_f32()));
+    ctx.set_global_alpha(1.0);
+    ctx.set_global_composite_operation(pf::CompositeOperation::SourceOver);
+    ctx.set_transform(&transform);
+    ctx
+}
+
+fn draw_stroke(
+    ctx: &mut pf::CanvasRenderingContext2D,
+    document: &Document,
+    stroke: &Stroke,
+    fit_options: &FitOptions,
+    paths: &mut StrokePaths,
+) {
+    let path = match paths.get(document, stroke, fit_options) {
+        Some(path) => path,
+        None => return,
+    };
+    ctx.set_global_alpha(stroke.style.opacity);
+    let shape_fill = stroke.shape.as_ref().and_then(|shape| Some((shape, shape.fill.as_ref()?)));
+    if let (Some(fill_path), Some((shape, fill))) = (path.fill.as_ref(), shape_fill) {
+        ctx.set_fill_style(fill_style(shape, fill));
+        ctx.fill_path(fill_path.clone(), fill.rule);
+    }
+    if let Some(ref line_path) = path.line {
+        ctx.set_fill_style(pf::FillStyle::Color(stroke.style.color));
+        ctx.fill_path(line_path.clone(), pf::FillRule::Winding);
+    }
+    ctx.set_global_alpha(1.0);
+}
+
+/// How to paint `fill` inside `shape`, in document units.
+fn fill_style(shape: &Shape, fill: &Fill) -> pf::FillStyle {
+    let gradient = match fill.paint {
+        Paint::Color(color) => return pf::FillStyle::Color(color),
+        Paint::Gradient(ref gradient) => gradient,
+    };
+    let mut result = match gradient.kind {
+        GradientKind::Linear { from, to } => pf::Gradient::linear_from_points(from, to),
+        GradientKind::Radial { focus, center, radius } => {
+            pf::Gradient::radial(pf::LineSegment2F::new(focus, center), pf::vec2f(0.0, radius).0)
+        }
+    };
+    for stop in gradient.stops.iter() {
+        result.add_color_stop(stop.color, stop.offset);
+    }
+    result.apply_transform(shape.transform * gradient.transform);
+    pf::FillStyle::Gradient(result)
+}
+
+/// What `stroke` paints, in document units. Nothing for empty strokes.
+fn build_stroke_path(stroke: &Stroke, fit_options: &FitOptions) -> Option<StrokePath> {
+    if stroke.is_empty() {
+        return None;
+    }
+    let fill = match stroke.shape {
+        Some(ref shape) if shape.fill.is_some() => Some(path_from_outline(&shape_outline(shape))),
+        _ => None,
+    };
+    let line = line_outline(stroke, fit_options).map(|outline| path_from_outline(&outline));
+    Some(StrokePath { fill, line })
+}
+
+/// The area the line of `stroke` covers, in document units, to fill in the
+/// stroke's color. `None` for empty strokes and shapes without a line.
+pub fn line_outline(stroke: &Stroke, fit_options: &FitOptions) -> Option<pf::Outline> {
+    if stroke.is_empty() {
+        return None;
+    }
+    let style = stroke.style;
+    if let Some(ref shape) = stroke.shape {
+        // Shapes have no pressure or speed to vary by.
+        if !shape.stroked {
+            return None;
+        }
+        return Some(stroke_to_fill(&shape_outline(shape), style.width, stroke));
+    }
+    let positions = stroke.points
+        .iter()
+        .map(|point| point.position)
+        .collect::<Vec<_>>();
+    let segments = fitting::fit_curve(
+        &positions,
+        fit_options.tolerance,
+    );
+    if style.dynamics.is_constant() && !segments.is_empty() {
+        let scale = style.dynamics.pressure.start * style.dynamics.velocity.start;
+        let mut contour = pf::Contour::new();
+        contour.push_endpoint(segments[0].from);
+        for segment in segments.iter() {
+            contour.push_cubic(segment.ctrl0, segment.ctrl1, segment.to);
+        }
+        let mut outline = pf::Outline::new();
+        outline.push_contour(contour);
+        return Some(stroke_to_fill(&outline, style.width * scale, stroke));
+    }
+    let widths = dynamics::width_profile(
+        &stroke.points,
+        style.width,
+        &style.dynamics,
+    );
+    Some(dynamics::stroke_outline(
+        &stroke.points,
+        &widths,
+        &segments,
+    ))
+}
+
+/// The contours of `shape`, unstroked.
+fn shape_outline(shape: &Shape) -> pf::Outline {
+    let mut outline = pf::Outline::new();
+    for contour in shape.contours() {
+        let mut path_contour = pf::Contour::new();
+        if let Some(first) = contour.segments.first() {
+            path_contour.push_endpoint(first.from);
+        }
+        for segment in contour.segments.iter() {
+            path_contour.push_cubic(segment.ctrl0, segment.ctrl1, segment.to);
+        }
+        if contour.closed {
+            path_contour.close();
+        }
+        outline.push_contour(path_contour);
+    }
+    outline
+}
+
+/// The area a line of `width` along `outline` covers, with the caps and
+/// joins of `stroke`.
+fn stroke_to_fill(outline: &pf::Outline, width: f32, stroke: &Stroke) -> pf::Outline {
+    let style = pf::StrokeStyle {
+        line_width: width,
+        line_cap: stroke.style.line_cap,
+        line_join: stroke.style.line_join,
+    };
+    let mut stroke_to_fill = pf::OutlineStrokeToFill::new(outline, style);
+    stroke_to_fill.offset();
+    stroke_to_fill.into_outline()
+}
+
+pub fn path_from_outline(outline: &pf::Outline) -> pf::Path2D {
+    let mut path = pf::Path2D::new();
+    for contour in outline.contours() {
+        let mut segments = contour.iter(pf::ContourIterFlags::IGNORE_CLOSE_SEGMENT).peekable();
+        if let Some(first) = segments.peek() {
+            path.move_to(first.baseline.from());
+        }
+        for segment in segments {
+            match segment.kind {
+                pf::SegmentKind::None => {}
+                pf::SegmentKind::Line => {
+                    path.line_to(segment.baseline.to());
+                }
+                pf::SegmentKind::Quadratic => {
+                    path.quadratic_curve_to(segment.ctrl.from(), segment.baseline.to());
+                }
+                pf::SegmentKind::Cubic => {
+                    path.bezier_curve_to(
+                        segment.ctrl.from(),
+                        segment.ctrl.to(),
+                        segment.baseline.to(),
+                    );
+                }
+            }
+        }
+        if contour.is_closed() {
+            path.close_path();
+        }
+    }
+    path
+}
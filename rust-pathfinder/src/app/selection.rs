@@ -0,0 +1,648 @@
+//! Selecting strokes with a lasso or a marquee, and transforming them with
+//! the handles of the selection box.
+//!
+//! Selections and gestures live in world coordinates; handles are sized and
+//! hit-tested in device pixels so they stay usable at every zoom level.
+use std::collections::HashSet;
+use std::f32::consts::{FRAC_PI_2, PI};
+use crate::prelude::*;
+use super::document::{Document, StrokeId};
+use super::history::Command;
+use super::tool::{PointerEvent, Tool, ToolContext};
+
+/// Half the side of a square handle, in device pixels.
+const HANDLE_RADIUS: f32 = 5.0;
+/// How far the rotation handle sits above the top edge, in device pixels.
+const ROTATE_HANDLE_OFFSET: f32 = 24.0;
+/// Scaling never collapses the selection below this factor.
+const MIN_SCALE: f32 = 0.01;
+
+const OVERLAY_COLOR: pf::ColorU = pf::ColorU { r: 0x1e, g: 0x88, b: 0xe5, a: 0xff };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectionMode {
+    Lasso,
+    Rectangle,
+}
+
+/// The part of the selection box under the cursor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Handle {
+    Move,
+    Rotate,
+    /// A corner or edge midpoint. `x` and `y` are -1, 0 or 1: the side of the
+    /// box the handle is on along each axis, or 0 if that axis is not scaled.
+    Scale { x: i8, y: i8 },
+}
+
+impl Handle {
+    const SCALE_HANDLES: [Handle; 8] = [
+        Handle::Scale { x: -1, y: -1 },
+        Handle::Scale { x: 0, y: -1 },
+        Handle::Scale { x: 1, y: -1 },
+        Handle::Scale { x: 1, y: 0 },
+        Handle::Scale { x: 1, y: 1 },
+        Handle::Scale { x: 0, y: 1 },
+        Handle::Scale { x: -1, y: 1 },
+        Handle::Scale { x: -1, y: 0 },
+    ];
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// SELECTION
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug)]
+pub struct Selection {
+    pub ids: Vec<StrokeId>,
+    /// Ink bounds of the strokes when they were selected.
+    pub bounds: RectF,
+    /// Everything the handles have done to the strokes since they were
+    /// selected. The selection box is `bounds` through this transform.
+    pub transform: pf::Transform2F,
+}
+
+impl Selection {
+    /// Selects the editable strokes that lie entirely within the polygon.
+    pub fn from_lasso(document: &Document, polygon: &[Vector2F]) -> Option<Selection> {
+        if polygon.len() < 3 {
+            return None;
+        }
+        let area = polygon
+            .iter()
+            .skip(1)
+            .fold(RectF::from_points(polygon[0], polygon[0]), |area, point| {
+                area.union_point(*point)
+            });
+        Selection::from_candidates(document, area, |points| {
+            points.iter().all(|point| polygon_contains_point(polygon, *point))
+        })
+    }
+    /// Selects the editable strokes that lie entirely within `rect`.
+    pub fn from_rect(document: &Document, rect: RectF) -> Option<Selection> {
+        Selection::from_candidates(document, rect, |points| {
+            points.iter().all(|point| rect.contains_point(*point))
+        })
+    }
+    fn from_candidates<F>(document: &Document, area: RectF, mut accept: F) -> Option<Selection>
+    where
+        F: FnMut(&[Vector2F]) -> bool,
+    {
+        let candidates: HashSet<StrokeId> = document.strokes_in_rect(area).into_iter().collect();
+        let mut ids = Vec::new();
+        let mut bounds: Option<RectF> = None;
+        for stroke in document.editable_strokes() {
+            if !candidates.contains(&stroke.id) {
+                continue;
+            }
+            let points: Vec<Vector2F> = stroke.points.iter().map(|point| point.position).collect();
+            let stroke_bounds = match stroke.bounds() {
+                Some(stroke_bounds) if accept(&points) => stroke_bounds,
+                _ => continue,
+            };
+            let stroke_bounds = stroke_bounds.dilate(stroke.style.width * 0.5);
+            bounds = Some(match bounds {
+                Some(bounds) => bounds.union_rect(stroke_bounds),
+                None => stroke_bounds,
+            });
+            ids.push(stroke.id);
+        }
+        Some(Selection { ids, bounds: bounds?, transform: pf::Transform2F::default() })
+    }
+    /// Corners of the selection box in world coordinates, clockwise from the
+    /// upper left of `bounds`.
+    pub fn corners(&self) -> [Vector2F; 4] {
+        let bounds = self.bounds;
+        [
+            self.transform * bounds.origin(),
+            self.transform * bounds.upper_right(),
+            self.transform * bounds.lower_right(),
+            self.transform * bounds.lower_left(),
+        ]
+    }
+    /// Where `handle` is drawn, in device pixels.
+    fn handle_position(&self, handle: Handle, camera: &pf::Transform2F) -> Vector2F {
+        let to_screen = *camera * self.transform;
+        let center = self.bounds.center();
+        let half_size = self.bounds.size() * 0.5;
+        match handle {
+            Handle::Move => to_screen * center,
+            Handle::Scale { x, y } => {
+                to_screen * (center + half_size * Vector2F::new(x as f32, y as f32))
+            }
+            Handle::Rotate => {
+                let top = to_screen * Vector2F::new(center.x(), self.bounds.min_y());
+                let outward = top - to_screen * center;
+                if outward.square_length() == 0.0 {
+                    return top - Vector2F::new(0.0, ROTATE_HANDLE_OFFSET);
+                }
+                top + outward.normalize() * ROTATE_HANDLE_OFFSET
+            }
+        }
+    }
+    /// The handle under `position`, in device pixels.
+    pub fn handle_at(&self, position: Vector2F, camera: &pf::Transform2F) -> Option<Handle> {
+        let handles = Some(Handle::Rotate).into_iter().chain(Handle::SCALE_HANDLES.iter().cloned());
+        for handle in handles {
+            let offset = self.handle_position(handle, camera) - position;
+            if offset.x().abs() <= HANDLE_RADIUS && offset.y().abs() <= HANDLE_RADIUS {
+                return Some(handle);
+            }
+        }
+        let to_screen = *camera * self.transform;
+        if self.bounds.contains_point(to_screen.inverse() * position) {
+            return Some(Handle::Move);
+        }
+        None
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// GESTURES
+///////////////////////////////////////////////////////////////////////////////
+
+/// A drag in progress with the selection tool.
+#[derive(Clone, Debug)]
+pub enum SelectionGesture {
+    /// The points of the lasso so far, in world coordinates.
+    Lasso(Vec<Vector2F>),
+    Rectangle { start: Vector2F, end: Vector2F },
+    Transform(TransformDrag),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TransformDrag {
+    pub handle: Handle,
+    /// Where the drag started, in world coordinates.
+    pub start: Vector2F,
+    /// The selection's transform when the drag started.
+    pub start_transform: pf::Transform2F,
+}
+
+impl TransformDrag {
+    pub fn new(selection: &Selection, handle: Handle, start: Vector2F) -> TransformDrag {
+        TransformDrag { handle, start, start_transform: selection.transform }
+    }
+    /// The selection transform for the cursor at `position`, in world
+    /// coordinates. With `constrain`, corner handles keep the aspect ratio
+    /// and rotation snaps to 15° steps.
+    pub fn transform_at(&self, bounds: RectF, position: Vector2F, constrain: bool) -> pf::Transform2F {
+        let start_transform = self.start_transform;
+        match self.handle {
+            Handle::Move => {
+                pf::Transform2F::from_translation(position - self.start) * start_transform
+            }
+            Handle::Rotate => {
+                let center = start_transform * bounds.center();
+                let from = self.start - center;
+                let to = position - center;
+                let mut angle = to.y().atan2(to.x()) - from.y().atan2(from.x());
+                if constrain {
+                    let step = FRAC_PI_2 / 6.0;
+                    angle = (angle / step).round() * step;
+                }
+                pf::Transform2F::from_translation(center) *
+                    pf::Transform2F::from_rotation(angle) *
+                    pf::Transform2F::from_translation(-center) *
+                    start_transform
+            }
+            Handle::Scale { x, y } => {
+                // Scale in the box's own frame, about the opposite handle.
+                let to_local = start_transform.inverse();
+                let local = to_local * position;
+                let center = bounds.center();
+                let half_size = bounds.size() * 0.5;
+                let side = Vector2F::new(x as f32, y as f32);
+                let anchor = center - half_size * side;
+                let handle = center + half_size * side;
+                let scale_along = |axis: i8, current: f32, handle: f32, anchor: f32| {
+                    if axis == 0 || handle == anchor {
+                        return 1.0;
+                    }
+                    let scale = (current - anchor) / (handle - anchor);
+                    if scale.abs() < MIN_SCALE {
+                        MIN_SCALE.copysign(scale)
+                    } else {
+                        scale
+                    }
+                };
+                let mut scale = Vector2F::new(
+                    scale_along(x, local.x(), handle.x(), anchor.x()),
+                    scale_along(y, local.y(), handle.y(), anchor.y()),
+                );
+                if constrain && x != 0 && y != 0 {
+                    let uniform = scale.x().abs().max(scale.y().abs());
+                    scale = Vector2F::new(
+                        uniform.copysign(scale.x()),
+                        uniform.copysign(scale.y()),
+                    );
+                }
+                start_transform *
+                    pf::Transform2F::from_translation(anchor) *
+                    pf::Transform2F::from_scale(scale) *
+                    pf::Transform2F::from_translation(-anchor)
+            }
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// TOOL
+///////////////////////////////////////////////////////////////////////////////
+
+pub struct SelectTool {
+    pub mode: SelectionMode,
+    pub selection: Option<Selection>,
+    pub gesture: Option<SelectionGesture>,
+}
+
+impl Default for SelectTool {
+    fn default() -> Self {
+        SelectTool { mode: SelectionMode::Lasso, selection: None, gesture: None }
+    }
+}
+
+impl SelectTool {
+    /// Drops the selection, for edits that may leave its box out of date.
+    pub fn clear(&mut self, ctx: &mut ToolContext) {
+        self.finish(ctx);
+        self.selection = None;
+        ctx.redraw = true;
+    }
+}
+
+impl Tool for SelectTool {
+    /// Starts transforming the selection if the pointer is on one of its
+    /// handles, and a new lasso or marquee otherwise.
+    fn pointer_down(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        let point = event.position;
+        let handle = self
+            .selection
+            .as_ref()
+            .and_then(|selection| selection.handle_at(event.screen_position, &ctx.view));
+        self.gesture = Some(match (handle, self.selection.as_ref()) {
+            (Some(handle), Some(selection)) => {
+                SelectionGesture::Transform(TransformDrag::new(selection, handle, point))
+            }
+            _ => {
+                self.selection = None;
+                match self.mode {
+                    SelectionMode::Lasso => SelectionGesture::Lasso(vec![point]),
+                    SelectionMode::Rectangle => {
+                        SelectionGesture::Rectangle { start: point, end: point }
+                    }
+                }
+            }
+        });
+        ctx.redraw = true;
+    }
+    /// Transforms are applied to the strokes as the drag goes, so the scene
+    /// previews them, and recorded once the drag ends.
+    fn pointer_move(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        let point = event.position;
+        match self.gesture {
+            Some(SelectionGesture::Lasso(ref mut points)) => points.push(point),
+            Some(SelectionGesture::Rectangle { ref mut end, .. }) => *end = point,
+            Some(SelectionGesture::Transform(drag)) => {
+                let selection = match self.selection {
+                    Some(ref mut selection) => selection,
+                    None => return,
+                };
+                let transform = drag.transform_at(selection.bounds, point, event.modifiers.shift());
+                let delta = transform * selection.transform.inverse();
+                selection.transform = transform;
+                Command::Transform { ids: selection.ids.clone(), transform: delta }
+                    .apply(ctx.document);
+            }
+            None => return,
+        }
+        ctx.redraw = true;
+    }
+    fn pointer_up(&mut self, ctx: &mut ToolContext, _: &PointerEvent) {
+        self.finish(ctx);
+    }
+    fn finish(&mut self, ctx: &mut ToolContext) {
+        match self.gesture.take() {
+            Some(SelectionGesture::Lasso(points)) => {
+                self.selection = Selection::from_lasso(ctx.document, &points);
+            }
+            Some(SelectionGesture::Rectangle { start, end }) => {
+                let rect = RectF::from_points(start.min(end), start.max(end));
+                self.selection = Selection::from_rect(ctx.document, rect);
+            }
+            Some(SelectionGesture::Transform(drag)) => {
+                if let Some(ref selection) = self.selection {
+                    let transform = selection.transform * drag.start_transform.inverse();
+                    if !transform.is_identity() {
+                        ctx.history.record(Command::Transform {
+                            ids: selection.ids.clone(),
+                            transform,
+                        });
+                    }
+                }
+            }
+            None => return,
+        }
+        ctx.redraw = true;
+    }
+    /// Puts transformed strokes back where the drag found them.
+    fn cancel(&mut self, ctx: &mut ToolContext) {
+        match self.gesture.take() {
+            Some(SelectionGesture::Transform(drag)) => {
+                if let Some(ref mut selection) = self.selection {
+                    let transform = drag.start_transform * selection.transform.inverse();
+                    selection.transform = drag.start_transform;
+                    Command::Transform { ids: selection.ids.clone(), transform }
+                        .apply(ctx.document);
+                }
+            }
+            Some(_) => {}
+            None => return,
+        }
+        ctx.redraw = true;
+    }
+    fn draw_overlay(&self, ctx: &mut pf::CanvasRenderingContext2D, view: &pf::Transform2F) {
+        draw_overlay(ctx, self.selection.as_ref(), self.gesture.as_ref(), view);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// GEOMETRY
+///////////////////////////////////////////////////////////////////////////////
+
+/// Even-odd test; the polygon is implicitly closed.
+pub fn polygon_contains_point(polygon: &[Vector2F], point: Vector2F) -> bool {
+    let mut inside = false;
+    let mut previous = match polygon.last() {
+        Some(previous) => *previous,
+        None => return false,
+    };
+    for current in polygon.iter().cloned() {
+        if (current.y() > point.y()) != (previous.y() > point.y()) {
+            let t = (point.y() - current.y()) / (previous.y() - current.y());
+            let x = current.x() + t * (previous.x() - current.x());
+            if point.x() < x {
+                inside = !inside;
+            }
+        }
+        previous = current;
+    }
+    inside
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// OVERLAY
+///////////////////////////////////////////////////////////////////////////////
+
+/// Draws the selection box and its handles, and the lasso or marquee being
+/// dragged, in device pixels. `camera` maps world coordinates there.
+pub fn draw_overlay(
+    ctx: &mut pf::CanvasRenderingContext2D,
+    selection: Option<&Selection>,
+    gesture: Option<&SelectionGesture>,
+    camera: &pf::Transform2F,
+) {
+    ctx.set_transform(&pf::Transform2F::default());
+    ctx.set_line_width(1.0);
+    ctx.set_line_dash(vec![]);
+    ctx.set_stroke_style(pf::FillStyle::Color(OVERLAY_COLOR));
+    ctx.set_fill_style(pf::FillStyle::Color(pf::ColorU::white()));
+    match gesture {
+        Some(SelectionGesture::Lasso(points)) if !points.is_empty() => {
+            let mut path = pf::Path2D::new();
+            path.move_to(*camera * points[0]);
+            for point in points.iter().skip(1) {
+                path.line_to(*camera * *point);
+            }
+            path.close_path();
+            ctx.set_line_dash(vec![4.0, 4.0]);
+            ctx.stroke_path(path);
+            ctx.set_line_dash(vec![]);
+        }
+        Some(SelectionGesture::Rectangle { start, end }) => {
+            let rect = RectF::from_points(start.min(*end), start.max(*end));
+            ctx.set_line_dash(vec![4.0, 4.0]);
+            ctx.stroke_path(closed_path(&corners_of(*camera, rect)));
+            ctx.set_line_dash(vec![]);
+        }
+        _ => {}
+    }
+    let selection = match selection {
+        Some(selection) => selection,
+        None => return,
+    };
+    let corners: Vec<Vector2F> = selection.corners().iter().map(|corner| *camera * *corner).collect();
+    ctx.stroke_path(closed_path(&corners));
+    let top = selection.handle_position(Handle::Scale { x: 0, y: -1 }, camera);
+    let rotate = selection.handle_position(Handle::Rotate, camera);
+    let mut stem = pf::Path2D::new();
+    stem.move_to(top);
+    stem.line_to(rotate);
+    ctx.stroke_path(stem);
+    for handle in Handle::SCALE_HANDLES.iter() {
+        let center = selection.handle_position(*handle, camera);
+        let rect = RectF::from_points(center, center).dilate(HANDLE_RADIUS);
+        ctx.fill_rect(rect);
+        ctx.stroke_rect(rect);
+    }
+    let mut knob = pf::Path2D::new();
+    knob.ellipse(rotate, Vector2F::splat(HANDLE_RADIUS), 0.0, 0.0, PI * 2.0);
+    ctx.fill_path(knob.clone(), pf::FillRule::Winding);
+    ctx.stroke_path(knob);
+}
+
+fn corners_of(transform: pf::Transform2F, rect: RectF) -> [Vector2F; 4] {
+    [
+        transform * rect.origin(),
+        transform * rect.upper_right(),
+        transform * rect.lower_right(),
+        transform * rect.lower_left(),
+    ]
+}
+
+fn closed_path(points: &[Vector2F]) -> pf::Path2D {
+    let mut path = pf::Path2D::new();
+    path.move_to(points[0]);
+    for point in points.iter().skip(1) {
+        path.line_to(*point);
+    }
+    path.close_path();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use crate::app::document::{StrokeOrigin, StrokePoint, StrokeStyle};
+    use crate::app::fitting::FitOptions;
+    use crate::app::history::History;
+    use crate::engine::pointer::{PointerButtons, PointerId, PointerKind};
+    use super::*;
+
+    fn bounds() -> RectF {
+        RectF::new(Vector2F::zero(), Vector2F::new(100.0, 50.0))
+    }
+
+    fn selection(transform: pf::Transform2F) -> Selection {
+        Selection { ids: Vec::new(), bounds: bounds(), transform }
+    }
+
+    fn assert_close(a: Vector2F, b: Vector2F) {
+        assert!((a - b).length() < 1.0e-3, "{:?} != {:?}", a, b);
+    }
+
+    /// Drags `handle` of the selection `bounds` by `offset`.
+    fn drag(handle: Handle, offset: Vector2F, constrain: bool) -> pf::Transform2F {
+        let selection = selection(Default::default());
+        let start = selection.handle_position(handle, &pf::Transform2F::default());
+        let drag = TransformDrag::new(&selection, handle, start);
+        drag.transform_at(bounds(), start + offset, constrain)
+    }
+
+    #[test]
+    fn polygons_contain_points_by_even_odd() {
+        let notched = [
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(30.0, 0.0),
+            Vector2F::new(30.0, 30.0),
+            Vector2F::new(20.0, 30.0),
+            Vector2F::new(20.0, 10.0),
+            Vector2F::new(10.0, 10.0),
+            Vector2F::new(10.0, 30.0),
+            Vector2F::new(0.0, 30.0),
+        ];
+        assert!(polygon_contains_point(&notched, Vector2F::new(5.0, 20.0)));
+        assert!(polygon_contains_point(&notched, Vector2F::new(15.0, 5.0)));
+        assert!(!polygon_contains_point(&notched, Vector2F::new(15.0, 20.0)));
+        assert!(!polygon_contains_point(&notched, Vector2F::new(40.0, 5.0)));
+        // A pentagram's middle is covered twice, which counts as outside.
+        let pentagram: Vec<Vector2F> = [0, 2, 4, 1, 3]
+            .iter()
+            .map(|&index| {
+                let angle = PI * (0.4 * index as f32 - 0.5);
+                Vector2F::new(angle.cos(), angle.sin()) * 10.0
+            })
+            .collect();
+        assert!(!polygon_contains_point(&pentagram, Vector2F::zero()));
+        assert!(polygon_contains_point(&pentagram, Vector2F::new(0.0, -7.0)));
+        assert!(!polygon_contains_point(&[], Vector2F::zero()));
+    }
+
+    #[test]
+    fn scale_handles_move_to_the_pointer_about_the_opposite_side() {
+        let center = bounds().center();
+        let half_size = bounds().size() * 0.5;
+        for handle in Handle::SCALE_HANDLES.iter() {
+            let (x, y) = match *handle {
+                Handle::Scale { x, y } => (x as f32, y as f32),
+                _ => unreachable!(),
+            };
+            let side = Vector2F::new(x, y);
+            let offset = Vector2F::new(10.0, -20.0);
+            let transform = drag(*handle, offset, false);
+            let anchor = center - half_size * side;
+            assert_close(transform * anchor, anchor);
+            // Only the axes the handle scales follow the pointer.
+            let moved = Vector2F::new(offset.x() * x.abs(), offset.y() * y.abs());
+            let handle = center + half_size * side;
+            assert_close(transform * handle, handle + moved);
+        }
+    }
+
+    #[test]
+    fn shift_keeps_the_aspect_ratio_of_corners_only() {
+        let corner = drag(Handle::Scale { x: 1, y: 1 }, Vector2F::new(50.0, 10.0), true);
+        assert_close(corner * bounds().lower_right(), Vector2F::new(150.0, 75.0));
+        assert_close(corner * bounds().origin(), Vector2F::zero());
+        let edge = drag(Handle::Scale { x: 1, y: 0 }, Vector2F::new(50.0, 10.0), true);
+        assert_close(edge * bounds().lower_right(), Vector2F::new(150.0, 50.0));
+    }
+
+    #[test]
+    fn scaling_past_the_anchor_flips_but_never_collapses() {
+        let handle = Handle::Scale { x: 1, y: 0 };
+        let collapsed = drag(handle, Vector2F::new(-100.0, 0.0), false);
+        assert_close(collapsed * bounds().upper_right(), Vector2F::new(100.0 * MIN_SCALE, 0.0));
+        let flipped = drag(handle, Vector2F::new(-200.0, 0.0), false);
+        assert_close(flipped * bounds().upper_right(), Vector2F::new(-100.0, 0.0));
+        assert_close(flipped * bounds().origin(), Vector2F::zero());
+    }
+
+    #[test]
+    fn rotation_snaps_to_fifteen_degrees_with_shift() {
+        let center = bounds().center();
+        // From straight above the center to 20° clockwise of it.
+        let angle = 20.0f32.to_radians();
+        let above = Vector2F::new(center.x(), -25.0);
+        let turned = center + Vector2F::new(angle.sin(), -angle.cos()) * (center.y() - above.y());
+        let selection = selection(Default::default());
+        let drag = TransformDrag::new(&selection, Handle::Rotate, above);
+        let free = drag.transform_at(bounds(), turned, false);
+        let snapped = drag.transform_at(bounds(), turned, true);
+        assert!((free.rotation() - angle).abs() < 1.0e-4);
+        assert!((snapped.rotation() - 15.0f32.to_radians()).abs() < 1.0e-4);
+        assert_close(snapped * center, center);
+    }
+
+    #[test]
+    fn drags_build_on_the_transform_they_start_from() {
+        let start_transform = pf::Transform2F::from_rotation(FRAC_PI_2);
+        let selection = selection(start_transform);
+        let corner = start_transform * bounds().lower_right();
+        let drag = TransformDrag::new(&selection, Handle::Scale { x: 1, y: 1 }, corner);
+        let target = start_transform * Vector2F::new(200.0, 100.0);
+        let transform = drag.transform_at(bounds(), target, false);
+        assert_close(transform * bounds().lower_right(), target);
+        assert_close(transform * bounds().origin(), Vector2F::zero());
+        let drag = TransformDrag::new(&selection, Handle::Move, corner);
+        let moved = drag.transform_at(bounds(), corner + Vector2F::new(5.0, 5.0), false);
+        assert_close(moved * bounds().origin(), Vector2F::new(5.0, 5.0));
+    }
+
+    #[test]
+    fn cancelled_transforms_put_strokes_back() {
+        let mut document = Document::new();
+        let id = document.begin_stroke(StrokeStyle::default(), StrokeOrigin::Brush).unwrap();
+        for index in 0..4 {
+            let position = Vector2F::splat(index as f32 * 20.0);
+            document.push_point(id, StrokePoint { position, pressure: 1.0, time: 0.0 });
+        }
+        let before = document.layers().to_vec();
+        let mut history = History::default();
+        let (fit_options, brush) = (FitOptions::default(), StrokeStyle::default());
+        let mut ctx = ToolContext {
+            document: &mut document,
+            history: &mut history,
+            fit_options: &fit_options,
+            brush: &brush,
+            view: pf::Transform2F::default(),
+            redraw: false,
+        };
+        let mut tool = SelectTool::default();
+        let area = RectF::new(Vector2F::splat(-20.0), Vector2F::splat(100.0));
+        tool.selection = Selection::from_rect(ctx.document, area);
+        let center = tool.selection.as_ref().unwrap().bounds.center();
+        tool.pointer_down(&mut ctx, &press(center));
+        tool.pointer_move(&mut ctx, &press(center + Vector2F::new(30.0, 0.0)));
+        assert_eq!(ctx.document.stroke(id).unwrap().points[0].position.x(), 30.0);
+        tool.cancel(&mut ctx);
+        assert_eq!(ctx.document.layers(), &before[..]);
+        assert!(tool.selection.as_ref().unwrap().transform.is_identity());
+        assert!(tool.gesture.is_none());
+        assert!(!ctx.history.can_undo());
+    }
+
+    fn press(position: Vector2F) -> PointerEvent {
+        PointerEvent {
+            id: PointerId::Mouse,
+            kind: PointerKind::Mouse,
+            position,
+            screen_position: position,
+            buttons: PointerButtons { primary: true, ..Default::default() },
+            modifiers: wit::ModifiersState::empty(),
+            pressure: 1.0,
+            tilt: None,
+            time: Instant::now(),
+        }
+    }
+}
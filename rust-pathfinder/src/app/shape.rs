@@ -0,0 +1,382 @@
+//! Parametric shapes: strokes that keep the parameters they were made from,
+//! so that they stay exact and editable rather than being a list of points.
+//!
+//! A shape is described in its own local coordinates and placed by a
+//! transform, which is what selection edits. Geometry is produced as cubic
+//! segments, the same currency as freehand strokes after fitting.
+use std::f32::consts::PI;
+use crate::prelude::*;
+use super::fitting::CubicSegment;
+use super::paint::Fill;
+
+/// Length, in document units, of the pieces `Shape::sample` flattens to.
+const SAMPLE_SPACING: f32 = 4.0;
+const MAX_SAMPLES_PER_SEGMENT: usize = 64;
+
+/// Arcs are split into pieces of at most a quarter turn, where a cubic is
+/// within 0.03% of the circle.
+const MAX_ARC_PIECE: f32 = PI * 0.5;
+
+const EPSILON: f32 = 1.0e-6;
+
+///////////////////////////////////////////////////////////////////////////////
+// SHAPES
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArrowHead {
+    None,
+    /// Two barbs.
+    Open,
+    /// A closed triangle.
+    Closed,
+}
+
+impl ArrowHead {
+    pub const ALL: [ArrowHead; 3] = [ArrowHead::None, ArrowHead::Open, ArrowHead::Closed];
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShapeKind {
+    Line {
+        from: Vector2F,
+        to: Vector2F,
+        start_head: ArrowHead,
+        end_head: ArrowHead,
+        /// Length of the arrowheads along the line.
+        head_size: f32,
+    },
+    /// Part of a circle. Angles are in radians, clockwise on screen since
+    /// the y axis points down.
+    Arc {
+        center: Vector2F,
+        radius: f32,
+        start_angle: f32,
+        sweep_angle: f32,
+    },
+    Ellipse {
+        center: Vector2F,
+        radii: Vector2F,
+    },
+    Rectangle {
+        rect: RectF,
+        /// Clamped to half the shorter side when drawn.
+        corner_radius: f32,
+    },
+    /// A regular polygon with a vertex at the top, stretched to `radii`.
+    Polygon {
+        center: Vector2F,
+        radii: Vector2F,
+        sides: u32,
+    },
+    /// Points alternate between `radii` and `radii * inner_ratio`, starting
+    /// at the top.
+    Star {
+        center: Vector2F,
+        radii: Vector2F,
+        inner_ratio: f32,
+        points: u32,
+    },
+    /// Straight segments through `vertices`, e.g. a triangle.
+    Polyline {
+        vertices: Vec<Vector2F>,
+        closed: bool,
+    },
+    /// Any outline, e.g. from imported artwork.
+    Path {
+        contours: Vec<ShapeContour>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shape {
+    pub kind: ShapeKind,
+    /// Maps the shape's local coordinates to the document.
+    pub transform: pf::Transform2F,
+    /// Paints the inside, in local coordinates.
+    pub fill: Option<Fill>,
+    /// Whether the outline is drawn, in the stroke's style.
+    pub stroked: bool,
+}
+
+/// A connected run of segments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeContour {
+    pub segments: Vec<CubicSegment>,
+    pub closed: bool,
+}
+
+impl Shape {
+    pub fn new(kind: ShapeKind) -> Self {
+        Shape { kind, transform: pf::Transform2F::default(), fill: None, stroked: true }
+    }
+
+    /// Applies `transform` after the shape's own.
+    pub fn apply_transform(&mut self, transform: &pf::Transform2F) {
+        self.transform = *transform * self.transform;
+    }
+
+    /// The outline to stroke, in document units.
+    pub fn contours(&self) -> Vec<ShapeContour> {
+        let mut contours = self.local_contours();
+        if !self.transform.is_identity() {
+            for contour in contours.iter_mut() {
+                for segment in contour.segments.iter_mut() {
+                    *segment = transform_segment(&self.transform, segment);
+                }
+            }
+        }
+        contours
+    }
+
+    /// Points along every contour in turn, in document units and no more
+    /// than `SAMPLE_SPACING` apart within a contour. This is what the
+    /// stroke's point list holds, for hit-testing and indexing.
+    pub fn sample(&self) -> Vec<Vector2F> {
+        let mut points = Vec::new();
+        for contour in self.contours() {
+            if let Some(first) = contour.segments.first() {
+                points.push(first.from);
+            }
+            for segment in contour.segments.iter() {
+                // The curve moves at most three times its longest control
+                // leg per unit of t, so even steps in t stay this close.
+                let max_leg = (segment.ctrl0 - segment.from)
+                    .length()
+                    .max((segment.ctrl1 - segment.ctrl0).length())
+                    .max((segment.to - segment.ctrl1).length());
+                let count = ((max_leg * 3.0 / SAMPLE_SPACING).ceil() as usize)
+                    .max(1)
+                    .min(MAX_SAMPLES_PER_SEGMENT);
+                for index in 1..=count {
+                    points.push(segment.sample(index as f32 / count as f32));
+                }
+            }
+        }
+        points
+    }
+
+    fn local_contours(&self) -> Vec<ShapeContour> {
+        match self.kind {
+            ShapeKind::Line { from, to, start_head, end_head, head_size } => {
+                let mut contours = vec![ShapeContour {
+                    segments: vec![CubicSegment::line(from, to)],
+                    closed: false,
+                }];
+                contours.extend(arrow_head(to, from, end_head, head_size));
+                contours.extend(arrow_head(from, to, start_head, head_size));
+                contours
+            }
+            ShapeKind::Arc { center, radius, start_angle, sweep_angle } => {
+                vec![ShapeContour {
+                    segments: arc(center, Vector2F::splat(radius), start_angle, sweep_angle),
+                    closed: false,
+                }]
+            }
+            ShapeKind::Ellipse { center, radii } => {
+                vec![ShapeContour { segments: arc(center, radii, 0.0, PI * 2.0), closed: true }]
+            }
+            ShapeKind::Rectangle { rect, corner_radius } => {
+                vec![ShapeContour { segments: rounded_rect(rect, corner_radius), closed: true }]
+            }
+            ShapeKind::Polygon { center, radii, sides } => {
+                let vertices = star_vertices(center, radii, 1.0, sides);
+                vec![closed_polyline(&vertices)]
+            }
+            ShapeKind::Star { center, radii, inner_ratio, points } => {
+                let vertices = star_vertices(center, radii, inner_ratio, points * 2);
+                vec![closed_polyline(&vertices)]
+            }
+            ShapeKind::Polyline { ref vertices, closed } => {
+                let mut segments: Vec<CubicSegment> = vertices
+                    .windows(2)
+                    .map(|pair| CubicSegment::line(pair[0], pair[1]))
+                    .collect();
+                if closed && vertices.len() > 2 {
+                    segments.push(CubicSegment::line(vertices[vertices.len() - 1], vertices[0]));
+                }
+                vec![ShapeContour { segments, closed }]
+            }
+            ShapeKind::Path { ref contours } => contours.clone(),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// GEOMETRY
+///////////////////////////////////////////////////////////////////////////////
+
+/// The head at `tip` of a line coming from `tail`: barb, tip, barb.
+fn arrow_head(tip: Vector2F, tail: Vector2F, head: ArrowHead, size: f32) -> Option<ShapeContour> {
+    let direction = tip - tail;
+    if head == ArrowHead::None || direction.length() < EPSILON {
+        return None;
+    }
+    let direction = direction.normalize();
+    let normal = Vector2F::new(-direction.y(), direction.x());
+    let base = tip - direction * size;
+    let left = base + normal * (size * 0.5);
+    let right = base - normal * (size * 0.5);
+    let mut segments = vec![CubicSegment::line(left, tip), CubicSegment::line(tip, right)];
+    let closed = head == ArrowHead::Closed;
+    if closed {
+        segments.push(CubicSegment::line(right, left));
+    }
+    Some(ShapeContour { segments, closed })
+}
+
+/// Cubic pieces of the elliptical arc around `center`, starting at
+/// `start_angle` and turning by `sweep_angle`.
+pub fn arc(center: Vector2F, radii: Vector2F, start_angle: f32, sweep_angle: f32) -> Vec<CubicSegment> {
+    let count = (sweep_angle.abs() / MAX_ARC_PIECE).ceil().max(1.0) as usize;
+    let step = sweep_angle / count as f32;
+    // Distance of the control points along the tangent, for a unit circle.
+    let handle = 4.0 / 3.0 * (step * 0.25).tan();
+    let point = |angle: f32| center + Vector2F::new(angle.cos(), angle.sin()) * radii;
+    let tangent = |angle: f32| Vector2F::new(-angle.sin(), angle.cos()) * radii * handle;
+    (0..count)
+        .map(|index| {
+            let angle0 = start_angle + step * index as f32;
+            let angle1 = angle0 + step;
+            let from = point(angle0);
+            let to = point(angle1);
+            CubicSegment { from, ctrl0: from + tangent(angle0), ctrl1: to - tangent(angle1), to }
+        })
+        .collect()
+}
+
+/// Clockwise from the top left corner.
+fn rounded_rect(rect: RectF, corner_radius: f32) -> Vec<CubicSegment> {
+    let (min, max) = (rect.origin(), rect.lower_right());
+    let radius = corner_radius
+        .max(0.0)
+        .min(rect.width().abs() * 0.5)
+        .min(rect.height().abs() * 0.5);
+    if radius < EPSILON {
+        let corners = [min, rect.upper_right(), max, rect.lower_left()];
+        return (0..4).map(|index| CubicSegment::line(corners[index], corners[(index + 1) % 4])).collect();
+    }
+    let inset = Vector2F::splat(radius);
+    let radii = Vector2F::splat(radius);
+    let centers = [
+        min + inset,
+        Vector2F::new(max.x() - radius, min.y() + radius),
+        max - inset,
+        Vector2F::new(min.x() + radius, max.y() - radius),
+    ];
+    let mut segments = Vec::with_capacity(8);
+    for (index, center) in centers.iter().enumerate() {
+        // The top left corner turns from pointing left to pointing up.
+        let start_angle = PI * (1.0 + 0.5 * index as f32);
+        let corner = arc(*center, radii, start_angle, PI * 0.5);
+        let corner_end = corner[corner.len() - 1].to;
+        segments.extend(corner);
+        let next_center = centers[(index + 1) % 4];
+        let next_start = next_center + Vector2F::new(
+            (start_angle + PI * 0.5).cos(),
+            (start_angle + PI * 0.5).sin(),
+        ) * radii;
+        segments.push(CubicSegment::line(corner_end, next_start));
+    }
+    segments
+}
+
+/// `count` vertices around `center` from the top, clockwise on screen, with
+/// every other one pulled in by `inner_ratio`.
+fn star_vertices(center: Vector2F, radii: Vector2F, inner_ratio: f32, count: u32) -> Vec<Vector2F> {
+    (0..count)
+        .map(|index| {
+            let angle = PI * (2.0 * index as f32 / count as f32 - 0.5);
+            let scale = if index % 2 == 0 { 1.0 } else { inner_ratio };
+            center + Vector2F::new(angle.cos(), angle.sin()) * radii * scale
+        })
+        .collect()
+}
+
+fn closed_polyline(vertices: &[Vector2F]) -> ShapeContour {
+    let segments = (0..vertices.len())
+        .map(|index| CubicSegment::line(vertices[index], vertices[(index + 1) % vertices.len()]))
+        .collect();
+    ShapeContour { segments, closed: true }
+}
+
+fn transform_segment(transform: &pf::Transform2F, segment: &CubicSegment) -> CubicSegment {
+    CubicSegment {
+        from: *transform * segment.from,
+        ctrl0: *transform * segment.ctrl0,
+        ctrl1: *transform * segment.ctrl1,
+        to: *transform * segment.to,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vector2F, b: Vector2F) {
+        assert!((a - b).length() < 1.0e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn corner_radii_clamp_to_half_the_shorter_side() {
+        let rect = RectF::new(Vector2F::zero(), Vector2F::new(100.0, 40.0));
+        let segments = rounded_rect(rect, 50.0);
+        // Corner, top edge, corner, right edge, and so on.
+        assert_eq!(segments.len(), 8);
+        assert_close(segments[0].from, Vector2F::new(0.0, 20.0));
+        assert_close(segments[1].from, Vector2F::new(20.0, 0.0));
+        assert_close(segments[1].to, Vector2F::new(80.0, 0.0));
+        // The sides are all corner.
+        assert_close(segments[3].from, segments[3].to);
+        assert_close(segments[7].to, segments[0].from);
+        let sharp = rounded_rect(rect, -5.0);
+        assert_eq!(sharp.len(), 4);
+        assert_close(sharp[2].from, rect.lower_right());
+    }
+
+    #[test]
+    fn arcs_stay_on_the_circle() {
+        let center = Vector2F::new(10.0, -20.0);
+        for &(sweep, pieces) in [(PI * 0.5, 1), (PI * -0.75, 2), (PI * 2.0, 4)].iter() {
+            let segments = arc(center, Vector2F::splat(100.0), 0.3, sweep);
+            assert_eq!(segments.len(), pieces);
+            let end = center + Vector2F::new((0.3 + sweep).cos(), (0.3 + sweep).sin()) * 100.0;
+            assert_close(segments[pieces - 1].to, end);
+            for segment in segments.iter() {
+                for step in 0..=16 {
+                    let distance = (segment.sample(step as f32 / 16.0) - center).length();
+                    // A quarter turn is within 0.03% of the radius.
+                    assert!((distance - 100.0).abs() < 0.03, "{} off the circle", distance);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn star_vertices_alternate_clockwise_from_the_top() {
+        let vertices = star_vertices(Vector2F::zero(), Vector2F::new(20.0, 10.0), 0.5, 10);
+        assert_eq!(vertices.len(), 10);
+        assert_close(vertices[0], Vector2F::new(0.0, -10.0));
+        assert!(vertices[1].x() > 0.0, "clockwise on screen");
+        for (index, vertex) in vertices.iter().enumerate() {
+            let scale = if index % 2 == 0 { 1.0 } else { 0.5 };
+            let on_ellipse = (*vertex / (Vector2F::new(20.0, 10.0) * scale)).length();
+            assert!((on_ellipse - 1.0).abs() < 1.0e-4);
+        }
+    }
+
+    #[test]
+    fn samples_follow_transformed_outlines_closely() {
+        let mut shape = Shape::new(ShapeKind::Ellipse {
+            center: Vector2F::zero(),
+            radii: Vector2F::new(60.0, 30.0),
+        });
+        shape.apply_transform(&pf::Transform2F::from_translation(Vector2F::new(100.0, 0.0)));
+        let points = shape.sample();
+        assert_close(points[0], Vector2F::new(160.0, 0.0));
+        assert_close(*points.last().unwrap(), points[0]);
+        for pair in points.windows(2) {
+            assert!((pair[1] - pair[0]).length() <= SAMPLE_SPACING);
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Tools that draw parametric shapes by dragging, and the parameters they
+//! draw with.
+//!
+//! Shift constrains the drag to a square, circle or regular polygon, and
+//! lines to multiples of 45°. Alt draws from the center instead of from a
+//! corner or end.
+use std::f32::consts::PI;
+use crate::prelude::*;
+use super::document::{StrokeId, StrokeOrigin};
+use super::shape::{ArrowHead, Shape, ShapeKind};
+use super::tool::{PointerEvent, Tool, ToolContext};
+
+/// Lines snap to multiples of this angle while constrained.
+const LINE_SNAP_ANGLE: f32 = PI * 0.25;
+
+/// Drags smaller than this, in document units, draw nothing.
+const MIN_DRAG_SIZE: f32 = 1.0;
+
+pub const MIN_VERTEX_COUNT: u32 = 3;
+pub const MAX_VERTEX_COUNT: u32 = 64;
+
+const CORNER_RADIUS_STEP: f32 = 4.0;
+const HEAD_SIZE_FACTOR: f32 = 1.25;
+const MIN_HEAD_SIZE: f32 = 2.0;
+const MAX_HEAD_SIZE: f32 = 512.0;
+
+///////////////////////////////////////////////////////////////////////////////
+// OPTIONS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShapeType {
+    Rectangle,
+    Ellipse,
+    Polygon,
+    Star,
+    Line,
+    Arrow,
+}
+
+impl ShapeType {
+    pub const ALL: [ShapeType; 6] = [
+        ShapeType::Rectangle,
+        ShapeType::Ellipse,
+        ShapeType::Polygon,
+        ShapeType::Star,
+        ShapeType::Line,
+        ShapeType::Arrow,
+    ];
+    pub fn next(self) -> ShapeType {
+        let index = ShapeType::ALL.iter().position(|other| *other == self).unwrap();
+        ShapeType::ALL[(index + 1) % ShapeType::ALL.len()]
+    }
+}
+
+/// Parameters of newly drawn shapes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShapeOptions {
+    pub shape_type: ShapeType,
+    pub corner_radius: f32,
+    pub sides: u32,
+    pub star_points: u32,
+    /// Inner radius of stars as a fraction of the outer radius.
+    pub star_inner_ratio: f32,
+    pub arrow_head: ArrowHead,
+    pub head_size: f32,
+}
+
+impl Default for ShapeOptions {
+    fn default() -> Self {
+        ShapeOptions {
+            shape_type: ShapeType::Rectangle,
+            corner_radius: 0.0,
+            sides: 6,
+            star_points: 5,
+            star_inner_ratio: 0.5,
+            arrow_head: ArrowHead::Open,
+            head_size: 16.0,
+        }
+    }
+}
+
+impl ShapeOptions {
+    /// Steps the main parameter of the current shape type, as
+    /// `step_parameter` does for existing shapes.
+    pub fn step_parameter(&mut self, steps: i32) {
+        match self.shape_type {
+            ShapeType::Rectangle => self.corner_radius = step_corner_radius(self.corner_radius, steps),
+            ShapeType::Ellipse | ShapeType::Line => {}
+            ShapeType::Polygon => self.sides = step_vertex_count(self.sides, steps),
+            ShapeType::Star => self.star_points = step_vertex_count(self.star_points, steps),
+            ShapeType::Arrow => self.head_size = step_head_size(self.head_size, steps),
+        }
+    }
+    pub fn cycle_arrow_head(&mut self) {
+        self.arrow_head = next_arrow_head(self.arrow_head);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// TOOL
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Default)]
+pub struct ShapeTool {
+    pub options: ShapeOptions,
+    /// Where the current drag started, in world coordinates, and the stroke
+    /// holding its shape.
+    drag: Option<(Vector2F, StrokeId)>,
+}
+
+impl ShapeTool {
+    /// Reshapes the stroke being dragged out to end at the pointer. Drags
+    /// too small to make a shape leave the stroke empty, which drops it
+    /// when the drag ends.
+    fn update_drag(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        let (start, id) = match self.drag {
+            Some(drag) => drag,
+            None => return,
+        };
+        let shape = shape_for_drag(
+            &self.options,
+            start,
+            event.position,
+            event.modifiers.shift(),
+            event.modifiers.alt(),
+        );
+        ctx.document.update_stroke(id, |stroke| match shape {
+            Some(shape) => stroke.set_shape(shape),
+            None => {
+                stroke.points.clear();
+                stroke.shape = None;
+            }
+        });
+        ctx.redraw = true;
+    }
+}
+
+impl Tool for ShapeTool {
+    fn pointer_down(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        self.finish(ctx);
+        if let Some(id) = ctx.document.begin_stroke(*ctx.brush, StrokeOrigin::ShapeTool) {
+            self.drag = Some((event.position, id));
+        }
+    }
+    fn pointer_move(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        self.update_drag(ctx, event);
+    }
+    fn pointer_up(&mut self, ctx: &mut ToolContext, _: &PointerEvent) {
+        self.finish(ctx);
+    }
+    /// Constraints apply as soon as Shift or Alt go down.
+    fn modifiers_changed(&mut self, ctx: &mut ToolContext, event: &PointerEvent) {
+        self.update_drag(ctx, event);
+    }
+    fn finish(&mut self, ctx: &mut ToolContext) {
+        if let Some((_, id)) = self.drag.take() {
+            ctx.finish_stroke(id);
+        }
+    }
+    fn cancel(&mut self, ctx: &mut ToolContext) {
+        if let Some((_, id)) = self.drag.take() {
+            ctx.document.remove_stroke(id);
+            ctx.redraw = true;
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// DRAWING
+///////////////////////////////////////////////////////////////////////////////
+
+/// The shape of a drag from `start` to `end`, in document units, or
+/// nothing if the drag is too small. `constrain` and `from_center` are the
+/// Shift and Alt behaviors.
+pub fn shape_for_drag(
+    options: &ShapeOptions,
+    start: Vector2F,
+    end: Vector2F,
+    constrain: bool,
+    from_center: bool,
+) -> Option<Shape> {
+    if let ShapeType::Line | ShapeType::Arrow = options.shape_type {
+        let mut delta = end - start;
+        if constrain {
+            let angle = (delta.y().atan2(delta.x()) / LINE_SNAP_ANGLE).round() * LINE_SNAP_ANGLE;
+            delta = Vector2F::new(angle.cos(), angle.sin()) * delta.length();
+        }
+        if delta.length() < MIN_DRAG_SIZE {
+            return None;
+        }
+        let from = if from_center { start - delta } else { start };
+        let end_head = match options.shape_type {
+            ShapeType::Arrow => options.arrow_head,
+            _ => ArrowHead::None,
+        };
+        return Some(Shape::new(ShapeKind::Line {
+            from,
+            to: start + delta,
+            start_head: ArrowHead::None,
+            end_head,
+            head_size: options.head_size,
+        }));
+    }
+
+    let mut delta = end - start;
+    if constrain {
+        let side = delta.x().abs().max(delta.y().abs());
+        delta = Vector2F::new(side.copysign(delta.x()), side.copysign(delta.y()));
+    }
+    let rect = if from_center {
+        let extent = Vector2F::new(delta.x().abs(), delta.y().abs());
+        RectF::from_points(start - extent, start + extent)
+    } else {
+        RectF::from_points(start.min(start + delta), start.max(start + delta))
+    };
+    if rect.width() < MIN_DRAG_SIZE && rect.height() < MIN_DRAG_SIZE {
+        return None;
+    }
+    let (center, radii) = (rect.center(), rect.size() * 0.5);
+    let kind = match options.shape_type {
+        ShapeType::Rectangle => ShapeKind::Rectangle { rect, corner_radius: options.corner_radius },
+        ShapeType::Ellipse => ShapeKind::Ellipse { center, radii },
+        ShapeType::Polygon => ShapeKind::Polygon { center, radii, sides: options.sides },
+        ShapeType::Star => ShapeKind::Star {
+            center,
+            radii,
+            inner_ratio: options.star_inner_ratio,
+            points: options.star_points,
+        },
+        ShapeType::Line | ShapeType::Arrow => unreachable!(),
+    };
+    Some(Shape::new(kind))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// EDITING
+///////////////////////////////////////////////////////////////////////////////
+
+/// Steps the main parameter of `kind`: the corner radius of rectangles, the
+/// sides of polygons, the points of stars, or the head size of arrows.
+/// Returns whether anything changed.
+pub fn step_parameter(kind: &mut ShapeKind, steps: i32) -> bool {
+    let before = kind.clone();
+    match kind {
+        ShapeKind::Rectangle { corner_radius, .. } => {
+            *corner_radius = step_corner_radius(*corner_radius, steps);
+        }
+        ShapeKind::Polygon { sides, .. } => *sides = step_vertex_count(*sides, steps),
+        ShapeKind::Star { points, .. } => *points = step_vertex_count(*points, steps),
+        ShapeKind::Line { start_head, end_head, head_size, .. } => {
+            if *start_head != ArrowHead::None || *end_head != ArrowHead::None {
+                *head_size = step_head_size(*head_size, steps);
+            }
+        }
+        ShapeKind::Arc { .. } |
+        ShapeKind::Ellipse { .. } |
+        ShapeKind::Polyline { .. } |
+        ShapeKind::Path { .. } => {}
+    }
+    *kind != before
+}
+
+/// Cycles the end of lines through no head, open and closed. Returns
+/// whether `kind` is a line.
+pub fn cycle_arrow_head(kind: &mut ShapeKind) -> bool {
+    match kind {
+        ShapeKind::Line { end_head, .. } => {
+            *end_head = next_arrow_head(*end_head);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn next_arrow_head(head: ArrowHead) -> ArrowHead {
+    let index = ArrowHead::ALL.iter().position(|other| *other == head).unwrap();
+    ArrowHead::ALL[(index + 1) % ArrowHead::ALL.len()]
+}
+
+fn step_corner_radius(radius: f32, steps: i32) -> f32 {
+    (radius + CORNER_RADIUS_STEP * steps as f32).max(0.0)
+}
+
+fn step_vertex_count(count: u32, steps: i32) -> u32 {
+    (count as i32 + steps).max(MIN_VERTEX_COUNT as i32).min(MAX_VERTEX_COUNT as i32) as u32
+}
+
+fn step_head_size(size: f32, steps: i32) -> f32 {
+    (size * HEAD_SIZE_FACTOR.powi(steps)).max(MIN_HEAD_SIZE).min(MAX_HEAD_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(shape_type: ShapeType) -> ShapeOptions {
+        ShapeOptions { shape_type, ..ShapeOptions::default() }
+    }
+
+    fn drag(shape_type: ShapeType, end: Vector2F, constrain: bool, from_center: bool) -> ShapeKind {
+        let start = Vector2F::new(50.0, 50.0);
+        shape_for_drag(&options(shape_type), start, end, constrain, from_center).unwrap().kind
+    }
+
+    fn assert_close(a: Vector2F, b: Vector2F) {
+        assert!((a - b).length() < 1.0e-3, "{:?} != {:?}", a, b);
+    }
+
+    fn rect(left: f32, top: f32, right: f32, bottom: f32) -> RectF {
+        RectF::from_points(Vector2F::new(left, top), Vector2F::new(right, bottom))
+    }
+
+    #[test]
+    fn drags_span_a_box_from_the_corner() {
+        let kind = drag(ShapeType::Rectangle, Vector2F::new(20.0, 60.0), false, false);
+        let rect = rect(20.0, 50.0, 50.0, 60.0);
+        assert_eq!(kind, ShapeKind::Rectangle { rect, corner_radius: 0.0 });
+    }
+
+    #[test]
+    fn shift_makes_squares_on_the_longer_side() {
+        let kind = drag(ShapeType::Rectangle, Vector2F::new(80.0, 40.0), true, false);
+        let rect = rect(50.0, 20.0, 80.0, 50.0);
+        assert_eq!(kind, ShapeKind::Rectangle { rect, corner_radius: 0.0 });
+        let kind = drag(ShapeType::Ellipse, Vector2F::new(40.0, 70.0), true, false);
+        let radii = Vector2F::splat(10.0);
+        assert_eq!(kind, ShapeKind::Ellipse { center: Vector2F::new(40.0, 60.0), radii });
+    }
+
+    #[test]
+    fn alt_draws_from_the_center() {
+        let kind = drag(ShapeType::Ellipse, Vector2F::new(60.0, 30.0), false, true);
+        let radii = Vector2F::new(10.0, 20.0);
+        assert_eq!(kind, ShapeKind::Ellipse { center: Vector2F::new(50.0, 50.0), radii });
+        let kind = drag(ShapeType::Polygon, Vector2F::new(60.0, 30.0), true, true);
+        let radii = Vector2F::splat(20.0);
+        assert_eq!(kind, ShapeKind::Polygon { center: Vector2F::new(50.0, 50.0), radii, sides: 6 });
+    }
+
+    #[test]
+    fn shift_snaps_lines_to_45_degrees() {
+        let cases = [
+            (Vector2F::new(60.0, 59.0), Vector2F::splat(1.0).normalize()),
+            (Vector2F::new(60.0, 51.0), Vector2F::new(1.0, 0.0)),
+            (Vector2F::new(49.0, 30.0), Vector2F::new(0.0, -1.0)),
+        ];
+        for &(end, direction) in cases.iter() {
+            let length = (end - Vector2F::splat(50.0)).length();
+            match drag(ShapeType::Line, end, true, false) {
+                ShapeKind::Line { from, to, end_head, .. } => {
+                    assert_close(from, Vector2F::splat(50.0));
+                    assert_close(to, Vector2F::splat(50.0) + direction * length);
+                    assert_eq!(end_head, ArrowHead::None);
+                }
+                kind => panic!("{:?} is not a line", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn alt_centers_arrows_on_the_start() {
+        match drag(ShapeType::Arrow, Vector2F::new(60.0, 40.0), false, true) {
+            ShapeKind::Line { from, to, end_head, head_size, .. } => {
+                assert_close(from, Vector2F::new(40.0, 60.0));
+                assert_close(to, Vector2F::new(60.0, 40.0));
+                assert_eq!(end_head, ArrowHead::Open);
+                assert_eq!(head_size, ShapeOptions::default().head_size);
+            }
+            kind => panic!("{:?} is not a line", kind),
+        }
+    }
+
+    #[test]
+    fn tiny_drags_draw_nothing() {
+        let start = Vector2F::splat(50.0);
+        let end = start + Vector2F::splat(0.5);
+        for shape_type in ShapeType::ALL.iter() {
+            assert!(shape_for_drag(&options(*shape_type), start, end, false, false).is_none());
+        }
+        // A thin box is still a box.
+        let end = start + Vector2F::new(0.5, 10.0);
+        assert!(shape_for_drag(&options(ShapeType::Rectangle), start, end, false, false).is_some());
+    }
+
+    #[test]
+    fn parameters_step_within_their_limits() {
+        let mut kind = drag(ShapeType::Polygon, Vector2F::new(60.0, 60.0), false, false);
+        assert!(step_parameter(&mut kind, -10));
+        assert!(!step_parameter(&mut kind, -1));
+        match kind {
+            ShapeKind::Polygon { sides, .. } => assert_eq!(sides, MIN_VERTEX_COUNT),
+            _ => unreachable!(),
+        }
+        let mut kind = drag(ShapeType::Rectangle, Vector2F::new(60.0, 60.0), false, false);
+        assert!(!step_parameter(&mut kind, -1), "corner radii stop at zero");
+        assert!(step_parameter(&mut kind, 2));
+        // Plain lines have no head to size.
+        let mut kind = drag(ShapeType::Line, Vector2F::new(60.0, 60.0), false, false);
+        assert!(!step_parameter(&mut kind, 1));
+        assert!(cycle_arrow_head(&mut kind));
+        assert!(step_parameter(&mut kind, 1));
+    }
+}
@@ -0,0 +1,149 @@
+//! A uniform grid over stroke bounds, for culling and hit-testing without
+//! visiting every stroke.
+use std::collections::{HashMap, HashSet};
+use crate::prelude::*;
+use super::document::StrokeId;
+
+/// Side of a grid cell in document units; about a screen at 100% zoom
+/// holds a few dozen cells.
+pub const DEFAULT_CELL_SIZE: f32 = 256.0;
+
+/// Entries spanning more cells than this are kept in a separate list that
+/// every query scans, instead of being copied into each cell.
+const MAX_CELLS_PER_ENTRY: i64 = 64;
+
+type Cell = (i32, i32);
+
+#[derive(Clone, Debug)]
+pub struct SpatialIndex {
+    cell_size: f32,
+    bounds: HashMap<StrokeId, RectF>,
+    cells: HashMap<Cell, Vec<StrokeId>>,
+    oversized: HashSet<StrokeId>,
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        SpatialIndex::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl SpatialIndex {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialIndex {
+            cell_size,
+            bounds: HashMap::new(),
+            cells: HashMap::new(),
+            oversized: HashSet::new(),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.bounds.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.bounds.clear();
+        self.cells.clear();
+        self.oversized.clear();
+    }
+    pub fn bounds(&self, id: StrokeId) -> Option<RectF> {
+        self.bounds.get(&id).cloned()
+    }
+    /// Adds `id`, or moves it if it is already indexed.
+    pub fn insert(&mut self, id: StrokeId, bounds: RectF) {
+        if let Some(old_bounds) = self.bounds.get(&id).cloned() {
+            if self.cell_range(old_bounds) == self.cell_range(bounds) {
+                self.bounds.insert(id, bounds);
+                return;
+            }
+            self.remove(id);
+        }
+        self.bounds.insert(id, bounds);
+        let (min, max) = self.cell_range(bounds);
+        if cell_count(min, max) > MAX_CELLS_PER_ENTRY {
+            self.oversized.insert(id);
+            return;
+        }
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                self.cells.entry((x, y)).or_insert_with(Vec::new).push(id);
+            }
+        }
+    }
+    pub fn remove(&mut self, id: StrokeId) {
+        let bounds = match self.bounds.remove(&id) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        if self.oversized.remove(&id) {
+            return;
+        }
+        let (min, max) = self.cell_range(bounds);
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                let now_empty = match self.cells.get_mut(&(x, y)) {
+                    Some(ids) => {
+                        ids.retain(|other| *other != id);
+                        ids.is_empty()
+                    }
+                    None => false,
+                };
+                if now_empty {
+                    self.cells.remove(&(x, y));
+                }
+            }
+        }
+    }
+    /// Strokes whose bounds intersect `rect`, in no particular order.
+    pub fn query_rect(&self, rect: RectF) -> Vec<StrokeId> {
+        self.query(rect, |bounds| bounds.intersects(rect))
+    }
+    pub fn query_point(&self, point: Vector2F) -> Vec<StrokeId> {
+        self.query_circle(point, 0.0)
+    }
+    /// Strokes whose bounds come within `radius` of `center`.
+    pub fn query_circle(&self, center: Vector2F, radius: f32) -> Vec<StrokeId> {
+        let rect = RectF::from_points(center, center).dilate(radius);
+        self.query(rect, |bounds| {
+            let nearest = center.clamp(bounds.origin(), bounds.lower_right());
+            (nearest - center).square_length() <= radius * radius
+        })
+    }
+    fn query<F>(&self, rect: RectF, mut accept: F) -> Vec<StrokeId> where F: FnMut(RectF) -> bool {
+        let (min, max) = self.cell_range(rect);
+        // Zoomed far out, scanning everything beats visiting empty cells.
+        if cell_count(min, max) > self.cells.len() as i64 {
+            return self.bounds
+                .iter()
+                .filter(|(_, bounds)| accept(**bounds))
+                .map(|(id, _)| *id)
+                .collect();
+        }
+        let mut seen = HashSet::new();
+        let mut result = Vec::new();
+        let candidates = (min.1..=max.1)
+            .flat_map(|y| (min.0..=max.0).map(move |x| (x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flat_map(|ids| ids.iter())
+            .chain(self.oversized.iter());
+        for id in candidates {
+            if seen.insert(*id) && accept(self.bounds[id]) {
+                result.push(*id);
+            }
+        }
+        result
+    }
+    fn cell_range(&self, rect: RectF) -> (Cell, Cell) {
+        let to_cell = |point: Vector2F| {
+            let cell = (point * (1.0 / self.cell_size)).floor();
+            (cell.x() as i32, cell.y() as i32)
+        };
+        (to_cell(rect.origin()), to_cell(rect.lower_right()))
+    }
+}
+
+fn cell_count(min: Cell, max: Cell) -> i64 {
+    (max.0 as i64 - min.0 as i64 + 1) * (max.1 as i64 - min.1 as i64 + 1)
+}
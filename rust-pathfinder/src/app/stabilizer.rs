@@ -0,0 +1,304 @@
+//! Steadies shaky input between the pointer and the stored stroke.
+//!
+//! Every mode trails the pointer to some degree; when it lifts, `finish`
+//! catches the stroke up so that it ends where it was released.
+use std::collections::VecDeque;
+use crate::prelude::*;
+use super::document::StrokePoint;
+
+/// Points averaged at full strength, by `MovingAverage`.
+const MAX_AVERAGE_WINDOW: usize = 16;
+
+/// Length of the string at full strength, by `PulledString`, in device
+/// pixels so that it feels the same at any zoom.
+const MAX_STRING_LENGTH: f32 = 48.0;
+
+/// Smallest share of each new point that `Predictive` takes in.
+const MIN_PREDICTIVE_GAIN: f32 = 0.1;
+
+/// Points closer in time than a fast input frame arrived together, e.g.
+/// coalesced events, and are too close to measure velocity by.
+const MIN_TREND_INTERVAL: f32 = 1.0 / 240.0;
+
+const STRENGTH_STEP: f32 = 0.1;
+
+///////////////////////////////////////////////////////////////////////////////
+// OPTIONS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StabilizerMode {
+    Off,
+    /// Averages the latest points. Smooth, but cuts corners and lags.
+    MovingAverage,
+    /// The "lazy brush": the stroke is pulled along on a string and only
+    /// moves once the pointer gets a string's length away. Best for slow,
+    /// deliberate lines.
+    PulledString,
+    /// Smooths position and velocity together, expecting each point where
+    /// the velocity so far would take it. Lags least on fast strokes.
+    Predictive,
+}
+
+impl StabilizerMode {
+    pub const ALL: [StabilizerMode; 4] = [
+        StabilizerMode::Off,
+        StabilizerMode::MovingAverage,
+        StabilizerMode::PulledString,
+        StabilizerMode::Predictive,
+    ];
+    pub fn next(self) -> StabilizerMode {
+        let index = StabilizerMode::ALL.iter().position(|mode| *mode == self).unwrap();
+        StabilizerMode::ALL[(index + 1) % StabilizerMode::ALL.len()]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StabilizerOptions {
+    pub mode: StabilizerMode,
+    /// In the range `0.0..=1.0`.
+    pub strength: f32,
+}
+
+impl StabilizerOptions {
+    pub fn none() -> Self {
+        StabilizerOptions { mode: StabilizerMode::Off, strength: 0.0 }
+    }
+    pub fn step_strength(&mut self, steps: i32) {
+        self.strength = (self.strength + STRENGTH_STEP * steps as f32).max(0.0).min(1.0);
+    }
+}
+
+impl Default for StabilizerOptions {
+    fn default() -> Self {
+        StabilizerOptions { mode: StabilizerMode::Off, strength: 0.5 }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// STABILIZER
+///////////////////////////////////////////////////////////////////////////////
+
+/// Stabilizes one stroke.
+pub struct Stabilizer {
+    options: StabilizerOptions,
+    /// Length of the string, in document units.
+    string_length: f32,
+    /// The latest points, for `MovingAverage`.
+    window: VecDeque<StrokePoint>,
+    /// Smoothed position and velocity per second, for `Predictive`, and the
+    /// time they were last updated.
+    trend: Option<(Vector2F, Vector2F, f32)>,
+    last_input: Option<StrokePoint>,
+    last_output: Option<StrokePoint>,
+}
+
+impl Stabilizer {
+    /// `scale` is device pixels per document unit.
+    pub fn new(options: StabilizerOptions, scale: f32) -> Self {
+        Stabilizer {
+            options,
+            string_length: options.strength * MAX_STRING_LENGTH / scale.max(f32::EPSILON),
+            window: VecDeque::new(),
+            trend: None,
+            last_input: None,
+            last_output: None,
+        }
+    }
+
+    /// The point to store for the pointer reaching `input`, if any yet.
+    pub fn push(&mut self, input: StrokePoint) -> Option<StrokePoint> {
+        self.last_input = Some(input);
+        let output = match self.options.mode {
+            StabilizerMode::Off => Some(input),
+            StabilizerMode::MovingAverage => {
+                let extra = (self.options.strength * MAX_AVERAGE_WINDOW as f32).round() as usize;
+                let size = 1 + extra;
+                self.window.push_back(input);
+                while self.window.len() > size {
+                    self.window.pop_front();
+                }
+                let count = self.window.len() as f32;
+                let (position, pressure) = self
+                    .window
+                    .iter()
+                    .fold((Vector2F::zero(), 0.0), |(position, pressure), point| {
+                        (position + point.position, pressure + point.pressure)
+                    });
+                Some(StrokePoint { position: position / count, pressure: pressure / count, ..input })
+            }
+            StabilizerMode::PulledString => match self.last_output {
+                None => Some(input),
+                Some(anchor) => {
+                    let offset = input.position - anchor.position;
+                    let distance = offset.length();
+                    if distance <= self.string_length {
+                        None
+                    } else {
+                        let pull = offset * ((distance - self.string_length) / distance);
+                        Some(StrokePoint { position: anchor.position + pull, ..input })
+                    }
+                }
+            },
+            StabilizerMode::Predictive => {
+                let gain = 1.0 - self.options.strength * (1.0 - MIN_PREDICTIVE_GAIN);
+                let position = match self.trend {
+                    Some((position, velocity, time)) => {
+                        let time_delta = input.time - time;
+                        // Holt's linear smoothing: the level follows the
+                        // input from where the trend predicted it to be.
+                        let predicted = position + velocity * time_delta.max(0.0);
+                        let level = predicted + (input.position - predicted) * gain;
+                        // Points that arrived together move the output but
+                        // leave the trend to the next point with a real
+                        // time delta.
+                        if time_delta >= MIN_TREND_INTERVAL {
+                            let observed = (level - position) / time_delta;
+                            let velocity = velocity + (observed - velocity) * gain;
+                            self.trend = Some((level, velocity, input.time));
+                        }
+                        level
+                    }
+                    None => {
+                        self.trend = Some((input.position, Vector2F::zero(), input.time));
+                        input.position
+                    }
+                };
+                Some(StrokePoint { position, ..input })
+            }
+        };
+        if output.is_some() {
+            self.last_output = output;
+        }
+        output
+    }
+
+    /// The point that takes the stroke the rest of the way to the last
+    /// input, when the pointer lifts.
+    pub fn finish(&mut self) -> Option<StrokePoint> {
+        let input = self.last_input?;
+        match self.last_output {
+            Some(output) if output.position == input.position => None,
+            _ => {
+                self.last_output = Some(input);
+                Some(input)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f32, time: f32) -> StrokePoint {
+        StrokePoint { position: Vector2F::new(x, 0.0), pressure: 1.0, time }
+    }
+
+    fn stabilizer(mode: StabilizerMode, strength: f32) -> Stabilizer {
+        Stabilizer::new(StabilizerOptions { mode, strength }, 1.0)
+    }
+
+    /// The x of each output for inputs at `xs`, a 60th of a second apart.
+    fn outputs(stabilizer: &mut Stabilizer, xs: &[f32]) -> Vec<Option<f32>> {
+        xs.iter()
+            .enumerate()
+            .map(|(index, x)| {
+                let output = stabilizer.push(point(*x, index as f32 / 60.0));
+                output.map(|output| output.position.x())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn off_passes_points_through() {
+        let mut stabilizer = stabilizer(StabilizerMode::Off, 1.0);
+        let input = StrokePoint { pressure: 0.25, ..point(3.0, 0.5) };
+        assert_eq!(stabilizer.push(input), Some(input));
+        assert_eq!(stabilizer.finish(), None);
+    }
+
+    #[test]
+    fn moving_averages_widen_with_strength() {
+        let xs = [0.0, 3.0, 6.0, 9.0];
+        let mut weak = stabilizer(StabilizerMode::MovingAverage, 0.0);
+        assert_eq!(outputs(&mut weak, &xs), vec![Some(0.0), Some(3.0), Some(6.0), Some(9.0)]);
+        // Three points.
+        let mut strong = stabilizer(StabilizerMode::MovingAverage, 0.125);
+        assert_eq!(outputs(&mut strong, &xs), vec![Some(0.0), Some(1.5), Some(3.0), Some(6.0)]);
+        assert_eq!(strong.finish(), Some(point(9.0, 0.05)));
+        assert_eq!(strong.finish(), None);
+    }
+
+    #[test]
+    fn pulled_strings_trail_by_their_length() {
+        let xs = [0.0, 10.0, 30.0, 20.0];
+        let mut stabilizer = stabilizer(StabilizerMode::PulledString, 0.5);
+        assert_eq!(outputs(&mut stabilizer, &xs), vec![Some(0.0), None, Some(6.0), None]);
+        assert_eq!(stabilizer.finish(), Some(point(20.0, 0.05)));
+
+        // The string is as long on screen at any zoom.
+        let options = StabilizerOptions { mode: StabilizerMode::PulledString, strength: 0.5 };
+        let mut zoomed = Stabilizer::new(options, 2.0);
+        assert_eq!(outputs(&mut zoomed, &xs), vec![Some(0.0), None, Some(18.0), None]);
+    }
+
+    #[test]
+    fn predictions_catch_up_with_steady_strokes() {
+        let xs: Vec<f32> = (0..60).map(|index| index as f32 * 5.0).collect();
+        let mut weak = stabilizer(StabilizerMode::Predictive, 0.0);
+        for (output, x) in outputs(&mut weak, &xs).into_iter().zip(xs.iter()) {
+            assert!((output.unwrap() - x).abs() < 1.0e-3);
+        }
+
+        let mut strong = stabilizer(StabilizerMode::Predictive, 0.5);
+        let smoothed = outputs(&mut strong, &xs);
+        assert!((smoothed[59].unwrap() - xs[59]).abs() < 1.0, "{:?}", smoothed[59]);
+    }
+
+    #[test]
+    fn predictions_ignore_points_that_arrive_together() {
+        let mut stabilizer = stabilizer(StabilizerMode::Predictive, 0.5);
+        let inputs = [
+            point(0.0, 0.0),
+            point(10.0, 1.0 / 60.0),
+            point(11.0, 1.0 / 60.0),
+            point(12.0, 1.0 / 60.0 + 0.0001),
+            point(20.0, 2.0 / 60.0),
+            point(30.0, 3.0 / 60.0),
+        ];
+        for input in inputs.iter() {
+            let output = stabilizer.push(*input).unwrap();
+            assert!(output.position.x() >= 0.0 && output.position.x() <= 31.0, "{:?}", output);
+        }
+        let velocity = stabilizer.trend.unwrap().1;
+        assert!(velocity.x() > 0.0 && velocity.x() < 1000.0, "{:?}", velocity);
+    }
+
+    #[test]
+    fn finishing_reaches_the_last_input_once() {
+        for mode in StabilizerMode::ALL.iter() {
+            let mut stabilizer = stabilizer(*mode, 1.0);
+            assert_eq!(stabilizer.finish(), None);
+            outputs(&mut stabilizer, &[0.0, 4.0, 8.0, 40.0, 41.0]);
+            let last = point(41.0, 4.0 / 60.0);
+            match *mode {
+                StabilizerMode::Off => assert_eq!(stabilizer.finish(), None),
+                _ => assert_eq!(stabilizer.finish(), Some(last)),
+            }
+            assert_eq!(stabilizer.finish(), None);
+        }
+    }
+
+    #[test]
+    fn strength_steps_stay_in_range() {
+        let mut options = StabilizerOptions::default();
+        options.step_strength(2);
+        assert!((options.strength - 0.7).abs() < 1.0e-6);
+        options.step_strength(10);
+        assert_eq!(options.strength, 1.0);
+        options.step_strength(-20);
+        assert_eq!(options.strength, 0.0);
+        assert_eq!(StabilizerMode::Predictive.next(), StabilizerMode::Off);
+    }
+}
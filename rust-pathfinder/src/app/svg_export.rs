@@ -0,0 +1,455 @@
+//! Exports documents as plain SVG that other programs can edit: each layer
+//! is a `<g>`, each stroke a `<path>` styled to look as it does here, and
+//! the view box is the document's bounds.
+//!
+//! Every layer and stroke also carries its native record from `format`,
+//! base64-encoded in an attribute of a private namespace, which other
+//! programs keep but ignore. Importing the SVG again restores the strokes
+//! from these, with their pressure, timing, brush, shape parameters and
+//! the tool they came from, rather than from the drawing.
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::prelude::*;
+use super::document::{BlendMode, Document, Layer, Stroke};
+use super::dynamics;
+use super::fitting::{self, CubicSegment, FitOptions};
+use super::format;
+use super::paint::{Fill, Gradient, GradientKind, Paint};
+
+pub const NAMESPACE: &'static str = "urn:alphadraw:svg";
+const PREFIX: &'static str = "alphadraw";
+/// On the root; the `format` version of the records.
+const VERSION_ATTRIBUTE: &'static str = "version";
+const LAYER_ATTRIBUTE: &'static str = "layer";
+const STROKE_ATTRIBUTE: &'static str = "stroke";
+
+const BASE64_ALPHABET: &'static [u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+///////////////////////////////////////////////////////////////////////////////
+// EXPORT
+///////////////////////////////////////////////////////////////////////////////
+
+pub fn export(document: &Document, fit_options: &FitOptions, path: &Path) -> io::Result<()> {
+    let file = File::create(path)?;
+    write_svg(document, fit_options, BufWriter::new(file))
+}
+
+/// Writes `document` with freehand strokes fitted by `fit_options`, as the
+/// scene draws them. Hidden layers are written with `display="none"`; empty
+/// strokes are left out.
+pub fn write_svg<W: Write>(
+    document: &Document,
+    fit_options: &FitOptions,
+    mut writer: W,
+) -> io::Result<()> {
+    let view_box = document
+        .bounds()
+        .map(RectF::round_out)
+        .unwrap_or(RectF::new(Vector2F::zero(), Vector2F::splat(1.0)));
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    write!(writer, r#"<svg xmlns="http://www.w3.org/2000/svg""#)?;
+    write!(writer, r#" xmlns:{}="{}""#, PREFIX, NAMESPACE)?;
+    write!(writer, r#" {}:{}="{}""#, PREFIX, VERSION_ATTRIBUTE, format::VERSION)?;
+    writeln!(
+        writer,
+        r#" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        view_box.width(),
+        view_box.height(),
+        view_box.origin_x(),
+        view_box.origin_y(),
+        view_box.width(),
+        view_box.height(),
+    )?;
+    for layer in document.layers().iter() {
+        write_layer(&mut writer, layer, fit_options)?;
+    }
+    writeln!(writer, "</svg>")?;
+    writer.flush()
+}
+
+fn write_layer<W: Write>(
+    writer: &mut W,
+    layer: &Layer,
+    fit_options: &FitOptions,
+) -> io::Result<()> {
+    let properties = &layer.properties;
+    write!(
+        writer,
+        r#"  <g id="layer-{}" {}:{}="{}""#,
+        layer.id.0,
+        PREFIX,
+        LAYER_ATTRIBUTE,
+        encode_base64(&format::encode_layer(layer)),
+    )?;
+    if properties.opacity < 1.0 {
+        write!(writer, r#" opacity="{}""#, properties.opacity)?;
+    }
+    if let Some(name) = blend_mode_name(properties.blend_mode) {
+        write!(writer, r#" style="mix-blend-mode:{}""#, name)?;
+    }
+    if !properties.visible {
+        write!(writer, r#" display="none""#)?;
+    }
+    writeln!(writer, ">")?;
+    for stroke in layer.strokes.iter().filter(|stroke| !stroke.is_empty()) {
+        write_stroke(writer, stroke, fit_options)?;
+    }
+    writeln!(writer, "  </g>")
+}
+
+/// Shapes keep their outline, fill and line. Freehand strokes of constant
+/// width become a line along the fitted curve, and others the area they
+/// cover, filled.
+fn write_stroke<W: Write>(
+    writer: &mut W,
+    stroke: &Stroke,
+    fit_options: &FitOptions,
+) -> io::Result<()> {
+    let style = &stroke.style;
+    let mut data = String::new();
+    let mut fill = None;
+    let mut line_width = None;
+    if let Some(ref shape) = stroke.shape {
+        for contour in shape.contours() {
+            push_segments(&mut data, &contour.segments, contour.closed);
+        }
+        if let Some(Fill { paint: Paint::Gradient(ref gradient), .. }) = shape.fill {
+            write_gradient(writer, stroke, gradient, &shape.transform)?;
+        }
+        fill = shape.fill.clone();
+        if shape.stroked {
+            line_width = Some(style.width);
+        }
+    } else {
+        let positions = stroke.points
+            .iter()
+            .map(|point| point.position)
+            .collect::<Vec<_>>();
+        let segments = fitting::fit_curve(&positions, fit_options.tolerance);
+        if style.dynamics.is_constant() && !segments.is_empty() {
+            let scale = style.dynamics.pressure.start * style.dynamics.velocity.start;
+            push_segments(&mut data, &segments, false);
+            line_width = Some(style.width * scale);
+        } else {
+            let widths = dynamics::width_profile(&stroke.points, style.width, &style.dynamics);
+            push_outline(&mut data, &dynamics::stroke_outline(&stroke.points, &widths, &segments));
+            fill = Some(Fill { paint: Paint::Color(style.color), rule: pf::FillRule::Winding });
+        }
+    }
+    write!(
+        writer,
+        r#"    <path id="stroke-{}" {}:{}="{}" d="{}""#,
+        stroke.id.0,
+        PREFIX,
+        STROKE_ATTRIBUTE,
+        encode_base64(&format::encode_stroke(stroke)),
+        data,
+    )?;
+    match fill {
+        Some(Fill { paint: Paint::Color(color), .. }) => {
+            write_color(writer, "fill", "fill-opacity", color)?;
+        }
+        Some(Fill { paint: Paint::Gradient(_), .. }) => {
+            write!(writer, r#" fill="url(#gradient-{})""#, stroke.id.0)?;
+        }
+        None => write!(writer, r#" fill="none""#)?,
+    }
+    if let Some(Fill { rule: pf::FillRule::EvenOdd, .. }) = fill {
+        write!(writer, r#" fill-rule="evenodd""#)?;
+    }
+    if let Some(width) = line_width {
+        write_color(writer, "stroke", "stroke-opacity", style.color)?;
+        write!(writer, r#" stroke-width="{}""#, width)?;
+        let line_cap = match style.line_cap {
+            pf::LineCap::Butt => "butt",
+            pf::LineCap::Square => "square",
+            pf::LineCap::Round => "round",
+        };
+        write!(writer, r#" stroke-linecap="{}""#, line_cap)?;
+        match style.line_join {
+            pf::LineJoin::Miter(limit) => {
+                write!(writer, r#" stroke-linejoin="miter" stroke-miterlimit="{}""#, limit)?;
+            }
+            pf::LineJoin::Bevel => write!(writer, r#" stroke-linejoin="bevel""#)?,
+            pf::LineJoin::Round => write!(writer, r#" stroke-linejoin="round""#)?,
+        }
+    }
+    if style.opacity < 1.0 {
+        write!(writer, r#" opacity="{}""#, style.opacity)?;
+    }
+    writeln!(writer, "/>")
+}
+
+/// In document units, as `userSpaceOnUse` gradients of the stroke's ID.
+fn write_gradient<W: Write>(
+    writer: &mut W,
+    stroke: &Stroke,
+    gradient: &Gradient,
+    shape_transform: &pf::Transform2F,
+) -> io::Result<()> {
+    let transform = *shape_transform * gradient.transform;
+    let element = match gradient.kind {
+        GradientKind::Linear { .. } => "linearGradient",
+        GradientKind::Radial { .. } => "radialGradient",
+    };
+    write!(
+        writer,
+        r#"    <defs><{} id="gradient-{}" gradientUnits="userSpaceOnUse""#,
+        element,
+        stroke.id.0,
+    )?;
+    match gradient.kind {
+        GradientKind::Linear { from, to } => {
+            write!(
+                writer,
+                r#" x1="{}" y1="{}" x2="{}" y2="{}""#,
+                from.x(),
+                from.y(),
+                to.x(),
+                to.y(),
+            )?;
+        }
+        GradientKind::Radial { focus, center, radius } => {
+            write!(
+                writer,
+                r#" cx="{}" cy="{}" r="{}" fx="{}" fy="{}""#,
+                center.x(),
+                center.y(),
+                radius,
+                focus.x(),
+                focus.y(),
+            )?;
+        }
+    }
+    if !transform.is_identity() {
+        let translation = transform.translation();
+        write!(
+            writer,
+            r#" gradientTransform="matrix({} {} {} {} {} {})""#,
+            transform.m11(),
+            transform.m21(),
+            transform.m12(),
+            transform.m22(),
+            translation.x(),
+            translation.y(),
+        )?;
+    }
+    write!(writer, ">")?;
+    for stop in gradient.stops.iter() {
+        write!(writer, r#"<stop offset="{}""#, stop.offset)?;
+        write_color(writer, "stop-color", "stop-opacity", stop.color)?;
+        write!(writer, "/>")?;
+    }
+    writeln!(writer, "</{}></defs>", element)
+}
+
+/// Writes `color` as the `name` attribute, and its alpha as the
+/// `opacity_name` attribute unless opaque.
+fn write_color<W: Write>(
+    writer: &mut W,
+    name: &str,
+    opacity_name: &str,
+    color: pf::ColorU,
+) -> io::Result<()> {
+    write!(writer, r##" {}="#{:02x}{:02x}{:02x}""##, name, color.r, color.g, color.b)?;
+    if color.a < u8::MAX {
+        write!(writer, r#" {}="{}""#, opacity_name, color.a as f32 / 255.0)?;
+    }
+    Ok(())
+}
+
+fn blend_mode_name(blend_mode: BlendMode) -> Option<&'static str> {
+    match blend_mode {
+        BlendMode::Normal => None,
+        BlendMode::Multiply => Some("multiply"),
+        BlendMode::Screen => Some("screen"),
+        BlendMode::Overlay => Some("overlay"),
+        BlendMode::Darken => Some("darken"),
+        BlendMode::Lighten => Some("lighten"),
+        BlendMode::Difference => Some("difference"),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// PATH DATA
+///////////////////////////////////////////////////////////////////////////////
+
+fn push_segments(data: &mut String, segments: &[CubicSegment], closed: bool) {
+    let first = match segments.first() {
+        Some(first) => first,
+        None => return,
+    };
+    push_command(data, 'M', &[first.from]);
+    for segment in segments.iter() {
+        push_command(data, 'C', &[segment.ctrl0, segment.ctrl1, segment.to]);
+    }
+    if closed {
+        push_command(data, 'Z', &[]);
+    }
+}
+
+fn push_outline(data: &mut String, outline: &pf::Outline) {
+    for contour in outline.contours() {
+        let mut segments = contour.iter(pf::ContourIterFlags::IGNORE_CLOSE_SEGMENT).peekable();
+        if let Some(first) = segments.peek() {
+            push_command(data, 'M', &[first.baseline.from()]);
+        }
+        for segment in segments {
+            match segment.kind {
+                pf::SegmentKind::None => {}
+                pf::SegmentKind::Line => {
+                    push_command(data, 'L', &[segment.baseline.to()]);
+                }
+                pf::SegmentKind::Quadratic => {
+                    push_command(data, 'Q', &[segment.ctrl.from(), segment.baseline.to()]);
+                }
+                pf::SegmentKind::Cubic => {
+                    let points = [segment.ctrl.from(), segment.ctrl.to(), segment.baseline.to()];
+                    push_command(data, 'C', &points);
+                }
+            }
+        }
+        if contour.is_closed() {
+            push_command(data, 'Z', &[]);
+        }
+    }
+}
+
+fn push_command(data: &mut String, command: char, points: &[Vector2F]) {
+    if !data.is_empty() {
+        data.push(' ');
+    }
+    data.push(command);
+    for point in points.iter() {
+        write!(data, " {} {}", point.x(), point.y()).unwrap();
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// RECORDS
+///////////////////////////////////////////////////////////////////////////////
+
+/// The layers of an SVG written by `write_svg`, restored from their records
+/// with fresh IDs from `document`, bottom to top. Nothing if the SVG was
+/// written otherwise, by a newer version, or its records are damaged, in
+/// which case it can still be imported as a drawing.
+pub fn read_layers(data: &[u8], document: &mut Document) -> Option<Vec<Layer>> {
+    let text = std::str::from_utf8(data).ok()?;
+    let svg = roxmltree::Document::parse(text).ok()?;
+    let version = svg.root_element().attribute((NAMESPACE, VERSION_ATTRIBUTE))?;
+    if version.parse::<u16>().ok()? > format::VERSION {
+        return None;
+    }
+    let mut layers = Vec::new();
+    for element in svg.descendants() {
+        let record = match element.attribute((NAMESPACE, LAYER_ATTRIBUTE)) {
+            Some(record) => record,
+            None => continue,
+        };
+        let mut layer = format::decode_layer(&decode_base64(record)?).ok()?;
+        layer.id = document.allocate_layer_id();
+        for node in element.descendants() {
+            if let Some(record) = node.attribute((NAMESPACE, STROKE_ATTRIBUTE)) {
+                let mut stroke = format::decode_stroke(&decode_base64(record)?).ok()?;
+                stroke.id = document.allocate_stroke_id();
+                layer.strokes.push(stroke);
+            }
+        }
+        layers.push(layer);
+    }
+    Some(layers)
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut text = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bits = (chunk[0] as u32) << 16 |
+            (*chunk.get(1).unwrap_or(&0) as u32) << 8 |
+            *chunk.get(2).unwrap_or(&0) as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(BASE64_ALPHABET[(bits >> (18 - 6 * index) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Nothing if `text` is not base64. Padding is optional.
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let (text, padding) = text.split_at(text.find('=').unwrap_or(text.len()));
+    if padding.len() > 2 || padding.bytes().any(|byte| byte != b'=') {
+        return None;
+    }
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6 | value as u32) & 0xffff;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            data.push((bits >> bit_count) as u8);
+        }
+    }
+    // A lone character left over holds less than a byte.
+    if bit_count >= 6 {
+        return None;
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_round_trips_every_length() {
+        let cases: [(&[u8], &str); 7] = [
+            (b"", ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"fooba", "Zm9vYmE="),
+            (b"foobar", "Zm9vYmFy"),
+        ];
+        for &(data, text) in cases.iter() {
+            assert_eq!(encode_base64(data), text);
+            assert_eq!(decode_base64(text).as_ref().map(Vec::as_slice), Some(data));
+        }
+        let every_byte: Vec<u8> = (0..=255).collect();
+        for length in 0..every_byte.len() {
+            let data = &every_byte[..length];
+            assert_eq!(decode_base64(&encode_base64(data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn base64_decodes_without_padding() {
+        assert_eq!(decode_base64("Zg").unwrap(), b"f");
+        assert_eq!(decode_base64("Zm8").unwrap(), b"fo");
+    }
+
+    #[test]
+    fn base64_refuses_invalid_input() {
+        for text in ["Zm9v!", "Zm 9v", "Zm9-", "Zm9vé", "Z", "Zm9vY", "Zg===", "Zg==Zg==", "=Zg="]
+            .iter()
+        {
+            assert_eq!(decode_base64(text), None, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,428 @@
+//! Imports SVG files as editable document content rather than a picture:
+//! each top-level group becomes a layer, and each path a shape stroke that
+//! keeps its outline, fill and stroke.
+//!
+//! Parsing is left to usvg, which already resolves styles, `<use>` and the
+//! basic shapes into paths. What documents cannot hold is left out or drawn
+//! more plainly, and listed in the import's warning. SVGs exported by
+//! AlphaDraw are restored from the records they carry instead.
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::path::Path;
+use crate::prelude::*;
+use crate::engine::get_svg_building_message;
+use super::document::{now_millis, Document, Layer, Stroke, StrokeOrigin, StrokeStyle};
+use super::dynamics::BrushDynamics;
+use super::fitting::CubicSegment;
+use super::paint::{Fill, Gradient, GradientKind, GradientStop, Paint};
+use super::shape::{Shape, ShapeContour, ShapeKind};
+use super::stabilizer::StabilizerOptions;
+use super::svg_export;
+
+pub static EXTENSION: &'static str = "svg";
+
+const CLIP_PATHS: &'static str = "clip paths";
+const MASKS: &'static str = "masks";
+const FILTERS: &'static str = "filters";
+const IMAGES: &'static str = "images";
+const PATTERNS: &'static str = "patterns";
+const DASHED_STROKES: &'static str = "dashed strokes";
+const GRADIENT_STROKES: &'static str = "gradient strokes";
+const GRADIENT_SPREAD_METHODS: &'static str = "gradient spread methods";
+
+///////////////////////////////////////////////////////////////////////////////
+// IMPORT
+///////////////////////////////////////////////////////////////////////////////
+
+/// SVG features that were left out or drawn more plainly, by name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Unsupported(BTreeSet<&'static str>);
+
+impl Unsupported {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn contains(&self, feature: &str) -> bool {
+        self.0.contains(feature)
+    }
+}
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, feature) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(feature)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Import {
+    /// Bottom to top, with IDs from the document they were imported for but
+    /// not yet part of it.
+    pub layers: Vec<Layer>,
+    pub unsupported: Unsupported,
+}
+
+impl Import {
+    /// The warning to show about unsupported features; empty if there were
+    /// none.
+    pub fn message(&self) -> String {
+        get_svg_building_message(&self.unsupported)
+    }
+}
+
+/// Reads the SVG at `path` for adding to `document`. Layers of loose paths
+/// are named after the file.
+pub fn import_file(path: &Path, document: &mut Document) -> Result<Import, String> {
+    let data = std::fs::read(path)
+        .map_err(|error| format!("Failed to read the SVG: {}", error))?;
+    let name = path.file_stem().map_or("SVG".into(), |stem| stem.to_string_lossy());
+    import_data(&data, &name, document)
+}
+
+pub fn import_data(data: &[u8], name: &str, document: &mut Document) -> Result<Import, String> {
+    if let Some(layers) = svg_export::read_layers(data, document) {
+        return Ok(Import { layers, unsupported: Unsupported::default() });
+    }
+    // Named groups are kept so that layers from drawing programs stay
+    // layers.
+    let options = usvg::Options { keep_named_groups: true, ..usvg::Options::default() };
+    let tree = usvg::Tree::from_data(data, &options)
+        .map_err(|error| format!("Failed to parse the SVG: {}", error))?;
+    Ok(import_tree(&tree, name, document))
+}
+
+/// Converts `tree` for adding to `document`, in the SVG's user units.
+/// Top-level groups become layers, with the group's opacity as the layer's;
+/// runs of paths outside any group become layers called `name`. Layers that
+/// end up empty are dropped.
+pub fn import_tree(tree: &usvg::Tree, name: &str, document: &mut Document) -> Import {
+    let mut importer = Importer { tree, document, unsupported: Unsupported::default() };
+    let mut layers = Vec::new();
+    let mut loose: Option<Layer> = None;
+    for node in tree.root().children() {
+        match *node.borrow() {
+            usvg::NodeKind::Group(ref group) => {
+                layers.extend(loose.take());
+                let id = importer.document.allocate_layer_id();
+                let layer_name = if group.id.is_empty() {
+                    format!("{} {}", name, layers.len() + 1)
+                } else {
+                    group.id.clone()
+                };
+                let mut layer = Layer::new(id, layer_name);
+                layer.properties.opacity = group.opacity.value() as f32;
+                importer.note_group(group);
+                let transform = convert_transform(&group.transform);
+                importer.add_children(&node, transform, 1.0, &mut layer.strokes);
+                layers.push(layer);
+            }
+            usvg::NodeKind::Path(_) | usvg::NodeKind::Image(_) => {
+                if loose.is_none() {
+                    let id = importer.document.allocate_layer_id();
+                    loose = Some(Layer::new(id, name.to_owned()));
+                }
+                let strokes = &mut loose.as_mut().unwrap().strokes;
+                importer.add_node(&node, pf::Transform2F::default(), 1.0, strokes);
+            }
+            _ => {}
+        }
+    }
+    layers.extend(loose);
+    layers.retain(|layer| !layer.strokes.is_empty());
+    Import { layers, unsupported: importer.unsupported }
+}
+
+struct Importer<'a> {
+    tree: &'a usvg::Tree,
+    document: &'a mut Document,
+    unsupported: Unsupported,
+}
+
+impl<'a> Importer<'a> {
+    fn add_children(
+        &mut self,
+        parent: &usvg::Node,
+        transform: pf::Transform2F,
+        opacity: f32,
+        strokes: &mut Vec<Stroke>,
+    ) {
+        for node in parent.children() {
+            self.add_node(&node, transform, opacity, strokes);
+        }
+    }
+
+    /// Adds the paths in `node` to `strokes`, placed by `transform` and
+    /// faded by `opacity` from the groups around it. Nested groups are
+    /// flattened.
+    fn add_node(
+        &mut self,
+        node: &usvg::Node,
+        transform: pf::Transform2F,
+        opacity: f32,
+        strokes: &mut Vec<Stroke>,
+    ) {
+        match *node.borrow() {
+            usvg::NodeKind::Group(ref group) => {
+                self.note_group(group);
+                let transform = transform * convert_transform(&group.transform);
+                let opacity = opacity * group.opacity.value() as f32;
+                self.add_children(node, transform, opacity, strokes);
+            }
+            usvg::NodeKind::Path(ref path) => {
+                let transform = transform * convert_transform(&path.transform);
+                strokes.extend(self.convert_path(path, transform, opacity));
+            }
+            usvg::NodeKind::Image(_) => {
+                self.unsupported.0.insert(IMAGES);
+            }
+            _ => {}
+        }
+    }
+
+    /// Group effects are not imported; the group's content is, as if they
+    /// were not there.
+    fn note_group(&mut self, group: &usvg::Group) {
+        if group.clip_path.is_some() {
+            self.unsupported.0.insert(CLIP_PATHS);
+        }
+        if group.mask.is_some() {
+            self.unsupported.0.insert(MASKS);
+        }
+        if group.filter.is_some() {
+            self.unsupported.0.insert(FILTERS);
+        }
+    }
+
+    fn convert_path(
+        &mut self,
+        path: &usvg::Path,
+        transform: pf::Transform2F,
+        opacity: f32,
+    ) -> Option<Stroke> {
+        let contours = convert_path_data(&path.data);
+        if contours.is_empty() {
+            return None;
+        }
+        let fill = match path.fill {
+            Some(ref fill) => self.convert_fill(fill, &path.data),
+            None => None,
+        };
+        let mut style = StrokeStyle {
+            opacity,
+            dynamics: BrushDynamics::none(),
+            stabilizer: StabilizerOptions::none(),
+            ..StrokeStyle::default()
+        };
+        if let Some(ref stroke) = path.stroke {
+            self.convert_stroke(stroke, &transform, &mut style);
+        } else if fill.is_none() {
+            return None;
+        }
+        let mut shape = Shape::new(ShapeKind::Path { contours });
+        shape.transform = transform;
+        shape.fill = fill;
+        shape.stroked = path.stroke.is_some();
+        let mut stroke = Stroke {
+            id: self.document.allocate_stroke_id(),
+            style,
+            timestamp: now_millis(),
+            points: Vec::new(),
+            shape: None,
+            origin: StrokeOrigin::Imported,
+        };
+        stroke.set_shape(shape);
+        Some(stroke)
+    }
+
+    fn convert_fill(&mut self, fill: &usvg::Fill, data: &usvg::PathData) -> Option<Fill> {
+        let opacity = fill.opacity.value() as f32;
+        let paint = match fill.paint {
+            usvg::Paint::Color(color) => Paint::Color(convert_color(color, opacity)),
+            usvg::Paint::Link(ref id) => Paint::Gradient(self.convert_gradient(id, data, opacity)?),
+        };
+        let rule = match fill.rule {
+            usvg::FillRule::NonZero => pf::FillRule::Winding,
+            usvg::FillRule::EvenOdd => pf::FillRule::EvenOdd,
+        };
+        Some(Fill { paint, rule })
+    }
+
+    /// Strokes keep one color and a width in document units, which is the
+    /// SVG width scaled by how much `transform` scales areas.
+    fn convert_stroke(
+        &mut self,
+        stroke: &usvg::Stroke,
+        transform: &pf::Transform2F,
+        style: &mut StrokeStyle,
+    ) {
+        let opacity = stroke.opacity.value() as f32;
+        style.color = match stroke.paint {
+            usvg::Paint::Color(color) => convert_color(color, opacity),
+            usvg::Paint::Link(ref id) => {
+                self.unsupported.0.insert(GRADIENT_STROKES);
+                let first_stop = self.tree.defs_by_id(id).and_then(|node| {
+                    match *node.borrow() {
+                        usvg::NodeKind::LinearGradient(ref gradient) => {
+                            gradient.base.stops.first().cloned()
+                        }
+                        usvg::NodeKind::RadialGradient(ref gradient) => {
+                            gradient.base.stops.first().cloned()
+                        }
+                        _ => None,
+                    }
+                });
+                match first_stop {
+                    Some(stop) => {
+                        convert_color(stop.color, opacity * stop.opacity.value() as f32)
+                    }
+                    None => pf::ColorU::new(0, 0, 0, (opacity * 255.0).round() as u8),
+                }
+            }
+        };
+        if stroke.dasharray.is_some() {
+            self.unsupported.0.insert(DASHED_STROKES);
+        }
+        let scale = transform.matrix.det().abs().sqrt();
+        style.width = stroke.width.value() as f32 * scale;
+        style.line_cap = match stroke.linecap {
+            usvg::LineCap::Butt => pf::LineCap::Butt,
+            usvg::LineCap::Round => pf::LineCap::Round,
+            usvg::LineCap::Square => pf::LineCap::Square,
+        };
+        style.line_join = match stroke.linejoin {
+            usvg::LineJoin::Miter => pf::LineJoin::Miter(stroke.miterlimit.value() as f32),
+            usvg::LineJoin::Round => pf::LineJoin::Round,
+            usvg::LineJoin::Bevel => pf::LineJoin::Bevel,
+        };
+    }
+
+    /// The gradient `id` refers to, in the local coordinates of the path
+    /// with `data`. Nothing for patterns and broken links.
+    fn convert_gradient(
+        &mut self,
+        id: &str,
+        data: &usvg::PathData,
+        opacity: f32,
+    ) -> Option<Gradient> {
+        let node = self.tree.defs_by_id(id)?;
+        let node = node.borrow();
+        let (kind, base) = match *node {
+            usvg::NodeKind::LinearGradient(ref gradient) => {
+                let from = pf::vec2f(gradient.x1 as f32, gradient.y1 as f32);
+                let to = pf::vec2f(gradient.x2 as f32, gradient.y2 as f32);
+                (GradientKind::Linear { from, to }, &gradient.base)
+            }
+            usvg::NodeKind::RadialGradient(ref gradient) => {
+                let kind = GradientKind::Radial {
+                    focus: pf::vec2f(gradient.fx as f32, gradient.fy as f32),
+                    center: pf::vec2f(gradient.cx as f32, gradient.cy as f32),
+                    radius: gradient.r.value() as f32,
+                };
+                (kind, &gradient.base)
+            }
+            usvg::NodeKind::Pattern(_) => {
+                self.unsupported.0.insert(PATTERNS);
+                return None;
+            }
+            _ => return None,
+        };
+        if base.spread_method != usvg::SpreadMethod::Pad {
+            self.unsupported.0.insert(GRADIENT_SPREAD_METHODS);
+        }
+        let mut transform = convert_transform(&base.transform);
+        if base.units == usvg::Units::ObjectBoundingBox {
+            let bbox = data.bbox()?;
+            let bbox_transform = pf::Transform2F::row_major(
+                bbox.width() as f32,
+                0.0,
+                0.0,
+                bbox.height() as f32,
+                bbox.x() as f32,
+                bbox.y() as f32,
+            );
+            transform = bbox_transform * transform;
+        }
+        let stops = base.stops
+            .iter()
+            .map(|stop| GradientStop {
+                offset: stop.offset.value() as f32,
+                color: convert_color(stop.color, opacity * stop.opacity.value() as f32),
+            })
+            .collect();
+        Some(Gradient { kind, stops, transform })
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// CONVERSION
+///////////////////////////////////////////////////////////////////////////////
+
+/// Closed subpaths end with a line back to their start, as closed
+/// polylines do.
+fn convert_path_data(data: &usvg::PathData) -> Vec<ShapeContour> {
+    let mut contours = Vec::new();
+    let mut segments = Vec::new();
+    let mut start = Vector2F::zero();
+    let mut current = start;
+    for segment in data.iter() {
+        match *segment {
+            usvg::PathSegment::MoveTo { x, y } => {
+                if !segments.is_empty() {
+                    contours.push(ShapeContour { segments, closed: false });
+                    segments = Vec::new();
+                }
+                start = pf::vec2f(x as f32, y as f32);
+                current = start;
+            }
+            usvg::PathSegment::LineTo { x, y } => {
+                let to = pf::vec2f(x as f32, y as f32);
+                segments.push(CubicSegment::line(current, to));
+                current = to;
+            }
+            usvg::PathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                let to = pf::vec2f(x as f32, y as f32);
+                segments.push(CubicSegment {
+                    from: current,
+                    ctrl0: pf::vec2f(x1 as f32, y1 as f32),
+                    ctrl1: pf::vec2f(x2 as f32, y2 as f32),
+                    to,
+                });
+                current = to;
+            }
+            usvg::PathSegment::ClosePath => {
+                if current != start {
+                    segments.push(CubicSegment::line(current, start));
+                }
+                if !segments.is_empty() {
+                    contours.push(ShapeContour { segments, closed: true });
+                    segments = Vec::new();
+                }
+                current = start;
+            }
+        }
+    }
+    if !segments.is_empty() {
+        contours.push(ShapeContour { segments, closed: false });
+    }
+    contours
+}
+
+fn convert_transform(transform: &usvg::Transform) -> pf::Transform2F {
+    pf::Transform2F::row_major(
+        transform.a as f32,
+        transform.c as f32,
+        transform.b as f32,
+        transform.d as f32,
+        transform.e as f32,
+        transform.f as f32,
+    )
+}
+
+fn convert_color(color: usvg::Color, opacity: f32) -> pf::ColorU {
+    let alpha = (opacity.max(0.0).min(1.0) * 255.0).round() as u8;
+    pf::ColorU::new(color.red, color.green, color.blue, alpha)
+}
@@ -0,0 +1,149 @@
+//! The tool interface. Pointer input goes to the active tool, which edits
+//! the document through a `ToolContext` and may draw feedback over it.
+use std::time::Instant;
+use crate::prelude::*;
+use crate::engine::pointer::{PointerButtons, PointerId, PointerKind};
+use super::brush::BrushTool;
+use super::document::{Document, StrokeId, StrokeStyle};
+use super::eraser::EraserTool;
+use super::fitting::FitOptions;
+use super::history::{Command, History};
+use super::selection::SelectTool;
+use super::shape_tool::ShapeTool;
+
+///////////////////////////////////////////////////////////////////////////////
+// POINTER EVENTS
+///////////////////////////////////////////////////////////////////////////////
+
+/// A `Pointer` as tools see it.
+#[derive(Clone, Copy, Debug)]
+pub struct PointerEvent {
+    pub id: PointerId,
+    pub kind: PointerKind,
+    /// In world coordinates, which strokes are stored in.
+    pub position: Vector2F,
+    /// In device pixels, for tolerances that should not change with zoom.
+    pub screen_position: Vector2F,
+    /// Held after the event.
+    pub buttons: PointerButtons,
+    pub modifiers: wit::ModifiersState,
+    /// Normalized; `1.0` for devices without pressure.
+    pub pressure: f32,
+    /// Pen altitude in radians, where reported.
+    pub tilt: Option<f32>,
+    pub time: Instant,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// TOOLS
+///////////////////////////////////////////////////////////////////////////////
+
+/// What tools may touch while handling input.
+pub struct ToolContext<'a> {
+    pub document: &'a mut Document,
+    pub history: &'a mut History,
+    pub fit_options: &'a FitOptions,
+    /// Style of new strokes.
+    pub brush: &'a StrokeStyle,
+    /// Maps world coordinates to device pixels.
+    pub view: pf::Transform2F,
+    /// Set by tools whose changes need a new frame.
+    pub redraw: bool,
+}
+
+impl<'a> ToolContext<'a> {
+    /// Applies `command` and records it for undo.
+    pub fn execute(&mut self, command: Command) {
+        self.history.execute(self.document, command);
+        self.redraw = true;
+    }
+    /// Records the stroke `id` as added, or removes it if it has no points.
+    /// For strokes a tool built up in the document as input arrived.
+    pub fn finish_stroke(&mut self, id: StrokeId) {
+        let (layer, index) = match self.document.locate(id) {
+            Some(location) => location,
+            None => return,
+        };
+        let stroke = self.document.layer(layer).unwrap().strokes[index].clone();
+        if stroke.is_empty() {
+            self.document.remove_stroke(id);
+            return;
+        }
+        self.history.record(Command::AddStroke { layer, index, stroke });
+    }
+}
+
+/// A pointer drag goes `pointer_down`, `pointer_move`s, then `pointer_up`,
+/// though tools should tolerate missing ends: input can be interrupted, and
+/// a tool can be switched to with a button already held.
+pub trait Tool {
+    fn pointer_down(&mut self, ctx: &mut ToolContext, event: &PointerEvent);
+    /// Every move over the window, with or without buttons held.
+    fn pointer_move(&mut self, ctx: &mut ToolContext, event: &PointerEvent);
+    fn pointer_up(&mut self, ctx: &mut ToolContext, event: &PointerEvent);
+    /// Modifiers changed with the pointer still at `event`.
+    fn modifiers_changed(&mut self, _ctx: &mut ToolContext, _event: &PointerEvent) {}
+    /// Ends whatever the tool is in the middle of, keeping what it has done
+    /// so far. Called before switching tools and when input is interrupted.
+    fn finish(&mut self, ctx: &mut ToolContext);
+    /// Drops whatever the tool is in the middle of, as if it never started.
+    /// Called when input is cancelled and cannot be trusted.
+    fn cancel(&mut self, ctx: &mut ToolContext);
+    /// Draws feedback over the document, in device pixels. `view` maps
+    /// world coordinates there.
+    fn draw_overlay(&self, _ctx: &mut pf::CanvasRenderingContext2D, _view: &pf::Transform2F) {}
+    /// When `handle_timers` next needs calling, if ever.
+    fn next_deadline(&self) -> Option<Instant> {
+        None
+    }
+    fn handle_timers(&mut self, _ctx: &mut ToolContext, _now: Instant) {}
+}
+
+/// What dragging with the primary button does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActiveTool {
+    Brush,
+    Eraser,
+    Select,
+    Shape,
+}
+
+impl Default for ActiveTool {
+    fn default() -> Self {
+        ActiveTool::Brush
+    }
+}
+
+/// Every tool, with its options, and which one gets the input.
+#[derive(Default)]
+pub struct Tools {
+    pub active: ActiveTool,
+    pub brush: BrushTool,
+    pub eraser: EraserTool,
+    pub select: SelectTool,
+    pub shape: ShapeTool,
+}
+
+impl Tools {
+    pub fn active(&self) -> &dyn Tool {
+        match self.active {
+            ActiveTool::Brush => &self.brush,
+            ActiveTool::Eraser => &self.eraser,
+            ActiveTool::Select => &self.select,
+            ActiveTool::Shape => &self.shape,
+        }
+    }
+    pub fn active_mut(&mut self) -> &mut dyn Tool {
+        match self.active {
+            ActiveTool::Brush => &mut self.brush,
+            ActiveTool::Eraser => &mut self.eraser,
+            ActiveTool::Select => &mut self.select,
+            ActiveTool::Shape => &mut self.shape,
+        }
+    }
+    /// Finishes the active tool and hands input to `tool`.
+    pub fn switch(&mut self, ctx: &mut ToolContext, tool: ActiveTool) {
+        self.active_mut().finish(ctx);
+        self.active = tool;
+    }
+}
@@ -13,12 +13,15 @@
 // TODO(#140, pcwalton): Move some of this out of the demo and into the library
 // proper.
 
+use crate::engine::CAMERA_SCALE_SPEED_2D;
+use crate::engine::gesture::GestureTransform;
 use crate::engine::window::{OcularTransform};
-use pathfinder_geometry::vector::{Vector2I, Vector4F};
+use pathfinder_geometry::vector::{Vector2F, Vector2I, Vector4F};
 use pathfinder_geometry::rect::RectF;
 use pathfinder_geometry::transform2d::Transform2F;
 use pathfinder_geometry::transform3d::{Perspective, Transform4F};
 use std::f32::consts::FRAC_PI_4;
+use winit::event::MouseScrollDelta;
 
 const NEAR_CLIP_PLANE: f32 = 0.01;
 const FAR_CLIP_PLANE: f32 = 10.0;
@@ -26,6 +29,14 @@ const FAR_CLIP_PLANE: f32 = 10.0;
 // Half of the eye separation distance.
 const DEFAULT_EYE_OFFSET: f32 = 0.025;
 
+// Limits on how far a 2D camera zooms out and in.
+const MIN_SCALE_2D: f32 = 0.02;
+const MAX_SCALE_2D: f32 = 64.0;
+
+// Zoom distance per notch of a scroll wheel, and per pixel of touchpad scrolling.
+const SCROLL_DISTANCE_PER_LINE: f32 = 0.02;
+const SCROLL_DISTANCE_PER_PIXEL: f32 = 0.001;
+
 pub enum Camera {
     TwoD(Transform2F),
 }
@@ -41,6 +52,72 @@ impl Camera {
         let origin = viewport_size.to_f32() * 0.5 - view_box.size() * (scale * 0.5);
         Camera::TwoD(Transform2F::from_scale(scale).translate(origin))
     }
+
+    /// Maps world coordinates to the screen, in device pixels.
+    pub fn transform(&self) -> Transform2F {
+        match *self {
+            Camera::TwoD(transform) => transform,
+        }
+    }
+
+    pub fn to_world(&self, position: Vector2F) -> Vector2F {
+        self.transform().inverse() * position
+    }
+
+    /// Scales by `scale_delta` around `position` on the screen, so that the
+    /// world point under it stays put. The overall scale is clamped, and
+    /// deltas that are not positive are ignored.
+    pub fn zoom_at(&mut self, scale_delta: f32, position: Vector2F) {
+        if !(scale_delta > 0.0 && scale_delta.is_finite()) {
+            return;
+        }
+        let Camera::TwoD(ref mut transform) = *self;
+        let scale = transform.scale_factor();
+        let new_scale = (scale * scale_delta).max(MIN_SCALE_2D).min(MAX_SCALE_2D);
+        *transform = transform
+            .translate(-position)
+            .scale(new_scale / scale)
+            .translate(position);
+    }
+
+    /// Moves the world by `delta` device pixels on the screen.
+    pub fn pan(&mut self, delta: Vector2F) {
+        let Camera::TwoD(ref mut transform) = *self;
+        *transform = transform.translate(delta);
+    }
+
+    /// Turns the world by `angle` radians around `position` on the screen.
+    pub fn rotate_at(&mut self, angle: f32, position: Vector2F) {
+        let Camera::TwoD(ref mut transform) = *self;
+        *transform = transform.translate(-position).rotate(angle).translate(position);
+    }
+
+    /// Moves the world along with a pinch, pan or twist.
+    pub fn apply_gesture(&mut self, gesture: &GestureTransform) {
+        self.zoom_at(gesture.scale, gesture.origin);
+        self.rotate_at(gesture.rotation, gesture.origin);
+        self.pan(gesture.pan);
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera::TwoD(Transform2F::default())
+    }
+}
+
+/// How far a scroll event zooms; positive when scrolling up, i.e. zooming in.
+pub fn scroll_distance(delta: MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, lines) => lines * SCROLL_DISTANCE_PER_LINE,
+        MouseScrollDelta::PixelDelta(pixels) => pixels.y as f32 * SCROLL_DISTANCE_PER_PIXEL,
+    }
+}
+
+/// The factor to zoom by for a scroll of `distance`. Exponential, so that
+/// however far a touchpad flings it stays positive and zooms out gradually.
+pub fn scroll_zoom(distance: f32) -> f32 {
+    (distance * CAMERA_SCALE_SPEED_2D).exp()
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -88,3 +165,46 @@ impl CameraTransform3D {
 pub fn scale_factor_for_view_box(view_box: RectF) -> f32 {
     1.0 / f32::min(view_box.size().x(), view_box.size().y())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_scrolls_zoom_as_before() {
+        let distance = SCROLL_DISTANCE_PER_LINE;
+        let linear = 1.0 + distance * CAMERA_SCALE_SPEED_2D;
+        assert!((scroll_zoom(distance) - linear).abs() < 0.02);
+        assert_eq!(scroll_zoom(0.0), 1.0);
+    }
+
+    #[test]
+    fn large_flings_zoom_out_gradually() {
+        let fling = -400.0 * SCROLL_DISTANCE_PER_PIXEL;
+        assert!(scroll_zoom(fling) > 0.0);
+        let mut camera = Camera::default();
+        camera.zoom_at(scroll_zoom(fling), Vector2F::splat(100.0));
+        let scale = camera.transform().scale_factor();
+        assert!(scale > MIN_SCALE_2D && scale < 1.0, "{}", scale);
+        assert!((scroll_zoom(fling) * scroll_zoom(-fling) - 1.0).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor() {
+        let transform = Transform2F::from_scale(2.0).translate(Vector2F::splat(5.0));
+        let mut camera = Camera::TwoD(transform);
+        let position = Vector2F::new(120.0, 80.0);
+        let world = camera.to_world(position);
+        camera.zoom_at(scroll_zoom(-0.3), position);
+        assert!((camera.transform() * world - position).length() < 1.0e-3);
+    }
+
+    #[test]
+    fn non_positive_deltas_are_ignored() {
+        let mut camera = Camera::default();
+        camera.zoom_at(0.0, Vector2F::zero());
+        camera.zoom_at(-2.0, Vector2F::zero());
+        camera.zoom_at(f32::NAN, Vector2F::zero());
+        assert_eq!(camera.transform(), Transform2F::default());
+    }
+}
@@ -14,6 +14,7 @@ use pathfinder_renderer::concurrent::executor::{Executor, SequentialExecutor};
 use pathfinder_renderer::concurrent::rayon::RayonExecutor;
 use rayon::ThreadPoolBuilder;
 
+#[derive(Clone, Copy)]
 pub struct DemoExecutor {
     sequential_mode: bool,
 }
@@ -0,0 +1,414 @@
+//! Two-finger gestures: pinch to zoom, drag to pan and twist to rotate, all
+//! at once and about the fingers' centroid.
+//!
+//! A lone touch is held back briefly in case a second finger follows, so a
+//! pinch that lands one finger at a time does not draw. Works on `Pointer`
+//! samples alone, with their timestamps standing in for the clock, so touch
+//! sequences can be replayed without a window.
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+use crate::prelude::*;
+use crate::engine::pointer::{Pointer, PointerButtons, PointerId, PointerKind, PointerPhase};
+
+/// How long a lone touch waits for a second finger before it draws.
+const SECOND_TOUCH_DELAY: Duration = Duration::from_millis(120);
+
+/// A lone touch that moves this many device pixels draws right away.
+const TOUCH_SLOP: f32 = 12.0;
+
+/// Fingers closer than this, in device pixels, give no reliable scale or
+/// angle, and only pan.
+const MIN_SPAN: f32 = 8.0;
+
+///////////////////////////////////////////////////////////////////////////////
+// OUTPUT
+///////////////////////////////////////////////////////////////////////////////
+
+/// One step of a gesture, as a change to the screen: scale by `scale` and
+/// rotate by `rotation` about `origin`, then move by `pan`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GestureTransform {
+    /// The centroid before the step, in device pixels.
+    pub origin: Vector2F,
+    pub pan: Vector2F,
+    pub scale: f32,
+    /// In radians, in the same sense as `Transform2F::from_rotation`.
+    pub rotation: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum GestureEvent {
+    /// Input that is not part of a gesture, for the active tool.
+    Pointer(Pointer),
+    Transform(GestureTransform),
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// RECOGNIZER
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug)]
+enum State {
+    Idle,
+    /// One touch is down and its samples are held back until it moves far
+    /// or long enough to be drawing.
+    Pending {
+        id: PointerId,
+        samples: Vec<Pointer>,
+        deadline: Instant,
+    },
+    /// One touch passes through to the tool. Holds its latest sample.
+    Drawing(Pointer),
+    /// Two or more touches move the camera, until they all lift.
+    Gesture,
+}
+
+/// Mouse and pen input passes straight through; only fingers gesture.
+pub struct GestureRecognizer {
+    state: State,
+    /// Touches in contact and where they are, in the order they landed.
+    touches: Vec<(PointerId, Vector2F)>,
+}
+
+impl GestureRecognizer {
+    pub fn new() -> Self {
+        GestureRecognizer { state: State::Idle, touches: Vec::new() }
+    }
+
+    pub fn handle_pointer(&mut self, pointer: Pointer) -> Vec<GestureEvent> {
+        if pointer.kind != PointerKind::Touch {
+            return vec![GestureEvent::Pointer(pointer)];
+        }
+        let before = self.touches.clone();
+        match pointer.phase {
+            PointerPhase::Down => self.touches.push((pointer.id, pointer.position)),
+            PointerPhase::Move => {
+                for touch in self.touches.iter_mut().filter(|touch| touch.0 == pointer.id) {
+                    touch.1 = pointer.position;
+                }
+            }
+            PointerPhase::Up | PointerPhase::Cancel => {
+                self.touches.retain(|touch| touch.0 != pointer.id);
+            }
+        }
+
+        let state = std::mem::replace(&mut self.state, State::Idle);
+        let (state, events) = match state {
+            State::Idle if pointer.phase == PointerPhase::Down && self.touches.len() == 1 => {
+                let pending = State::Pending {
+                    id: pointer.id,
+                    samples: vec![pointer],
+                    deadline: pointer.time + SECOND_TOUCH_DELAY,
+                };
+                (pending, vec![])
+            }
+            // Joining touches left over from an interrupted gesture.
+            State::Idle if pointer.phase == PointerPhase::Down => (State::Gesture, vec![]),
+            State::Idle => (State::Idle, vec![]),
+            State::Pending { .. } | State::Drawing(_)
+                if pointer.phase == PointerPhase::Down =>
+            {
+                let events = match state {
+                    State::Drawing(last) => vec![GestureEvent::Pointer(cancelled(last))],
+                    _ => vec![],
+                };
+                (State::Gesture, events)
+            }
+            State::Pending { id, mut samples, deadline } if id == pointer.id => {
+                samples.push(pointer);
+                let moved = (pointer.position - samples[0].position).length() > TOUCH_SLOP;
+                match pointer.phase {
+                    PointerPhase::Move if !moved && pointer.time < deadline => {
+                        (State::Pending { id, samples, deadline }, vec![])
+                    }
+                    PointerPhase::Move => (State::Drawing(pointer), release(samples)),
+                    PointerPhase::Cancel => (State::Idle, vec![]),
+                    // A tap draws a dot.
+                    _ => (State::Idle, release(samples)),
+                }
+            }
+            State::Drawing(last) if last.id == pointer.id => {
+                let state = match pointer.phase {
+                    PointerPhase::Move => State::Drawing(pointer),
+                    _ => State::Idle,
+                };
+                (state, vec![GestureEvent::Pointer(pointer)])
+            }
+            State::Gesture if self.touches.is_empty() => (State::Idle, vec![]),
+            State::Gesture if pointer.phase == PointerPhase::Move => {
+                let events = gesture_step(&before, &self.touches)
+                    .map(GestureEvent::Transform)
+                    .into_iter()
+                    .collect();
+                (State::Gesture, events)
+            }
+            state => (state, vec![]),
+        };
+        self.state = state;
+        events
+    }
+
+    /// When `handle_timers` next needs calling, if ever.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.state {
+            State::Pending { deadline, .. } => Some(deadline),
+            _ => None,
+        }
+    }
+
+    /// Lets a touch that waited long enough for a second finger draw.
+    pub fn handle_timers(&mut self, now: Instant) -> Vec<GestureEvent> {
+        match self.state {
+            State::Pending { deadline, ref samples, .. } if deadline <= now => {
+                let last = samples[samples.len() - 1];
+                let events = release(samples.clone());
+                self.state = State::Drawing(last);
+                events
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Forgets every touch, e.g. after focus was lost and their ends may
+    /// never arrive.
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.touches.clear();
+    }
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        GestureRecognizer::new()
+    }
+}
+
+fn release(samples: Vec<Pointer>) -> Vec<GestureEvent> {
+    samples.into_iter().map(GestureEvent::Pointer).collect()
+}
+
+fn cancelled(last: Pointer) -> Pointer {
+    Pointer {
+        phase: PointerPhase::Cancel,
+        button: None,
+        buttons: PointerButtons::default(),
+        ..last
+    }
+}
+
+/// The change from the first two touches `before` to the same touches
+/// `after`. Nothing if either has fewer than two, or they are different
+/// touches.
+fn gesture_step(
+    before: &[(PointerId, Vector2F)],
+    after: &[(PointerId, Vector2F)],
+) -> Option<GestureTransform> {
+    if before.len() < 2 || after.len() < 2 {
+        return None;
+    }
+    if before[0].0 != after[0].0 || before[1].0 != after[1].0 {
+        return None;
+    }
+    let (from0, from1) = (before[0].1, before[1].1);
+    let (to0, to1) = (after[0].1, after[1].1);
+    let origin = (from0 + from1) * 0.5;
+    let pan = (to0 + to1) * 0.5 - origin;
+    let (from_span, to_span) = (from1 - from0, to1 - to0);
+    if from_span.length() < MIN_SPAN || to_span.length() < MIN_SPAN {
+        return Some(GestureTransform { origin, pan, scale: 1.0, rotation: 0.0 });
+    }
+    let scale = to_span.length() / from_span.length();
+    let mut rotation = to_span.y().atan2(to_span.x()) - from_span.y().atan2(from_span.x());
+    if rotation > PI {
+        rotation -= 2.0 * PI;
+    } else if rotation < -PI {
+        rotation += 2.0 * PI;
+    }
+    Some(GestureTransform { origin, pan, scale, rotation })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::pointer::PointerButton;
+    use super::*;
+
+    struct Fingers {
+        recognizer: GestureRecognizer,
+        start: Instant,
+    }
+
+    impl Fingers {
+        fn new() -> Fingers {
+            Fingers { recognizer: GestureRecognizer::new(), start: Instant::now() }
+        }
+        fn at(&self, ms: u64) -> Instant {
+            self.start + Duration::from_millis(ms)
+        }
+        fn touch(
+            &mut self,
+            id: u64,
+            phase: PointerPhase,
+            x: f32,
+            y: f32,
+            ms: u64,
+        ) -> Vec<GestureEvent> {
+            let button = match phase {
+                PointerPhase::Down | PointerPhase::Up => Some(PointerButton::Primary),
+                _ => None,
+            };
+            let primary = phase == PointerPhase::Down || phase == PointerPhase::Move;
+            self.recognizer.handle_pointer(Pointer {
+                id: PointerId::Touch(id),
+                kind: PointerKind::Touch,
+                phase,
+                button,
+                buttons: PointerButtons { primary, ..PointerButtons::default() },
+                position: Vector2F::new(x, y),
+                pressure: 1.0,
+                tilt: None,
+                time: self.at(ms),
+            })
+        }
+    }
+
+    /// What `step` does to a point on the screen.
+    fn step_transform(step: &GestureTransform) -> pf::Transform2F {
+        pf::Transform2F::from_translation(step.origin + step.pan) *
+            pf::Transform2F::from_rotation(step.rotation) *
+            pf::Transform2F::from_scale(step.scale) *
+            pf::Transform2F::from_translation(-step.origin)
+    }
+
+    /// The steps of `events` combined, first to last. Panics on pointer
+    /// events, which gestures must not pass to tools.
+    fn combine(events: &[GestureEvent]) -> pf::Transform2F {
+        events.iter().fold(pf::Transform2F::default(), |transform, event| match *event {
+            GestureEvent::Transform(ref step) => step_transform(step) * transform,
+            GestureEvent::Pointer(pointer) => panic!("gesture drew: {:?}", pointer),
+        })
+    }
+
+    fn assert_close(a: Vector2F, b: Vector2F) {
+        assert!((a - b).length() < 1.0e-3, "{:?} != {:?}", a, b);
+    }
+
+    /// Two fingers down 50 ms apart at `a` and `b`.
+    fn two_fingers(a: Vector2F, b: Vector2F) -> Fingers {
+        let mut fingers = Fingers::new();
+        assert!(fingers.touch(0, PointerPhase::Down, a.x(), a.y(), 0).is_empty());
+        assert!(fingers.touch(1, PointerPhase::Down, b.x(), b.y(), 50).is_empty());
+        fingers
+    }
+
+    #[test]
+    fn pinches_scale_about_the_centroid() {
+        let mut fingers = two_fingers(Vector2F::new(100.0, 100.0), Vector2F::new(200.0, 100.0));
+        let mut events = fingers.touch(0, PointerPhase::Move, 50.0, 100.0, 60);
+        events.extend(fingers.touch(1, PointerPhase::Move, 250.0, 100.0, 70));
+        assert_eq!(events.len(), 2);
+        let transform = combine(&events);
+        assert_close(transform * Vector2F::new(150.0, 100.0), Vector2F::new(150.0, 100.0));
+        assert_close(transform * Vector2F::new(100.0, 100.0), Vector2F::new(50.0, 100.0));
+        assert_close(transform * Vector2F::new(200.0, 100.0), Vector2F::new(250.0, 100.0));
+        assert!((transform.scale_factor() - 2.0).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn two_finger_drags_pan() {
+        let mut fingers = two_fingers(Vector2F::new(100.0, 100.0), Vector2F::new(200.0, 100.0));
+        let mut events = Vec::new();
+        for step in 1..=4 {
+            let offset = Vector2F::new(10.0, -5.0) * step as f32;
+            let ms = 60 + step * 10;
+            let (x, y) = (offset.x(), 100.0 + offset.y());
+            events.extend(fingers.touch(0, PointerPhase::Move, 100.0 + x, y, ms));
+            events.extend(fingers.touch(1, PointerPhase::Move, 200.0 + x, y, ms));
+        }
+        let transform = combine(&events);
+        for point in [Vector2F::new(0.0, 0.0), Vector2F::new(150.0, 100.0)].iter() {
+            assert_close(transform * *point, *point + Vector2F::new(40.0, -20.0));
+        }
+    }
+
+    #[test]
+    fn twists_rotate_about_the_centroid() {
+        let mut fingers = two_fingers(Vector2F::new(100.0, 100.0), Vector2F::new(200.0, 100.0));
+        // The second finger swings round the first a quarter turn.
+        let events = fingers.touch(1, PointerPhase::Move, 100.0, 200.0, 60);
+        match events[..] {
+            [GestureEvent::Transform(step)] => {
+                assert!((step.rotation - PI / 2.0).abs() < 1.0e-4, "{}", step.rotation);
+            }
+            _ => panic!("{:?}", events),
+        }
+        // Both fingers turn a quarter turn about their centroid.
+        let mut fingers = two_fingers(Vector2F::new(100.0, 100.0), Vector2F::new(200.0, 100.0));
+        let mut events = fingers.touch(0, PointerPhase::Move, 150.0, 50.0, 60);
+        events.extend(fingers.touch(1, PointerPhase::Move, 150.0, 150.0, 70));
+        let transform = combine(&events);
+        assert_close(transform * Vector2F::new(150.0, 100.0), Vector2F::new(150.0, 100.0));
+        assert_close(transform * Vector2F::new(100.0, 100.0), Vector2F::new(150.0, 50.0));
+        assert_close(transform * Vector2F::new(150.0, 50.0), Vector2F::new(200.0, 100.0));
+    }
+
+    #[test]
+    fn quick_second_fingers_never_draw() {
+        let mut fingers = Fingers::new();
+        let mut events = fingers.touch(0, PointerPhase::Down, 100.0, 100.0, 0);
+        events.extend(fingers.touch(0, PointerPhase::Move, 104.0, 100.0, 40));
+        assert!(fingers.recognizer.next_deadline().is_some());
+        events.extend(fingers.touch(1, PointerPhase::Down, 200.0, 100.0, 80));
+        assert_eq!(fingers.recognizer.next_deadline(), None);
+        let later = fingers.at(1000);
+        events.extend(fingers.recognizer.handle_timers(later));
+        events.extend(fingers.touch(1, PointerPhase::Move, 220.0, 100.0, 1000));
+        events.extend(fingers.touch(0, PointerPhase::Up, 104.0, 100.0, 1010));
+        events.extend(fingers.touch(1, PointerPhase::Up, 220.0, 100.0, 1020));
+        combine(&events);
+        // Both lifted, so the next touch may draw again.
+        assert!(fingers.touch(2, PointerPhase::Down, 0.0, 0.0, 2000).is_empty());
+        assert!(fingers.recognizer.next_deadline().is_some());
+    }
+
+    #[test]
+    fn lone_touches_draw_after_the_delay() {
+        let mut fingers = Fingers::new();
+        assert!(fingers.touch(0, PointerPhase::Down, 100.0, 100.0, 0).is_empty());
+        assert!(fingers.touch(0, PointerPhase::Move, 102.0, 100.0, 20).is_empty());
+        let deadline = fingers.recognizer.next_deadline().unwrap();
+        assert_eq!(deadline, fingers.at(0) + SECOND_TOUCH_DELAY);
+        let events = fingers.recognizer.handle_timers(deadline);
+        assert_eq!(events.len(), 2);
+        let events = fingers.touch(0, PointerPhase::Move, 110.0, 100.0, 200);
+        assert_eq!(events.len(), 1);
+        // A second finger now takes the stroke back from the tool.
+        match fingers.touch(1, PointerPhase::Down, 200.0, 100.0, 300)[..] {
+            [GestureEvent::Pointer(pointer)] => {
+                assert_eq!(pointer.phase, PointerPhase::Cancel);
+                assert_eq!(pointer.id, PointerId::Touch(0));
+            }
+            ref events => panic!("{:?}", events),
+        }
+    }
+
+    #[test]
+    fn mice_and_pens_pass_through() {
+        let mut fingers = Fingers::new();
+        let pointer = Pointer {
+            id: PointerId::Mouse,
+            kind: PointerKind::Mouse,
+            phase: PointerPhase::Down,
+            button: Some(PointerButton::Primary),
+            buttons: PointerButtons { primary: true, ..PointerButtons::default() },
+            position: Vector2F::zero(),
+            pressure: 1.0,
+            tilt: None,
+            time: fingers.at(0),
+        };
+        for kind in [PointerKind::Mouse, PointerKind::Pen].iter() {
+            let pointer = Pointer { kind: *kind, ..pointer };
+            let events = fingers.recognizer.handle_pointer(pointer);
+            assert!(matches!(events[..], [GestureEvent::Pointer(_)]));
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! A `Window` without a window: renders into an offscreen surface and replays
+//! scripted winit events, so the app can be driven from tests and scripts.
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use pathfinder_geometry::vector::vec2i;
+use pathfinder_resources::ResourceLoader;
+use pathfinder_resources::fs::FilesystemResourceLoader;
+use crate::engine::surface::{AdapterKind, GpuSurface};
+use crate::engine::window::{Window, WindowSize};
+use crate::prelude::*;
+
+lazy_static! {
+    /// `(message_type, message_data)` pairs pushed through `push_user_event`.
+    static ref USER_EVENTS: Mutex<VecDeque<(u32, u32)>> = Mutex::new(VecDeque::new());
+}
+
+pub struct HeadlessWindow {
+    gpu: GpuSurface,
+    size: WindowSize,
+    resource_loader: FilesystemResourceLoader,
+    events: VecDeque<wit::WindowEvent<'static>>,
+    /// Paths handed out by the next open dialogs, in order. Once empty,
+    /// dialogs behave as if cancelled.
+    pub open_dialog_responses: RefCell<VecDeque<PathBuf>>,
+    /// Same as `open_dialog_responses`, for save dialogs.
+    pub save_dialog_responses: RefCell<VecDeque<PathBuf>>,
+    next_user_event_id: Cell<u32>,
+    frames_presented: u32,
+}
+
+impl HeadlessWindow {
+    /// `size` is in device pixels. Honors `LIBGL_ALWAYS_SOFTWARE` so that CI
+    /// can render on llvmpipe.
+    pub fn new(size: Vector2I) -> HeadlessWindow {
+        let gpu = GpuSurface::new_offscreen(size, AdapterKind::from_env(false));
+        HeadlessWindow {
+            gpu,
+            size: WindowSize { logical_size: size, backing_scale_factor: 1.0 },
+            resource_loader: FilesystemResourceLoader::locate(),
+            events: VecDeque::new(),
+            open_dialog_responses: RefCell::new(VecDeque::new()),
+            save_dialog_responses: RefCell::new(VecDeque::new()),
+            next_user_event_id: Cell::new(0),
+            frames_presented: 0,
+        }
+    }
+
+    pub fn push_event(&mut self, event: wit::WindowEvent<'static>) {
+        self.events.push_back(event);
+    }
+
+    /// Pops the next scripted event. A `Resized` event takes effect here, as
+    /// it would have in a real window before the event was delivered.
+    pub fn next_event(&mut self) -> Option<wit::WindowEvent<'static>> {
+        let event = self.events.pop_front()?;
+        if let wit::WindowEvent::Resized(size) = event {
+            self.size.logical_size = vec2i(size.width as i32, size.height as i32);
+        }
+        Some(event)
+    }
+
+    pub fn frames_presented(&self) -> u32 {
+        self.frames_presented
+    }
+
+    /// Drains the user events pushed so far, oldest first.
+    pub fn take_user_events() -> Vec<(u32, u32)> {
+        USER_EVENTS.lock().unwrap().drain(..).collect()
+    }
+}
+
+impl Drop for HeadlessWindow {
+    fn drop(&mut self) {
+        self.gpu.destroy();
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// SCRIPTED EVENTS
+///////////////////////////////////////////////////////////////////////////////
+
+#[allow(deprecated)]
+impl HeadlessWindow {
+    pub fn cursor_moved(position: Vector2F) -> wit::WindowEvent<'static> {
+        wit::WindowEvent::CursorMoved {
+            device_id: dummy_device_id(),
+            position: wit::PhysicalPosition::new(position.x() as f64, position.y() as f64),
+            modifiers: wit::ModifiersState::empty(),
+        }
+    }
+
+    pub fn mouse_input(state: wit::ElementState, button: wit::MouseButton) -> wit::WindowEvent<'static> {
+        wit::WindowEvent::MouseInput {
+            device_id: dummy_device_id(),
+            state,
+            button,
+            modifiers: wit::ModifiersState::empty(),
+        }
+    }
+
+    /// A touch, or a pen if `force` reports an altitude angle.
+    pub fn touch(
+        id: u64,
+        phase: wit::TouchPhase,
+        position: Vector2F,
+        force: Option<wit::Force>,
+    ) -> wit::WindowEvent<'static> {
+        wit::WindowEvent::Touch(wit::Touch {
+            device_id: dummy_device_id(),
+            phase,
+            location: wit::PhysicalPosition::new(position.x() as f64, position.y() as f64),
+            force,
+            id,
+        })
+    }
+
+    pub fn key_input(state: wit::ElementState, key: wit::VirtualKeyCode) -> wit::WindowEvent<'static> {
+        wit::WindowEvent::KeyboardInput {
+            device_id: dummy_device_id(),
+            input: wit::KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(key),
+                modifiers: wit::ModifiersState::empty(),
+            },
+            is_synthetic: true,
+        }
+    }
+
+    /// Focus and cursor-entered events, which a window receives before the
+    /// app will accept any drawing input.
+    pub fn activate() -> Vec<wit::WindowEvent<'static>> {
+        vec![
+            wit::WindowEvent::Focused(true),
+            wit::WindowEvent::CursorEntered { device_id: dummy_device_id() },
+        ]
+    }
+}
+
+fn dummy_device_id() -> wit::DeviceId {
+    // Only ever compared against other IDs, which the app never does.
+    unsafe { wit::DeviceId::dummy() }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// WINDOW
+///////////////////////////////////////////////////////////////////////////////
+
+impl Window for HeadlessWindow {
+    type Device = pf::DeviceImpl;
+
+    fn create_device(&mut self) -> pf::DeviceImpl {
+        self.gpu.create_device()
+    }
+
+    fn present(&mut self, device: &mut pf::DeviceImpl) {
+        self.gpu.present(device);
+        self.frames_presented += 1;
+    }
+
+    fn resize_surface(&mut self, device: &mut pf::DeviceImpl) {
+        self.gpu.resize(self.size.device_size(), device);
+    }
+
+    fn size(&self) -> WindowSize {
+        self.size
+    }
+
+    fn resource_loader(&self) -> &dyn ResourceLoader {
+        &self.resource_loader
+    }
+
+    fn create_user_event_id(&self) -> u32 {
+        let id = self.next_user_event_id.get();
+        self.next_user_event_id.set(id + 1);
+        id
+    }
+
+    fn push_user_event(message_type: u32, message_data: u32) {
+        USER_EVENTS.lock().unwrap().push_back((message_type, message_data));
+    }
+
+    /// Delivers the chosen file the way a drop onto the window would be.
+    fn present_open_svg_dialog(&mut self) {
+        if let Ok(path) = self.run_open_dialog("svg,pdf") {
+            self.events.push_back(wit::WindowEvent::DroppedFile(path));
+        }
+    }
+
+    fn run_open_dialog(&self, _: &str) -> Result<PathBuf, ()> {
+        self.open_dialog_responses.borrow_mut().pop_front().ok_or(())
+    }
+
+    fn run_save_dialog(&self, _: &str) -> Result<PathBuf, ()> {
+        self.save_dialog_responses.borrow_mut().pop_front().ok_or(())
+    }
+}
@@ -9,11 +9,15 @@
 // except according to those terms.
 pub mod window;
 pub mod view;
-mod camera;
+pub mod surface;
+pub mod headless;
+pub mod camera;
+pub mod pointer;
+pub mod gesture;
 mod concurrent;
 mod device;
-mod renderer;
-mod ui;
+pub mod renderer;
+pub mod ui;
 use clap::{App, Arg};
 use pathfinder_content::effects::DEFRINGING_KERNEL_CORE_GRAPHICS;
 use pathfinder_content::effects::PatternFilter;
@@ -21,6 +25,7 @@ use pathfinder_content::effects::STEM_DARKENING_FACTORS;
 use pathfinder_content::outline::Outline;
 use pathfinder_content::pattern::Pattern;
 use pathfinder_content::render_target::RenderTargetId;
+use pathfinder_color::ColorU;
 use pathfinder_export::{Export, FileFormat};
 use pathfinder_geometry::rect::{RectF, RectI};
 use pathfinder_geometry::transform2d::Transform2F;
@@ -37,18 +42,22 @@ use pathfinder_renderer::scene::{DrawPath, RenderTarget, Scene};
 use pathfinder_resources::ResourceLoader;
 use pathfinder_svg::SVGScene;
 use pathfinder_ui::{MousePosition, UIEvent};
+use std::fmt::Display;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::PathBuf;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use usvg::{Options as UsvgOptions, Tree as SvgTree};
-use pathfinder_metal::MetalDevice as DeviceImpl;
 use crate::engine::camera::Camera;
 use crate::engine::concurrent::DemoExecutor;
+use crate::app::document::{Document, Stroke, StrokeId, StrokeOrigin, StrokePoint, StrokeStyle};
+use crate::app::fitting::FitOptions;
+use crate::app::scene::line_outline;
+use crate::engine::pointer::{Pointer, PointerButton, PointerPhase};
 use crate::engine::device::{GroundProgram, GroundVertexArray};
 use crate::engine::ui::{DemoUIModel, ScreenshotInfo, ScreenshotType, UIAction};
-use crate::engine::window::{Event, Keycode, DataPath, WindowImpl, WindowSize};
+use crate::engine::window::{Event, Keycode, DataPath, Window, WindowSize};
 
 
 
@@ -62,7 +71,7 @@ const MOUSELOOK_ROTATION_SPEED: f32 = 0.007;
 const CAMERA_VELOCITY: f32 = 0.02;
 
 // How much the scene is scaled when a scale gesture is performed.
-const CAMERA_SCALE_SPEED_2D: f32 = 6.0;
+pub const CAMERA_SCALE_SPEED_2D: f32 = 6.0;
 // How much the scene is scaled when a zoom button is clicked.
 const CAMERA_ZOOM_AMOUNT_2D: f32 = 0.1;
 
@@ -79,13 +88,13 @@ const MESSAGE_TIMEOUT_SECS: u64 = 5;
 ///////////////////////////////////////////////////////////////////////////////
 
 
-pub struct DemoApp {
+pub struct DemoApp<W: Window> {
     pub should_exit: bool,
     pub options: Options,
 
     window_size: WindowSize,
 
-    svg_model: SvgDataModel,
+    content: ContentModel,
     scene_metadata: SceneMetadata,
     render_transform: Option<RenderTransform>,
 
@@ -103,20 +112,22 @@ pub struct DemoApp {
     ui_model: DemoUIModel,
 
     scene_proxy: SceneProxy,
-    renderer: Renderer<DeviceImpl>,
-
-    scene_framebuffer: Option<<DeviceImpl as Device>::Framebuffer>,
-
-    points: Vec<Vector2I>,
+    /// The scene last handed to `scene_proxy`, which finished strokes are
+    /// appended to instead of building it all again.
+    scene: Scene,
+    /// The stroke being drawn, rendered over `scene` and moved into it when
+    /// the drag ends, so that a move only builds this.
+    drag_scene_proxy: SceneProxy,
+    renderer: Renderer<W::Device>,
+
+    scene_framebuffer: Option<<W::Device as Device>::Framebuffer>,
 }
 
-impl DemoApp {
-    pub fn new(window: WindowImpl, window_size: WindowSize, options: Options) -> DemoApp {
+impl<W: Window> DemoApp<W> {
+    pub fn new(mut window: W, window_size: WindowSize, options: Options) -> DemoApp<W> {
         let expire_message_event_id = window.create_user_event_id();
 
-        let device = unsafe {
-            DeviceImpl::new(window.metal_device(), window.metal_io_surface())
-        };
+        let device = window.create_device();
 
         let resources = window.resource_loader();
 
@@ -143,13 +154,8 @@ impl DemoApp {
         let filter = build_filter(&ui_model);
 
         let viewport = window.viewport();
-        let mut svg_model = load_scene(
-            &window_size,
-            &[],
-            resources
-        );
-
-        let (mut scene, message) = svg_model.render(viewport.size(), filter);
+        let mut content = ContentModel::Drawing(Drawing::default());
+        let (mut scene, message) = content.render(viewport.size(), filter);
         let renderer = Renderer::new(device, resources, render_mode, render_options);
         let scene_metadata = SceneMetadata::new_clipping_view_box(
             &mut scene,
@@ -159,7 +165,8 @@ impl DemoApp {
             scene_metadata.view_box,
             viewport.size()
         );
-        let scene_proxy = SceneProxy::from_scene(scene, level, executor);
+        let drag_scene_proxy = SceneProxy::from_scene(empty_scene(&scene), level, executor);
+        let scene_proxy = SceneProxy::from_scene(scene.clone(), level, executor);
 
         let mut message_epoch = 0;
         emit_message(
@@ -167,23 +174,28 @@ impl DemoApp {
             &mut message_epoch,
             expire_message_event_id,
             message,
+            W::push_user_event,
         );
 
         // let ui_presenter = DemoUIPresenter::new();
 
+        let pending_screenshot_info = options.screenshot_path
+            .clone()
+            .and_then(ScreenshotInfo::from_path);
+
         DemoApp {
             should_exit: false,
             options,
 
             window_size,
 
-            svg_model,
+            content,
             scene_metadata,
             render_transform: None,
 
             camera,
             frame_counter: 0,
-            pending_screenshot_info: None,
+            pending_screenshot_info,
             mouselook_enabled: false,
             dirty: true,
             expire_message_event_id,
@@ -196,11 +208,11 @@ impl DemoApp {
             ui_model,
 
             scene_proxy,
+            scene,
+            drag_scene_proxy,
             renderer,
 
             scene_framebuffer: None,
-
-            points: Default::default(),
         }
     }
 
@@ -222,7 +234,7 @@ impl DemoApp {
         // }
     }
 
-    pub fn prepare_frame(&mut self, events: Vec<Event>, window: &WindowImpl) -> u32 {
+    pub fn prepare_frame(&mut self, events: Vec<Event>, window: &W) -> u32 {
         // Clear dirty flag.
         self.dirty = false;
 
@@ -247,18 +259,52 @@ impl DemoApp {
             Camera::TwoD(transform) => Some(RenderTransform::Transform2D(transform)),
         };
 
-        let build_options = BuildOptions {
-            transform: self.render_transform.clone().unwrap(),
-            dilation: if self.ui_model.stem_darkening_effect_enabled {
-                let font_size = APPROX_FONT_SIZE * self.window_size.backing_scale_factor;
-                vec2f(STEM_DARKENING_FACTORS[0], STEM_DARKENING_FACTORS[1]) * font_size
-            } else {
-                Vector2F::zero()
-            },
-            subpixel_aa_enabled: self.ui_model.subpixel_aa_effect_enabled,
+        let dilation = if self.ui_model.stem_darkening_effect_enabled {
+            let font_size = APPROX_FONT_SIZE * self.window_size.backing_scale_factor;
+            vec2f(STEM_DARKENING_FACTORS[0], STEM_DARKENING_FACTORS[1]) * font_size
+        } else {
+            Vector2F::zero()
+        };
+        let transform = self.render_transform.clone().unwrap();
+        let subpixel_aa_enabled = self.ui_model.subpixel_aa_effect_enabled;
+        let build_options = || BuildOptions {
+            transform: transform.clone(),
+            dilation,
+            subpixel_aa_enabled,
+        };
+
+        self.scene_proxy.build(build_options());
+        self.drag_scene_proxy.build(build_options());
+    }
+
+    /// Makes `scene`, with every finished stroke in it, the one rendered.
+    fn replace_scene(&mut self, scene: Scene) {
+        self.scene = scene;
+        self.hand_over_scene();
+    }
+
+    /// Adds where `pointer` is to the stroke being drawn, and draws that
+    /// stroke alone into the drag scene. Imported files are only looked at.
+    fn draw_point(&mut self, pointer: &Pointer) {
+        let drawing = match self.content {
+            ContentModel::Drawing(ref mut drawing) => drawing,
+            ContentModel::Svg(_) => return,
         };
+        let backing_scale_factor = self.window_size.backing_scale_factor;
+        let position = self.camera.to_world(pointer.position * backing_scale_factor);
+        if let Some(stroke) = drawing.push_point(position, pointer.pressure, pointer.time) {
+            let mut scene = empty_scene(&self.scene);
+            push_stroke(&mut scene, stroke);
+            self.drag_scene_proxy.replace_scene(scene);
+        }
+        self.dirty = true;
+    }
 
-        self.scene_proxy.build(build_options);
+    /// Hands a copy of `scene` to `scene_proxy` and empties the drag scene.
+    /// Copies the scene once per stroke rather than once per move.
+    fn hand_over_scene(&mut self) {
+        self.drag_scene_proxy.replace_scene(empty_scene(&self.scene));
+        self.scene_proxy.replace_scene(self.scene.clone());
     }
 
     ///////////////////////////////////////////////////////////////////////////
@@ -268,26 +314,23 @@ impl DemoApp {
         &mut self,
         event_sink: &mut Vec<UIEvent>,
         event: Event,
-        window: &WindowImpl
+        window: &W
     ) {
         // RENAME TODO: What is this?
-        let check_user_event = |app: &DemoApp, event_id: u32, expected_epoch: u32| {
+        let check_user_event = |app: &DemoApp<W>, event_id: u32, expected_epoch: u32| {
             event_id == app.expire_message_event_id && expected_epoch as u32 == app.message_epoch
         };
         let mut handle_zoom = {
-            |app: &mut DemoApp, d_dist: f32, position: Vector2I| {
-                let Camera::TwoD(ref mut transform) = app.camera;
+            |app: &mut DemoApp<W>, d_dist: f32, position: Vector2I| {
                 let backing_scale_factor = app.window_size.backing_scale_factor;
-                    let position = position.to_f32() * backing_scale_factor;
-                    let scale_delta = 1.0 + d_dist * CAMERA_SCALE_SPEED_2D;
-                    *transform = transform
-                        .translate(-position)
-                        .scale(scale_delta)
-                        .translate(position);
+                let position = position.to_f32() * backing_scale_factor;
+                let scale_delta = camera::scroll_zoom(d_dist);
+                app.camera.zoom_at(scale_delta, position);
+                app.dirty = true;
             }
         };
         let process_mouse_position = {
-            |app: &mut DemoApp, new_position: Vector2I| -> MousePosition {
+            |app: &mut DemoApp<W>, new_position: Vector2I| -> MousePosition {
                 let value =
                     new_position.to_f32() *
                     app.window_size.backing_scale_factor;
@@ -297,23 +340,16 @@ impl DemoApp {
                 MousePosition { absolute, relative }
             }
         };
-        let render_scene = |app: &mut DemoApp| {
+        let render_scene = |app: &mut DemoApp<W>| {
             let viewport = window.viewport();
             let filter = build_filter(&app.ui_model);
-            app.svg_model = load_scene(
-                &app.window_size,
-                &app.points,
-                window.resource_loader(),
-            );
-            let (mut scene, message) = app.svg_model.render(viewport.size(), filter);
+            let (mut scene, message) = app.content.render(viewport.size(), filter);
             app.ui_model.message = message;
             let viewport_size = window.viewport().size();
+            // Points are in world coordinates, so the camera stays where the
+            // user left it.
             app.scene_metadata = SceneMetadata::new_clipping_view_box(&mut scene, viewport_size);
-            app.camera = Camera::new(
-                app.scene_metadata.view_box,
-                viewport_size
-            );
-            app.scene_proxy.replace_scene(scene);
+            app.replace_scene(scene);
             app.dirty = true;
         };
         match event {
@@ -324,30 +360,65 @@ impl DemoApp {
             Event::WindowResized(new_size) => {
                 self.window_size = new_size;
                 let viewport = window.viewport();
-                self.scene_proxy.set_view_box(RectF::new(Vector2F::zero(), viewport.size().to_f32()));
+                let view_box = RectF::new(Vector2F::zero(), viewport.size().to_f32());
+                self.scene_proxy.set_view_box(view_box);
+                self.drag_scene_proxy.set_view_box(view_box);
+                self.scene.set_view_box(view_box);
                 self.renderer.options_mut().dest =
                     DestFramebuffer::full_window(self.window_size.device_size());
                 self.renderer.dest_framebuffer_size_changed();
                 self.dirty = true;
             }
-            Event::MouseDown(new_position) => {
-                let mouse_position = process_mouse_position(self, new_position);
-                event_sink.push(UIEvent::MouseDown(mouse_position));
+            Event::Pointer(pointer) => {
+                let position = pointer.position.to_i32();
+                match pointer.phase {
+                    PointerPhase::Down if pointer.button == Some(PointerButton::Primary) => {
+                        let mouse_position = process_mouse_position(self, position);
+                        event_sink.push(UIEvent::MouseDown(mouse_position));
+                        self.draw_point(&pointer);
+                    }
+                    PointerPhase::Move if pointer.buttons.primary => {
+                        let mouse_position = process_mouse_position(self, position);
+                        // event_sink.push(UIEvent::MouseDragged(mouse_position));
+                        self.draw_point(&pointer);
+                    }
+                    PointerPhase::Up | PointerPhase::Cancel => {
+                        let drawing = match self.content {
+                            ContentModel::Drawing(ref mut drawing) => drawing,
+                            ContentModel::Svg(_) => return,
+                        };
+                        let first = drawing.document.stroke_count() == 1;
+                        let stroke = match drawing.end_stroke() {
+                            Some(stroke) => stroke,
+                            None => return,
+                        };
+                        // Filters draw the scene into a render target first,
+                        // which the stroke must go into too, and the first
+                        // stroke replaces the placeholder.
+                        if first || build_filter(&self.ui_model).is_some() {
+                            render_scene(self)
+                        } else {
+                            push_stroke(&mut self.scene, stroke);
+                            self.hand_over_scene();
+                        }
+                        self.dirty = true;
+                    }
+                    PointerPhase::Move if self.mouselook_enabled => {
+                        let mouse_position = process_mouse_position(self, position);
+                    }
+                    _ => {}
+                }
             }
-            Event::MouseMoved(new_position) if self.mouselook_enabled => {
-                let mouse_position = process_mouse_position(self, new_position);
+            Event::Zoom(d_dist, position) => {
+                handle_zoom(self, d_dist, position)
             }
-            Event::MouseDragged{delta, global} => {
-                let global_mouse_pos = global;
-                let mouse_position = process_mouse_position(self, delta);
-                // event_sink.push(UIEvent::MouseDragged(mouse_position));
-                self.dirty = true;
-                self.points.push(global_mouse_pos);
-                render_scene(self)
+            // The screenshot buttons of the UI, which is not drawn.
+            Event::KeyDown(Keycode::Alphanumeric(b'P')) => {
+                self.request_screenshot(ScreenshotType::PNG, window);
+            }
+            Event::KeyDown(Keycode::Alphanumeric(b'S')) => {
+                self.request_screenshot(ScreenshotType::SVG, window);
             }
-            // Event::Zoom(d_dist, position) => {
-            //     handle_zoom(self, d_dist, position)
-            // }
             Event::KeyDown(Keycode::Tab) => {
                 self.options.ui = match self.options.ui {
                     UIVisibility::None => UIVisibility::Stats,
@@ -356,8 +427,23 @@ impl DemoApp {
                 }
             }
 
-            Event::OpenData(..) => {
-                render_scene(self)
+            Event::OpenData(ref data_path) => {
+                match SvgDataModel::load(data_path, window.resource_loader()) {
+                    Ok(svg_model) => {
+                        self.content = ContentModel::Svg(svg_model);
+                        render_scene(self);
+                    }
+                    Err(message) => {
+                        emit_message(
+                            &mut self.ui_model,
+                            &mut self.message_epoch,
+                            self.expire_message_event_id,
+                            message,
+                            W::push_user_event,
+                        );
+                        self.dirty = true;
+                    }
+                }
             }
 
             Event::User {
@@ -374,13 +460,13 @@ impl DemoApp {
     fn handle_events(
         &mut self,
         events: Vec<Event>,
-        window: &WindowImpl
+        window: &W
     ) -> Vec<UIEvent> {
         let mut ui_events = vec![];
         self.dirty = false;
 
         // RENAME TODO: What is this?
-        let check_user_event = |app: &DemoApp, event_id: u32, expected_epoch: u32| {
+        let check_user_event = |app: &DemoApp<W>, event_id: u32, expected_epoch: u32| {
             event_id == app.expire_message_event_id && expected_epoch as u32 == app.message_epoch
         };
 
@@ -391,7 +477,7 @@ impl DemoApp {
         ui_events
     }
 
-    pub fn finish_drawing_frame(&mut self, window: &mut WindowImpl) {
+    pub fn finish_drawing_frame(&mut self, window: &mut W) {
         let frame = self.current_frame.take().unwrap();
         for ui_event in &frame.ui_events {
             self.dirty = true;
@@ -417,6 +503,11 @@ impl DemoApp {
         }
 
         self.handle_ui_events(frame, &mut ui_action);
+        self.handle_ui_action(&mut ui_action, window);
+
+        if let Some(screenshot_info) = self.pending_screenshot_info.take() {
+            self.take_screenshot(screenshot_info);
+        }
 
         self.renderer.device().end_commands();
 
@@ -443,7 +534,15 @@ impl DemoApp {
         }
     }
 
-    fn handle_ui_action(&mut self, ui_action: &mut UIAction, window: &WindowImpl) {
+    /// Asks where to save a screenshot of the next frame.
+    fn request_screenshot(&mut self, kind: ScreenshotType, window: &W) {
+        if let Ok(path) = window.run_save_dialog(kind.extension()) {
+            let mut action = UIAction::TakeScreenshot(ScreenshotInfo { kind, path });
+            self.handle_ui_action(&mut action, window);
+        }
+    }
+
+    fn handle_ui_action(&mut self, ui_action: &mut UIAction, window: &W) {
         match ui_action {
             UIAction::None => {}
             UIAction::ModelChanged => {
@@ -452,10 +551,10 @@ impl DemoApp {
             UIAction::EffectsChanged => {
                 let viewport_size = window.viewport().size();
                 let filter = build_filter(&self.ui_model);
-                let (mut scene, _) = self.svg_model.render(viewport_size, filter);
+                let (mut scene, _) = self.content.render(viewport_size, filter);
                 self.scene_metadata =
                     SceneMetadata::new_clipping_view_box(&mut scene, viewport_size);
-                self.scene_proxy.replace_scene(scene);
+                self.replace_scene(scene);
                 self.dirty = true;
             }
             UIAction::TakeScreenshot(ref info) => {
@@ -507,6 +606,8 @@ pub struct Options {
     pub background_color: BackgroundColor,
     pub high_performance_gpu: bool,
     pub renderer_level: Option<RendererLevel>,
+    /// Saved after the first frame, as PNG or SVG by its extension.
+    pub screenshot_path: Option<PathBuf>,
     hidden_field_for_future_proofing: (),
 }
 
@@ -519,6 +620,7 @@ impl Default for Options {
             background_color: BackgroundColor::Light,
             high_performance_gpu: false,
             renderer_level: None,
+            screenshot_path: None,
             hidden_field_for_future_proofing: (),
         }
     }
@@ -579,6 +681,7 @@ impl Options {
                     .takes_value(true)
                     .possible_values(&["9", "11"])
             )
+            .arg(screenshot_arg())
             .arg(
                 Arg::with_name("INPUT")
                     .help("Path to the SVG file to render")
@@ -599,11 +702,8 @@ impl Options {
         }
 
         if let Some(background_color) = matches.value_of("background") {
-            self.background_color = match background_color {
-                "light" => BackgroundColor::Light,
-                "dark" => BackgroundColor::Dark,
-                _ => BackgroundColor::Transparent,
-            };
+            self.background_color = BackgroundColor::from_name(background_color)
+                .unwrap_or(BackgroundColor::Transparent);
         }
 
         if matches.is_present("high-performance-gpu") {
@@ -618,12 +718,31 @@ impl Options {
             }
         }
 
+        if let Some(path) = matches.value_of("screenshot") {
+            self.screenshot_path = Some(PathBuf::from(path));
+        }
+
         if let Some(path) = matches.value_of("INPUT") {
             self.input_path = DataPath::Path(PathBuf::from(path));
         };
     }
 }
 
+/// `--screenshot PATH`, which saves the first frame as PNG or SVG by the
+/// extension of `PATH`. Read with `ScreenshotInfo::from_path`.
+pub fn screenshot_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("screenshot")
+        .short("s")
+        .long("screenshot")
+        .value_name("PATH")
+        .takes_value(true)
+        .validator(|path| match ScreenshotInfo::from_path(PathBuf::from(&path)) {
+            Some(_) => Ok(()),
+            None => Err(String::from("expected a .png or .svg path")),
+        })
+        .help("Save a screenshot of the first frame")
+}
+
 
 ///////////////////////////////////////////////////////////////////////////////
 // CONTENT RELATED
@@ -636,55 +755,31 @@ pub enum UIVisibility {
     All,
 }
 
-fn load_scene(
-    &window_size: &WindowSize,
-    points: &[Vector2I],
-    resource_loader: &dyn ResourceLoader,
-) -> SvgDataModel {
-    let size = window_size.device_size();
-    if points.is_empty() {
-        let data = format!(
-            "
-            <svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {width} {height}\">
-            <path d=\"M 10 10 H 90 V 90 H 10 Z\" stroke=\"white\" fill=\"white\"/>
-            </svg>
-            ",
-            width=size.x(),
-            height=size.y(),
-        );
-        let tree = SvgTree::from_str(&data, &UsvgOptions::default()).unwrap();
-        return SvgDataModel::Svg(tree)
-    }
-    println!("POINTS: {:?}", points.len());
-    let path_data = points
-        .into_iter()
-        .map(|p| {
-            format!("L {} {}", p.x(), p.y())
-        })
-        .collect::<Vec<_>>()
-        .join(" ");
-    let path_data = format!(
-        "M 0 0 {}",
-        path_data
-    );
-    let data = format!(
-        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {width} {height}\">
-        <path d=\"{path_data}\" stroke=\"#000\" stroke-width=\"1px\"/>
-        </svg>
-        ",
-        path_data=path_data,
-        width=size.x(),
-        height=size.y(),
-    );
-    let tree = SvgTree::from_str(data.as_str(), &UsvgOptions::default()).unwrap();
-    SvgDataModel::Svg(tree)
-}
-
 // FIXME(pcwalton): Rework how transforms work in the demo. The transform affects the final
 // composite steps, breaking this approach.
 fn build_svg_tree(tree: &SvgTree, viewport_size: Vector2I, filter: Option<PatternFilter>) -> SVGScene {
     let mut scene = Scene::new();
-    let filter_info = filter.map(|filter| {
+    let filter_info = push_filter(&mut scene, viewport_size, filter);
+    let mut built_svg = SVGScene::from_tree_and_scene(&tree, scene);
+    pop_filter(&mut built_svg.scene, viewport_size, filter_info);
+    built_svg
+}
+
+/// Where content goes while `filter` is applied to it.
+struct FilterInfo {
+    filter: PatternFilter,
+    render_target_id: RenderTargetId,
+    render_target_size: Vector2I,
+}
+
+/// Starts drawing into a render target that `pop_filter` then draws to the
+/// viewport through `filter`, if there is one.
+fn push_filter(
+    scene: &mut Scene,
+    viewport_size: Vector2I,
+    filter: Option<PatternFilter>,
+) -> Option<FilterInfo> {
+    filter.map(|filter| {
         let scale = match filter {
             PatternFilter::Text { defringing_kernel: Some(_), .. } => vec2i(3, 1),
             _ => vec2i(1, 1),
@@ -694,9 +789,10 @@ fn build_svg_tree(tree: &SvgTree, viewport_size: Vector2I, filter: Option<Patter
         let render_target = RenderTarget::new(render_target_size, name);
         let render_target_id = scene.push_render_target(render_target);
         FilterInfo { filter, render_target_id, render_target_size }
-    });
+    })
+}
 
-    let mut built_svg = SVGScene::from_tree_and_scene(&tree, scene);
+fn pop_filter(scene: &mut Scene, viewport_size: Vector2I, filter_info: Option<FilterInfo>) {
     if let Some(FilterInfo {
         filter,
         render_target_id,
@@ -704,22 +800,33 @@ fn build_svg_tree(tree: &SvgTree, viewport_size: Vector2I, filter: Option<Patter
     }) = filter_info {
         let mut pattern = Pattern::from_render_target(render_target_id, render_target_size);
         pattern.set_filter(Some(filter));
-        let paint_id = built_svg.scene.push_paint(&Paint::from_pattern(pattern));
+        let paint_id = scene.push_paint(&Paint::from_pattern(pattern));
 
         let outline = Outline::from_rect(RectI::new(vec2i(0, 0), viewport_size).to_f32());
         let path = DrawPath::new(outline, paint_id);
 
-        built_svg.scene.pop_render_target();
-        built_svg.scene.push_draw_path(path);
+        scene.pop_render_target();
+        scene.push_draw_path(path);
     }
+}
 
-    return built_svg;
+/// A scene with nothing in it and the view box of `scene`.
+fn empty_scene(scene: &Scene) -> Scene {
+    let mut empty = Scene::new();
+    empty.set_view_box(scene.view_box());
+    empty
+}
 
-    struct FilterInfo {
-        filter: PatternFilter,
-        render_target_id: RenderTargetId,
-        render_target_size: Vector2I,
-    }
+/// Appends the line of `stroke`, filled in its color at its opacity.
+fn push_stroke(scene: &mut Scene, stroke: &Stroke) {
+    let outline = match line_outline(stroke, &FitOptions::default()) {
+        Some(outline) => outline,
+        None => return,
+    };
+    let mut color = stroke.style.color;
+    color.a = (color.a as f32 * stroke.style.opacity).round() as u8;
+    let paint_id = scene.push_paint(&Paint::from_color(color));
+    scene.push_draw_path(DrawPath::new(outline, paint_id));
 }
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -730,21 +837,22 @@ fn center_of_window(window_size: &WindowSize) -> Vector2F {
     window_size.device_size().to_f32() * 0.5
 }
 
-fn get_svg_building_message(built_svg: &SVGScene) -> String {
-    if built_svg.result_flags.is_empty() {
+/// The warning shown for the SVG `features` that could not be rendered or
+/// imported, given as a list; empty when the list is.
+pub fn get_svg_building_message<F>(features: &F) -> String where F: Display {
+    let features = features.to_string();
+    if features.is_empty() {
         return String::new();
     }
-    format!(
-        "Warning: These features in the SVG are unsupported: {}.",
-        built_svg.result_flags
-    )
+    format!("Warning: These features in the SVG are unsupported: {}.", features)
 }
 
 fn emit_message(
     ui_model: &mut DemoUIModel,
     message_epoch: &mut u32,
     expire_message_event_id: u32,
-    message: String
+    message: String,
+    push_user_event: fn(u32, u32),
 ) {
     if message.is_empty() {
         return;
@@ -755,7 +863,7 @@ fn emit_message(
     *message_epoch = expected_epoch;
     thread::spawn(move || {
         thread::sleep(Duration::from_secs(MESSAGE_TIMEOUT_SECS));
-        WindowImpl::push_user_event(expire_message_event_id, expected_epoch);
+        push_user_event(expire_message_event_id, expected_epoch);
     });
 }
 
@@ -791,16 +899,121 @@ impl Frame {
     }
 }
 
+///////////////////////////////////////////////////////////////////////////////
+// CONTENT MODEL
+///////////////////////////////////////////////////////////////////////////////
+
+/// What the demo shows.
+pub enum ContentModel {
+    Drawing(Drawing),
+    /// An imported SVG file.
+    Svg(SvgDataModel),
+}
+
+impl ContentModel {
+    /// Builds the scene in the content's own coordinates, along with a
+    /// warning about unsupported features, which is empty if there were
+    /// none.
+    pub fn render(
+        &mut self,
+        viewport_size: Vector2I,
+        filter: Option<PatternFilter>,
+    ) -> (Scene, String) {
+        match *self {
+            ContentModel::Drawing(ref drawing) => {
+                (drawing.render(viewport_size, filter), String::new())
+            }
+            ContentModel::Svg(ref mut svg_model) => svg_model.render(viewport_size, filter),
+        }
+    }
+}
+
+/// Strokes drawn by dragging, one per drag, built straight into a scene.
+#[derive(Clone, Default)]
+pub struct Drawing {
+    /// In world coordinates.
+    pub document: Document,
+    /// The stroke being drawn and when it started. `render` leaves it out,
+    /// since it is drawn over the scene until the drag ends.
+    stroke: Option<(StrokeId, Instant)>,
+}
+
+impl Drawing {
+    /// Builds a scene the size of the viewport, with a placeholder square
+    /// while nothing is drawn yet.
+    pub fn render(&self, viewport_size: Vector2I, filter: Option<PatternFilter>) -> Scene {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(Vector2F::zero(), viewport_size.to_f32()));
+        let filter_info = push_filter(&mut scene, viewport_size, filter);
+        if self.document.stroke_count() == 0 {
+            // Filled and outlined one unit wide, hence the extra half unit.
+            let square = RectF::new(vec2f(10.0, 10.0), vec2f(80.0, 80.0)).dilate(0.5);
+            let paint_id = scene.push_paint(&Paint::from_color(ColorU::white()));
+            scene.push_draw_path(DrawPath::new(Outline::from_rect(square), paint_id));
+        }
+        let drawn = self.stroke.map(|(id, _)| id);
+        for stroke in self.document.strokes().filter(|stroke| Some(stroke.id) != drawn) {
+            push_stroke(&mut scene, stroke);
+        }
+        pop_filter(&mut scene, viewport_size, filter_info);
+        scene
+    }
+
+    /// Adds a point to the stroke being drawn, starting one if there is
+    /// none, and returns that stroke.
+    pub fn push_point(
+        &mut self,
+        position: Vector2F,
+        pressure: f32,
+        time: Instant,
+    ) -> Option<&Stroke> {
+        let (id, started) = match self.stroke {
+            Some(stroke) => stroke,
+            None => {
+                let id = self.document.begin_stroke(StrokeStyle::default(), StrokeOrigin::Brush)?;
+                *self.stroke.get_or_insert((id, time))
+            }
+        };
+        let time = time.saturating_duration_since(started).as_secs_f32();
+        self.document.push_point(id, StrokePoint { position, pressure, time });
+        self.document.stroke(id)
+    }
+
+    /// Finishes the stroke being drawn, if any, and returns it.
+    pub fn end_stroke(&mut self) -> Option<&Stroke> {
+        let (id, _) = self.stroke.take()?;
+        self.document.stroke(id)
+    }
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 // SVG SCENE TREE
 ///////////////////////////////////////////////////////////////////////////////
 
-enum SvgDataModel {
+pub enum SvgDataModel {
     Svg(SvgTree)
 }
 
 impl SvgDataModel {
-    fn render(
+    pub fn load(
+        input_path: &DataPath,
+        resource_loader: &dyn ResourceLoader,
+    ) -> Result<SvgDataModel, String> {
+        let data = match *input_path {
+            DataPath::Default => resource_loader.slurp(DEFAULT_SVG_VIRTUAL_PATH),
+            DataPath::Resource(ref name) => resource_loader.slurp(name),
+            DataPath::Path(ref path) => std::fs::read(path),
+        };
+        let data = data.map_err(|error| format!("Failed to read the SVG: {}", error))?;
+        match SvgTree::from_data(&data, &UsvgOptions::default()) {
+            Ok(tree) => Ok(SvgDataModel::Svg(tree)),
+            Err(error) => Err(format!("Failed to parse the SVG: {}", error)),
+        }
+    }
+
+    /// Builds the scene in the SVG's own coordinates, along with a warning
+    /// about unsupported features, which is empty if there were none.
+    pub fn render(
         &mut self,
         viewport_size: Vector2I,
         filter: Option<PatternFilter>
@@ -808,7 +1021,29 @@ impl SvgDataModel {
         match *self {
             SvgDataModel::Svg(ref tree) => {
                 let built_svg = build_svg_tree(&tree, viewport_size, filter);
-                let message = get_svg_building_message(&built_svg);
+                let message = get_svg_building_message(&built_svg.result_flags);
+                (built_svg.scene, message)
+            }
+        }
+    }
+
+    /// Like `render`, but on top of a rectangle of `background` that covers
+    /// the SVG's view box.
+    pub fn render_with_background(&self, background: ColorU) -> (Scene, String) {
+        match *self {
+            SvgDataModel::Svg(ref tree) => {
+                let rect = tree.svg_node().view_box.rect;
+                let view_box = RectF::new(
+                    vec2f(rect.x() as f32, rect.y() as f32),
+                    vec2f(rect.width() as f32, rect.height() as f32),
+                );
+                let mut scene = Scene::new();
+                if !background.is_fully_transparent() {
+                    let paint_id = scene.push_paint(&Paint::from_color(background));
+                    scene.push_draw_path(DrawPath::new(Outline::from_rect(view_box), paint_id));
+                }
+                let built_svg = SVGScene::from_tree_and_scene(tree, scene);
+                let message = get_svg_building_message(&built_svg.result_flags);
                 (built_svg.scene, message)
             }
         }
@@ -826,6 +1061,18 @@ pub enum BackgroundColor {
     Transparent = 2,
 }
 
+impl BackgroundColor {
+    /// Parses the names `--background` accepts.
+    pub fn from_name(name: &str) -> Option<BackgroundColor> {
+        match name {
+            "light" => Some(BackgroundColor::Light),
+            "dark" => Some(BackgroundColor::Dark),
+            "transparent" => Some(BackgroundColor::Transparent),
+            _ => None,
+        }
+    }
+}
+
 struct SceneMetadata {
     view_box: RectF,
 }
@@ -0,0 +1,220 @@
+//! One model for mouse, touch and pen input. Winit reports the mouse as
+//! cursor and button events and everything else as touches; `PointerInput`
+//! turns both into `Pointer` samples that carry what drawing needs.
+use std::collections::HashMap;
+use std::time::Instant;
+use crate::prelude::*;
+
+///////////////////////////////////////////////////////////////////////////////
+// POINTERS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PointerId {
+    Mouse,
+    /// Winit's touch ID, unique among contacts at any one time.
+    Touch(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerKind {
+    Mouse,
+    Touch,
+    /// A stylus. Winit reports these as touches; they are told apart by
+    /// reporting an altitude angle, which fingers never do.
+    Pen,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerPhase {
+    /// A button was pressed, or a touch or pen made contact.
+    Down,
+    Move,
+    /// A button was released, or a contact lifted.
+    Up,
+    /// The platform took the contact away, e.g. for a system gesture. What
+    /// it did so far should not be trusted as a finished drag.
+    Cancel,
+}
+
+/// Touches and pens in contact press `primary`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerButton {
+    Primary,
+    Secondary,
+    Middle,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PointerButtons {
+    pub primary: bool,
+    pub secondary: bool,
+    pub middle: bool,
+}
+
+impl PointerButtons {
+    fn set(&mut self, button: PointerButton, pressed: bool) {
+        match button {
+            PointerButton::Primary => self.primary = pressed,
+            PointerButton::Secondary => self.secondary = pressed,
+            PointerButton::Middle => self.middle = pressed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Pointer {
+    pub id: PointerId,
+    pub kind: PointerKind,
+    pub phase: PointerPhase,
+    /// The button that went down or up, for those phases.
+    pub button: Option<PointerButton>,
+    /// Held after the event.
+    pub buttons: PointerButtons,
+    /// In device pixels.
+    pub position: Vector2F,
+    /// Normalized; `1.0` for devices without pressure.
+    pub pressure: f32,
+    /// Angle between a pen and the surface, in radians: `π/2` is upright.
+    /// Only reported by some pens.
+    pub tilt: Option<f32>,
+    /// When the event arrived. Winit does not pass on the time the device
+    /// reported it.
+    pub time: Instant,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// INPUT
+///////////////////////////////////////////////////////////////////////////////
+
+/// Tracks every pointer across window events.
+pub struct PointerInput {
+    mouse: Pointer,
+    /// Touches and pens in contact, by winit touch ID.
+    contacts: HashMap<u64, Pointer>,
+}
+
+impl PointerInput {
+    pub fn new() -> Self {
+        PointerInput {
+            mouse: Pointer {
+                id: PointerId::Mouse,
+                kind: PointerKind::Mouse,
+                phase: PointerPhase::Move,
+                button: None,
+                buttons: PointerButtons::default(),
+                position: Vector2F::zero(),
+                pressure: 1.0,
+                tilt: None,
+                time: Instant::now(),
+            },
+            contacts: HashMap::new(),
+        }
+    }
+
+    /// The pointer sample `event` amounts to, if it is pointer input at all.
+    /// Mouse events are dropped while anything is in contact: platforms
+    /// emulate the mouse from touches, which would otherwise draw twice.
+    pub fn handle_event(&mut self, event: &wit::WindowEvent, time: Instant) -> Option<Pointer> {
+        match *event {
+            wit::WindowEvent::CursorMoved { position, .. } if self.contacts.is_empty() => {
+                self.mouse.position = Vector2F::new(position.x as f32, position.y as f32);
+                Some(self.update_mouse(PointerPhase::Move, None, time))
+            }
+            wit::WindowEvent::MouseInput { state, button, .. } if self.contacts.is_empty() => {
+                let button = match button {
+                    wit::MouseButton::Left => PointerButton::Primary,
+                    wit::MouseButton::Right => PointerButton::Secondary,
+                    wit::MouseButton::Middle => PointerButton::Middle,
+                    wit::MouseButton::Other(_) => return None,
+                };
+                let pressed = state == wit::ElementState::Pressed;
+                self.mouse.buttons.set(button, pressed);
+                let phase = if pressed { PointerPhase::Down } else { PointerPhase::Up };
+                Some(self.update_mouse(phase, Some(button), time))
+            }
+            wit::WindowEvent::Touch(ref touch) => self.handle_touch(touch, time),
+            _ => None,
+        }
+    }
+
+    /// The latest sample of `id`, if it is still around. The mouse always is.
+    pub fn pointer(&self, id: PointerId) -> Option<Pointer> {
+        match id {
+            PointerId::Mouse => Some(self.mouse),
+            PointerId::Touch(touch_id) => self.contacts.get(&touch_id).copied(),
+        }
+    }
+
+    /// Touches and pens in contact, in no particular order.
+    pub fn contacts(&self) -> impl Iterator<Item = &Pointer> {
+        self.contacts.values()
+    }
+
+    /// Forgets held buttons and contacts, whose releases a window that lost
+    /// focus may never see.
+    pub fn reset(&mut self) {
+        self.mouse.buttons = PointerButtons::default();
+        self.contacts.clear();
+    }
+
+    fn update_mouse(
+        &mut self,
+        phase: PointerPhase,
+        button: Option<PointerButton>,
+        time: Instant,
+    ) -> Pointer {
+        self.mouse.phase = phase;
+        self.mouse.button = button;
+        self.mouse.time = time;
+        self.mouse
+    }
+
+    fn handle_touch(&mut self, touch: &wit::Touch, time: Instant) -> Option<Pointer> {
+        let (phase, in_contact) = match touch.phase {
+            wit::TouchPhase::Started => (PointerPhase::Down, true),
+            wit::TouchPhase::Moved => (PointerPhase::Move, true),
+            wit::TouchPhase::Ended => (PointerPhase::Up, false),
+            wit::TouchPhase::Cancelled => (PointerPhase::Cancel, false),
+        };
+        let (pressure, tilt) = match touch.force {
+            Some(wit::Force::Calibrated { force, max_possible_force, altitude_angle }) => {
+                let pressure = if max_possible_force > 0.0 { force / max_possible_force } else { 1.0 };
+                (pressure as f32, altitude_angle.map(|angle| angle as f32))
+            }
+            Some(wit::Force::Normalized(force)) => (force as f32, None),
+            None => (1.0, None),
+        };
+        // Moves of contacts that were never seen starting, e.g. ones that
+        // began before focus returned, are not drags.
+        if phase == PointerPhase::Move && !self.contacts.contains_key(&touch.id) {
+            return None;
+        }
+        let pointer = Pointer {
+            id: PointerId::Touch(touch.id),
+            kind: if tilt.is_some() { PointerKind::Pen } else { PointerKind::Touch },
+            phase,
+            button: match phase {
+                PointerPhase::Move => None,
+                _ => Some(PointerButton::Primary),
+            },
+            buttons: PointerButtons { primary: in_contact, ..PointerButtons::default() },
+            position: Vector2F::new(touch.location.x as f32, touch.location.y as f32),
+            pressure: pressure.max(0.0).min(1.0),
+            tilt,
+            time,
+        };
+        if in_contact {
+            self.contacts.insert(touch.id, pointer);
+        } else if self.contacts.remove(&touch.id).is_none() {
+            return None;
+        }
+        Some(pointer)
+    }
+}
+
+impl Default for PointerInput {
+    fn default() -> Self {
+        PointerInput::new()
+    }
+}
@@ -11,19 +11,25 @@
 //! Rendering functionality for the demo.
 
 use crate::engine::camera::Camera;
-use crate::engine::window::WindowImpl;
+use crate::engine::window::Window;
+use crate::engine::ui::{ScreenshotInfo, ScreenshotType};
 use crate::engine::{BackgroundColor, DemoApp, UIVisibility};
 use image::ColorType;
 use pathfinder_color::{ColorF, ColorU};
 use pathfinder_gpu::{ClearOps, DepthFunc, DepthState, Device, Primitive, RenderOptions};
 use pathfinder_gpu::{RenderState, RenderTarget, TextureData, TextureFormat, UniformData};
+use pathfinder_export::{Export, FileFormat};
 use pathfinder_geometry::rect::RectI;
+use pathfinder_geometry::transform2d::Transform2F;
 use pathfinder_geometry::transform3d::Transform4F;
 use pathfinder_geometry::vector::{Vector2I, Vector4F};
+use pathfinder_renderer::concurrent::scene_proxy::SceneProxy;
 use pathfinder_renderer::gpu::options::{DestFramebuffer, RendererOptions};
 use pathfinder_renderer::options::RenderTransform;
+use pathfinder_renderer::scene::Scene;
+use std::fs;
 use std::mem;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const GROUND_SOLID_COLOR: ColorU = ColorU {
     r: 80,
@@ -41,8 +47,8 @@ const GROUND_LINE_COLOR: ColorU = ColorU {
 
 const GRIDLINE_COUNT: i32 = 10;
 
-impl DemoApp {
-    pub fn prepare_frame_rendering(&mut self, window: &WindowImpl) -> u32 {
+impl<W: Window> DemoApp<W> {
+    pub fn prepare_frame_rendering(&mut self, window: &W) -> u32 {
         // MAKE THE CONTEXT CURRENT.
 
         // CLEAR TO THE APPROPRIATE COLOR.
@@ -66,7 +72,7 @@ impl DemoApp {
         scene_count
     }
 
-    pub fn draw_scene(&mut self, window: &WindowImpl) {
+    pub fn draw_scene(&mut self, window: &W) {
         self.renderer.device().begin_commands();
         // window.make_current();
         self.renderer.device().end_commands();
@@ -82,5 +88,194 @@ impl DemoApp {
         self.renderer.disable_depth();
         // ISSUE RENDER COMMANDS!
         self.scene_proxy.render(&mut self.renderer);
+        // The drag goes over the scene without clearing it.
+        let background_color = self.renderer.options_mut().background_color.take();
+        self.drag_scene_proxy.render(&mut self.renderer);
+        self.renderer.options_mut().background_color = background_color;
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// SCREENSHOTS
+///////////////////////////////////////////////////////////////////////////////
+
+impl<W: Window> DemoApp<W> {
+    /// Must run after the frame is rendered and before it is presented.
+    pub fn take_screenshot(&mut self, info: ScreenshotInfo) {
+        let Camera::TwoD(transform) = self.camera;
+        let result = save_screenshot(
+            self.renderer.device(),
+            &[&self.scene_proxy, &self.drag_scene_proxy],
+            &info,
+            &transform,
+            self.window_size.device_size(),
+            self.ui_model.background_color(),
+        );
+        if let Err(error) = result {
+            self.ui_model.message =
+                format!("Failed to save screenshot to {}: {}", info.path.display(), error);
+        }
+    }
+}
+
+/// Saves the frame just rendered to the default framebuffer, of
+/// `view_size`, as `info` asks. Must run before the frame is presented.
+///
+/// SVG screenshots export the scenes of `scene_proxies`, bottom to top, in
+/// their own coordinates, then place them through `transform`, which maps
+/// the scenes to the screen, over `background`.
+pub fn save_screenshot<D: Device>(
+    device: &D,
+    scene_proxies: &[&SceneProxy],
+    info: &ScreenshotInfo,
+    transform: &Transform2F,
+    view_size: Vector2I,
+    background: ColorU,
+) -> Result<(), String> {
+    match info.kind {
+        ScreenshotType::PNG => {
+            let pixels = read_default_framebuffer(device, view_size);
+            save_png(&info.path, &pixels, view_size)
+        }
+        ScreenshotType::SVG => {
+            let mut scenes = scene_proxies.iter().map(|scene_proxy| scene_proxy.copy_scene());
+            let mut scene = scenes.next().unwrap_or_else(Scene::new);
+            for above in scenes {
+                scene.append_scene(above);
+            }
+            let mut svg = Vec::new();
+            scene
+                .export(&mut svg, FileFormat::SVG)
+                .map_err(|error| error.to_string())?;
+            let svg = String::from_utf8(svg).map_err(|error| error.to_string())?;
+            let svg = place_svg_content(&svg, transform, view_size, background)
+                .ok_or_else(|| String::from("Unexpected SVG export"))?;
+            fs::write(&info.path, svg).map_err(|error| error.to_string())
+        }
+    }
+}
+
+/// Writes RGBA8 `pixels`, top row first, as a PNG of `size`.
+fn save_png(path: &Path, pixels: &[u8], size: Vector2I) -> Result<(), String> {
+    image::save_buffer(path, pixels, size.x() as u32, size.y() as u32, ColorType::Rgba8)
+        .map_err(|error| error.to_string())
+}
+
+/// Wraps the content of the exported `svg` in a group with `transform`,
+/// draws `background` behind it and sets the view box to `view_size`.
+fn place_svg_content(
+    svg: &str,
+    transform: &Transform2F,
+    view_size: Vector2I,
+    background: ColorU,
+) -> Option<String> {
+    let content_start = svg.find("<svg").and_then(|start| {
+        svg[start..].find('>').map(|end| start + end + 1)
+    })?;
+    let content_end = svg.rfind("</svg>")?;
+    let mut output = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">\n",
+        view_size.x(),
+        view_size.y(),
+    );
+    if !background.is_fully_transparent() {
+        output.push_str(&format!(
+            "<rect width=\"{}\" height=\"{}\" fill=\"#{:02x}{:02x}{:02x}\" fill-opacity=\"{}\"/>\n",
+            view_size.x(),
+            view_size.y(),
+            background.r,
+            background.g,
+            background.b,
+            background.a as f32 / 255.0,
+        ));
+    }
+    output.push_str(&format!(
+        "<g transform=\"matrix({} {} {} {} {} {})\">",
+        transform.matrix.m11(),
+        transform.matrix.m21(),
+        transform.matrix.m12(),
+        transform.matrix.m22(),
+        transform.vector.x(),
+        transform.vector.y(),
+    ));
+    output.push_str(&svg[content_start..content_end]);
+    output.push_str("</g>\n</svg>\n");
+    Some(output)
+}
+
+/// Reads back the top-left `size` of the default framebuffer as RGBA8 rows,
+/// top row first.
+pub fn read_default_framebuffer<D: Device>(device: &D, size: Vector2I) -> Vec<u8> {
+    let viewport = RectI::new(Vector2I::default(), size);
+    let texture_data_receiver = device.read_pixels(&RenderTarget::Default, viewport);
+    match device.recv_texture_data(&texture_data_receiver) {
+        TextureData::U8(pixels) => pixels,
+        _ => panic!("Unexpected pixel format for default framebuffer!"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    const EXPORTED: &str = "<svg xmlns=\"http://www.w3.org/2000/svg\" \
+        width=\"10\" height=\"10\" viewBox=\"0 0 10 10\">\n\
+        <path fill=\"#ff0000\" d=\"M 0 0 L 10 0 L 10 10 Z\"/>\n</svg>\n";
+
+    fn temporary_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("alphadraw-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn png_screenshots_keep_pixels_and_rows() {
+        let size = Vector2I::new(2, 3);
+        let pixels = (0..size.x() * size.y() * 4).map(|value| value as u8).collect::<Vec<_>>();
+        let path = temporary_path("screenshot.png");
+        save_png(&path, &pixels, size).unwrap();
+        let image = image::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(image.dimensions(), (2, 3));
+        assert_eq!(image.color(), ColorType::Rgba8);
+        assert_eq!(image.to_bytes(), pixels);
+    }
+
+    #[test]
+    fn png_screenshots_report_unwritable_paths() {
+        let path = temporary_path("missing").join("screenshot.png");
+        assert!(save_png(&path, &[0; 4], Vector2I::splat(1)).is_err());
+    }
+
+    #[test]
+    fn svg_screenshots_place_content_as_on_screen() {
+        let transform = Transform2F::row_major(2.0, 0.0, 0.0, 2.0, 5.0, -7.0);
+        let background = ColorU { r: 248, g: 248, b: 248, a: 255 };
+        let svg = place_svg_content(EXPORTED, &transform, Vector2I::new(640, 480), background)
+            .unwrap();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" \
+            viewBox=\"0 0 640 480\">"));
+        assert!(svg.contains("<rect width=\"640\" height=\"480\" fill=\"#f8f8f8\" \
+            fill-opacity=\"1\"/>"));
+        assert!(svg.contains("<g transform=\"matrix(2 0 0 2 5 -7)\">\n\
+            <path fill=\"#ff0000\" d=\"M 0 0 L 10 0 L 10 10 Z\"/>"));
+        assert!(svg.ends_with("</g>\n</svg>\n"));
+        assert_eq!(svg.matches("<svg").count(), 1);
+    }
+
+    #[test]
+    fn svg_screenshots_skip_transparent_backgrounds() {
+        let transparent = ColorU { r: 0, g: 0, b: 0, a: 0 };
+        let transform = Transform2F::default();
+        let svg = place_svg_content(EXPORTED, &transform, Vector2I::splat(8), transparent).unwrap();
+        assert!(!svg.contains("<rect"));
+        assert!(svg.contains("matrix(1 0 0 1 0 0)"));
+    }
+
+    #[test]
+    fn svg_screenshots_refuse_unexpected_exports() {
+        let transform = Transform2F::default();
+        let background = ColorU { r: 0, g: 0, b: 0, a: 0 };
+        assert!(place_svg_content("", &transform, Vector2I::splat(8), background).is_none());
+        assert!(place_svg_content("<svg>", &transform, Vector2I::splat(8), background).is_none());
     }
 }
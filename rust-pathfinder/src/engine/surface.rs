@@ -0,0 +1,215 @@
+//! The GPU surface behind a window: Metal on macOS, and OpenGL through
+//! surfman on X11 and Wayland everywhere else.
+use euclid::default::Size2D;
+use surfman::{SurfaceAccess, SurfaceType};
+use crate::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdapterKind {
+    LowPower,
+    HighPerformance,
+    /// A CPU rasterizer such as Mesa's llvmpipe.
+    Software,
+}
+
+impl AdapterKind {
+    /// Follows Mesa's `LIBGL_ALWAYS_SOFTWARE`, which is how CI machines
+    /// without a GPU are told to render on llvmpipe.
+    pub fn from_env(high_performance: bool) -> AdapterKind {
+        let software = std::env::var("LIBGL_ALWAYS_SOFTWARE")
+            .map(|value| value != "0" && !value.is_empty())
+            .unwrap_or(false);
+        match (software, high_performance) {
+            (true, _) => AdapterKind::Software,
+            (false, true) => AdapterKind::HighPerformance,
+            (false, false) => AdapterKind::LowPower,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// METAL
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(target_os = "macos")]
+pub struct GpuSurface {
+    connection: surfman::SystemConnection,
+    device: surfman::SystemDevice,
+    native_device: surfman::NativeDevice,
+    surface: surfman::SystemSurface,
+}
+
+#[cfg(target_os = "macos")]
+impl GpuSurface {
+    pub fn new(window: &wit::Window, adapter_kind: AdapterKind) -> GpuSurface {
+        let connection = surfman::SystemConnection::from_winit_window(window).unwrap();
+        let native_widget = connection.create_native_widget_from_winit_window(window).unwrap();
+        let surface_type = SurfaceType::Widget { native_widget };
+        GpuSurface::with_surface_type(connection, adapter_kind, surface_type)
+    }
+
+    /// A surface that is never shown, for rendering without a window.
+    pub fn new_offscreen(size: Vector2I, adapter_kind: AdapterKind) -> GpuSurface {
+        let connection = surfman::SystemConnection::new().unwrap();
+        let surface_type = SurfaceType::Generic { size: Size2D::new(size.x(), size.y()) };
+        GpuSurface::with_surface_type(connection, adapter_kind, surface_type)
+    }
+
+    fn with_surface_type(
+        connection: surfman::SystemConnection,
+        adapter_kind: AdapterKind,
+        surface_type: SurfaceType<surfman::NativeWidget>,
+    ) -> GpuSurface {
+        let adapter = match adapter_kind {
+            AdapterKind::HighPerformance => connection.create_hardware_adapter().unwrap(),
+            AdapterKind::LowPower => connection.create_low_power_adapter().unwrap(),
+            AdapterKind::Software => connection.create_software_adapter().unwrap(),
+        };
+        let mut device = connection.create_device(&adapter).unwrap();
+        let native_device = device.native_device();
+        let surface = device.create_surface(SurfaceAccess::GPUOnly, surface_type).unwrap();
+        GpuSurface { connection, device, native_device, surface }
+    }
+
+    pub fn create_device(&mut self) -> pf::DeviceImpl {
+        unsafe {
+            pf::MetalDevice::new(self.metal_device(), self.metal_io_surface())
+        }
+    }
+
+    fn metal_device(&self) -> metal::Device {
+        // FIXME(pcwalton): Remove once `surfman` upgrades `metal-rs` version.
+        unsafe {
+            std::mem::transmute(self.native_device.0.clone())
+        }
+    }
+
+    fn metal_io_surface(&self) -> io_surface::IOSurfaceRef {
+        self.device.native_surface(&self.surface).0
+    }
+
+    pub fn present(&mut self, device: &mut pf::DeviceImpl) {
+        self.device.present_surface(&mut self.surface).expect("Failed to present surface!");
+        device.swap_texture(self.device.native_surface(&self.surface).0);
+    }
+
+    pub fn resize(&mut self, size: Vector2I, device: &mut pf::DeviceImpl) {
+        self.device.resize_surface(&mut self.surface, Size2D::new(size.x(), size.y()));
+        device.swap_texture(self.metal_io_surface());
+    }
+
+    pub fn destroy(&mut self) {
+        self.device.destroy_surface(&mut self.surface);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// OPENGL
+///////////////////////////////////////////////////////////////////////////////
+
+/// Pathfinder's GL3 shaders need a 3.3 core context, which llvmpipe provides.
+#[cfg(not(target_os = "macos"))]
+const GL_CONTEXT_VERSION: (u8, u8) = (3, 3);
+
+#[cfg(not(target_os = "macos"))]
+pub struct GpuSurface {
+    connection: surfman::Connection,
+    device: surfman::Device,
+    context: surfman::Context,
+}
+
+#[cfg(not(target_os = "macos"))]
+impl GpuSurface {
+    pub fn new(window: &wit::Window, adapter_kind: AdapterKind) -> GpuSurface {
+        let connection = surfman::Connection::from_winit_window(window).unwrap();
+        let native_widget = connection.create_native_widget_from_winit_window(window).unwrap();
+        let surface_type = SurfaceType::Widget { native_widget };
+        GpuSurface::with_surface_type(connection, adapter_kind, surface_type)
+    }
+
+    /// A surface that is never shown, for rendering without a window.
+    pub fn new_offscreen(size: Vector2I, adapter_kind: AdapterKind) -> GpuSurface {
+        let connection = surfman::Connection::new().unwrap();
+        let surface_type = SurfaceType::Generic { size: Size2D::new(size.x(), size.y()) };
+        GpuSurface::with_surface_type(connection, adapter_kind, surface_type)
+    }
+
+    fn with_surface_type(
+        connection: surfman::Connection,
+        adapter_kind: AdapterKind,
+        surface_type: SurfaceType<surfman::NativeWidget>,
+    ) -> GpuSurface {
+        let adapter = match adapter_kind {
+            AdapterKind::HighPerformance => connection.create_hardware_adapter().unwrap(),
+            AdapterKind::LowPower => connection.create_low_power_adapter().unwrap(),
+            AdapterKind::Software => connection.create_software_adapter().unwrap(),
+        };
+        let mut device = connection.create_device(&adapter).unwrap();
+        let (major, minor) = GL_CONTEXT_VERSION;
+        let context_attributes = surfman::ContextAttributes {
+            version: surfman::GLVersion::new(major, minor),
+            flags: surfman::ContextAttributeFlags::ALPHA,
+        };
+        let context_descriptor = device.create_context_descriptor(&context_attributes).unwrap();
+        let mut context = device.create_context(&context_descriptor, None).unwrap();
+        let surface = device
+            .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
+            .unwrap();
+        device
+            .bind_surface_to_context(&mut context, surface)
+            .map_err(|(error, _)| error)
+            .unwrap();
+        device.make_context_current(&context).unwrap();
+        gl::load_with(|symbol| device.get_proc_address(&context, symbol));
+        GpuSurface { connection, device, context }
+    }
+
+    pub fn create_device(&mut self) -> pf::DeviceImpl {
+        pf::GLDevice::new(pf::GLVersion::GL3, self.framebuffer_object())
+    }
+
+    fn framebuffer_object(&self) -> u32 {
+        self.device
+            .context_surface_info(&self.context)
+            .unwrap()
+            .expect("No surface bound to the context!")
+            .framebuffer_object
+    }
+
+    pub fn present(&mut self, device: &mut pf::DeviceImpl) {
+        let mut surface = self.unbind_surface();
+        self.device
+            .present_surface(&self.context, &mut surface)
+            .expect("Failed to present surface!");
+        self.bind_surface(surface);
+        device.set_default_framebuffer(self.framebuffer_object());
+    }
+
+    pub fn resize(&mut self, size: Vector2I, device: &mut pf::DeviceImpl) {
+        let mut surface = self.unbind_surface();
+        self.device
+            .resize_surface(&self.context, &mut surface, Size2D::new(size.x(), size.y()))
+            .unwrap();
+        self.bind_surface(surface);
+        device.set_default_framebuffer(self.framebuffer_object());
+    }
+
+    /// Also destroys the bound surface.
+    pub fn destroy(&mut self) {
+        self.device.destroy_context(&mut self.context).unwrap();
+    }
+
+    fn unbind_surface(&mut self) -> surfman::Surface {
+        self.device
+            .unbind_surface_from_context(&mut self.context)
+            .unwrap()
+            .expect("No surface bound to the context!")
+    }
+
+    fn bind_surface(&mut self, surface: surfman::Surface) {
+        self.device
+            .bind_surface_to_context(&mut self.context, surface)
+            .map_err(|(error, _)| error)
+            .unwrap();
+    }
+}
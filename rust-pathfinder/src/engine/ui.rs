@@ -87,7 +87,13 @@ impl DemoUIModel {
     }
 
     pub fn background_color(&self) -> ColorU {
-        match self.background_color {
+        self.background_color.color()
+    }
+}
+
+impl BackgroundColor {
+    pub fn color(&self) -> ColorU {
+        match *self {
             BackgroundColor::Light => LIGHT_BG_COLOR,
             BackgroundColor::Dark => DARK_BG_COLOR,
             BackgroundColor::Transparent => TRANSPARENT_BG_COLOR,
@@ -114,6 +120,18 @@ pub struct ScreenshotInfo {
     pub path: PathBuf,
 }
 
+impl ScreenshotInfo {
+    /// A screenshot of the type named by the extension of `path`, if any.
+    pub fn from_path(path: PathBuf) -> Option<ScreenshotInfo> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        let kind = [ScreenshotType::PNG, ScreenshotType::SVG]
+            .iter()
+            .cloned()
+            .find(|kind| kind.extension() == extension)?;
+        Some(ScreenshotInfo { kind, path })
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ScreenshotType {
     PNG = 0,
@@ -121,7 +139,7 @@ pub enum ScreenshotType {
 }
 
 impl ScreenshotType {
-    fn extension(&self) -> &'static str {
+    pub fn extension(&self) -> &'static str {
         match *self {
             ScreenshotType::PNG => "png",
             ScreenshotType::SVG => "svg",
@@ -135,3 +153,19 @@ impl ScreenshotType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshot_types_follow_the_extension() {
+        let png = ScreenshotInfo::from_path(PathBuf::from("out/frame.PNG")).unwrap();
+        assert_eq!(png.kind, ScreenshotType::PNG);
+        assert_eq!(png.path, PathBuf::from("out/frame.PNG"));
+        let svg = ScreenshotInfo::from_path(PathBuf::from("frame.svg")).unwrap();
+        assert_eq!(svg.kind, ScreenshotType::SVG);
+        assert!(ScreenshotInfo::from_path(PathBuf::from("frame.pdf")).is_none());
+        assert!(ScreenshotInfo::from_path(PathBuf::from("frame")).is_none());
+    }
+}
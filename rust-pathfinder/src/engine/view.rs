@@ -1,10 +1,7 @@
-use foreign_types::ForeignTypeRef;
-use metal::{CAMetalLayer, CoreAnimationLayerRef};
 use pathfinder_canvas::{Canvas, CanvasFontContext, Path2D};
 use pathfinder_color::ColorF;
 use pathfinder_geometry::vector::{vec2f, vec2i, Vector2F, Vector2I};
 use pathfinder_geometry::rect::RectF;
-use pathfinder_metal::MetalDevice;
 use pathfinder_renderer::concurrent::executor::Executor;
 use pathfinder_renderer::concurrent::rayon::RayonExecutor;
 use pathfinder_renderer::concurrent::scene_proxy::SceneProxy;
@@ -15,8 +12,8 @@ use pathfinder_renderer::options::BuildOptions;
 use pathfinder_resources::embedded::EmbeddedResourceLoader;
 use pathfinder_renderer::gpu::options::RendererLevel;
 
-pub fn view<E: Executor + Send + 'static>(
-    renderer: &mut Renderer<MetalDevice>,
+pub fn view<D: Device, E: Executor + Send + 'static>(
+    renderer: &mut Renderer<D>,
     window_size: Vector2I,
     executor: E
 ) {
@@ -12,20 +12,21 @@
 use std::cell::Cell;
 use std::collections::VecDeque;
 use std::path::PathBuf;
+use std::time::Instant;
 use pathfinder_geometry::rect::RectI;
 use pathfinder_geometry::transform3d::{Perspective, Transform4F};
 use pathfinder_geometry::vector::{Vector2I, vec2i};
+use pathfinder_gpu::Device;
 use pathfinder_resources::ResourceLoader;
 use pathfinder_resources::fs::FilesystemResourceLoader;
 use rayon::ThreadPoolBuilder;
 use std::sync::Mutex;
-use io_surface::IOSurfaceRef;
-// use metal::Device as MetalDevice;
-// use pathfinder_metal::MetalDevice;
 use lazy_static::lazy_static;
-use surfman::{NativeDevice, SystemConnection, SystemDevice, SystemSurface};
-use surfman::{SurfaceAccess, SurfaceType};
 use euclid::default::Size2D;
+use crate::engine::camera::scroll_distance;
+use crate::engine::pointer::{Pointer, PointerId, PointerInput};
+use crate::engine::surface::{AdapterKind, GpuSurface};
+use crate::prelude::pf::DeviceImpl;
 
 lazy_static! {
     static ref EVENT_QUEUE: Mutex<Option<EventQueue>> = Mutex::new(None);
@@ -42,38 +43,47 @@ enum CustomEvent {
 
 struct EventQueue {
     event_loop_proxy: winit::event_loop::EventLoopProxy<CustomEvent>,
-    pending_custom_events: VecDeque<CustomEvent>,
 }
 
-// pub trait Window {
-//     fn metal_device(&self) -> metal::Device;
-//     fn metal_io_surface(&self) -> IOSurfaceRef;
-//     fn present(&mut self, device: &mut pathfinder_metal::MetalDevice);
-
-//     fn make_current(&mut self);
-//     fn viewport(&self) -> RectI;
-//     fn resource_loader(&self) -> &dyn ResourceLoader;
-//     fn create_user_event_id(&self) -> u32;
-//     fn push_user_event(message_type: u32, message_data: u32);
-//     fn present_open_svg_dialog(&mut self);
-//     fn run_save_dialog(&self, extension: &str) -> Result<PathBuf, ()>;
-
-//     fn adjust_thread_pool_settings(&self, builder: ThreadPoolBuilder) -> ThreadPoolBuilder {
-//         builder
-//     }
-// }
+/// What the engine and the app need from the thing they draw into: a GPU
+/// device and surface, a size, resources, and somewhere to send messages.
+/// `WindowImpl` and `AppWindow` are backed by winit; `HeadlessWindow`
+/// renders offscreen and replays scripted events.
+pub trait Window {
+    type Device: Device;
+
+    fn create_device(&mut self) -> Self::Device;
+    fn present(&mut self, device: &mut Self::Device);
+    /// Brings the surface in line with `size()` after the window resized.
+    fn resize_surface(&mut self, device: &mut Self::Device);
+
+    fn size(&self) -> WindowSize;
+    fn viewport(&self) -> RectI {
+        RectI::new(vec2i(0, 0), self.size().device_size())
+    }
+    fn window_size(&self) -> Vector2I {
+        self.size().device_size()
+    }
+    fn resource_loader(&self) -> &dyn ResourceLoader;
+    fn create_user_event_id(&self) -> u32;
+    fn push_user_event(message_type: u32, message_data: u32);
+    fn present_open_svg_dialog(&mut self);
+    fn run_open_dialog(&self, extension: &str) -> Result<PathBuf, ()>;
+    fn run_save_dialog(&self, extension: &str) -> Result<PathBuf, ()>;
+
+    fn adjust_thread_pool_settings(&self, builder: ThreadPoolBuilder) -> ThreadPoolBuilder {
+        builder
+    }
+}
 
 pub enum Event {
     Quit,
     WindowResized(WindowSize),
     KeyDown(Keycode),
     KeyUp(Keycode),
-    MouseDown(Vector2I),
-    MouseMoved(Vector2I),
-    MouseDragged {
-        delta: Vector2I,
-        global: Vector2I,
-    },
+    /// Mouse, touch and pen input alike.
+    Pointer(Pointer),
+    Zoom(f32, Vector2I),
     Look {
         pitch: f32,
         yaw: f32,
@@ -90,62 +100,27 @@ impl Event {
     fn init_from_winit_event(
         winit_event: winit::event::Event<CustomEvent>,
         window: &winit::window::Window,
-        mouse_position: &mut Vector2I,
-        mouse_down: &mut bool
+        pointers: &mut PointerInput,
     ) -> Option<Event> {
-        let orig_mouse_position = *mouse_position;
         match winit_event {
-            // winit::event::Event::Awakened => {
-            //     let mut event_queue = EVENT_QUEUE.lock().unwrap();
-            //     let event_queue = event_queue.as_mut().unwrap();
-            //     match event_queue.pending_custom_events
-            //                     .pop_front()
-            //                     .expect("`Awakened` with no pending custom event!") {
-            //         CustomEvent::OpenData(data_path) => Some(Event::OpenData(DataPath::Path(data_path))),
-            //         CustomEvent::User { message_data, message_type } => {
-            //             Some(Event::User { message_data, message_type })
-            //         }
-            //     }
-            // }
+            winit::event::Event::UserEvent(custom_event) => {
+                match custom_event {
+                    CustomEvent::OpenData(data_path) => Some(Event::OpenData(DataPath::Path(data_path))),
+                    CustomEvent::User { message_data, message_type } => {
+                        Some(Event::User { message_data, message_type })
+                    }
+                }
+            }
             winit::event::Event::WindowEvent { event: window_event, .. } => {
                 match window_event {
-                    // winit::WindowEvent::MouseWheel{delta, ..} => {
-                    //     match delta {
-                    //         e @ winit::MouseScrollDelta::LineDelta(..) => {
-                    //             println!("{:?}", e);
-                    //         }
-                    //         e @ winit::MouseScrollDelta::PixelDelta(..) => {
-                    //             println!("{:?}", e);
-                    //         }
-                    //     }
-                    //     None
-                    // }
-                    winit::event::WindowEvent::MouseInput {
-                        state: winit::event::ElementState::Pressed,
-                        button: winit::event::MouseButton::Left,
-                        ..
-                    } => {
-                        *mouse_down = true;
-                        Some(Event::MouseDown(*mouse_position))
+                    winit::event::WindowEvent::MouseWheel { delta, .. } => {
+                        let mouse_position = pointers.pointer(PointerId::Mouse).unwrap().position;
+                        Some(Event::Zoom(scroll_distance(delta), mouse_position.to_i32()))
                     }
-                    winit::event::WindowEvent::MouseInput {
-                        state: winit::event::ElementState::Released,
-                        button: winit::event::MouseButton::Left,
-                        ..
-                    } => {
-                        *mouse_down = false;
-                        None
-                    }
-                    winit::event::WindowEvent::CursorMoved { position, .. } => {
-                        *mouse_position = vec2i(position.x as i32, position.y as i32);
-                        if *mouse_down {
-                            Some(Event::MouseDragged {
-                                delta: *mouse_position,
-                                global: orig_mouse_position,
-                            })
-                        } else {
-                            Some(Event::MouseMoved(*mouse_position))
-                        }
+                    winit::event::WindowEvent::MouseInput { .. } |
+                    winit::event::WindowEvent::CursorMoved { .. } |
+                    winit::event::WindowEvent::Touch(..) => {
+                        pointers.handle_event(&window_event, Instant::now()).map(Event::Pointer)
                     }
                     winit::event::WindowEvent::KeyboardInput { input, .. } => {
                         input.virtual_keycode.and_then(|virtual_keycode| {
@@ -229,33 +204,46 @@ pub enum DataPath {
 
 pub struct WindowImpl {
     window: winit::window::Window,
-    connection: SystemConnection,
-    device: SystemDevice,
-    metal_device: NativeDevice,
-    surface: SystemSurface,
+    gpu: GpuSurface,
     event_loop: winit::event_loop::EventLoop<CustomEvent>,
     pending_events: VecDeque<Event>,
-    mouse_position: Vector2I,
-    mouse_down: bool,
+    pointers: PointerInput,
     next_user_event_id: Cell<u32>,
     resource_loader: FilesystemResourceLoader,
 }
 
 
 
-impl WindowImpl {
-    pub fn metal_device(&self) -> metal::Device {
-        // FIXME(pcwalton): Remove once `surfman` upgrades `metal-rs` version.
-        unsafe {
-            std::mem::transmute(self.metal_device.0.clone())
-        }
+impl Window for WindowImpl {
+    type Device = DeviceImpl;
+
+    fn create_device(&mut self) -> DeviceImpl {
+        self.gpu.create_device()
+    }
+
+    fn present(&mut self, device: &mut DeviceImpl) {
+        self.gpu.present(device);
     }
 
-    pub fn metal_io_surface(&self) -> IOSurfaceRef {
-        self.device.native_surface(&self.surface).0
+    fn resize_surface(&mut self, device: &mut DeviceImpl) {
+        let size = self.size().device_size();
+        self.gpu.resize(size, device);
     }
 
-    pub fn viewport(&self) -> RectI {
+    fn size(&self) -> WindowSize {
+        let window = self.window();
+        let (monitor, size) = {
+            let monitor = window.current_monitor().unwrap();
+            (monitor, window.inner_size())
+        };
+
+        WindowSize {
+            logical_size: vec2i(size.width as i32, size.height as i32),
+            backing_scale_factor: monitor.scale_factor() as f32,
+        }
+    }
+
+    fn viewport(&self) -> RectI {
         let WindowSize { logical_size, backing_scale_factor } = self.size();
         let mut size = (logical_size.to_f32() * backing_scale_factor).to_i32();
         let mut x_offset = 0;
@@ -266,56 +254,48 @@ impl WindowImpl {
         RectI::new(vec2i(x_offset, 0), size)
     }
 
-    pub fn window_size(&self) -> Vector2I {
-        let WindowSize { logical_size, backing_scale_factor } = self.size();
-        let mut size = (logical_size.to_f32() * backing_scale_factor).to_i32();
-        size
+    fn resource_loader(&self) -> &dyn ResourceLoader {
+        &self.resource_loader
     }
 
-    pub fn present(&mut self, metal_device: &mut pathfinder_metal::MetalDevice) {
-        self.device.present_surface(&mut self.surface).expect("Failed to present surface!");
-        metal_device.swap_texture(self.device.native_surface(&self.surface).0);
+    fn present_open_svg_dialog(&mut self) {
+        if let Ok(path) = self.run_open_dialog("svg,pdf") {
+            push_custom_event(CustomEvent::OpenData(path));
+        }
     }
 
-    pub fn resource_loader(&self) -> &dyn ResourceLoader {
-        &self.resource_loader
+    fn run_open_dialog(&self, extension: &str) -> Result<PathBuf, ()> {
+        match nfd::open_file_dialog(Some(extension), None) {
+            Ok(nfd::Response::Okay(file)) => Ok(PathBuf::from(file)),
+            _ => Err(()),
+        }
     }
 
-    // pub fn present_open_svg_dialog(&mut self) {
-    //     if let Ok(nfd::Response::Okay(path)) = nfd::open_file_dialog(Some("svg,pdf"), None) {
-    //         let mut event_queue = EVENT_QUEUE.lock().unwrap();
-    //         let event_queue = event_queue.as_mut().unwrap();
-    //         event_queue.pending_custom_events.push_back(CustomEvent::OpenData(PathBuf::from(path)));
-    //         // drop(event_queue.event_loop_proxy.wakeup());
-    //         unimplemented!()
-    //     }
-    // }
-
-    // pub fn run_save_dialog(&self, extension: &str) -> Result<PathBuf, ()> {
-    //     match nfd::open_save_dialog(Some(extension), None) {
-    //         Ok(nfd::Response::Okay(file)) => Ok(PathBuf::from(file)),
-    //         _ => Err(()),
-    //     }
-    // }
+    fn run_save_dialog(&self, extension: &str) -> Result<PathBuf, ()> {
+        match nfd::open_save_dialog(Some(extension), None) {
+            Ok(nfd::Response::Okay(file)) => Ok(PathBuf::from(file)),
+            _ => Err(()),
+        }
+    }
 
-    pub fn create_user_event_id(&self) -> u32 {
+    fn create_user_event_id(&self) -> u32 {
         let id = self.next_user_event_id.get();
         self.next_user_event_id.set(id + 1);
         id
     }
 
-    pub fn push_user_event(message_type: u32, message_data: u32) {
-        let mut event_queue = EVENT_QUEUE.lock().unwrap();
-        let event_queue = event_queue.as_mut().unwrap();
-        event_queue.pending_custom_events.push_back(CustomEvent::User {
-            message_type,
-            message_data,
-        });
-        // drop(event_queue.event_loop_proxy.wakeup());
-        unimplemented!()
+    fn push_user_event(message_type: u32, message_data: u32) {
+        push_custom_event(CustomEvent::User { message_type, message_data });
     }
 }
 
+/// Wakes the event loop with `event`. Dropped if the loop already exited.
+fn push_custom_event(event: CustomEvent) {
+    let event_queue = EVENT_QUEUE.lock().unwrap();
+    let event_queue = event_queue.as_ref().unwrap();
+    drop(event_queue.event_loop_proxy.send_event(event));
+}
+
 impl WindowImpl {
     pub fn new(options: &crate::engine::Options) -> WindowImpl {
         let event_loop = winit::event_loop::EventLoop::<CustomEvent>::with_user_event();
@@ -329,71 +309,39 @@ impl WindowImpl {
         
         // window.show();
 
-        let connection = SystemConnection::from_winit_window(&window).unwrap();
-        let native_widget = connection.create_native_widget_from_winit_window(&window).unwrap();
-
-        let adapter = if options.high_performance_gpu {
-            connection.create_hardware_adapter().unwrap()
-        } else {
-            connection.create_low_power_adapter().unwrap()
-        };
-
-        let mut device = connection.create_device(&adapter).unwrap();
-        let native_device = device.native_device();
-
-        let surface_type = SurfaceType::Widget { native_widget };
-        let surface = device.create_surface(SurfaceAccess::GPUOnly, surface_type).unwrap();
+        let adapter_kind = AdapterKind::from_env(options.high_performance_gpu);
+        let gpu = GpuSurface::new(&window, adapter_kind);
 
         let resource_loader = FilesystemResourceLoader::locate();
 
         *EVENT_QUEUE.lock().unwrap() = Some(EventQueue {
             event_loop_proxy: event_loop.create_proxy(),
-            pending_custom_events: VecDeque::new(),
         });
 
         WindowImpl {
             window,
             event_loop,
-            connection,
-            device,
-            metal_device: native_device,
-            surface,
+            gpu,
             next_user_event_id: Cell::new(0),
             pending_events: VecDeque::new(),
-            mouse_position: vec2i(0, 0),
-            mouse_down: false,
+            pointers: PointerInput::new(),
             resource_loader,
         }
     }
 
     fn window(&self) -> &winit::window::Window { &self.window }
 
-    pub fn size(&self) -> WindowSize {
-        let window = self.window();
-        let (monitor, size) = {
-            let monitor = window.current_monitor().unwrap();
-            (monitor, window.inner_size())
-        };
-
-        WindowSize {
-            logical_size: vec2i(size.width as i32, size.height as i32),
-            backing_scale_factor: monitor.scale_factor() as f32,
-        }
-    }
-
     // pub fn get_event(&mut self) -> Event {
     //     if self.pending_events.is_empty() {
     //         let window = &self.window;
-    //         let mouse_position = &mut self.mouse_position;
-    //         let mouse_down = &mut self.mouse_down;
+    //         let pointers = &mut self.pointers;
     //         let pending_events = &mut self.pending_events;
     //         self.event_loop.run(|winit_event, _, control| {
     //             //println!("blocking {:?}", winit_event);
     //             // match Event::init_from_winit_event(
     //             //     winit_event,
     //             //     window,
-    //             //     mouse_position,
-    //             //     mouse_down
+    //             //     pointers,
     //             // ) {
     //             //     Some(event) => {
     //             //         //println!("handled");
@@ -413,16 +361,14 @@ impl WindowImpl {
     pub fn try_get_event(&mut self) -> Option<Event> {
         // if self.pending_events.is_empty() {
         //     let window = &self.window;
-        //     let mouse_position = &mut self.mouse_position;
-        //     let mouse_down = &mut self.mouse_down;
+        //     let pointers = &mut self.pointers;
         //     let pending_events = &mut self.pending_events;
         //     self.event_loop.poll_events(|winit_event| {
         //         //println!("nonblocking {:?}", winit_event);
         //         if let Some(event) = Event::init_from_winit_event(
         //             winit_event,
         //             window,
-        //             mouse_position,
-        //             mouse_down
+        //             pointers,
         //         ) {
         //             //println!("handled");
         //             pending_events.push_back(event);
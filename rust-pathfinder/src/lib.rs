@@ -0,0 +1,4 @@
+#![allow(unused)]
+pub mod prelude;
+pub mod engine;
+pub mod app;
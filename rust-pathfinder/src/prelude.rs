@@ -7,6 +7,8 @@ pub use pathfinder_geometry::vector::{Vector2F, Vector2I, Vector4F};
 pub mod wit {
     pub use winit::event::{Event, WindowEvent, DeviceEvent, DeviceId, MouseScrollDelta};
     pub use winit::event::ElementState;
+    pub use winit::event::{KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode};
+    pub use winit::event::{Force, Touch, TouchPhase};
     pub use winit::event_loop::{ControlFlow, EventLoop, EventLoopProxy, EventLoopClosed};
     pub use winit::event_loop::EventLoopWindowTarget;
     pub use winit::window::Window;
@@ -23,10 +25,14 @@ pub mod pf {
     pub use pathfinder_content::effects::DEFRINGING_KERNEL_CORE_GRAPHICS;
     pub use pathfinder_content::effects::PatternFilter;
     pub use pathfinder_content::effects::STEM_DARKENING_FACTORS;
-    pub use pathfinder_content::outline::Outline;
+    pub use pathfinder_content::gradient::Gradient;
+    pub use pathfinder_content::outline::{Contour, ContourIterFlags, Outline};
+    pub use pathfinder_content::segment::SegmentKind;
+    pub use pathfinder_content::stroke::{OutlineStrokeToFill, StrokeStyle};
     pub use pathfinder_content::pattern::Pattern;
     pub use pathfinder_content::render_target::RenderTargetId;
     pub use pathfinder_export::{Export, FileFormat};
+    pub use pathfinder_geometry::line_segment::LineSegment2F;
     pub use pathfinder_geometry::rect::{RectF, RectI};
     pub use pathfinder_geometry::transform2d::Transform2F;
     pub use pathfinder_geometry::transform3d::Transform4F;
@@ -42,13 +48,23 @@ pub mod pf {
     pub use pathfinder_resources::ResourceLoader;
     pub use pathfinder_svg::SVGScene;
     pub use pathfinder_ui::{MousePosition, UIEvent};
+    #[cfg(target_os = "macos")]
     pub use pathfinder_metal::MetalDevice;
+    #[cfg(not(target_os = "macos"))]
+    pub use pathfinder_gl::{GLDevice, GLVersion};
+    /// The `Device` the renderer uses on this platform.
+    #[cfg(target_os = "macos")]
+    pub type DeviceImpl = pathfinder_metal::MetalDevice;
+    #[cfg(not(target_os = "macos"))]
+    pub type DeviceImpl = pathfinder_gl::GLDevice;
     pub use pathfinder_resources::fs::FilesystemResourceLoader;
     pub use pathfinder_color::{ColorF, ColorU};
     pub use pathfinder_canvas::{Canvas, CanvasRenderingContext2D, CanvasFontContext};
     pub use pathfinder_canvas::Path2D;
     pub use pathfinder_canvas::FillRule;
     pub use pathfinder_canvas::FillStyle;
+    pub use pathfinder_canvas::{LineCap, LineJoin};
+    pub use pathfinder_canvas::CompositeOperation;
     pub use pathfinder_renderer::concurrent::rayon::RayonExecutor;
     pub use pathfinder_canvas::ArcDirection;
 }
\ No newline at end of file
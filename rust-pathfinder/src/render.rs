@@ -0,0 +1,437 @@
+//! Renders `.alphadraw` documents and SVG files to PNG, SVG, PDF or
+//! PostScript without a display, e.g.
+//!
+//!     alphadraw-render --format png --dpi 192 --output out/ a.alphadraw b.svg
+//!
+//! With `--page`, documents are laid out on printed pages instead:
+//!
+//!     alphadraw-render --format pdf --page a4 --orientation landscape a.alphadraw
+//!
+//! PNG goes through an offscreen GPU surface; set `LIBGL_ALWAYS_SOFTWARE=1`
+//! on machines without a GPU.
+#![allow(unused)]
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use clap::{App, Arg};
+use image::ColorType;
+use surfman::declare_surfman;
+use jemallocator;
+use rust_pathfinder::prelude::*;
+use rust_pathfinder::prelude::pf::Export;
+use rust_pathfinder::app::{self, format, scene};
+use rust_pathfinder::app::fitting::FitOptions;
+use rust_pathfinder::app::page_export::{self, PageFormat, PageOptions};
+use rust_pathfinder::engine::{BackgroundColor, SvgDataModel};
+use rust_pathfinder::engine::headless::HeadlessWindow;
+use rust_pathfinder::engine::renderer::read_default_framebuffer;
+use rust_pathfinder::engine::window::{DataPath, Window};
+
+#[global_allocator]
+static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+
+declare_surfman!();
+
+/// Document units are CSS pixels, so this DPI renders them one to one.
+const DEFAULT_DPI: f32 = 96.0;
+
+/// Ink-fitted output keeps this much space around the strokes.
+const FIT_MARGIN: f32 = 8.0;
+
+///////////////////////////////////////////////////////////////////////////////
+// OPTIONS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    Png,
+    Svg,
+    Pdf,
+    Ps,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "png" => Some(OutputFormat::Png),
+            "svg" => Some(OutputFormat::Svg),
+            "pdf" => Some(OutputFormat::Pdf),
+            "ps" => Some(OutputFormat::Ps),
+            _ => None,
+        }
+    }
+    fn extension(&self) -> &'static str {
+        match *self {
+            OutputFormat::Png => "png",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Ps => "ps",
+        }
+    }
+    fn page_format(&self) -> Option<PageFormat> {
+        match *self {
+            OutputFormat::Pdf => Some(PageFormat::Pdf),
+            OutputFormat::Ps => Some(PageFormat::PostScript),
+            _ => None,
+        }
+    }
+}
+
+struct RenderOptions {
+    inputs: Vec<PathBuf>,
+    format: OutputFormat,
+    /// Defaults to the directory of each input.
+    output_dir: Option<PathBuf>,
+    /// Area to render in input units, from the input's origin. Documents
+    /// default to their ink bounds and SVGs to their view box.
+    size: Option<Vector2F>,
+    /// Only affects PNG; vector output stays in input units.
+    dpi: f32,
+    background: BackgroundColor,
+    /// Lays documents out on pages; only for PDF and PostScript.
+    pages: Option<PageOptions>,
+}
+
+impl RenderOptions {
+    fn from_command_line() -> Result<RenderOptions, String> {
+        let matches = App::new("alphadraw-render")
+            .about("Renders .alphadraw documents and SVG files without a display")
+            .arg(
+                Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["png", "svg", "pdf", "ps"])
+                    .default_value("png")
+                    .help("The output format"),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .value_name("DIR")
+                    .takes_value(true)
+                    .help("Directory to write to, instead of next to each input"),
+            )
+            .arg(
+                Arg::with_name("size")
+                    .short("s")
+                    .long("size")
+                    .value_name("WIDTHxHEIGHT")
+                    .takes_value(true)
+                    .help("Area to render, in document units"),
+            )
+            .arg(
+                Arg::with_name("dpi")
+                    .short("d")
+                    .long("dpi")
+                    .takes_value(true)
+                    .help("Resolution of PNG output; 96 maps one unit to one pixel"),
+            )
+            .arg(
+                Arg::with_name("background")
+                    .short("b")
+                    .long("background")
+                    .takes_value(true)
+                    .possible_values(&["light", "dark", "transparent"])
+                    .default_value("transparent")
+                    .help("The background color to use"),
+            )
+            .arg(
+                Arg::with_name("page")
+                    .short("p")
+                    .long("page")
+                    .value_name("SIZE")
+                    .takes_value(true)
+                    .conflicts_with("size")
+                    .help("Lays documents out on a4, letter or WIDTHxHEIGHT pages, in points"),
+            )
+            .arg(
+                Arg::with_name("orientation")
+                    .long("orientation")
+                    .takes_value(true)
+                    .possible_values(&["portrait", "landscape"])
+                    .requires("page")
+                    .help("The page orientation [default: portrait]"),
+            )
+            .arg(
+                Arg::with_name("placement")
+                    .long("placement")
+                    .takes_value(true)
+                    .possible_values(&["fit", "crop"])
+                    .requires("page")
+                    .help("Scale the drawing to the page, or keep its size and crop it \
+                           [default: fit]"),
+            )
+            .arg(
+                Arg::with_name("margin")
+                    .long("margin")
+                    .value_name("POINTS")
+                    .takes_value(true)
+                    .requires("page")
+                    .help("Space left on each side of the page [default: 36]"),
+            )
+            .arg(
+                Arg::with_name("per-layer")
+                    .long("per-layer")
+                    .requires("page")
+                    .help("Puts each visible layer on a page of its own"),
+            )
+            .arg(
+                Arg::with_name("INPUT")
+                    .help(".alphadraw or .svg files to render")
+                    .required(true)
+                    .multiple(true),
+            )
+            .get_matches();
+
+        let format = OutputFormat::from_name(matches.value_of("format").unwrap()).unwrap();
+        let background = BackgroundColor::from_name(matches.value_of("background").unwrap())
+            .unwrap();
+        let size = match matches.value_of("size") {
+            Some(size) => Some(parse_size(size)?),
+            None => None,
+        };
+        let dpi = match matches.value_of("dpi") {
+            Some(dpi) => match dpi.parse::<f32>() {
+                Ok(dpi) if dpi > 0.0 => dpi,
+                _ => return Err(format!("Invalid DPI \"{}\"", dpi)),
+            },
+            None => DEFAULT_DPI,
+        };
+        let pages = match matches.value_of("page") {
+            Some(page) => {
+                if format.page_format().is_none() {
+                    return Err(String::from("Pages need --format pdf or ps"));
+                }
+                // Page options files take the same settings.
+                let mut pages = PageOptions::default();
+                pages.set("page", Some(page))?;
+                for name in ["orientation", "placement", "margin"].iter() {
+                    if let Some(value) = matches.value_of(name) {
+                        pages.set(name, Some(value))?;
+                    }
+                }
+                if matches.is_present("per-layer") {
+                    pages.set("per-layer", None)?;
+                }
+                Some(pages)
+            }
+            None => None,
+        };
+        Ok(RenderOptions {
+            inputs: matches.values_of("INPUT").unwrap().map(PathBuf::from).collect(),
+            format,
+            output_dir: matches.value_of("output").map(PathBuf::from),
+            size,
+            dpi,
+            background,
+            pages,
+        })
+    }
+
+    fn output_path(&self, input: &Path) -> PathBuf {
+        let directory = match self.output_dir {
+            Some(ref directory) => directory.clone(),
+            None => input.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let stem = input.file_stem().unwrap_or_default();
+        directory.join(stem).with_extension(self.format.extension())
+    }
+}
+
+fn parse_size(size: &str) -> Result<Vector2F, String> {
+    let invalid = || format!("Invalid size \"{}\", expected WIDTHxHEIGHT", size);
+    let mut parts = size.splitn(2, 'x');
+    let width = parts.next().and_then(|width| width.trim().parse::<f32>().ok());
+    let height = parts.next().and_then(|height| height.trim().parse::<f32>().ok());
+    match (width, height) {
+        (Some(width), Some(height)) if width > 0.0 && height > 0.0 => {
+            Ok(Vector2F::new(width, height))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// INPUTS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Loads `path` into a scene in its own units, with the background painted
+/// under it. Returns the area to render along with the scene.
+fn load_input(path: &Path, options: &RenderOptions) -> Result<(pf::Scene, RectF), String> {
+    match input_extension(path).as_ref().map(String::as_str) {
+        Some(format::EXTENSION) => {
+            let document = format::load(path).map_err(|error| error.to_string())?;
+            let view_box = match options.size {
+                Some(size) => RectF::new(Vector2F::zero(), size),
+                None => document
+                    .bounds()
+                    .map(|bounds| bounds.dilate(FIT_MARGIN))
+                    .unwrap_or(RectF::new(Vector2F::zero(), Vector2F::splat(1.0))),
+            };
+            let canvas = pf::Canvas::new(view_box.size());
+            let mut ctx = canvas.get_context_2d(pf::CanvasFontContext::from_system_source());
+            ctx.set_fill_style(pf::FillStyle::Color(options.background.color()));
+            ctx.fill_rect(RectF::new(Vector2F::zero(), view_box.size()));
+            ctx.set_transform(&pf::Transform2F::from_translation(-view_box.origin()));
+            let ctx = scene::draw_document(
+                ctx,
+                &document,
+                &FitOptions::default(),
+                None,
+                &mut scene::StrokePaths::default(),
+            );
+            let scene = ctx.into_canvas().into_scene();
+            Ok((scene, RectF::new(Vector2F::zero(), view_box.size())))
+        }
+        Some("svg") => {
+            let resource_loader = pf::FilesystemResourceLoader::locate();
+            let data_path = DataPath::Path(path.to_path_buf());
+            let svg_model = SvgDataModel::load(&data_path, &resource_loader)?;
+            let (mut scene, message) = svg_model.render_with_background(
+                options.background.color(),
+            );
+            if !message.is_empty() {
+                eprintln!("{}: {}", path.display(), message);
+            }
+            let mut view_box = scene.view_box();
+            if let Some(size) = options.size {
+                view_box = RectF::new(view_box.origin(), size);
+                scene.set_view_box(view_box);
+            }
+            Ok((scene, view_box))
+        }
+        _ => Err(String::from("Unsupported input; expected .alphadraw or .svg")),
+    }
+}
+
+fn input_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// OUTPUTS
+///////////////////////////////////////////////////////////////////////////////
+
+fn write_output(
+    mut scene: pf::Scene,
+    view_box: RectF,
+    output: &Path,
+    options: &RenderOptions,
+) -> Result<(), String> {
+    match options.format {
+        OutputFormat::Png => write_png(scene, view_box, output, options),
+        OutputFormat::Svg | OutputFormat::Pdf | OutputFormat::Ps => {
+            let file_format = match options.format.page_format() {
+                Some(page_format) => page_format.file_format(),
+                None => pf::FileFormat::SVG,
+            };
+            let file = File::create(output).map_err(|error| error.to_string())?;
+            let mut writer = BufWriter::new(file);
+            scene.export(&mut writer, file_format).map_err(|error| error.to_string())
+        }
+    }
+}
+
+fn write_png(
+    mut scene: pf::Scene,
+    view_box: RectF,
+    output: &Path,
+    options: &RenderOptions,
+) -> Result<(), String> {
+    let scale = options.dpi / DEFAULT_DPI;
+    let pixel_size = (view_box.size() * scale).ceil().to_i32().max(Vector2I::splat(1));
+    // The window has to outlive the renderer, which frees GPU resources on
+    // drop.
+    let mut window = HeadlessWindow::new(pixel_size);
+    let device = window.create_device();
+    let (mut renderer, mut scene_proxy) = app::create_renderer(
+        device,
+        window.viewport(),
+        window.window_size(),
+        window.resource_loader(),
+    );
+    renderer.options_mut().background_color = Some(options.background.color().to_f32());
+    renderer.options_mut().show_debug_ui = false;
+    scene.set_view_box(RectF::new(Vector2F::zero(), pixel_size.to_f32()));
+    scene_proxy.replace_scene(scene);
+    let transform = pf::Transform2F::from_scale(scale) *
+        pf::Transform2F::from_translation(-view_box.origin());
+    scene_proxy.build_and_render(&mut renderer, pf::BuildOptions {
+        transform: pf::RenderTransform::Transform2D(transform),
+        ..pf::BuildOptions::default()
+    });
+    let pixels = read_default_framebuffer(renderer.device(), pixel_size);
+    image::save_buffer(
+        output,
+        &pixels,
+        pixel_size.x() as u32,
+        pixel_size.y() as u32,
+        ColorType::Rgba8,
+    ).map_err(|error| error.to_string())
+}
+
+/// Writes the pages of the document at `input`, which may be several
+/// numbered files next to `output`.
+fn write_pages(
+    input: &Path,
+    output: &Path,
+    pages: &PageOptions,
+    options: &RenderOptions,
+) -> Result<Vec<PathBuf>, String> {
+    if input_extension(input).as_ref().map(String::as_str) != Some(format::EXTENSION) {
+        return Err(String::from("Pages need an .alphadraw input"));
+    }
+    let document = format::load(input).map_err(|error| error.to_string())?;
+    let page_format = options.format.page_format().unwrap();
+    page_export::export(&document, &FitOptions::default(), pages, page_format, output)
+        .map_err(|error| error.to_string())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// MAIN
+///////////////////////////////////////////////////////////////////////////////
+
+fn main() {
+    pretty_env_logger::init();
+    let options = match RenderOptions::from_command_line() {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        }
+    };
+    if let Some(ref directory) = options.output_dir {
+        if let Err(error) = fs::create_dir_all(directory) {
+            eprintln!("Failed to create {}: {}", directory.display(), error);
+            std::process::exit(1);
+        }
+    }
+    let mut failures = 0;
+    for input in options.inputs.iter() {
+        let output = options.output_path(input);
+        let result = match options.pages {
+            Some(ref pages) => write_pages(input, &output, pages, &options),
+            None => load_input(input, &options).and_then(|(scene, view_box)| {
+                write_output(scene, view_box, &output, &options).map(|()| vec![output.clone()])
+            }),
+        };
+        match result {
+            Ok(outputs) => {
+                for output in outputs.iter() {
+                    println!("{} -> {}", input.display(), output.display());
+                }
+            }
+            Err(error) => {
+                eprintln!("Failed to render {}: {}", input.display(), error);
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,52 @@
+//! Drives the app through a headless window: draws a stroke with the mouse
+//! and undoes it. Needs a GPU, or llvmpipe through `LIBGL_ALWAYS_SOFTWARE=1`,
+//! hence ignored by default. Run with
+//! `cargo test --test headless -- --ignored`.
+use rust_pathfinder::prelude::*;
+use rust_pathfinder::app::AppState;
+use rust_pathfinder::engine::headless::HeadlessWindow;
+
+fn key(window: &mut HeadlessWindow, key: wit::VirtualKeyCode) {
+    window.push_event(HeadlessWindow::key_input(wit::ElementState::Pressed, key));
+    window.push_event(HeadlessWindow::key_input(wit::ElementState::Released, key));
+}
+
+#[test]
+#[ignore]
+fn drags_draw_strokes_that_undo_takes_back() {
+    let mut window = HeadlessWindow::new(pf::vec2i(320, 240));
+    let mut app = AppState::new(&mut window);
+    for event in HeadlessWindow::activate() {
+        window.push_event(event);
+    }
+    window.push_event(HeadlessWindow::cursor_moved(pf::vec2f(40.0, 40.0)));
+    window.push_event(HeadlessWindow::mouse_input(
+        wit::ElementState::Pressed,
+        wit::MouseButton::Left,
+    ));
+    for step in 1..=10 {
+        let position = pf::vec2f(40.0 + 20.0 * step as f32, 40.0 + 10.0 * step as f32);
+        window.push_event(HeadlessWindow::cursor_moved(position));
+    }
+    window.push_event(HeadlessWindow::mouse_input(
+        wit::ElementState::Released,
+        wit::MouseButton::Left,
+    ));
+    app.run_scripted(&mut window);
+
+    assert_eq!(app.document.stroke_count(), 1);
+    let stroke = app.document.strokes().next().unwrap();
+    assert!(stroke.points.len() > 1);
+    let bounds = stroke.bounds().unwrap();
+    assert!(bounds.min_x() >= 39.0 && bounds.max_x() <= 241.0);
+    let frames = window.frames_presented();
+    assert!(frames > 0);
+
+    window.push_event(wit::WindowEvent::ModifiersChanged(wit::ModifiersState::CTRL));
+    key(&mut window, wit::VirtualKeyCode::Z);
+    window.push_event(wit::WindowEvent::ModifiersChanged(wit::ModifiersState::empty()));
+    app.run_scripted(&mut window);
+
+    assert_eq!(app.document.stroke_count(), 0);
+    assert!(window.frames_presented() > frames);
+}
@@ -0,0 +1,119 @@
+//! Exports documents as SVG, reads them back with usvg, and imports them
+//! again. Run with `cargo test --test svg_export`.
+use rust_pathfinder::prelude::*;
+use rust_pathfinder::app::document::{
+    BlendMode, Document, Layer, Stroke, StrokeId, StrokeOrigin, StrokePoint, StrokeStyle,
+};
+use rust_pathfinder::app::dynamics::BrushDynamics;
+use rust_pathfinder::app::fitting::FitOptions;
+use rust_pathfinder::app::paint::{Fill, Gradient, GradientKind, GradientStop, Paint};
+use rust_pathfinder::app::shape::{Shape, ShapeKind};
+use rust_pathfinder::app::svg_export;
+use rust_pathfinder::app::svg_import;
+
+/// Two layers: freehand strokes of constant and of varying width, and a
+/// translucent multiplied layer with a filled shape.
+fn sample_document() -> Document {
+    let mut document = Document::new();
+    let constant = StrokeStyle { dynamics: BrushDynamics::none(), ..StrokeStyle::default() };
+    draw(&mut document, constant, 0.0, StrokeOrigin::Brush);
+    draw(&mut document, StrokeStyle::default(), 40.0, StrokeOrigin::EraserPiece);
+
+    let mut layer = document.new_layer();
+    layer.properties.opacity = 0.5;
+    layer.properties.blend_mode = BlendMode::Multiply;
+    document.active_layer = layer.id;
+    document.insert_layer(1, layer);
+    let id = document.begin_stroke(StrokeStyle::default(), StrokeOrigin::ShapeTool).unwrap();
+    let mut shape = Shape::new(ShapeKind::Ellipse {
+        center: pf::vec2f(100.0, 100.0),
+        radii: pf::vec2f(30.0, 20.0),
+    });
+    shape.transform = pf::Transform2F::from_rotation(0.5);
+    shape.fill = Some(Fill {
+        paint: Paint::Gradient(Gradient {
+            kind: GradientKind::Linear { from: pf::vec2f(70.0, 0.0), to: pf::vec2f(130.0, 0.0) },
+            stops: vec![
+                GradientStop { offset: 0.0, color: pf::ColorU::new(255, 0, 0, 255) },
+                GradientStop { offset: 1.0, color: pf::ColorU::new(0, 0, 255, 128) },
+            ],
+            transform: pf::Transform2F::default(),
+        }),
+        rule: pf::FillRule::EvenOdd,
+    });
+    document.push_point(id, StrokePoint { position: Vector2F::zero(), pressure: 1.0, time: 0.0 });
+    document.update_stroke(id, |stroke| stroke.set_shape(shape));
+    document
+}
+
+/// A wavy stroke with rising pressure, `offset` down the page.
+fn draw(document: &mut Document, style: StrokeStyle, offset: f32, origin: StrokeOrigin) {
+    let id = document.begin_stroke(style, origin).unwrap();
+    for index in 0..20 {
+        let t = index as f32 / 19.0;
+        document.push_point(id, StrokePoint {
+            position: pf::vec2f(10.0 + 150.0 * t, offset + 20.0 + 10.0 * (t * 6.0).sin()),
+            pressure: 0.2 + 0.8 * t,
+            time: t * 0.5,
+        });
+    }
+}
+
+fn export(document: &Document) -> Vec<u8> {
+    let mut data = Vec::new();
+    svg_export::write_svg(document, &FitOptions::default(), &mut data).unwrap();
+    data
+}
+
+/// The strokes of each layer without their IDs, which importing replaces.
+fn strokes(layers: &[Layer]) -> Vec<Vec<Stroke>> {
+    layers
+        .iter()
+        .map(|layer| {
+            layer.strokes
+                .iter()
+                .map(|stroke| Stroke { id: StrokeId(0), ..stroke.clone() })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn export_is_valid_svg() {
+    let document = sample_document();
+    let tree = usvg::Tree::from_data(&export(&document), &usvg::Options::default()).unwrap();
+    let paths = tree.root().descendants().filter(|node| match *node.borrow() {
+        usvg::NodeKind::Path(_) => true,
+        _ => false,
+    });
+    assert_eq!(paths.count(), document.strokes().count());
+    let view_box = tree.svg_node().view_box.rect;
+    let bounds = document.bounds().unwrap().round_out();
+    assert_eq!(view_box.x() as f32, bounds.origin_x());
+    assert_eq!(view_box.width() as f32, bounds.width());
+}
+
+#[test]
+fn export_imports_as_the_same_document() {
+    let document = sample_document();
+    let mut imported = Document::new();
+    let import = svg_import::import_data(&export(&document), "export", &mut imported).unwrap();
+    assert!(import.unsupported.is_empty());
+    assert_eq!(import.layers.len(), document.layers().len());
+    for (layer, original) in import.layers.iter().zip(document.layers().iter()) {
+        assert_eq!(layer.properties, original.properties);
+    }
+    assert_eq!(strokes(&import.layers), strokes(document.layers()));
+}
+
+#[test]
+fn export_without_records_imports_as_a_drawing() {
+    let document = sample_document();
+    let svg = String::from_utf8(export(&document)).unwrap();
+    let svg = svg.replace("alphadraw:version", "alphadraw:unknown");
+    let mut imported = Document::new();
+    let import = svg_import::import_data(svg.as_bytes(), "export", &mut imported).unwrap();
+    let strokes = import.layers.iter().flat_map(|layer| layer.strokes.iter());
+    assert!(strokes.clone().all(|stroke| stroke.origin == StrokeOrigin::Imported));
+    assert_eq!(strokes.count(), document.strokes().count());
+}
@@ -0,0 +1,78 @@
+//! Imports the SVGs in `resources/svg` as document content. Run with
+//! `cargo test --test svg_import`.
+use std::fs;
+use std::path::{Path, PathBuf};
+use rust_pathfinder::app::document::Document;
+use rust_pathfinder::app::format;
+use rust_pathfinder::app::svg_import::{self, Import};
+
+/// Only text, which becomes paths only if its fonts are installed.
+const TEXT_ONLY: &str = "sample.svg";
+
+fn resources() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("resources").join("svg")
+}
+
+fn import(name: &str) -> (Document, Import) {
+    let mut document = Document::new();
+    let path = resources().join(name);
+    match svg_import::import_file(&path, &mut document) {
+        Ok(import) => (document, import),
+        Err(error) => panic!("{}: {}", name, error),
+    }
+}
+
+#[test]
+fn every_bundled_svg_becomes_shapes() {
+    for entry in fs::read_dir(resources()).unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        let (_, import) = import(&name);
+        if name == TEXT_ONLY {
+            continue;
+        }
+        assert!(!import.layers.is_empty(), "{} imported nothing", name);
+        for layer in import.layers.iter() {
+            assert!(!layer.strokes.is_empty(), "{}: empty layer {}", name, layer.properties.name);
+            for stroke in layer.strokes.iter() {
+                let shape = stroke.shape.as_ref().expect("imported strokes are shapes");
+                assert!(shape.stroked || shape.fill.is_some(), "{}: invisible shape", name);
+                assert!(!stroke.points.is_empty(), "{}: shape without samples", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn tiger_keeps_fills_and_strokes() {
+    let (_, import) = import("Ghostscript_Tiger.svg");
+    let shapes = import.layers
+        .iter()
+        .flat_map(|layer| layer.strokes.iter())
+        .filter_map(|stroke| stroke.shape.as_ref())
+        .collect::<Vec<_>>();
+    assert!(shapes.len() > 100);
+    assert!(shapes.iter().any(|shape| shape.fill.is_some() && shape.stroked));
+    assert!(shapes.iter().any(|shape| shape.fill.is_some() && !shape.stroked));
+    assert!(import.message().is_empty(), "{}", import.message());
+}
+
+#[test]
+fn unsupported_features_are_reported() {
+    let (_, import) = import("magicleap-quickstart-p03.svg");
+    assert!(import.unsupported.contains("clip paths"));
+    assert!(import.unsupported.contains("dashed strokes"));
+    assert!(import.message().starts_with("Warning:"), "{}", import.message());
+}
+
+#[test]
+fn imported_layers_survive_saving() {
+    let (mut document, import) = import("pathfinder_logo.svg");
+    for layer in import.layers {
+        let index = document.layers().len();
+        document.insert_layer(index, layer);
+    }
+    let mut data = Vec::new();
+    format::write_document(&document, &mut data).unwrap();
+    let loaded = format::read_document(&data[..]).unwrap();
+    assert_eq!(loaded.layers(), document.layers());
+}